@@ -0,0 +1,8 @@
+/* build.rs */
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The gRPC service and client are generated from the proto file; this needs `protoc`.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/kvmap.proto")?;
+    Ok(())
+}
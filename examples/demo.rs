@@ -12,7 +12,7 @@ struct User {
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize with a custom path in the user's home directory
-    let pm = Pathmap::new().with_base_path("/opt/ns");
+    let pm = Pathmap::builder().base_path("/opt/ns").build()?;
 
     // Cleanup previous runs if necessary
     if pm.exists("words").await? {
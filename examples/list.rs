@@ -44,7 +44,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n--- Testing Listing ---");
 
     // 1. List all namespaces
-    let mut all_ns = pm.list_ns()?;
+    let mut all_ns = pm.list_ns().await?;
     println!("All namespaces: {:?}", all_ns);
     // Sort for predictable testing
     all_ns.sort();
@@ -0,0 +1,80 @@
+/* include/kvmap.h */
+/* Generated by cbindgen from src/ffi.rs (see cbindgen.toml); do not edit by hand. */
+
+#ifndef KVMAP_H
+#define KVMAP_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Result of every `kvmap_*` call.
+ */
+typedef enum KvmapStatus {
+  KVMAP_OK = 0,
+  KVMAP_NOT_FOUND = 1,
+  KVMAP_ALREADY_EXISTS = 2,
+  KVMAP_INVALID_ARGUMENT = 3,
+  KVMAP_ERROR = 4,
+} KvmapStatus;
+
+/**
+ * Opaque store handle owned by the caller.
+ */
+typedef struct KvmapHandle KvmapHandle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a store rooted at `base_path` and writes its handle to `out`.
+ */
+KvmapStatus kvmap_open(const char *base_path, KvmapHandle **out);
+
+/**
+ * Closes a store opened with `kvmap_open`, committing pending writes first. Passing null is a
+ * no-op.
+ */
+void kvmap_close(KvmapHandle *handle);
+
+/**
+ * Reads the JSON value at `path` into a new buffer, released with `kvmap_buffer_free`.
+ */
+KvmapStatus kvmap_get(const KvmapHandle *handle,
+                      const char *path,
+                      uint8_t **out_data,
+                      size_t *out_len);
+
+/**
+ * Stores `len` bytes of JSON at `path`. With `overwrite` set an existing value is replaced,
+ * otherwise the call fails with `KVMAP_ALREADY_EXISTS`.
+ */
+KvmapStatus kvmap_set(const KvmapHandle *handle,
+                      const char *path,
+                      const uint8_t *data,
+                      size_t len,
+                      bool overwrite);
+
+/**
+ * Deletes the value at `path`.
+ */
+KvmapStatus kvmap_delete(const KvmapHandle *handle, const char *path);
+
+/**
+ * Releases a buffer returned by `kvmap_get`. Passing null is a no-op.
+ */
+void kvmap_buffer_free(uint8_t *data, size_t len);
+
+/**
+ * The message of the last failed call on this thread, or null. The pointer stays valid until the
+ * next failing call on the same thread.
+ */
+const char *kvmap_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* KVMAP_H */
@@ -0,0 +1,193 @@
+/* kvmap-derive/src/lib.rs */
+
+//! `#[derive(KvEntity)]`, re-exported by `kvmap` with its `derive` feature. See
+//! `kvmap::KvEntity` for the generated methods.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{Data, DeriveInput, Fields, LitStr, Member, parse_macro_input};
+
+/// Maps a struct to a group of a namespace: `#[kvmap(ns = "app", group = "users")]` on the
+/// struct, and `#[kvmap(id)]` on the field whose value names each entity.
+#[proc_macro_derive(KvEntity, attributes(kvmap))]
+pub fn derive_kv_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// `UserProfile` -> `user_profile` and `HTTPConfig` -> `http_config`, the default group.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let prev = i.checked_sub(1).map(|p| chars[p]);
+            let next = chars.get(i + 1);
+            if prev.is_some_and(|p| p.is_lowercase() || p.is_ascii_digit())
+                || (prev.is_some_and(char::is_uppercase) && next.is_some_and(|n| n.is_lowercase()))
+            {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut ns: Option<LitStr> = None;
+    let mut group: Option<LitStr> = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("kvmap")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("ns") {
+                ns = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("group") {
+                group = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `ns = \"...\"` or `group = \"...\"`"))
+            }
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "KvEntity can only be derived for structs",
+            ));
+        }
+    };
+    let mut id: Option<Member> = None;
+    let members: Vec<(Member, &syn::Field)> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|f| (Member::Named(f.ident.clone().unwrap()), f))
+            .collect(),
+        Fields::Unnamed(unnamed) => unnamed
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (Member::from(i), f))
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (member, field) in members {
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("kvmap")) {
+            attr.parse_nested_meta(|meta| {
+                if !meta.path.is_ident("id") {
+                    return Err(meta.error("expected `id`"));
+                }
+                if id.is_some() {
+                    return Err(meta.error("only one field can be the `id`"));
+                }
+                id = Some(member.clone());
+                Ok(())
+            })?;
+        }
+    }
+
+    let name = &input.ident;
+    let group =
+        group.unwrap_or_else(|| LitStr::new(&snake_case(&name.to_string()), Span::call_site()));
+    if group.value().is_empty() {
+        return Err(syn::Error::new_spanned(&group, "group must not be empty"));
+    }
+    // The namespace is only known at run time without `ns`; any valid name checks the group.
+    let checked = format!(
+        "{}::{}",
+        ns.as_ref().map_or_else(|| "_".to_string(), LitStr::value),
+        group.value()
+    );
+    let namespace = match &ns {
+        Some(ns) => quote!(::core::option::Option::Some(#ns)),
+        None => quote!(::core::option::Option::None),
+    };
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let (entity_id, methods) = match &id {
+        Some(id) => (
+            quote! {
+                ::core::option::Option::Some(::std::string::ToString::to_string(&self.#id))
+            },
+            quote! {
+                /// Reads the entity with `id`.
+                pub async fn load(
+                    pm: &::kvmap::Pathmap,
+                    id: impl ::std::fmt::Display,
+                ) -> ::kvmap::error::Result<Self> {
+                    pm.load_entity::<Self>(::core::option::Option::Some(&id.to_string()))
+                        .await
+                }
+
+                /// Writes the entity, replacing any stored under the same id.
+                pub async fn save(&self, pm: &::kvmap::Pathmap) -> ::kvmap::error::Result<()> {
+                    pm.save_entity(self).await
+                }
+
+                /// Deletes the entity with `id`.
+                pub async fn delete(
+                    pm: &::kvmap::Pathmap,
+                    id: impl ::std::fmt::Display,
+                ) -> ::kvmap::error::Result<()> {
+                    pm.delete_entity::<Self>(::core::option::Option::Some(&id.to_string()))
+                        .await
+                }
+
+                /// Ids of the stored entities.
+                pub async fn ids(
+                    pm: &::kvmap::Pathmap,
+                ) -> ::kvmap::error::Result<::std::vec::Vec<::std::string::String>> {
+                    pm.entity_ids::<Self>().await
+                }
+            },
+        ),
+        None => (
+            quote!(::core::option::Option::None),
+            quote! {
+                /// Reads the entity.
+                pub async fn load(pm: &::kvmap::Pathmap) -> ::kvmap::error::Result<Self> {
+                    pm.load_entity::<Self>(::core::option::Option::None).await
+                }
+
+                /// Writes the entity, replacing the stored one.
+                pub async fn save(&self, pm: &::kvmap::Pathmap) -> ::kvmap::error::Result<()> {
+                    pm.save_entity(self).await
+                }
+
+                /// Deletes the entity.
+                pub async fn delete(pm: &::kvmap::Pathmap) -> ::kvmap::error::Result<()> {
+                    pm.delete_entity::<Self>(::core::option::Option::None).await
+                }
+            },
+        ),
+    };
+
+    Ok(quote! {
+        const _: () = ::core::assert!(
+            ::kvmap::path::is_valid_path(#checked),
+            ::core::concat!("invalid kvmap namespace or group: ", #checked)
+        );
+
+        impl #impl_generics ::kvmap::KvEntity for #name #ty_generics #where_clause {
+            const NAMESPACE: ::core::option::Option<&'static str> = #namespace;
+            const GROUP: &'static str = #group;
+
+            fn entity_id(&self) -> ::core::option::Option<::std::string::String> {
+                #entity_id
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #methods
+        }
+    })
+}
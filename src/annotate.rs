@@ -0,0 +1,166 @@
+/* src/annotate.rs */
+
+use crate::Pathmap;
+use crate::blob;
+use crate::db;
+use crate::error::{PathmapError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Human-readable documentation attached to a key, so shared stores describe themselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMeta {
+    /// What the value is for.
+    pub description: Option<String>,
+    /// Who is responsible for the value, e.g. a team or an email address.
+    pub owner: Option<String>,
+}
+
+impl KeyMeta {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none() && self.owner.is_none()
+    }
+}
+
+/// The JSON type of a stored value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueKind {
+    Object,
+    Array,
+    String,
+    Number,
+    Bool,
+    Null,
+}
+
+impl ValueKind {
+    /// The type of a serialized value, from its first byte.
+    fn of(serialized: &[u8]) -> Option<ValueKind> {
+        match serialized.first()? {
+            b'{' => Some(ValueKind::Object),
+            b'[' => Some(ValueKind::Array),
+            b'"' => Some(ValueKind::String),
+            b'-' | b'0'..=b'9' => Some(ValueKind::Number),
+            b't' | b'f' => Some(ValueKind::Bool),
+            b'n' => Some(ValueKind::Null),
+            _ => None,
+        }
+    }
+}
+
+/// A value in a detailed listing, with its annotation if it has one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedValue {
+    pub name: String,
+    pub meta: Option<KeyMeta>,
+    /// Length of the serialized value in bytes.
+    pub size: u64,
+    /// `None` if the value cannot be read, e.g. a missing blob file.
+    pub kind: Option<ValueKind>,
+    /// When the value was last written; `None` for values written before schema version 7.
+    pub updated_at: Option<SystemTime>,
+}
+
+/// The contents of a namespace or group, with value annotations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetailedListing {
+    /// A list of sub-groups.
+    pub groups: Vec<String>,
+    /// A list of values.
+    pub values: Vec<ListedValue>,
+}
+
+impl Pathmap {
+    /// Attaches a description and owner to an existing value, replacing any previous annotation.
+    /// An empty `KeyMeta` removes it. Annotations survive overwrites and are dropped with the value.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn annotate(&self, path: &str, meta: KeyMeta) -> Result<()> {
+        let (ns, key) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        let (grp, key) = self.split_key(&key);
+        if meta.is_empty() {
+            return db::remove_annotation(&pool, grp, key).await;
+        }
+        if !db::set_annotation(&pool, grp, key, &meta).await? {
+            return Err(PathmapError::ValueNotFound(db::join_key(grp, key)));
+        }
+        Ok(())
+    }
+
+    /// The annotation of a value, or `None` if it has none (or does not exist).
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn annotation(&self, path: &str) -> Result<Option<KeyMeta>> {
+        let (ns, key) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        let (grp, key) = self.split_key(&key);
+        db::annotation(&pool, grp, key).await
+    }
+
+    /// Like `list`, but describes every value with its size, JSON type, last write time and
+    /// annotation, without reading whole values (only blob files are opened, for their first byte).
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn list_detailed(&self, path: &str) -> Result<DetailedListing> {
+        let listing = self.list(path).await?;
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        let grp = self.normalize_key(grp);
+        let (ns, grp) = self.resolve_soft(ns, grp)?;
+        let pool = self.get_pool(ns).await?;
+        let mut annotations: HashMap<String, KeyMeta> = db::group_annotations(&pool, &grp)
+            .await?
+            .into_iter()
+            .collect();
+        let mut entries: HashMap<String, db::EntryRow> = db::group_entries(&pool, &grp)
+            .await?
+            .into_iter()
+            .map(|entry| (entry.key.clone(), entry))
+            .collect();
+        let blob_dir = blob::blob_dir(&self.get_db_path(ns));
+        let mut values = Vec::with_capacity(listing.values.len());
+        for name in listing.values {
+            // Skips values deleted between the listing and this lookup.
+            let Some(entry) = entries.remove(&name) else {
+                continue;
+            };
+            let meta = annotations.remove(&name);
+            let (size, kind) = if entry.external {
+                let blob = String::from_utf8_lossy(&entry.head);
+                let prefix = blob::read_prefix(&blob_dir, &blob, 1).await.ok();
+                (
+                    blob::stored_size(&blob_dir, &entry.head, true).await,
+                    prefix.as_deref().and_then(ValueKind::of),
+                )
+            } else {
+                (entry.size, ValueKind::of(&entry.head))
+            };
+            values.push(ListedValue {
+                name,
+                meta,
+                size,
+                kind,
+                updated_at: entry
+                    .updated_at
+                    .map(|ms| UNIX_EPOCH + Duration::from_millis(ms.max(0) as u64)),
+            });
+        }
+        Ok(DetailedListing {
+            groups: listing.groups,
+            values,
+        })
+    }
+}
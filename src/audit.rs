@@ -0,0 +1,354 @@
+/* src/audit.rs */
+
+//! The opt-in audit trail: a `kv_audit` table in each audited namespace, written in the same
+//! transaction as the mutation it records.
+
+use crate::Pathmap;
+use crate::config::AuditMode;
+use crate::db::{self, AuditRow, StoredValue};
+use crate::digest;
+use crate::error::{PathmapError, Result};
+use crate::event::{Operation, current_actor};
+use sqlx::{Executor, Sqlite, SqlitePool};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Rows returned by `audit_log` unless the filter sets a limit.
+const DEFAULT_LIMIT: u32 = 1000;
+
+/// One recorded mutation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Position in the namespace's trail; pass it to `AuditFilter::after` to page.
+    pub seq: u64,
+    pub at: SystemTime,
+    /// Who the mutation was made for, as set with `as_actor`.
+    pub actor: Option<String>,
+    /// `Set`, `Overwrite`, `Delete` or `DeleteGroup`.
+    pub op: Operation,
+    /// The dotted key, or the group for `DeleteGroup`.
+    pub key: String,
+    /// Hex SHA-256 of the serialized value that was replaced or deleted, with
+    /// `AuditMode::WithOldHash`.
+    pub old_hash: Option<String>,
+}
+
+/// Which audit entries `audit_log` returns, oldest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditFilter {
+    group: String,
+    actor: Option<String>,
+    since: Option<SystemTime>,
+    until: Option<SystemTime>,
+    after: u64,
+    limit: u32,
+}
+
+impl Default for AuditFilter {
+    fn default() -> Self {
+        Self {
+            group: String::new(),
+            actor: None,
+            since: None,
+            until: None,
+            after: 0,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl AuditFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only entries for keys in `group` or a group nested below it.
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = group.to_string();
+        self
+    }
+
+    /// Only entries made on behalf of `actor`.
+    pub fn actor(mut self, actor: &str) -> Self {
+        self.actor = Some(actor.to_string());
+        self
+    }
+
+    /// Only entries recorded at or after `time`.
+    pub fn since(mut self, time: SystemTime) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// Only entries recorded before `time`.
+    pub fn until(mut self, time: SystemTime) -> Self {
+        self.until = Some(time);
+        self
+    }
+
+    /// Only entries after the one with `seq`, to continue from the last entry of a page.
+    pub fn after(mut self, seq: u64) -> Self {
+        self.after = seq;
+        self
+    }
+
+    /// Returns at most `limit` entries (default 1000).
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+/// A single-row mutation, applied by `write_row`.
+pub(crate) enum RowWrite<'a> {
+    /// Inserts a new row; fails if the key exists.
+    Set(&'a [u8], bool),
+    Overwrite(&'a [u8], bool),
+    Delete,
+}
+
+async fn apply_row<'e, E>(
+    executor: E,
+    grp: &str,
+    key: &str,
+    write: &RowWrite<'_>,
+    now: i64,
+) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    match *write {
+        RowWrite::Set(data, external) => db::set(executor, grp, key, data, external, now).await,
+        RowWrite::Overwrite(data, external) => {
+            db::overwrite(executor, grp, key, data, external, now).await
+        }
+        RowWrite::Delete => db::delete(executor, grp, key).await,
+    }
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+impl Pathmap {
+    /// Applies `write` to `grp`/`key` of `ns`, whose current row is `previous`. In an audited
+    /// namespace the audit entry is written in the same transaction, so no mutation commits
+    /// without it; the key and byte limits of the namespace's quota are checked there as well.
+    pub(crate) async fn write_row(
+        &self,
+        ns: &str,
+        pool: &SqlitePool,
+        grp: &str,
+        key: &str,
+        write: RowWrite<'_>,
+        previous: Option<&StoredValue>,
+    ) -> Result<()> {
+        let mode = self.ns_config(ns).audit;
+        let mut quota = self.ns_config(ns).quota;
+        if previous.is_some() {
+            // Replacing a value adds no key, so a namespace at its limit may still update.
+            quota.max_keys = None;
+        }
+        let check_quota = !matches!(write, RowWrite::Delete) && quota.limits_totals();
+        let now = unix_millis(self.clock.system_now());
+        // Deleting a missing key changes nothing and leaves no trace.
+        let audited =
+            mode != AuditMode::Off && !(matches!(write, RowWrite::Delete) && previous.is_none());
+        if !audited && !check_quota {
+            return apply_row(pool, grp, key, &write, now).await;
+        }
+        let mut tx = pool.begin().await?;
+        self.write_row_in(ns, &mut tx, grp, key, write, previous)
+            .await?;
+        if check_quota {
+            quota.check_totals(ns, &mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Like `write_row`, inside the caller's transaction on `conn`.
+    pub(crate) async fn write_row_in(
+        &self,
+        ns: &str,
+        conn: &mut sqlx::SqliteConnection,
+        grp: &str,
+        key: &str,
+        write: RowWrite<'_>,
+        previous: Option<&StoredValue>,
+    ) -> Result<()> {
+        let mode = self.ns_config(ns).audit;
+        let now = unix_millis(self.clock.system_now());
+        apply_row(&mut *conn, grp, key, &write, now).await?;
+        if mode == AuditMode::Off || (matches!(write, RowWrite::Delete) && previous.is_none()) {
+            return Ok(());
+        }
+        let old_hash = match previous {
+            Some(previous) if mode == AuditMode::WithOldHash => Some(digest::sha256_hex(
+                &self.load_value(ns, previous.clone()).await?,
+            )),
+            _ => None,
+        };
+        let op = match write {
+            RowWrite::Set(..) => Operation::Set,
+            RowWrite::Overwrite(..) => Operation::Overwrite,
+            RowWrite::Delete => Operation::Delete,
+        };
+        self.record_audit(conn, op, grp, key, old_hash).await
+    }
+
+    /// Exchanges the values of two existing keys of `ns` in one transaction, recording both as
+    /// overwrites in an audited namespace. Returns the sizes of the values now stored at `a` and
+    /// `b`.
+    pub(crate) async fn swap_rows(
+        &self,
+        ns: &str,
+        pool: &SqlitePool,
+        a: (&str, &str),
+        b: (&str, &str),
+    ) -> Result<(u64, u64)> {
+        let mode = self.ns_config(ns).audit;
+        let now = unix_millis(self.clock.system_now());
+        let mut tx = pool.begin().await?;
+        let mut rows = Vec::with_capacity(2);
+        for (grp, key) in [a, b] {
+            let row = db::find(&mut *tx, grp, key)
+                .await?
+                .ok_or_else(|| PathmapError::ValueNotFound(db::join_key(grp, key)))?;
+            rows.push(row);
+        }
+        let (row_b, row_a) = (rows.pop().unwrap(), rows.pop().unwrap());
+        // Otherwise `a` and `b` would briefly hold the same value; parking `b` on `null`, whose
+        // fields are all NULL, keeps a unique index from rejecting the swap.
+        db::overwrite(&mut *tx, b.0, b.1, b"null", false, now).await?;
+        for ((grp, key), old, new) in [(a, &row_a, &row_b), (b, &row_b, &row_a)] {
+            db::overwrite(&mut *tx, grp, key, &new.data, new.external, now).await?;
+            if mode != AuditMode::Off {
+                let old_hash = match mode {
+                    AuditMode::WithOldHash => {
+                        Some(digest::sha256_hex(&self.load_value(ns, old.clone()).await?))
+                    }
+                    _ => None,
+                };
+                self.record_audit(&mut tx, Operation::Overwrite, grp, key, old_hash)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok((
+            self.stored_size(ns, &row_b).await,
+            self.stored_size(ns, &row_a).await,
+        ))
+    }
+
+    /// Records `op` on `grp`/`key`, whose serialized value was `old`, in the transaction that
+    /// made it (for `take` and `patch`); does nothing unless `ns` is audited.
+    pub(crate) async fn audit_replaced(
+        &self,
+        ns: &str,
+        conn: &mut sqlx::SqliteConnection,
+        op: Operation,
+        grp: &str,
+        key: &str,
+        old: &[u8],
+    ) -> Result<()> {
+        let old_hash = match self.ns_config(ns).audit {
+            AuditMode::Off => return Ok(()),
+            AuditMode::Mutations => None,
+            AuditMode::WithOldHash => Some(digest::sha256_hex(old)),
+        };
+        self.record_audit(conn, op, grp, key, old_hash).await
+    }
+
+    /// Deletes `grp` of `ns` and everything below it, recording a `DeleteGroup` entry in an
+    /// audited namespace when anything was removed.
+    pub(crate) async fn delete_group_rows(
+        &self,
+        ns: &str,
+        pool: &SqlitePool,
+        grp: &str,
+    ) -> Result<u64> {
+        if self.ns_config(ns).audit == AuditMode::Off {
+            return db::delete_group(pool, grp).await;
+        }
+        let mut tx = pool.begin().await?;
+        let removed = db::delete_group(&mut *tx, grp).await?;
+        if removed > 0 {
+            self.record_audit(&mut tx, Operation::DeleteGroup, grp, "", None)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    async fn record_audit(
+        &self,
+        conn: &mut sqlx::SqliteConnection,
+        op: Operation,
+        grp: &str,
+        key: &str,
+        old_hash: Option<String>,
+    ) -> Result<()> {
+        let op = match serde_json::to_value(op)? {
+            serde_json::Value::String(name) => name,
+            other => other.to_string(),
+        };
+        let row = AuditRow {
+            seq: 0,
+            at: unix_millis(self.clock.system_now()),
+            actor: current_actor(),
+            op,
+            grp: grp.to_string(),
+            key: key.to_string(),
+            old_hash,
+        };
+        db::insert_audit(conn, &row).await
+    }
+
+    /// Whether writes to `ns` are audited, which bypasses write batching.
+    pub(crate) fn is_audited(&self, ns: &str) -> bool {
+        self.ns_config(ns).audit != AuditMode::Off
+    }
+
+    /// Reads the audit trail of `ns`, recorded with `NamespaceConfig::audit`. Entries are kept
+    /// until `trim_audit_log` removes them, also after the keys they name are gone.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn audit_log(&self, ns: &str, filter: AuditFilter) -> Result<Vec<AuditEntry>> {
+        let pool = self.get_pool(ns).await?;
+        let rows = db::audit_rows(
+            &pool,
+            &filter.group,
+            filter.actor.as_deref(),
+            filter.since.map_or(i64::MIN, unix_millis),
+            filter.until.map_or(i64::MAX, unix_millis),
+            filter.after as i64,
+            i64::from(filter.limit),
+        )
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                Some(AuditEntry {
+                    seq: row.seq as u64,
+                    at: UNIX_EPOCH + Duration::from_millis(row.at.max(0) as u64),
+                    actor: row.actor,
+                    op: serde_json::from_value(serde_json::Value::String(row.op)).ok()?,
+                    key: if row.key.is_empty() {
+                        row.grp
+                    } else {
+                        db::join_key(&row.grp, &row.key)
+                    },
+                    old_hash: row.old_hash,
+                })
+            })
+            .collect())
+    }
+
+    /// Removes the entries of `ns`'s audit trail recorded before `before`, returning how many,
+    /// e.g. to enforce a retention period.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn trim_audit_log(&self, ns: &str, before: SystemTime) -> Result<u64> {
+        let pool = self.get_pool(ns).await?;
+        db::trim_audit(&pool, unix_millis(before)).await
+    }
+}
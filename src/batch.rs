@@ -0,0 +1,391 @@
+/* src/batch.rs */
+
+use crate::blob;
+use crate::cache::ReadCache;
+use crate::config::{Collation, Quota};
+use crate::db;
+use crate::error::{PathmapError, Result};
+use crate::event::{Events, Operation};
+use crate::{Pathmap, PoolLease};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// When batched writes are committed: after `max_entries` distinct keys or `max_delay` after the
+/// first write of a batch, whichever comes first.
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    pub max_entries: usize,
+    pub max_delay: Duration,
+}
+
+impl BatchConfig {
+    pub fn new(max_entries: usize, max_delay: Duration) -> Self {
+        BatchConfig {
+            max_entries: max_entries.max(1),
+            max_delay,
+        }
+    }
+}
+
+/// Resolves once the batch holding a write has been committed, with the batch's outcome.
+/// Dropping the handle does not cancel the write.
+pub struct FlushHandle {
+    done: Option<oneshot::Receiver<std::result::Result<(), String>>>,
+}
+
+impl Future for FlushHandle {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let Some(done) = self.done.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        Pin::new(done).poll(cx).map(|outcome| match outcome {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(message)) => Err(PathmapError::BatchFailed(message)),
+            Err(_) => Err(PathmapError::BatchFailed("batch was dropped".to_string())),
+        })
+    }
+}
+
+/// A coalesced write: the stored row payload, whether it is a blob reference and when it was
+/// queued (Unix milliseconds), or `None` for a delete.
+type Write = Option<(Vec<u8>, bool, i64)>;
+
+#[derive(Default)]
+struct Pending {
+    writes: BTreeMap<(String, String), Write>,
+    waiters: Vec<oneshot::Sender<std::result::Result<(), String>>>,
+}
+
+/// Where a namespace's batches are committed.
+#[derive(Clone)]
+struct Target {
+    pool: PoolLease,
+    blob_dir: PathBuf,
+    exact_keys: bool,
+    /// Checked in the committing transaction.
+    quota: Quota,
+}
+
+/// The open batch of one namespace.
+struct NsBatch {
+    /// Incremented whenever the batch is taken, so a timer only flushes the batch it was started for.
+    seq: u64,
+    target: Target,
+    pending: Pending,
+    /// Held while a batch commits, so batches of a namespace are applied in order.
+    commit: Arc<tokio::sync::Mutex<()>>,
+}
+
+pub(crate) struct Batcher {
+    config: BatchConfig,
+    cache: Arc<ReadCache>,
+    events: Arc<Events>,
+    namespaces: Mutex<HashMap<String, NsBatch>>,
+}
+
+impl Batcher {
+    pub(crate) fn new(config: BatchConfig, cache: Arc<ReadCache>, events: Arc<Events>) -> Self {
+        Batcher {
+            config,
+            cache,
+            events,
+            namespaces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds a write to the namespace's open batch, replacing an earlier write of the same key.
+    /// Returns the handle, whether the batch is now full, and the blob of a replaced write, which
+    /// no row ever referenced.
+    fn push(
+        self: &Arc<Self>,
+        ns: &str,
+        target: Target,
+        grp: &str,
+        key: &str,
+        write: Write,
+    ) -> (FlushHandle, bool, Option<Vec<u8>>) {
+        let (tx, rx) = oneshot::channel();
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let batch = namespaces.entry(ns.to_string()).or_insert_with(|| NsBatch {
+            seq: 0,
+            target: target.clone(),
+            pending: Pending::default(),
+            commit: Arc::default(),
+        });
+        batch.target = target;
+        if batch.pending.writes.is_empty() {
+            let batcher = Arc::clone(self);
+            let (ns, seq, delay) = (ns.to_string(), batch.seq, self.config.max_delay);
+            crate::rt::spawn(async move {
+                crate::rt::sleep(delay).await;
+                // Failures are reported to the waiting handles.
+                let _ = batcher.flush(&ns, Some(seq)).await;
+            });
+        }
+        let replaced = batch
+            .pending
+            .writes
+            .insert((grp.to_string(), key.to_string()), write);
+        batch.pending.waiters.push(tx);
+        let full = batch.pending.writes.len() >= self.config.max_entries;
+        let orphan = match replaced {
+            Some(Some((name, true, _))) => Some(name),
+            _ => None,
+        };
+        (FlushHandle { done: Some(rx) }, full, orphan)
+    }
+
+    /// Commits the open batch of a namespace in one transaction. With `only_seq` set, does nothing
+    /// if that batch was already taken.
+    pub(crate) async fn flush(&self, ns: &str, only_seq: Option<u64>) -> Result<()> {
+        let Some(commit) = self
+            .namespaces
+            .lock()
+            .unwrap()
+            .get(ns)
+            .map(|batch| Arc::clone(&batch.commit))
+        else {
+            return Ok(());
+        };
+        let _guard = commit.lock().await;
+        let (target, pending) = {
+            let mut namespaces = self.namespaces.lock().unwrap();
+            let Some(batch) = namespaces.get_mut(ns) else {
+                return Ok(());
+            };
+            if only_seq.is_some_and(|seq| seq != batch.seq) || batch.pending.writes.is_empty() {
+                return Ok(());
+            }
+            batch.seq += 1;
+            (batch.target.clone(), std::mem::take(&mut batch.pending))
+        };
+        let Target {
+            blob_dir,
+            exact_keys,
+            ..
+        } = &target;
+
+        let describe = self.events.is_active();
+        let result = commit_batch(ns, &target, &pending.writes, describe).await;
+        for (grp, key) in pending.writes.keys() {
+            self.cache.invalidate(ns, grp, key, *exact_keys);
+        }
+        if let Ok(old_sizes) = &result {
+            for (((grp, key), write), old_size) in pending.writes.iter().zip(old_sizes) {
+                let new_size = match write {
+                    Some((data, external, _)) => {
+                        Some(blob::stored_size(blob_dir, data, *external).await)
+                    }
+                    None if old_size.is_none() => continue,
+                    None => None,
+                };
+                let op = match write {
+                    Some(_) => Operation::Overwrite,
+                    None => Operation::Delete,
+                };
+                let path = format!("{}::{}", ns, db::join_key(grp, key));
+                self.events.emit(op, path, *old_size, new_size);
+            }
+        }
+        let outcome = result.as_ref().map(|_| ()).map_err(|e| e.to_string());
+        for waiter in pending.waiters {
+            let _ = waiter.send(outcome.clone());
+        }
+        result.map(|_| ())
+    }
+
+    /// Makes sure no batch of `ns` commits a write of `key` (all keys with `None`) after the
+    /// caller's own write: commits the open batch if it holds one, and waits for a batch being
+    /// committed. Under a collation other than binary, any open batch may hold an equal key.
+    async fn settle(&self, ns: &str, key: Option<(&str, &str)>) {
+        let (commit, holds_key) = {
+            let namespaces = self.namespaces.lock().unwrap();
+            let Some(batch) = namespaces.get(ns) else {
+                return;
+            };
+            let writes = &batch.pending.writes;
+            let holds_key = match key {
+                Some((grp, key)) if batch.target.exact_keys => {
+                    writes.contains_key(&(grp.to_string(), key.to_string()))
+                }
+                _ => !writes.is_empty(),
+            };
+            (Arc::clone(&batch.commit), holds_key)
+        };
+        if holds_key {
+            // Failures are reported to the waiting handles.
+            let _ = self.flush(ns, None).await;
+        } else {
+            drop(commit.lock().await);
+        }
+    }
+
+    fn namespaces(&self) -> Vec<String> {
+        self.namespaces.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+/// Applies a batch to the namespace `ns` in one transaction, within its quota, and releases the
+/// blobs it replaced. On failure the batch's own blobs are removed and nothing is written. With
+/// `describe` set, returns the size of each key's previous value, in the order of `writes`;
+/// otherwise the list is empty.
+async fn commit_batch(
+    ns: &str,
+    target: &Target,
+    writes: &BTreeMap<(String, String), Write>,
+    describe: bool,
+) -> Result<Vec<Option<u64>>> {
+    let Target { pool, blob_dir, .. } = target;
+    let result = async {
+        let mut replaced = Vec::new();
+        let mut old_sizes = Vec::new();
+        let mut tx = pool.begin().await?;
+        for ((grp, key), write) in writes {
+            let previous = db::find(&mut *tx, grp, key).await?;
+            if describe {
+                old_sizes.push(match &previous {
+                    Some(p) => Some(blob::stored_size(blob_dir, &p.data, p.external).await),
+                    None => None,
+                });
+            }
+            if let Some(previous) = previous
+                && previous.external
+            {
+                replaced.push(previous.data);
+            }
+            match write {
+                Some((data, external, queued_at)) => {
+                    db::overwrite(&mut *tx, grp, key, data, *external, *queued_at).await?
+                }
+                None => db::delete(&mut *tx, grp, key).await?,
+            }
+        }
+        if target.quota.limits_totals() {
+            target.quota.check_totals(ns, &mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok::<_, PathmapError>((replaced, old_sizes))
+    }
+    .await;
+
+    match result {
+        Ok((replaced, old_sizes)) => {
+            for name in replaced {
+                blob::remove(blob_dir, &String::from_utf8_lossy(&name)).await?;
+            }
+            Ok(old_sizes)
+        }
+        Err(e) => {
+            for (data, ..) in writes
+                .values()
+                .flatten()
+                .filter(|(_, external, _)| *external)
+            {
+                blob::remove(blob_dir, &String::from_utf8_lossy(data)).await?;
+            }
+            Err(e)
+        }
+    }
+}
+
+impl Pathmap {
+    /// Queues an overwrite in the namespace's open batch and returns without waiting for the disk.
+    /// Later writes of the same key within the batch replace it. Await the handle to learn when
+    /// (and whether) the batch was committed. Reads only see the value after that. A direct write
+    /// of the same key commits the batch first, so the batched value never lands on top of it.
+    /// Without `with_write_batching`, or in an audited namespace, the value is written immediately.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn overwrite_batched<T: Serialize>(
+        &self,
+        path: &str,
+        value: T,
+    ) -> Result<FlushHandle> {
+        let (ns, key) = self.parse_path(path)?;
+        let serialized_value = self.encode_write(ns, &key, &value)?;
+        let (grp, key) = self.split_key(&key);
+        self.check_value_quota(ns, serialized_value.len() as u64)?;
+        let Some(batcher) = self.batcher.as_ref().filter(|_| !self.is_audited(ns)) else {
+            self.write(ns, grp, key, Some(serialized_value)).await?;
+            return Ok(FlushHandle { done: None });
+        };
+        let target = self.batch_target(ns, true).await?;
+        let stored = self.store_value(ns, serialized_value).await?;
+        let queued_at = db::unix_millis(self.clock.system_now());
+        let write = Some((stored.0, stored.1, queued_at));
+        let (handle, full, orphan) = batcher.push(ns, target, grp, key, write);
+        if let Some(name) = orphan {
+            self.remove_blob(ns, &name).await?;
+        }
+        if full {
+            batcher.flush(ns, None).await?;
+        }
+        Ok(handle)
+    }
+
+    /// Queues a delete in the namespace's open batch; see `overwrite_batched`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn delete_batched(&self, path: &str) -> Result<FlushHandle> {
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        let Some(batcher) = self.batcher.as_ref().filter(|_| !self.is_audited(ns)) else {
+            self.write(ns, grp, key, None).await?;
+            return Ok(FlushHandle { done: None });
+        };
+        let target = self.batch_target(ns, false).await?;
+        let (handle, full, orphan) = batcher.push(ns, target, grp, key, None);
+        if let Some(name) = orphan {
+            self.remove_blob(ns, &name).await?;
+        }
+        if full {
+            batcher.flush(ns, None).await?;
+        }
+        Ok(handle)
+    }
+
+    /// Commits the open batches of all namespaces now.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn flush_batches(&self) -> Result<()> {
+        let Some(batcher) = &self.batcher else {
+            return Ok(());
+        };
+        for ns in batcher.namespaces() {
+            batcher.flush(&ns, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Commits batched writes of `key` of `ns` (of the whole namespace with `None`) before a
+    /// direct write, so a batch committing later cannot overwrite the newer value.
+    pub(crate) async fn settle_batches(&self, ns: &str, key: Option<(&str, &str)>) {
+        if let Some(batcher) = &self.batcher {
+            batcher.settle(ns, key).await;
+        }
+    }
+
+    async fn batch_target(&self, ns: &str, create: bool) -> Result<Target> {
+        let pool = if create {
+            self.get_pool_or_init(ns).await?
+        } else {
+            self.get_pool(ns).await?
+        };
+        let config = self.ns_config(ns);
+        Ok(Target {
+            pool,
+            blob_dir: blob::blob_dir(&self.get_db_path(ns)),
+            exact_keys: matches!(config.collation, Collation::Binary),
+            quota: config.quota,
+        })
+    }
+}
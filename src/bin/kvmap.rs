@@ -0,0 +1,219 @@
+/* src/bin/kvmap.rs */
+
+//! Command-line maintenance for a kvmap base directory.
+
+use kvmap::blocking::Pathmap;
+use kvmap::{Collation, FloatPolicy, IntegerPolicy, MigrateOptions, NamespaceConfig};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: kvmap migrate --path <dir> --to-latest [--dry-run] [--reencode]
+       kvmap tree --path <dir> [--depth <n>] <ns[::group]>
+
+Commands:
+  migrate    Upgrade every namespace under <dir> to the current schema and rebuild its indexes
+  tree       Print a namespace or group and everything below it as a tree
+
+Options:
+  --path <dir>               Base directory of the namespaces
+  --to-latest                Migrate to the latest schema version (the only supported target)
+  --dry-run                  Report what would change without writing anything
+  --reencode                 Also rewrite values that do not match the policies below
+  --group-depth <n>          Group depth the application opens the store with
+  --collation <name>         binary (default), nocase or rtrim
+  --float-policy <name>      null (default), error or string
+  --integer-policy <name>    number (default) or string
+  --blob-threshold <bytes>   Store values above this size as blob files
+  --depth <n>                Levels of groups `tree` expands (default: all)
+  -h, --help                 Print this help
+
+The options describe how the application configures its namespaces and apply to all of them.
+Namespaces with a custom collation must be migrated from the application with `Pathmap::migrate`.";
+
+struct MigrateArgs {
+    path: String,
+    options: MigrateOptions,
+    group_depth: Option<usize>,
+    config: NamespaceConfig,
+}
+
+fn parse_migrate(mut args: impl Iterator<Item = String>) -> Result<MigrateArgs, String> {
+    let mut path = None;
+    let mut to_latest = false;
+    let mut options = MigrateOptions::new();
+    let mut group_depth = None;
+    let mut config = NamespaceConfig::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--path" => path = Some(value()?),
+            "--to-latest" => to_latest = true,
+            "--dry-run" => options = options.dry_run(true),
+            "--reencode" => options = options.reencode(true),
+            "--group-depth" => group_depth = Some(parse_number(&arg, &value()?)?),
+            "--collation" => {
+                config = config.collation(match value()?.as_str() {
+                    "binary" => Collation::Binary,
+                    "nocase" => Collation::NoCase,
+                    "rtrim" => Collation::RTrim,
+                    other => return Err(format!("unknown collation '{}'", other)),
+                })
+            }
+            "--float-policy" => {
+                config = config.float_policy(match value()?.as_str() {
+                    "null" => FloatPolicy::Null,
+                    "error" => FloatPolicy::Error,
+                    "string" => FloatPolicy::String,
+                    other => return Err(format!("unknown float policy '{}'", other)),
+                })
+            }
+            "--integer-policy" => {
+                config = config.integer_policy(match value()?.as_str() {
+                    "number" => IntegerPolicy::Number,
+                    "string" => IntegerPolicy::String,
+                    other => return Err(format!("unknown integer policy '{}'", other)),
+                })
+            }
+            "--blob-threshold" => config = config.blob_threshold(parse_number(&arg, &value()?)?),
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    let path = path.ok_or("--path is required")?;
+    if !to_latest {
+        return Err("--to-latest is required".to_string());
+    }
+    Ok(MigrateArgs {
+        path,
+        options,
+        group_depth,
+        config,
+    })
+}
+
+struct TreeArgs {
+    path: String,
+    root: String,
+    depth: Option<usize>,
+}
+
+fn parse_tree(mut args: impl Iterator<Item = String>) -> Result<TreeArgs, String> {
+    let mut path = None;
+    let mut root = None;
+    let mut depth = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--path" => path = Some(value()?),
+            "--depth" => depth = Some(parse_number(&arg, &value()?)?),
+            other if other.starts_with("--") => {
+                return Err(format!("unknown argument '{}'", other));
+            }
+            _ if root.is_some() => return Err(format!("unexpected argument '{}'", arg)),
+            _ => root = Some(arg),
+        }
+    }
+    Ok(TreeArgs {
+        path: path.ok_or("--path is required")?,
+        root: root.ok_or("a namespace or group to print is required")?,
+        depth,
+    })
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got '{}'", flag, value))
+}
+
+fn migrate(args: MigrateArgs) -> kvmap::error::Result<()> {
+    if !std::path::Path::new(&args.path).is_dir() {
+        return Err(kvmap::error::PathmapError::InvalidPath(format!(
+            "'{}' is not a directory",
+            args.path
+        )));
+    }
+    let mut pm = kvmap::Pathmap::new()
+        .with_base_path(&args.path)
+        .with_default_ns_config(args.config);
+    if let Some(depth) = args.group_depth {
+        pm = pm.with_group_depth(depth);
+    }
+    let pm = Pathmap::from_async(pm)?;
+    let report = pm.migrate(args.options);
+    pm.close()?;
+    let report = report?;
+
+    let (verb, reencode_verb) = if report.dry_run {
+        ("would migrate", "would re-encode")
+    } else {
+        ("migrated", "re-encoded")
+    };
+    for ns in &report.namespaces {
+        let schema = if ns.from_version < ns.to_version {
+            format!("{} v{} -> v{}", verb, ns.from_version, ns.to_version)
+        } else {
+            format!("up to date at v{}", ns.to_version)
+        };
+        if ns.reencoded > 0 {
+            println!(
+                "{}: {}, {} {} values",
+                ns.ns, schema, reencode_verb, ns.reencoded
+            );
+        } else {
+            println!("{}: {}", ns.ns, schema);
+        }
+    }
+    let changed = report.namespaces.iter().filter(|ns| ns.changed()).count();
+    println!(
+        "{} of {} namespaces {}",
+        changed,
+        report.namespaces.len(),
+        if report.dry_run {
+            "need changes"
+        } else {
+            "changed"
+        }
+    );
+    Ok(())
+}
+
+fn tree(args: TreeArgs) -> kvmap::error::Result<()> {
+    let pm = Pathmap::from_async(
+        kvmap::Pathmap::new()
+            .with_base_path(&args.path)
+            .with_default_ns_config(NamespaceConfig::new().read_only(true)),
+    )?;
+    let tree = pm.render_tree(&args.root, args.depth);
+    pm.close()?;
+    print!("{}", tree?);
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let result = match command.as_deref() {
+        Some("migrate") => parse_migrate(args).map(migrate),
+        Some("tree") => parse_tree(args).map(tree),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(other) => Err(format!("unknown command '{}'", other)),
+        None => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(Ok(())) => ExitCode::SUCCESS,
+        Ok(Err(e)) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
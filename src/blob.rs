@@ -0,0 +1,113 @@
+/* src/blob.rs */
+
+use crate::error::{PathmapError, Result};
+use crate::rt;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+static BLOB_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Age below which `sweep` leaves unreferenced blob files alone: a write may have created the
+/// file and not yet committed the row that references it, e.g. while its batch is open.
+pub const SWEEP_GRACE: Duration = Duration::from_secs(15 * 60);
+
+/// Directory holding a namespace's externalized values, next to its SQLite file.
+pub fn blob_dir(db_path: &Path) -> PathBuf {
+    db_path.with_extension("blobs")
+}
+
+/// Writes a value to a fresh blob file and returns its file name.
+/// The data is written to a temporary file first so readers never see a partial blob.
+pub async fn write(dir: &Path, data: &[u8]) -> Result<String> {
+    rt::fs::create_dir_all(dir).await?;
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let name = format!(
+        "{:x}-{:x}-{:x}.blob",
+        nanos,
+        std::process::id(),
+        BLOB_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+    let tmp_path = dir.join(format!("{}.tmp", name));
+    rt::fs::write(&tmp_path, data.to_vec()).await?;
+    rt::fs::rename(&tmp_path, dir.join(&name)).await?;
+    Ok(name)
+}
+
+pub async fn read(dir: &Path, name: &str) -> Result<Vec<u8>> {
+    Ok(rt::fs::read(dir.join(checked_name(name)?)).await?)
+}
+
+/// The first `len` bytes of a blob.
+pub async fn read_prefix(dir: &Path, name: &str, len: u64) -> Result<Vec<u8>> {
+    Ok(rt::fs::read_prefix(dir.join(checked_name(name)?), len).await?)
+}
+
+/// Size of the value a stored row holds: the blob file's length for external rows, or 0 if the
+/// blob cannot be read.
+pub async fn stored_size(dir: &Path, data: &[u8], external: bool) -> u64 {
+    if !external {
+        return data.len() as u64;
+    }
+    let name = String::from_utf8_lossy(data);
+    let Ok(name) = checked_name(&name) else {
+        return 0;
+    };
+    rt::fs::metadata(dir.join(name))
+        .await
+        .map_or(0, |meta| meta.len())
+}
+
+/// Removes a blob file; a missing file is not an error.
+pub async fn remove(dir: &Path, name: &str) -> Result<()> {
+    match rt::fs::remove_file(dir.join(checked_name(name)?)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Deletes blob files that are no longer referenced by any row, and temporary files left by
+/// interrupted writes, returning how many were removed. Files younger than `SWEEP_GRACE` are
+/// kept, since `referenced` cannot list the rows of writes still in flight.
+pub async fn sweep(dir: &Path, referenced: &HashSet<String>) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let dir = dir.to_path_buf();
+    let referenced = referenced.clone();
+    let removed = rt::unblock(move || {
+        let mut removed = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let unreferenced = name.ends_with(".blob") && !referenced.contains(&name);
+            if !unreferenced && !name.ends_with(".blob.tmp") {
+                continue;
+            }
+            let age = entry
+                .metadata()?
+                .modified()?
+                .elapsed()
+                .unwrap_or(Duration::ZERO);
+            if age >= SWEEP_GRACE {
+                std::fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    })
+    .await?;
+    Ok(removed)
+}
+
+/// Guards against references that would escape the blob directory.
+fn checked_name(name: &str) -> Result<&str> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(PathmapError::InvalidPath(name.to_string()));
+    }
+    Ok(name)
+}
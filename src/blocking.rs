@@ -0,0 +1,421 @@
+/* src/blocking.rs */
+
+//! A synchronous wrapper around the async API for CLI tools and codebases without a runtime.
+//!
+//! Every call blocks the current thread on an internal tokio runtime owned by the wrapper.
+//! Do not call these methods (or drop the wrapper) from inside an async context; use the async
+//! [`crate::Pathmap`] there instead.
+
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::config::{NamespaceConfig, OpenMode};
+use crate::error::Result;
+use crate::import::{BulkImportOptions, ConflictRules, ImportReport};
+use crate::integrity::{CheckMode, IntegrityReport};
+use crate::snapshot::BackupReport;
+use crate::{
+    DetailedListing, FieldFilter, JsonIndex, KeyMeta, Listing, MigrateOptions, MigrationReport,
+    MultiTransaction, NameRules, NamespaceInfo, Normalizer, RetryConfig, SelfTestReport,
+    ThrottleConfig, WriteOutcome,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tokio::runtime::Runtime;
+
+/// Blocking counterpart of [`crate::Pathmap`].
+pub struct Pathmap {
+    // Declared before the runtime so pools are dropped while it still exists.
+    inner: crate::Pathmap,
+    runtime: Runtime,
+}
+
+impl Pathmap {
+    /// Creates a new instance with the default path ("/opt/pathmap/").
+    /// Panics if the internal runtime cannot be started; use `from_async` to handle that error.
+    pub fn new() -> Self {
+        Self::from_async(crate::Pathmap::new()).expect("failed to start the kvmap runtime")
+    }
+
+    /// Wraps an already configured async instance, e.g. to use builder methods not mirrored here.
+    pub fn from_async(inner: crate::Pathmap) -> Result<Self> {
+        // One worker keeps background work (pool maintenance, cleanup, shadow reads) running
+        // between calls without spawning a thread per core.
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("kvmap-blocking")
+            .enable_all()
+            .build()?;
+        Ok(Pathmap { inner, runtime })
+    }
+
+    /// The wrapped async instance.
+    pub fn as_async(&self) -> &crate::Pathmap {
+        &self.inner
+    }
+
+    /// Runs any future on the internal runtime, for async methods without a blocking counterpart.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Overrides the default base path. This must be called before any other operations.
+    pub fn with_base_path<P: AsRef<Path>>(self, path: P) -> Self {
+        self.map(|inner| inner.with_base_path(path))
+    }
+
+    /// See [`crate::Pathmap::with_open_mode`].
+    pub fn with_open_mode(self, mode: OpenMode) -> Self {
+        self.map(|inner| inner.with_open_mode(mode))
+    }
+
+    /// See [`crate::Pathmap::with_ns_path`].
+    pub fn with_ns_path<P: AsRef<Path>>(self, ns: &str, dir: P) -> Self {
+        self.map(|inner| inner.with_ns_path(ns, dir))
+    }
+
+    /// See [`crate::Pathmap::with_soft_ns`].
+    pub fn with_soft_ns(self, ns: &str, host: &str) -> Self {
+        self.map(|inner| inner.with_soft_ns(ns, host))
+    }
+
+    /// See [`crate::Pathmap::with_default_ns_config`].
+    pub fn with_default_ns_config(self, config: NamespaceConfig) -> Self {
+        self.map(|inner| inner.with_default_ns_config(config))
+    }
+
+    /// See [`crate::Pathmap::with_ns_config`].
+    pub fn with_ns_config(self, ns: &str, config: NamespaceConfig) -> Self {
+        self.map(|inner| inner.with_ns_config(ns, config))
+    }
+
+    /// See [`crate::Pathmap::with_group_depth`].
+    pub fn with_group_depth(self, depth: usize) -> Self {
+        self.map(|inner| inner.with_group_depth(depth))
+    }
+
+    /// See [`crate::Pathmap::with_key_normalization`].
+    pub fn with_key_normalization(self, enabled: bool) -> Self {
+        self.map(|inner| inner.with_key_normalization(enabled))
+    }
+
+    /// See [`crate::Pathmap::with_name_rules`].
+    pub fn with_name_rules(self, rules: NameRules) -> Self {
+        self.map(|inner| inner.with_name_rules(rules))
+    }
+
+    /// See [`crate::Pathmap::with_default_ns`].
+    pub fn with_default_ns(self, ns: &str) -> Self {
+        self.map(|inner| inner.with_default_ns(ns))
+    }
+
+    /// See [`crate::Pathmap::with_busy_retry`].
+    pub fn with_busy_retry(self, config: RetryConfig) -> Self {
+        self.map(|inner| inner.with_busy_retry(config))
+    }
+
+    /// See [`crate::Pathmap::with_group_throttle`].
+    pub fn with_group_throttle(self, ns: &str, group: &str, config: ThrottleConfig) -> Self {
+        self.map(|inner| inner.with_group_throttle(ns, group, config))
+    }
+
+    /// See [`crate::Pathmap::with_normalizer`].
+    pub fn with_normalizer(self, ns: &str, group: &str, normalizer: Normalizer) -> Self {
+        self.map(|inner| inner.with_normalizer(ns, group, normalizer))
+    }
+
+    fn map<F: FnOnce(crate::Pathmap) -> crate::Pathmap>(self, f: F) -> Self {
+        let Pathmap { inner, runtime } = self;
+        Pathmap {
+            inner: f(inner),
+            runtime,
+        }
+    }
+
+    /// Lists all available namespaces.
+    pub fn list_ns(&self) -> Result<Vec<String>> {
+        self.block_on(self.inner.list_ns())
+    }
+
+    /// Namespaces starting with `prefix`, with their file stats.
+    pub fn list_ns_info(&self, prefix: &str) -> Result<Vec<NamespaceInfo>> {
+        self.block_on(self.inner.list_ns_info(prefix))
+    }
+
+    /// Lists the contents of a namespace or group.
+    pub fn list(&self, path: &str) -> Result<Listing> {
+        self.block_on(self.inner.list(path))
+    }
+
+    /// See [`crate::Pathmap::list_recursive`].
+    pub fn list_recursive(&self, path: &str) -> Result<Vec<String>> {
+        self.block_on(self.inner.list_recursive(path))
+    }
+
+    /// See [`crate::Pathmap::count`].
+    pub fn count(&self, path: &str) -> Result<u64> {
+        self.block_on(self.inner.count(path))
+    }
+
+    /// See [`crate::Pathmap::render_tree`].
+    pub fn render_tree(&self, path: &str, max_depth: Option<usize>) -> Result<String> {
+        self.block_on(self.inner.render_tree(path, max_depth))
+    }
+
+    /// Lists the contents of a namespace or group with value annotations.
+    pub fn list_detailed(&self, path: &str) -> Result<DetailedListing> {
+        self.block_on(self.inner.list_detailed(path))
+    }
+
+    /// Initializes a new namespace. Returns `true` if it was created.
+    pub fn init_ns(&self, ns: &str) -> Result<bool> {
+        self.block_on(self.inner.init_ns(ns))
+    }
+
+    /// Mounts an existing database file as a namespace.
+    pub fn attach_ns<P: AsRef<Path>>(&self, ns: &str, db_path: P) -> Result<bool> {
+        self.block_on(self.inner.attach_ns(ns, db_path))
+    }
+
+    /// Unmounts a namespace mounted with `attach_ns`.
+    pub fn detach_ns(&self, ns: &str) -> Result<bool> {
+        self.block_on(self.inner.detach_ns(ns))
+    }
+
+    /// Deletes a namespace and its database file.
+    pub fn delete_ns(&self, ns: &str) -> Result<bool> {
+        self.block_on(self.inner.delete_ns(ns))
+    }
+
+    /// Gets a value from a given path.
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.block_on(self.inner.get(path))
+    }
+
+    /// The values of a namespace or group and its nested groups that match `filter`.
+    pub fn find<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        filter: FieldFilter,
+    ) -> Result<Vec<(String, T)>> {
+        self.block_on(self.inner.find(path, filter))
+    }
+
+    /// Searches the string contents of a namespace's values, returning keys and snippets.
+    pub fn search(&self, ns: &str, query: &str) -> Result<Vec<(String, String)>> {
+        self.block_on(self.inner.search(ns, query))
+    }
+
+    /// Indexes the JSON field at `json_path` of the values directly in the group at `path`.
+    pub fn create_index(&self, path: &str, json_path: &str) -> Result<()> {
+        self.block_on(self.inner.create_index(path, json_path))
+    }
+
+    /// Like `create_index`, rejecting values that would duplicate the field within the group.
+    pub fn create_unique_index(&self, path: &str, json_path: &str) -> Result<()> {
+        self.block_on(self.inner.create_unique_index(path, json_path))
+    }
+
+    /// Removes an index, returning whether it existed.
+    pub fn drop_index(&self, path: &str, json_path: &str) -> Result<bool> {
+        self.block_on(self.inner.drop_index(path, json_path))
+    }
+
+    /// The JSON field indexes of a namespace.
+    pub fn list_indexes(&self, ns: &str) -> Result<Vec<JsonIndex>> {
+        self.block_on(self.inner.list_indexes(ns))
+    }
+
+    /// Reads one field of the document at `path`, such as `$.profile.email`.
+    pub fn get_field<T: DeserializeOwned>(&self, path: &str, json_path: &str) -> Result<T> {
+        self.block_on(self.inner.get_field(path, json_path))
+    }
+
+    /// Returns the value at `path`, waiting up to `timeout` for it to be written.
+    pub fn get_wait<T: DeserializeOwned>(&self, path: &str, timeout: Duration) -> Result<T> {
+        self.block_on(self.inner.get_wait(path, timeout))
+    }
+
+    /// Sets a value at a given path. Fails if the value already exists.
+    pub fn set<T: Serialize>(&self, path: &str, value: T) -> Result<()> {
+        self.block_on(self.inner.set(path, value))
+    }
+
+    /// Sets a value at a given path, returning `false` if one already exists.
+    pub fn try_set<T: Serialize>(&self, path: &str, value: T) -> Result<bool> {
+        self.block_on(self.inner.try_set(path, value))
+    }
+
+    /// Sets a value at a given path, overwriting any existing value.
+    pub fn overwrite<T: Serialize>(&self, path: &str, value: T) -> Result<WriteOutcome> {
+        self.block_on(self.inner.overwrite(path, value))
+    }
+
+    /// Deletes a value at a given path, returning whether there was one.
+    pub fn delete(&self, path: &str) -> Result<bool> {
+        self.block_on(self.inner.delete(path))
+    }
+
+    /// Reads a value and deletes it in one transaction.
+    pub fn take<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.block_on(self.inner.take(path))
+    }
+
+    /// Applies an RFC 7396 JSON merge patch to the value at `path` in one transaction.
+    pub fn patch<T: Serialize>(&self, path: &str, patch: T) -> Result<()> {
+        self.block_on(self.inner.patch(path, patch))
+    }
+
+    /// Exchanges the values at two paths of the same namespace in one transaction.
+    pub fn swap(&self, path_a: &str, path_b: &str) -> Result<()> {
+        self.block_on(self.inner.swap(path_a, path_b))
+    }
+
+    /// See [`crate::Pathmap::transaction_multi`].
+    pub fn transaction_multi<R, F>(&self, namespaces: &[&str], stage: F) -> Result<R>
+    where
+        F: FnOnce(&mut MultiTransaction<'_>) -> Result<R>,
+    {
+        self.block_on(self.inner.transaction_multi(namespaces, stage))
+    }
+
+    /// Deletes a group and everything below it, returning how many values were removed.
+    pub fn delete_group(&self, path: &str) -> Result<u64> {
+        self.block_on(self.inner.delete_group(path))
+    }
+
+    /// Checks if a value exists at a given path.
+    pub fn exists(&self, path: &str) -> Result<bool> {
+        self.block_on(self.inner.exists(path))
+    }
+
+    /// Checks if a value is stored at exactly this path.
+    pub fn exists_value(&self, path: &str) -> Result<bool> {
+        self.block_on(self.inner.exists_value(path))
+    }
+
+    /// Checks if a group holds any value, directly or nested.
+    pub fn exists_group(&self, path: &str) -> Result<bool> {
+        self.block_on(self.inner.exists_group(path))
+    }
+
+    /// Checks if a namespace exists.
+    pub fn exists_ns(&self, ns: &str) -> bool {
+        self.inner.exists_ns(ns)
+    }
+
+    /// Attaches a description and owner to an existing value; an empty `KeyMeta` removes it.
+    pub fn annotate(&self, path: &str, meta: KeyMeta) -> Result<()> {
+        self.block_on(self.inner.annotate(path, meta))
+    }
+
+    /// The annotation of a value, if any.
+    pub fn annotation(&self, path: &str) -> Result<Option<KeyMeta>> {
+        self.block_on(self.inner.annotation(path))
+    }
+
+    /// Runs SQLite's full integrity check on a namespace's database.
+    pub fn check_ns(&self, ns: &str) -> Result<IntegrityReport> {
+        self.block_on(self.inner.check_ns(ns))
+    }
+
+    /// Runs a `Quick` or `Full` integrity check on a namespace's database.
+    pub fn check_ns_with(&self, ns: &str, mode: CheckMode) -> Result<IntegrityReport> {
+        self.block_on(self.inner.check_ns_with(ns, mode))
+    }
+
+    /// Reads the audit trail of a namespace.
+    pub fn audit_log(&self, ns: &str, filter: AuditFilter) -> Result<Vec<AuditEntry>> {
+        self.block_on(self.inner.audit_log(ns, filter))
+    }
+
+    /// Removes the audit entries of a namespace recorded before `before`.
+    pub fn trim_audit_log(&self, ns: &str, before: SystemTime) -> Result<u64> {
+        self.block_on(self.inner.trim_audit_log(ns, before))
+    }
+
+    /// Manually triggers a cleanup (VACUUM) on a namespace's database.
+    pub fn manual_cleanup(&self, ns: &str) -> Result<()> {
+        self.block_on(self.inner.manual_cleanup(ns))
+    }
+
+    /// Writes an online snapshot of a namespace to `dest`.
+    pub fn snapshot_ns<P: AsRef<Path>>(&self, ns: &str, dest: P) -> Result<()> {
+        self.block_on(self.inner.snapshot_ns(ns, dest))
+    }
+
+    /// Writes a compacted backup of a namespace to `dest`.
+    pub fn backup_ns<P: AsRef<Path>>(&self, ns: &str, dest: P) -> Result<()> {
+        self.block_on(self.inner.backup_ns(ns, dest))
+    }
+
+    /// Replaces a namespace with the contents of a snapshot or backup.
+    pub fn restore_ns<P: AsRef<Path>>(&self, ns: &str, src: P) -> Result<()> {
+        self.block_on(self.inner.restore_ns(ns, src))
+    }
+
+    /// Checks that a backup file is sound and matches the live namespace.
+    pub fn verify_backup<P: AsRef<Path>>(&self, ns: &str, path: P) -> Result<BackupReport> {
+        self.block_on(self.inner.verify_backup(ns, path))
+    }
+
+    /// Dumps a whole namespace as a pretty-printed, nested JSON document.
+    pub fn export_ns<W: Write>(&self, ns: &str, writer: &mut W) -> Result<()> {
+        let mut buffer = Vec::new();
+        self.block_on(self.inner.export_ns(ns, &mut buffer))?;
+        writer.write_all(&buffer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads a nested JSON document (as produced by `export_ns`) into a namespace.
+    pub fn import_ns<R: Read>(
+        &self,
+        ns: &str,
+        reader: &mut R,
+        policy: impl Into<ConflictRules>,
+    ) -> Result<ImportReport> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        self.block_on(self.inner.import_ns(ns, &mut buffer.as_slice(), policy))
+    }
+
+    /// Writes `(dotted key, value)` pairs in one transaction using multi-row INSERTs.
+    pub fn import_bulk<I, K, V>(
+        &self,
+        ns: &str,
+        entries: I,
+        options: BulkImportOptions,
+    ) -> Result<u64>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Serialize,
+    {
+        self.block_on(self.inner.import_bulk(ns, entries, options))
+    }
+
+    /// Flushes pending writes and closes every pool; see `crate::Pathmap::close` for what dropping
+    /// without closing does.
+    pub fn close(&self) -> Result<()> {
+        self.block_on(self.inner.close())
+    }
+
+    /// Runs a write/read/delete round trip on a scratch group of `ns`.
+    pub fn self_test(&self, ns: &str) -> Result<SelfTestReport> {
+        self.block_on(self.inner.self_test(ns))
+    }
+
+    /// Upgrades every namespace to the current schema; see `crate::Pathmap::migrate`.
+    pub fn migrate(&self, options: MigrateOptions) -> Result<MigrationReport> {
+        self.block_on(self.inner.migrate(options))
+    }
+}
+
+impl Default for Pathmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,211 @@
+/* src/builder.rs */
+
+//! `PathmapBuilder`: the instance-wide settings in one place, checked together at `build()`.
+
+use crate::Pathmap;
+use crate::cleanup::CleanupPolicy;
+use crate::config::{NamespaceConfig, OpenMode, PoolConfig};
+use crate::error::{PathmapError, Result};
+use crate::names::NameRules;
+use crate::number::{FloatPolicy, IntegerPolicy};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Configures a `Pathmap`, as an alternative to chaining `with_*` methods whose mistakes only
+/// show once a namespace is opened. The settings here apply to every namespace that has no
+/// `with_ns_config` entry; `build` validates them and fails with `InvalidConfig`.
+///
+/// The serialization format is not among them: values are always stored as JSON text, which
+/// `find`, `get_field`, indexes, full-text search and `patch` evaluate inside SQLite, so another
+/// encoding would disable them. `float_policy` and `integer_policy` tune how numbers are
+/// serialized into that JSON.
+#[derive(Debug, Clone)]
+pub struct PathmapBuilder {
+    base_path: PathBuf,
+    pool: PoolConfig,
+    float_policy: FloatPolicy,
+    integer_policy: IntegerPolicy,
+    read_cache: usize,
+    cleanup_policy: CleanupPolicy,
+    idle_pool_close: Option<Duration>,
+    read_only: bool,
+    open_mode: Option<OpenMode>,
+    name_rules: NameRules,
+    default_ns: Option<String>,
+}
+
+impl Default for PathmapBuilder {
+    fn default() -> Self {
+        Self {
+            base_path: PathBuf::from("/opt/pathmap/"),
+            pool: PoolConfig::default(),
+            float_policy: FloatPolicy::default(),
+            integer_policy: IntegerPolicy::default(),
+            read_cache: 0,
+            cleanup_policy: CleanupPolicy::default(),
+            idle_pool_close: None,
+            read_only: false,
+            open_mode: None,
+            name_rules: NameRules::default(),
+            default_ns: None,
+        }
+    }
+}
+
+fn invalid(message: String) -> PathmapError {
+    PathmapError::InvalidConfig(message)
+}
+
+impl PathmapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Directory holding the namespace databases (default "/opt/pathmap/").
+    pub fn base_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.base_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// Connection pool sizing of each namespace.
+    pub fn pool(mut self, pool: PoolConfig) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// How NaN and infinite floats are stored.
+    pub fn float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// How integers beyond ±2^53 are stored.
+    pub fn integer_policy(mut self, policy: IntegerPolicy) -> Self {
+        self.integer_policy = policy;
+        self
+    }
+
+    /// Number of values each namespace caches for `get`; 0 (the default) disables the cache.
+    pub fn read_cache(mut self, capacity: usize) -> Self {
+        self.read_cache = capacity;
+        self
+    }
+
+    /// When the background cleanup task vacuums a namespace.
+    pub fn cleanup_policy(mut self, policy: CleanupPolicy) -> Self {
+        self.cleanup_policy = policy;
+        self
+    }
+
+    /// Lets the background cleanup task close pools idle for longer than `after`.
+    pub fn idle_pool_close(mut self, after: Duration) -> Self {
+        self.idle_pool_close = Some(after);
+        self
+    }
+
+    /// Opens every namespace read-only; the base path must then exist already.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Locks the base path for the instance, see `Pathmap::with_open_mode`.
+    pub fn open_mode(mut self, mode: OpenMode) -> Self {
+        self.open_mode = Some(mode);
+        self
+    }
+
+    /// Which namespace names and keys are accepted.
+    pub fn name_rules(mut self, rules: NameRules) -> Self {
+        self.name_rules = rules;
+        self
+    }
+
+    /// Namespace of paths without `::`, see `Pathmap::with_default_ns`.
+    pub fn default_ns(mut self, ns: &str) -> Self {
+        self.default_ns = Some(ns.to_string());
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.base_path.as_os_str().is_empty() {
+            return Err(invalid("base path is empty".to_string()));
+        }
+        if self.base_path.exists() && !self.base_path.is_dir() {
+            return Err(invalid(format!(
+                "base path '{}' is not a directory",
+                self.base_path.display()
+            )));
+        }
+        if self.read_only && !self.base_path.exists() {
+            return Err(invalid(format!(
+                "base path '{}' does not exist, so there is nothing to open read-only",
+                self.base_path.display()
+            )));
+        }
+        let pool = &self.pool;
+        if pool.max_connections == 0 {
+            return Err(invalid(
+                "pool max_connections must be at least 1".to_string(),
+            ));
+        }
+        if pool.min_connections > pool.max_connections {
+            return Err(invalid(format!(
+                "pool min_connections ({}) exceeds max_connections ({})",
+                pool.min_connections, pool.max_connections
+            )));
+        }
+        if pool.acquire_timeout.is_zero() {
+            return Err(invalid("pool acquire_timeout must not be zero".to_string()));
+        }
+        if self.cleanup_policy.min_freelist_percent > 100 {
+            return Err(invalid(format!(
+                "cleanup min_freelist_percent is {}, above 100",
+                self.cleanup_policy.min_freelist_percent
+            )));
+        }
+        if self.idle_pool_close.is_some_and(|after| after.is_zero()) {
+            return Err(invalid("idle_pool_close must not be zero".to_string()));
+        }
+        if let Some(ns) = &self.default_ns {
+            self.name_rules
+                .check_ns(ns)
+                .map_err(|e| invalid(format!("default namespace: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// Validates the settings and creates the instance. Nothing is opened or created; the
+    /// instance lock of an open mode is taken once a namespace is.
+    pub fn build(self) -> Result<Pathmap> {
+        self.validate()?;
+        let config = NamespaceConfig::new()
+            .pool(self.pool)
+            .float_policy(self.float_policy)
+            .integer_policy(self.integer_policy)
+            .read_cache(self.read_cache)
+            .read_only(self.read_only);
+        let mut pm = Pathmap::new()
+            .with_base_path(&self.base_path)
+            .with_default_ns_config(config)
+            .with_cleanup_policy(self.cleanup_policy)
+            .with_name_rules(self.name_rules);
+        if let Some(after) = self.idle_pool_close {
+            pm = pm.with_idle_pool_close(after);
+        }
+        if let Some(ns) = &self.default_ns {
+            pm = pm.with_default_ns(ns);
+        }
+        if let Some(mode) = self.open_mode {
+            pm = pm.with_open_mode(mode);
+        }
+        Ok(pm)
+    }
+}
+
+impl Pathmap {
+    /// Starts configuring an instance with a `PathmapBuilder`.
+    pub fn builder() -> PathmapBuilder {
+        PathmapBuilder::new()
+    }
+}
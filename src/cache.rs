@@ -0,0 +1,161 @@
+/* src/cache.rs */
+
+use crate::Pathmap;
+use crate::config::Collation;
+use crate::db;
+use crate::error::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+type CacheKey = (String, String);
+
+/// Least-recently-used serialized values of one namespace.
+#[derive(Default)]
+struct Lru {
+    /// Bumped by every invalidation, so a read that started before a write cannot cache the
+    /// value it saw once the write has finished.
+    generation: u64,
+    tick: u64,
+    entries: HashMap<CacheKey, (u64, Vec<u8>)>,
+    /// Entries by last use; the first one is evicted when the cache is full.
+    order: BTreeMap<u64, CacheKey>,
+}
+
+impl Lru {
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Per-namespace read caches, created on first use. Only `get` reads from them; every write made
+/// through the owning `Pathmap` invalidates what it touches.
+#[derive(Default)]
+pub(crate) struct ReadCache {
+    namespaces: Mutex<HashMap<String, Lru>>,
+}
+
+impl ReadCache {
+    /// Returns a cached value and marks it as most recently used.
+    pub(crate) fn get(&self, ns: &str, grp: &str, key: &str) -> Option<Vec<u8>> {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let lru = namespaces.get_mut(ns)?;
+        let cache_key = (grp.to_string(), key.to_string());
+        lru.tick += 1;
+        let tick = lru.tick;
+        let (last_used, value) = lru.entries.get_mut(&cache_key)?;
+        lru.order.remove(last_used);
+        *last_used = tick;
+        lru.order.insert(tick, cache_key);
+        Some(value.clone())
+    }
+
+    /// The namespace's current generation, to be passed to `insert` after reading the database.
+    pub(crate) fn generation(&self, ns: &str) -> u64 {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        namespaces.entry(ns.to_string()).or_default().generation
+    }
+
+    /// Caches a value read from the database, unless a write invalidated the namespace since
+    /// `generation` was taken.
+    pub(crate) fn insert(
+        &self,
+        ns: &str,
+        generation: u64,
+        grp: &str,
+        key: &str,
+        value: &[u8],
+        capacity: usize,
+    ) {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let Some(lru) = namespaces.get_mut(ns) else {
+            return;
+        };
+        if lru.generation != generation || capacity == 0 {
+            return;
+        }
+        let cache_key = (grp.to_string(), key.to_string());
+        lru.tick += 1;
+        let tick = lru.tick;
+        if let Some((last_used, _)) = lru.entries.remove(&cache_key) {
+            lru.order.remove(&last_used);
+        }
+        while lru.entries.len() >= capacity {
+            let Some((_, oldest)) = lru.order.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+        lru.entries
+            .insert(cache_key.clone(), (tick, value.to_vec()));
+        lru.order.insert(tick, cache_key);
+    }
+
+    /// Drops one key. With `exact` unset (non-binary collations, where other spellings may name
+    /// the same row) the whole namespace is dropped instead.
+    pub(crate) fn invalidate(&self, ns: &str, grp: &str, key: &str, exact: bool) {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        let Some(lru) = namespaces.get_mut(ns) else {
+            return;
+        };
+        if !exact {
+            lru.clear();
+            return;
+        }
+        lru.generation += 1;
+        if let Some((last_used, _)) = lru.entries.remove(&(grp.to_string(), key.to_string())) {
+            lru.order.remove(&last_used);
+        }
+    }
+
+    /// Drops every cached value of a namespace, e.g. after a bulk write or restore.
+    pub(crate) fn invalidate_ns(&self, ns: &str) {
+        if let Some(lru) = self.namespaces.lock().unwrap().get_mut(ns) {
+            lru.clear();
+        }
+    }
+}
+
+impl Pathmap {
+    /// Reads a serialized value, through the namespace's read cache when one is configured.
+    pub(crate) async fn read_value(
+        &self,
+        ns: &str,
+        grp: &str,
+        key: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let capacity = self.ns_config(ns).read_cache;
+        if capacity == 0 {
+            let pool = self.get_pool(ns).await?;
+            return match db::find(&*pool, grp, key).await? {
+                Some(stored) => Ok(Some(self.load_value(ns, stored).await?)),
+                None => Ok(None),
+            };
+        }
+        if let Some(value) = self.read_cache.get(ns, grp, key) {
+            self.access.touch(ns, self.clock.now());
+            return Ok(Some(value));
+        }
+        let generation = self.read_cache.generation(ns);
+        let pool = self.get_pool(ns).await?;
+        let Some(stored) = db::find(&*pool, grp, key).await? else {
+            return Ok(None);
+        };
+        let value = self.load_value(ns, stored).await?;
+        self.read_cache
+            .insert(ns, generation, grp, key, &value, capacity);
+        Ok(Some(value))
+    }
+
+    /// Forgets the cached value of a key after it was written or deleted.
+    pub(crate) fn invalidate_cached(&self, ns: &str, grp: &str, key: &str) {
+        let exact = matches!(self.ns_config(ns).collation, Collation::Binary);
+        self.read_cache.invalidate(ns, grp, key, exact);
+    }
+
+    /// Forgets every cached value of a namespace after a bulk write, restore or removal.
+    pub(crate) fn invalidate_cached_ns(&self, ns: &str) {
+        self.read_cache.invalidate_ns(ns);
+    }
+}
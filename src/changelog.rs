@@ -0,0 +1,256 @@
+/* src/changelog.rs */
+
+use crate::Pathmap;
+use crate::audit::RowWrite;
+use crate::blob;
+use crate::db;
+use crate::error::{self, PathmapError, Result};
+use crate::event::Operation;
+use crate::rt;
+use crate::snapshot::{copy_blobs, open};
+use sqlx::{Connection, Row, SqliteConnection};
+use std::path::Path;
+
+impl Pathmap {
+    /// The namespace's current change log sequence. Read it before taking a full backup and pass it
+    /// to the first `backup_incremental`; changes made while the backup runs are simply included twice.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn changelog_seq(&self, ns: &str) -> Result<i64> {
+        let pool = self.get_pool(ns).await?;
+        db::changelog_seq(&*pool).await
+    }
+
+    /// Writes the values changed after sequence `since` to a new delta file at `dest` and returns
+    /// the sequence it covers up to, which is the `since` of the next delta. Deleted keys are recorded
+    /// as tombstones. Requires `NamespaceConfig::changelog(true)`; a `restore_ns` starts a new chain.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn backup_incremental<P: AsRef<Path>>(
+        &self,
+        ns: &str,
+        since: i64,
+        dest: P,
+    ) -> Result<i64> {
+        error::in_context("backup_incremental", ns, async {
+        let dest = dest.as_ref();
+        if dest.exists() {
+            return Err(PathmapError::BackupError(format!(
+                "backup target '{}' already exists",
+                dest.display()
+            )));
+        }
+        let (until, changes) = self
+            .in_background(ns, self.changes_after(ns, since))
+            .await?;
+
+        if let Some(parent) = dest.parent() {
+            rt::fs::create_dir_all(parent).await?;
+        }
+        let mut delta = open(dest, true).await?;
+        let mut tx = delta.begin().await?;
+        sqlx::query(
+            "CREATE TABLE kv_delta (grp TEXT NOT NULL, key TEXT NOT NULL, value BLOB, external INTEGER NOT NULL DEFAULT 0)",
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "CREATE TABLE kv_delta_meta (since INTEGER NOT NULL, until INTEGER NOT NULL, created_at TEXT NOT NULL)",
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("INSERT INTO kv_delta_meta (since, until, created_at) VALUES (?, ?, ?)")
+            .bind(since)
+            .bind(until)
+            .bind(self.format_timestamp(ns, self.clock.system_now()))
+            .execute(&mut *tx)
+            .await?;
+        let mut refs = Vec::new();
+        for change in &changes {
+            let (value, external) = match &change.value {
+                Some(stored) => (Some(stored.data.as_slice()), stored.external),
+                None => (None, false),
+            };
+            if external && let Some(value) = value {
+                refs.push(String::from_utf8_lossy(value).into_owned());
+            }
+            sqlx::query("INSERT INTO kv_delta (grp, key, value, external) VALUES (?, ?, ?, ?)")
+                .bind(&change.grp)
+                .bind(&change.key)
+                .bind(value)
+                .bind(external)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        delta.close().await?;
+
+        copy_blobs(
+            &blob::blob_dir(&self.get_db_path(ns)),
+            &blob::blob_dir(dest),
+            &refs,
+        )
+        .await?;
+        Ok(until)
+        })
+        .await
+    }
+
+    /// Applies a delta written by `backup_incremental` in one transaction, returning the sequence
+    /// it covers up to. Deltas must be applied in order on top of the matching full backup.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn apply_incremental<P: AsRef<Path>>(&self, ns: &str, src: P) -> Result<i64> {
+        error::in_context("apply_incremental", ns, async {
+            let src = src.as_ref();
+            if !src.is_file() {
+                return Err(PathmapError::BackupError(format!(
+                    "delta '{}' not found",
+                    src.display()
+                )));
+            }
+            let mut delta = open(src, false).await?;
+            let (_, until) = delta_range(&mut delta).await?;
+            let rows = sqlx::query("SELECT grp, key, value, external FROM kv_delta")
+                .fetch_all(&mut delta)
+                .await?;
+            delta.close().await?;
+            // A delta from elsewhere may hold keys this instance's rules reject; refuse it whole
+            // rather than store values that `get` and `delete` could not reach.
+            for row in &rows {
+                self.name_rules
+                    .check_key(&db::join_key(row.get("grp"), row.get("key")))?;
+            }
+
+            let refs: Vec<String> = rows
+                .iter()
+                .filter(|row| row.get::<bool, _>("external"))
+                .filter_map(|row| row.get::<Option<Vec<u8>>, _>("value"))
+                .map(|name| String::from_utf8_lossy(&name).into_owned())
+                .collect();
+            copy_blobs(
+                &blob::blob_dir(src),
+                &blob::blob_dir(&self.get_db_path(ns)),
+                &refs,
+            )
+            .await?;
+
+            let pool = self.get_pool_or_init(ns).await?;
+            let mut tx = pool.begin().await?;
+            let mut replaced = Vec::new();
+            for row in &rows {
+                let grp: &str = row.get("grp");
+                let key: &str = row.get("key");
+                let previous = db::find(&mut *tx, grp, key).await?;
+                let value = row.get::<Option<Vec<u8>>, _>("value");
+                let write = match &value {
+                    Some(value) => RowWrite::Overwrite(value, row.get("external")),
+                    None => RowWrite::Delete,
+                };
+                self.write_row_in(ns, &mut tx, grp, key, write, previous.as_ref())
+                    .await?;
+                if let Some(previous) = previous
+                    && previous.external
+                {
+                    replaced.push(previous.data);
+                }
+            }
+            tx.commit().await?;
+            self.invalidate_cached_ns(ns);
+            self.emit_change(Operation::Restore, ns, "", None, None);
+
+            for name in replaced {
+                if !refs.iter().any(|r| r.as_bytes() == name) {
+                    self.remove_blob(ns, &name).await?;
+                }
+            }
+            Ok(until)
+        })
+        .await
+    }
+
+    /// Restores a namespace from a full backup (`backup_ns` or `snapshot_ns`) followed by a chain
+    /// of deltas, returning the sequence the result is current to. The chain is checked before
+    /// anything is restored: each delta must start no later than where the previous one (or the
+    /// base) ended, otherwise changes would be silently missing.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn restore_incremental<P: AsRef<Path>>(
+        &self,
+        ns: &str,
+        base: P,
+        deltas: &[P],
+    ) -> Result<i64> {
+        error::in_context("restore_incremental", ns, async {
+            let base = base.as_ref();
+            if !base.is_file() {
+                return Err(PathmapError::BackupError(format!(
+                    "snapshot '{}' not found",
+                    base.display()
+                )));
+            }
+            let snapshot = db::open_read_only(base, self.ns_config(ns)).await?;
+            let mut covered = db::changelog_seq(&snapshot).await?;
+            snapshot.close().await;
+
+            for path in deltas {
+                let path = path.as_ref();
+                let mut delta = open(path, false).await?;
+                let (since, until) = delta_range(&mut delta).await?;
+                delta.close().await?;
+                if since > covered {
+                    return Err(PathmapError::BackupError(format!(
+                        "delta '{}' starts at sequence {} but the chain only reaches {}",
+                        path.display(),
+                        since,
+                        covered
+                    )));
+                }
+                covered = covered.max(until);
+            }
+
+            self.restore_ns(ns, base).await?;
+            for path in deltas {
+                self.apply_incremental(ns, path).await?;
+            }
+            Ok(covered)
+        })
+        .await
+    }
+
+    /// Lists the keys changed after `since` with their current rows, and the sequence the list is
+    /// current to. Both are read from one snapshot so nothing falls between two calls.
+    pub(crate) async fn changes_after(
+        &self,
+        ns: &str,
+        since: i64,
+    ) -> Result<(i64, Vec<db::Change>)> {
+        let pool = self.get_pool(ns).await?;
+        let mut tx = pool.begin().await?;
+        let floor = db::changelog_floor(&mut *tx).await?.ok_or_else(|| {
+            PathmapError::BackupError(format!("change log is not enabled for namespace '{}'", ns))
+        })?;
+        if since < floor {
+            return Err(PathmapError::BackupError(format!(
+                "changes before sequence {} are no longer recorded; take a full backup",
+                floor
+            )));
+        }
+        let until = db::changelog_seq(&mut *tx).await?;
+        let changes = db::changes_since(&mut *tx, since).await?;
+        tx.commit().await?;
+        Ok((until, changes))
+    }
+
+    /// Drops change log entries up to and including `upto` once every delta covering them has been
+    /// taken, returning how many were removed. Deltas can no longer start before `upto`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn trim_changelog(&self, ns: &str, upto: i64) -> Result<u64> {
+        let pool = self.get_pool(ns).await?;
+        db::trim_changelog(&pool, upto).await
+    }
+}
+
+/// Reads the `(since, until)` sequence range a delta file covers.
+async fn delta_range(delta: &mut SqliteConnection) -> Result<(i64, i64)> {
+    let row = sqlx::query("SELECT since, until FROM kv_delta_meta")
+        .fetch_one(delta)
+        .await?;
+    Ok((row.get("since"), row.get("until")))
+}
@@ -0,0 +1,138 @@
+/* src/chaos.rs */
+
+use sqlx::error::{DatabaseError, ErrorKind};
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Probabilities (0.0 to 1.0) of injecting faults into namespace operations.
+/// Only available with the `chaos` feature; intended for testing retry and degradation handling.
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    /// Chance of failing with a simulated `SQLITE_BUSY` database error.
+    pub busy_probability: f64,
+    /// Chance of failing with a simulated I/O error.
+    pub io_error_probability: f64,
+    /// Chance of delaying the operation by `delay`.
+    pub delay_probability: f64,
+    /// Injected latency for slow responses.
+    pub delay: Duration,
+    /// Seed for the fault generator, so failing runs can be replayed.
+    pub seed: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            busy_probability: 0.0,
+            io_error_probability: 0.0,
+            delay_probability: 0.0,
+            delay: Duration::from_millis(100),
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+impl ChaosConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn busy(mut self, probability: f64) -> Self {
+        self.busy_probability = probability;
+        self
+    }
+
+    pub fn io_error(mut self, probability: f64) -> Self {
+        self.io_error_probability = probability;
+        self
+    }
+
+    pub fn slow(mut self, probability: f64, delay: Duration) -> Self {
+        self.delay_probability = probability;
+        self.delay = delay;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// Decides which faults to inject, using a small deterministic xorshift generator.
+pub(crate) struct Chaos {
+    config: ChaosConfig,
+    state: Mutex<u64>,
+}
+
+impl Chaos {
+    pub(crate) fn new(config: ChaosConfig) -> Self {
+        let state = Mutex::new(config.seed.max(1));
+        Chaos { config, state }
+    }
+
+    fn roll(&self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        (*state >> 11) as f64 / (1u64 << 53) as f64 <= probability
+    }
+
+    /// Possibly delays, then possibly fails, the operation about to run.
+    pub(crate) async fn inject(&self) -> crate::error::Result<()> {
+        if self.roll(self.config.delay_probability) {
+            crate::rt::sleep(self.config.delay).await;
+        }
+        if self.roll(self.config.busy_probability) {
+            return Err(sqlx::Error::Database(Box::new(InjectedBusy)).into());
+        }
+        if self.roll(self.config.io_error_probability) {
+            return Err(std::io::Error::other("injected I/O error").into());
+        }
+        Ok(())
+    }
+}
+
+/// A simulated `SQLITE_BUSY` error, indistinguishable by code from the real one.
+#[derive(Debug)]
+struct InjectedBusy;
+
+impl fmt::Display for InjectedBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for InjectedBusy {}
+
+impl DatabaseError for InjectedBusy {
+    fn message(&self) -> &str {
+        "database is locked (injected)"
+    }
+
+    fn code(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed("5"))
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
@@ -0,0 +1,185 @@
+/* src/checkpoint.rs */
+
+use crate::Pathmap;
+use crate::audit::RowWrite;
+use crate::db;
+use crate::error::{self, PathmapError, Result};
+use crate::event::Operation;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// A completed step of a multi-step job and the state it left behind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint<T> {
+    pub step: String,
+    /// Position of the step in completion order, starting at 1.
+    pub index: u64,
+    pub state: T,
+    /// When the step was recorded, rendered in the namespace's time zone.
+    pub completed_at: String,
+}
+
+/// How a step is stored, under `<job>.<step>`.
+#[derive(Serialize, Deserialize)]
+struct StepRecord<T> {
+    index: u64,
+    state: T,
+    completed_at: String,
+}
+
+impl Pathmap {
+    /// Records that `step` of the job at `job` (a group path such as `"jobs::migrate_42"`) has
+    /// completed with `state`. Each step is recorded at most once: returns `false`, leaving the
+    /// stored state untouched, if it was already checkpointed (e.g. by a concurrent runner). The
+    /// step's index is allocated in the transaction that records it, so concurrent runners never
+    /// share one. Goes straight to the database, bypassing the write queue and write batches, and
+    /// is not available on a replica.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(job = %job, step = %step), err(Display)))]
+    pub async fn checkpoint<T: Serialize>(&self, job: &str, step: &str, state: T) -> Result<bool> {
+        let path = step_path(&self.qualify(job), step)?;
+        error::in_context("checkpoint", &path, async {
+            self.throttle_write(&path).await?;
+            self.retry_busy(|| self.checkpoint_once(&path, step, &state))
+                .await
+        })
+        .await
+    }
+
+    async fn checkpoint_once<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        step: &str,
+        state: &T,
+    ) -> Result<bool> {
+        if self.replica.is_some() {
+            return Err(PathmapError::InvalidConfig(
+                "checkpoint cannot be forwarded to the primary of a replica".to_string(),
+            ));
+        }
+        let (ns, full_key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&full_key);
+        // The part of the value name naming the job, when the group depth splits inside it.
+        let job_prefix = &key[..key.len() - step.len()];
+        self.settle_batches(ns, Some((grp, key))).await;
+        let pool = self.get_pool(ns).await?;
+        // Taking the write lock up front makes concurrent runners wait for each other's index
+        // instead of failing to upgrade a read transaction.
+        let mut tx = pool.begin_with("BEGIN IMMEDIATE").await?;
+        if db::find(&mut *tx, grp, key).await?.is_some()
+            || db::exists_group(&mut *tx, &full_key).await?
+        {
+            return Ok(false);
+        }
+        let record = StepRecord {
+            index: db::count_direct_values(&mut *tx, grp, job_prefix).await? + 1,
+            state,
+            completed_at: self.format_timestamp(ns, self.clock.system_now()),
+        };
+        let serialized_value = self.encode_write(ns, &full_key, &record)?;
+        let new_size = serialized_value.len() as u64;
+        self.check_value_quota(ns, new_size)?;
+        let (data, external) = self.store_value(ns, serialized_value).await?;
+        let written = async {
+            self.write_row_in(ns, &mut tx, grp, key, RowWrite::Set(&data, external), None)
+                .await?;
+            self.check_quota_totals(ns, &mut tx).await?;
+            tx.commit().await?;
+            Ok::<_, PathmapError>(())
+        };
+        if let Err(e) = written.await {
+            if external {
+                self.remove_blob(ns, &data).await?;
+            }
+            return Err(e);
+        }
+        self.invalidate_cached(ns, grp, key);
+        self.emit_change(Operation::Set, ns, &full_key, None, Some(new_size));
+        Ok(true)
+    }
+
+    /// Whether `step` of a job has been checkpointed.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(job = %job, step = %step), err(Display)))]
+    pub async fn step_done(&self, job: &str, step: &str) -> Result<bool> {
+        let state = self
+            .step_state::<serde_json::Value>(&step_path(&self.qualify(job), step)?)
+            .await?;
+        Ok(state.is_some())
+    }
+
+    /// The most recently completed step of a job, to resume from after a crash, or `None` if no
+    /// step has completed yet.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(job = %job), err(Display)))]
+    pub async fn resume<T: DeserializeOwned>(&self, job: &str) -> Result<Option<Checkpoint<T>>> {
+        let steps = match self.list(job).await {
+            Ok(listing) => listing.values,
+            Err(e) if matches!(e.root(), PathmapError::NamespaceNotFound(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut latest: Option<(String, StepRecord<serde_json::Value>)> = None;
+        for step in steps {
+            let record: StepRecord<serde_json::Value> =
+                self.get(&step_path(&self.qualify(job), &step)?).await?;
+            if latest.as_ref().is_none_or(|(_, l)| record.index > l.index) {
+                latest = Some((step, record));
+            }
+        }
+        let Some((step, record)) = latest else {
+            return Ok(None);
+        };
+        Ok(Some(Checkpoint {
+            step,
+            index: record.index,
+            state: serde_json::from_value(record.state)?,
+            completed_at: record.completed_at,
+        }))
+    }
+
+    /// Runs a step unless it already completed, passing it the state of the latest checkpoint, and
+    /// checkpoints its result. Returns the step's state either way. A crash between the step's work
+    /// and its checkpoint reruns the step on resume, so its side effects should be idempotent.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(job = %job, step = %step), err(Display)))]
+    pub async fn run_step<T, F, Fut>(&self, job: &str, step: &str, run: F) -> Result<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce(Option<T>) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let path = step_path(&self.qualify(job), step)?;
+        if let Some(state) = self.step_state(&path).await? {
+            return Ok(state);
+        }
+        let previous = self.resume::<T>(job).await?.map(|c| c.state);
+        let state = run(previous).await?;
+        if self.checkpoint(job, step, &state).await? {
+            return Ok(state);
+        }
+        // Another runner completed the step first; its state wins.
+        self.step_state(&path)
+            .await?
+            .ok_or_else(|| PathmapError::ValueNotFound(path))
+    }
+
+    /// Forgets every checkpoint of a job, returning how many steps were removed.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(job = %job), err(Display)))]
+    pub async fn clear_job(&self, job: &str) -> Result<u64> {
+        self.delete_group(job).await
+    }
+
+    async fn step_state<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>> {
+        match self.get::<StepRecord<T>>(path).await {
+            Ok(record) => Ok(Some(record.state)),
+            Err(e) if matches!(e.root(), PathmapError::ValueNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Path of a step's record. Step names are single key segments.
+fn step_path(job: &str, step: &str) -> Result<String> {
+    let has_group = job.split_once("::").is_some_and(|(_, grp)| !grp.is_empty());
+    if step.is_empty() || step.contains('.') || !has_group {
+        return Err(PathmapError::InvalidPath(format!("{}.{}", job, step)));
+    }
+    Ok(format!("{}.{}", job, step))
+}
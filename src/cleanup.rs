@@ -0,0 +1,239 @@
+/* src/cleanup.rs */
+
+use crate::rt;
+use futures_util::future::{self, Either};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, watch};
+
+/// When each namespace was last used, recorded by every operation that opens its pool or hits
+/// its read cache. The background cleanup's idle detection is based on these times.
+#[derive(Default)]
+pub(crate) struct AccessTimes(Mutex<HashMap<String, Instant>>);
+
+impl AccessTimes {
+    pub(crate) fn touch(&self, ns: &str, now: Instant) {
+        let mut times = self.0.lock().unwrap();
+        match times.get_mut(ns) {
+            Some(last) => *last = now,
+            None => {
+                times.insert(ns.to_string(), now);
+            }
+        }
+    }
+
+    /// The last use of `ns`, or `now` for a namespace not seen yet, which is recorded.
+    pub(crate) fn last_or_touch(&self, ns: &str, now: Instant) -> Instant {
+        *self.0.lock().unwrap().entry(ns.to_string()).or_insert(now)
+    }
+
+    pub(crate) fn forget(&self, ns: &str) {
+        self.0.lock().unwrap().remove(ns);
+    }
+}
+
+/// When the background cleanup task vacuums a namespace. Vacuuming rewrites the whole file, so
+/// it is only worth it once enough of the file is free pages; both thresholds must be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanupPolicy {
+    /// Share of the file's pages on the freelist, in percent (default 20).
+    pub min_freelist_percent: u64,
+    /// Number of free pages (default 64), so small files are left alone.
+    pub min_free_pages: u64,
+    /// Only vacuum namespaces idle for the task's idle timeout (default `true`). Otherwise a
+    /// fragmented namespace is also vacuumed while in use, blocking its writers meanwhile.
+    pub require_idle: bool,
+}
+
+impl Default for CleanupPolicy {
+    fn default() -> Self {
+        Self {
+            min_freelist_percent: 20,
+            min_free_pages: 64,
+            require_idle: true,
+        }
+    }
+}
+
+impl CleanupPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn min_freelist_percent(mut self, percent: u64) -> Self {
+        self.min_freelist_percent = percent;
+        self
+    }
+
+    pub fn min_free_pages(mut self, pages: u64) -> Self {
+        self.min_free_pages = pages;
+        self
+    }
+
+    pub fn require_idle(mut self, require: bool) -> Self {
+        self.require_idle = require;
+        self
+    }
+
+    /// Whether a file of `pages` pages, `free` of them on the freelist, should be vacuumed.
+    pub(crate) fn is_fragmented(&self, pages: u64, free: u64) -> bool {
+        free > 0
+            && free >= self.min_free_pages
+            && (free * 100).checked_div(pages).unwrap_or(0) >= self.min_freelist_percent
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    check_interval: Duration,
+    idle_timeout: Duration,
+    policy: CleanupPolicy,
+    stopped: bool,
+}
+
+/// Work run at the start of every cleanup pass, e.g. the scheduled actions under `supervise`.
+pub(crate) type PassHook =
+    Arc<dyn Fn() -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>> + Send + Sync>;
+
+/// State shared by a cleanup task and its handle. The task holds it too, so dropping the handle
+/// leaves the task running.
+struct Shared {
+    settings: watch::Sender<Settings>,
+    trigger: Notify,
+}
+
+/// Controls the task started by `start_background_cleanup`. Dropping the handle does not stop
+/// the task; it runs until `stop`, `close` or the instance is dropped.
+#[derive(Clone)]
+pub struct CleanupHandle {
+    shared: Arc<Shared>,
+}
+
+impl CleanupHandle {
+    /// Stops the task. A pass in progress is finished first.
+    pub fn stop(&self) {
+        self.shared.settings.send_modify(|s| s.stopped = true);
+    }
+
+    /// Whether `stop` has been called.
+    pub fn is_stopped(&self) -> bool {
+        self.shared.settings.borrow().stopped
+    }
+
+    /// Runs a pass right away instead of at the next interval. Triggers while a pass is running
+    /// start one more pass after it.
+    pub fn trigger_now(&self) {
+        self.shared.trigger.notify_one();
+    }
+
+    /// Changes how often the task checks for idle namespaces, starting with the next wait.
+    pub fn set_check_interval(&self, interval: Duration) {
+        self.shared
+            .settings
+            .send_modify(|s| s.check_interval = interval);
+    }
+
+    /// Changes how long a namespace must go unused before it is vacuumed.
+    pub fn set_idle_timeout(&self, timeout: Duration) {
+        self.shared
+            .settings
+            .send_modify(|s| s.idle_timeout = timeout);
+    }
+
+    /// Changes when namespaces are vacuumed, starting with the next pass.
+    pub fn set_policy(&self, policy: CleanupPolicy) {
+        self.shared.settings.send_modify(|s| s.policy = policy);
+    }
+
+    pub fn check_interval(&self) -> Duration {
+        self.shared.settings.borrow().check_interval
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        self.shared.settings.borrow().idle_timeout
+    }
+
+    pub fn policy(&self) -> CleanupPolicy {
+        self.shared.settings.borrow().policy
+    }
+}
+
+/// Why the cleanup task woke up.
+pub(crate) enum Wake {
+    /// Run a pass with this idle timeout and vacuum policy.
+    Pass {
+        idle_timeout: Duration,
+        policy: CleanupPolicy,
+    },
+    Stop,
+}
+
+/// The task's side of a `CleanupHandle`.
+pub(crate) struct CleanupControl {
+    shared: Arc<Shared>,
+    settings: watch::Receiver<Settings>,
+}
+
+impl CleanupControl {
+    pub(crate) fn new(
+        check_interval: Duration,
+        idle_timeout: Duration,
+        policy: CleanupPolicy,
+    ) -> (Self, CleanupHandle) {
+        let (sender, settings) = watch::channel(Settings {
+            check_interval,
+            idle_timeout,
+            policy,
+            stopped: false,
+        });
+        let shared = Arc::new(Shared {
+            settings: sender,
+            trigger: Notify::new(),
+        });
+        let handle = CleanupHandle {
+            shared: Arc::clone(&shared),
+        };
+        (CleanupControl { shared, settings }, handle)
+    }
+
+    /// Waits until the next pass is due, or until the task should stop: on `stop`, or when
+    /// `shutdown` changes or its sender is dropped.
+    pub(crate) async fn next(&mut self, shutdown: &mut watch::Receiver<bool>) -> Wake {
+        loop {
+            let settings = *self.settings.borrow_and_update();
+            if settings.stopped {
+                return Wake::Stop;
+            }
+            let due = {
+                let tick = std::pin::pin!(rt::sleep(settings.check_interval));
+                let trigger = std::pin::pin!(self.shared.trigger.notified());
+                let stop = std::pin::pin!(shutdown.changed());
+                // The task's own `Shared` keeps the sender alive, so this only fires on changes.
+                let reconfigured = std::pin::pin!(self.settings.changed());
+                match future::select(
+                    future::select(tick, trigger),
+                    future::select(stop, reconfigured),
+                )
+                .await
+                {
+                    Either::Left(_) => true,
+                    Either::Right((Either::Left(_), _)) => return Wake::Stop,
+                    // Start over with the new settings.
+                    Either::Right((Either::Right(_), _)) => false,
+                }
+            };
+            if due {
+                // Settings changed while waking up apply to this pass already.
+                let settings = *self.settings.borrow_and_update();
+                if settings.stopped {
+                    return Wake::Stop;
+                }
+                return Wake::Pass {
+                    idle_timeout: settings.idle_timeout,
+                    policy: settings.policy,
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+/* src/clock.rs */
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Time zone that timestamps are rendered in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeZone {
+    /// Coordinated Universal Time, written with a `Z` suffix.
+    #[default]
+    Utc,
+    /// The host's local time zone, written with its UTC offset.
+    Local,
+}
+
+/// Formats a wall-clock time as RFC 3339 with millisecond precision, e.g.
+/// `2024-05-01T12:30:00.000Z` or `2024-05-01T14:30:00.000+02:00`.
+pub fn format_rfc3339(time: SystemTime, zone: TimeZone) -> String {
+    let utc: DateTime<Utc> = time.into();
+    match zone {
+        TimeZone::Utc => utc.to_rfc3339_opts(SecondsFormat::Millis, true),
+        TimeZone::Local => utc
+            .with_timezone(&Local)
+            .to_rfc3339_opts(SecondsFormat::Millis, false),
+    }
+}
+
+/// Source of time for idle detection, TTLs, timestamps, scheduling and reported durations.
+/// Inject a `ManualClock` with `Pathmap::with_clock` to make time-based behaviour deterministic in tests.
+pub trait Clock: Send + Sync + 'static {
+    /// Monotonic time, used for idle and interval decisions.
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time, used for persisted timestamps.
+    fn system_now(&self) -> SystemTime;
+}
+
+/// The real system clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    system_start: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Creates a clock frozen at the current time.
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::now())
+    }
+
+    /// Creates a clock whose wall-clock time starts at `system_start`.
+    pub fn starting_at(system_start: SystemTime) -> Self {
+        ManualClock {
+            start: Instant::now(),
+            system_start,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + *self.elapsed.lock().unwrap()
+    }
+}
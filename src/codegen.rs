@@ -0,0 +1,227 @@
+/* src/codegen.rs */
+
+//! Source code generation from stored configuration, so build pipelines can bake the store's
+//! defaults into clients.
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+    "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+    "use", "where", "while", "yield",
+];
+
+const TYPESCRIPT_KEYWORDS: &[&str] = &[
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Target language of `export_codegen`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    Rust,
+    TypeScript,
+}
+
+/// Turns a key into an identifier in the case `convert` gives it: characters that are not
+/// allowed become `_`, a leading digit gets a `_` prefix and keywords a `_` suffix.
+fn identifier(name: &str, keywords: &[&str], convert: fn(&str) -> String) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let mut ident = convert(&sanitized);
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if keywords.contains(&ident.as_str()) || ident == "_" {
+        ident.push('_');
+    }
+    ident
+}
+
+/// Two keys of a group that map to the same identifier cannot both be emitted.
+fn clash(path: &str, ident: &str) -> PathmapError {
+    PathmapError::InvalidPath(format!(
+        "{}: several keys map to the identifier '{}'",
+        path, ident
+    ))
+}
+
+/// The Rust type and literal of a scalar or homogeneous scalar array, if it has one.
+fn rust_literal(value: &Value) -> Option<(String, String)> {
+    match value {
+        Value::Bool(b) => Some(("bool".into(), b.to_string())),
+        Value::Number(n) if n.is_i64() => Some(("i64".into(), n.to_string())),
+        Value::Number(n) if n.is_u64() => Some(("u64".into(), n.to_string())),
+        Value::Number(n) => Some(("f64".into(), format!("{:?}", n.as_f64()?))),
+        Value::String(s) => Some(("&str".into(), format!("{:?}", s))),
+        Value::Array(items) if !items.is_empty() => {
+            let element = if items.iter().all(Value::is_boolean) {
+                "bool"
+            } else if items.iter().all(Value::is_i64) {
+                "i64"
+            } else if items.iter().all(Value::is_number) {
+                "f64"
+            } else if items.iter().all(Value::is_string) {
+                "&str"
+            } else {
+                return None;
+            };
+            let literals = items
+                .iter()
+                .map(|item| match (element, item) {
+                    ("f64", Value::Number(n)) => format!("{:?}", n.as_f64().unwrap_or_default()),
+                    (_, Value::String(s)) => format!("{:?}", s),
+                    (_, other) => other.to_string(),
+                })
+                .collect::<Vec<_>>();
+            Some((
+                format!("&[{}]", element),
+                format!("&[{}]", literals.join(", ")),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Emits the contents of a Rust module for `map`, one level deeper than `depth`.
+fn write_rust_items(
+    out: &mut String,
+    path: &str,
+    map: &Map<String, Value>,
+    depth: usize,
+) -> Result<()> {
+    let indent = "    ".repeat(depth);
+    let mut used = HashSet::new();
+    for (name, value) in map {
+        let path = format!("{}.{}", path, name);
+        if let Value::Object(child) = value {
+            let ident = identifier(name, RUST_KEYWORDS, str::to_ascii_lowercase);
+            if !used.insert(ident.clone()) {
+                return Err(clash(&path, &ident));
+            }
+            let _ = writeln!(out, "{}pub mod {} {{", indent, ident);
+            write_rust_items(out, &path, child, depth + 1)?;
+            let _ = writeln!(out, "{}}}", indent);
+            continue;
+        }
+        let (ident, ty, literal) = match rust_literal(value) {
+            Some((ty, literal)) => (
+                identifier(name, RUST_KEYWORDS, str::to_ascii_uppercase),
+                ty,
+                literal,
+            ),
+            None => (
+                format!(
+                    "{}_JSON",
+                    identifier(name, RUST_KEYWORDS, str::to_ascii_uppercase)
+                ),
+                "&str".to_string(),
+                format!("{:?}", value.to_string()),
+            ),
+        };
+        if !used.insert(ident.clone()) {
+            return Err(clash(&path, &ident));
+        }
+        let _ = writeln!(out, "{}pub const {}: {} = {};", indent, ident, ty, literal);
+    }
+    Ok(())
+}
+
+impl Pathmap {
+    /// Writes the values of the group (or namespace) at `path` as constant definitions for
+    /// `lang`, e.g. to bake defaults from the store into clients at build time.
+    ///
+    /// `Lang::Rust` emits a `pub mod` named after the group with one `pub const` per value and a
+    /// nested module per subgroup or object value. Booleans, numbers, strings and arrays of one of
+    /// those become typed constants (`bool`, `i64`, `u64`, `f64`, `&str`, `&[T]`); anything else
+    /// (null, mixed or nested arrays) is emitted as JSON text in a `<NAME>_JSON: &str` constant.
+    /// `Lang::TypeScript` emits `export const <group> = { ... } as const;` and a matching type.
+    /// Keys become identifiers by replacing invalid characters with `_`; keys of one group that
+    /// end up with the same identifier fail with `InvalidPath`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn export_codegen<W: AsyncWrite + Unpin>(
+        &self,
+        path: &str,
+        lang: Lang,
+        writer: &mut W,
+    ) -> Result<()> {
+        let (ns, grp) = self.parse_path(path)?;
+        let Value::Object(map) = self.export_value(ns, &grp).await? else {
+            unreachable!("export_value always builds an object");
+        };
+        if map.is_empty() && !grp.is_empty() {
+            return Err(PathmapError::GroupNotFound(grp.to_string(), ns.to_string()));
+        }
+        let name = grp
+            .rsplit('.')
+            .next()
+            .filter(|n| !n.is_empty())
+            .unwrap_or(ns);
+        let mut out = format!("// Generated by kvmap from {}; do not edit.\n\n", path);
+        match lang {
+            Lang::Rust => {
+                let module = identifier(name, RUST_KEYWORDS, str::to_ascii_lowercase);
+                let _ = writeln!(out, "pub mod {} {{", module);
+                write_rust_items(&mut out, path, &map, 1)?;
+                out.push_str("}\n");
+            }
+            Lang::TypeScript => {
+                let constant = identifier(name, TYPESCRIPT_KEYWORDS, str::to_string);
+                let type_name = match constant.chars().next() {
+                    Some(first) => first.to_ascii_uppercase().to_string() + &constant[1..],
+                    None => constant.clone(),
+                };
+                let object = serde_json::to_string_pretty(&Value::Object(map))?;
+                let _ = writeln!(out, "export const {} = {} as const;\n", constant, object);
+                let _ = writeln!(out, "export type {} = typeof {};", type_name, constant);
+            }
+        }
+        writer.write_all(out.as_bytes()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
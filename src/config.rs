@@ -0,0 +1,459 @@
+/* src/config.rs */
+
+use crate::clock::TimeZone;
+use crate::number::{FloatPolicy, IntegerPolicy};
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Comparison function backing a custom collation.
+pub type CollationFn = Arc<dyn Fn(&str, &str) -> Ordering + Send + Sync>;
+
+/// How keys and groups are compared (and therefore matched) inside a namespace.
+#[derive(Clone, Default)]
+pub enum Collation {
+    /// Byte-wise comparison, SQLite's default.
+    #[default]
+    Binary,
+    /// ASCII case-insensitive comparison, so `Users.John` and `users.john` are the same key.
+    NoCase,
+    /// Like `Binary`, but trailing spaces are ignored.
+    RTrim,
+    /// A user-supplied comparison registered on every connection under `name`.
+    Custom { name: String, compare: CollationFn },
+}
+
+impl Collation {
+    /// Creates a custom collation from a comparison function.
+    pub fn custom<F>(name: &str, compare: F) -> Self
+    where
+        F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        Collation::Custom {
+            name: name.to_string(),
+            compare: Arc::new(compare),
+        }
+    }
+
+    /// The collation name as used in SQL.
+    pub fn name(&self) -> &str {
+        match self {
+            Collation::Binary => "BINARY",
+            Collation::NoCase => "NOCASE",
+            Collation::RTrim => "RTRIM",
+            Collation::Custom { name, .. } => name,
+        }
+    }
+}
+
+impl fmt::Debug for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Collation::Custom { name, .. } => f.debug_tuple("Custom").field(name).finish(),
+            other => f.write_str(other.name()),
+        }
+    }
+}
+
+/// How `list` orders the groups and values it returns. Only the output is sorted; storage,
+/// matching and the collation are unaffected.
+#[derive(Clone, Default)]
+pub enum ListingOrder {
+    /// Byte order of the UTF-8 keys.
+    #[default]
+    Binary,
+    /// Human-expected order for non-ASCII keys without locale data: letters are compared
+    /// ignoring accents and case first (`é` sorts with `e`, `Z` after `a`), then by accent,
+    /// then with lowercase before uppercase.
+    Unicode,
+    /// A user-supplied comparison, e.g. a locale-specific ICU collator.
+    Custom(CollationFn),
+}
+
+impl ListingOrder {
+    /// Creates a custom order from a comparison function.
+    pub fn custom<F>(compare: F) -> Self
+    where
+        F: Fn(&str, &str) -> Ordering + Send + Sync + 'static,
+    {
+        ListingOrder::Custom(Arc::new(compare))
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        match self {
+            ListingOrder::Binary => a.cmp(b),
+            ListingOrder::Unicode => unicode_compare(a, b),
+            ListingOrder::Custom(compare) => compare(a, b),
+        }
+    }
+
+    /// Sorts `names` in this order.
+    pub fn sort(&self, names: &mut [String]) {
+        match self {
+            ListingOrder::Binary => names.sort(),
+            _ => names.sort_by(|a, b| self.compare(a, b)),
+        }
+    }
+}
+
+impl fmt::Debug for ListingOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListingOrder::Binary => f.write_str("Binary"),
+            ListingOrder::Unicode => f.write_str("Unicode"),
+            ListingOrder::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// Compares on base letters first, then accents, then case, then bytes.
+fn unicode_compare(a: &str, b: &str) -> Ordering {
+    use unicode_normalization::UnicodeNormalization;
+    use unicode_normalization::char::is_combining_mark;
+
+    let base = |s: &str| {
+        s.nfd()
+            .filter(|c| !is_combining_mark(*c))
+            .flat_map(char::to_lowercase)
+            .collect::<Vec<_>>()
+    };
+    let accents = |s: &str| s.nfd().flat_map(char::to_lowercase).collect::<Vec<_>>();
+    let case = |s: &str| s.nfd().map(|c| (c.is_uppercase(), c)).collect::<Vec<_>>();
+    base(a)
+        .cmp(&base(b))
+        .then_with(|| accents(a).cmp(&accents(b)))
+        .then_with(|| case(a).cmp(&case(b)))
+        .then_with(|| a.cmp(b))
+}
+
+/// How a `Pathmap` instance shares its base path with other processes. Configuration files name
+/// the modes in lowercase (`exclusive`, `shared`, `coordinated`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OpenMode {
+    /// Only this instance may use the base path; any other locking opener is rejected.
+    Exclusive,
+    /// Any number of shared openers may coexist, but none while an exclusive one holds the path.
+    Shared,
+    /// Shares the path like `Shared`, and additionally takes a per-namespace lock file while
+    /// vacuuming, restoring or migrating a namespace. A second coordinated process gets
+    /// `StoreLocked` for the same operation meanwhile; its background cleanup skips the namespace.
+    Coordinated,
+}
+
+/// Connection pool sizing for a namespace's database.
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// Maximum number of open connections.
+    pub max_connections: u32,
+    /// Number of connections kept open even when idle.
+    pub min_connections: u32,
+    /// How long an operation waits for a free connection before failing.
+    pub acquire_timeout: Duration,
+    /// How long an unused connection above `min_connections` stays open.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_connections(mut self, max: u32) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    pub fn min_connections(mut self, min: u32) -> Self {
+        self.min_connections = min;
+        self
+    }
+
+    pub fn acquire_timeout(mut self, timeout: Duration) -> Self {
+        self.acquire_timeout = timeout;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+}
+
+/// Storage sizes that raise a `StorageAlert` event when a namespace crosses them, in either
+/// direction. Unset thresholds are not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageThresholds {
+    /// Size of the database file in bytes.
+    pub file_bytes: Option<u64>,
+    /// Share of the database's pages that are free (reclaimable by a vacuum), in percent.
+    pub freelist_percent: Option<u64>,
+    /// Size of the write-ahead log in bytes.
+    pub wal_bytes: Option<u64>,
+}
+
+impl StorageThresholds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file_bytes(mut self, bytes: u64) -> Self {
+        self.file_bytes = Some(bytes);
+        self
+    }
+
+    pub fn freelist_percent(mut self, percent: u64) -> Self {
+        self.freelist_percent = Some(percent);
+        self
+    }
+
+    pub fn wal_bytes(mut self, bytes: u64) -> Self {
+        self.wal_bytes = Some(bytes);
+        self
+    }
+}
+
+/// What the audit trail of a namespace records; see `Pathmap::audit_log`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuditMode {
+    /// No audit trail.
+    #[default]
+    Off,
+    /// Every `set`, `overwrite`, `delete` and `delete_group`: when, by which actor, the operation
+    /// and the key.
+    Mutations,
+    /// Like `Mutations`, plus the SHA-256 of the value each mutation replaced or deleted.
+    WithOldHash,
+}
+
+/// Limits on what a namespace may hold, enforced when a write would cross them, which then
+/// fails with `QuotaExceeded`. Unset limits are not checked. The key and byte limits are checked
+/// in the transaction that writes, so concurrent writers cannot cross them together; writes held
+/// in a batch or the write queue are checked when they are committed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Number of stored values.
+    pub max_keys: Option<u64>,
+    /// Bytes of database pages in use, i.e. the file size minus free pages. Values stored as
+    /// blob files count only by their reference.
+    pub max_bytes: Option<u64>,
+    /// Size of a single serialized value.
+    pub max_value_bytes: Option<u64>,
+}
+
+impl Quota {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_keys(mut self, keys: u64) -> Self {
+        self.max_keys = Some(keys);
+        self
+    }
+
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_value_bytes(mut self, bytes: u64) -> Self {
+        self.max_value_bytes = Some(bytes);
+        self
+    }
+}
+
+/// Per-namespace settings, applied when the namespace's database is opened.
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceConfig {
+    /// Collation of the group and key columns. Only takes effect when the
+    /// namespace's table is created; existing namespaces keep their collation.
+    pub collation: Collation,
+    /// Connection pool sizing.
+    pub pool: PoolConfig,
+    /// Serialized values larger than this many bytes are stored as files next to the
+    /// database, with only a reference kept in SQLite. `None` keeps every value inline.
+    pub blob_threshold: Option<usize>,
+    /// How NaN and infinite floats are stored.
+    pub float_policy: FloatPolicy,
+    /// How integers too large for an `f64` are stored.
+    pub integer_policy: IntegerPolicy,
+    /// Keeps an FTS5 full-text index of the strings in the namespace's values for `search`.
+    /// `Some(false)` drops the index; `Some(true)` indexes the existing values. `None`, the
+    /// default, keeps whatever the file has, so opening a copy with the default config does not
+    /// drop its index.
+    pub full_text_search: Option<bool>,
+    /// Fields that must be unique within a group, as `group.*.field` patterns: `users.*.email`
+    /// rejects a value in `users` whose `email` another value there already has, and `*.id`
+    /// applies to the namespace's root. Each becomes a unique index when the namespace is
+    /// opened; removing a pattern leaves its index in place until `drop_index`. Not combinable
+    /// with `blob_threshold`: values in blob files could not be checked.
+    ///
+    /// If the group already holds duplicates, the namespace still opens, without that index,
+    /// and a warning naming the pattern is logged; `list_indexes` shows it missing. To repair,
+    /// locate the duplicates with `find` and a `FieldFilter` on the field, resolve them, and
+    /// call `create_unique_index` (or reopen the namespace).
+    pub unique_fields: Vec<String>,
+    /// Records every mutation in a change log so `backup_incremental` can copy only the delta.
+    /// Turning it off discards the floor, so the next incremental chain needs a new full backup.
+    pub changelog: bool,
+    /// Lets `start_change_polling` report mutations that other processes make to the namespace's
+    /// file. Keeps the change log (regardless of `changelog`) and tags the entries written
+    /// through this instance, so they are not reported twice. Without `changelog`, the polling
+    /// task trims the entries it consumed `change_retention` after polling them. Once a file has been
+    /// opened with it, every instance keeps its change log, also with both options off, so a
+    /// process opening the file with the default config does not stop other processes' polling.
+    pub external_changes: bool,
+    /// How long the polling task keeps change log entries after it polled them (default one
+    /// minute). Every other process polling the file must poll at least this often, or it misses
+    /// the trimmed entries.
+    pub change_retention: Option<Duration>,
+    /// Time zone of timestamps the namespace writes for people and tools. Stored instants are
+    /// unaffected; this only changes how they are rendered.
+    pub time_zone: TimeZone,
+    /// Connections that background work (exports, backups, imports, vacuums and jobs run with
+    /// `Priority::Background`) may hold at once, counting one per job; the rest of the pool is
+    /// reserved for foreground operations. Clamped to between one and `pool.max_connections`
+    /// minus one, so the foreground always keeps a connection unless the pool has only one.
+    pub background_connections: u32,
+    /// Number of values `get` keeps in an in-process LRU cache; 0 disables it. Writes through
+    /// this instance invalidate the cache, but writes by other processes or instances sharing
+    /// the file are not seen until the entry is evicted.
+    pub read_cache: usize,
+    /// Sizes that raise storage alerts, checked by `check_storage` and the background cleanup.
+    pub storage_alerts: StorageThresholds,
+    /// Order of the groups and values returned by `list`.
+    pub listing_order: ListingOrder,
+    /// Limits on the namespace's size, enforced at write time.
+    pub quota: Quota,
+    /// Records every mutation in the namespace's audit trail.
+    pub audit: AuditMode,
+    /// Opens the database read-only: writes fail, the file is never created, and it must already
+    /// be at the current schema version since it cannot be migrated.
+    pub read_only: bool,
+}
+
+impl NamespaceConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the key collation.
+    pub fn collation(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+
+    /// Sets the connection pool sizing.
+    pub fn pool(mut self, pool: PoolConfig) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Externalizes values larger than `bytes` to blob files.
+    pub fn blob_threshold(mut self, bytes: usize) -> Self {
+        self.blob_threshold = Some(bytes);
+        self
+    }
+
+    /// Sets how NaN and infinite floats are stored.
+    pub fn float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// Sets how integers beyond ±2^53 are stored, for consumers that read numbers as `f64`.
+    pub fn integer_policy(mut self, policy: IntegerPolicy) -> Self {
+        self.integer_policy = policy;
+        self
+    }
+
+    /// Enables or disables the full-text index used by `search`.
+    pub fn full_text_search(mut self, enabled: bool) -> Self {
+        self.full_text_search = Some(enabled);
+        self
+    }
+
+    /// Requires a JSON field to be unique within a group, e.g. `users.*.email`.
+    pub fn unique_field(mut self, pattern: &str) -> Self {
+        self.unique_fields.push(pattern.to_string());
+        self
+    }
+
+    /// Enables or disables the change log used by incremental backups.
+    pub fn changelog(mut self, enabled: bool) -> Self {
+        self.changelog = enabled;
+        self
+    }
+
+    /// Enables or disables the detection of other processes' writes for change polling.
+    pub fn external_changes(mut self, enabled: bool) -> Self {
+        self.external_changes = enabled;
+        self
+    }
+
+    /// Sets how long polled change log entries are kept for other processes.
+    pub fn change_retention(mut self, retention: Duration) -> Self {
+        self.change_retention = Some(retention);
+        self
+    }
+
+    /// Sets the time zone timestamps are rendered in.
+    pub fn time_zone(mut self, zone: TimeZone) -> Self {
+        self.time_zone = zone;
+        self
+    }
+
+    /// Sets how many connections background work may hold at once.
+    pub fn background_connections(mut self, connections: u32) -> Self {
+        self.background_connections = connections;
+        self
+    }
+
+    /// Caches up to `capacity` recently read values in memory.
+    pub fn read_cache(mut self, capacity: usize) -> Self {
+        self.read_cache = capacity;
+        self
+    }
+
+    /// Raises storage alerts when the namespace crosses `thresholds`.
+    pub fn storage_alerts(mut self, thresholds: StorageThresholds) -> Self {
+        self.storage_alerts = thresholds;
+        self
+    }
+
+    /// Sets the order `list` returns groups and values in.
+    pub fn listing_order(mut self, order: ListingOrder) -> Self {
+        self.listing_order = order;
+        self
+    }
+
+    /// Limits the namespace's size; writes that would exceed `quota` fail.
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Sets what the namespace's audit trail records.
+    pub fn audit(mut self, mode: AuditMode) -> Self {
+        self.audit = mode;
+        self
+    }
+
+    /// Opens the namespace's database read-only.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+}
@@ -1,87 +1,939 @@
 /* src/db.rs */
 
+use crate::annotate::KeyMeta;
+use crate::config::{Collation, NamespaceConfig};
 use crate::error::{PathmapError, Result};
+use crate::index::unique_index_error;
+pub use crate::path::{join_key, split_key};
 use sqlx::{
-    Row, SqlitePool,
+    ConnectOptions, Connection, Executor, Row, Sqlite, SqliteConnection, SqlitePool,
     sqlite::{SqliteConnectOptions, SqlitePoolOptions},
 };
 use std::path::Path;
 
-pub async fn connect(db_path: &Path) -> Result<SqlitePool> {
+/// On-disk layout version, stored in `PRAGMA user_version`.
+pub const SCHEMA_VERSION: i64 = 8;
+
+// Statements behind the public operations, shared with `explain`.
+const FIND_SQL: &str = "SELECT value, external FROM kv_store WHERE grp = ? AND key = ?";
+const SET_SQL: &str =
+    "INSERT INTO kv_store (grp, key, value, external, updated_at) VALUES (?, ?, ?, ?, ?)";
+// An upsert rather than `INSERT OR REPLACE`, which would delete other rows that collide in a
+// unique index instead of failing.
+const OVERWRITE_SQL: &str = "INSERT INTO kv_store (grp, key, value, external, updated_at) VALUES (?, ?, ?, ?, ?) \
+     ON CONFLICT (grp, key) DO UPDATE SET value = excluded.value, external = excluded.external, updated_at = excluded.updated_at";
+const EXISTS_VALUE_SQL: &str = "SELECT EXISTS(SELECT 1 FROM kv_store WHERE grp = ? AND key = ?)";
+const EXISTS_GROUP_SQL: &str =
+    "SELECT EXISTS(SELECT 1 FROM kv_store WHERE grp = ? OR (grp >= ? AND grp < ?))";
+const EXISTS_ANY_SQL: &str = "SELECT EXISTS(SELECT 1 FROM kv_store)";
+const DELETE_SQL: &str = "DELETE FROM kv_store WHERE grp = ? AND key = ?";
+const GROUP_REFS_SQL: &str =
+    "SELECT value FROM kv_store WHERE external = 1 AND (grp = ? OR (grp >= ? AND grp < ?))";
+const DELETE_GROUP_SQL: &str = "DELETE FROM kv_store WHERE grp = ? OR (grp >= ? AND grp < ?)";
+const LIST_VALUES_SQL: &str = "SELECT key FROM kv_store WHERE grp = ? ORDER BY key";
+// Subgroup listing seeks through the primary key one subgroup at a time.
+const NEXT_GROUP_SQL: &str =
+    "SELECT grp FROM kv_store WHERE grp > ? AND grp < ? ORDER BY grp LIMIT 1";
+const NEXT_ROOT_GROUP_SQL: &str = "SELECT grp FROM kv_store WHERE grp > ? ORDER BY grp LIMIT 1";
+const SKIP_GROUP_SQL: &str =
+    "SELECT grp FROM kv_store WHERE grp >= ? AND grp < ? ORDER BY grp LIMIT 1";
+const SKIP_ROOT_GROUP_SQL: &str = "SELECT grp FROM kv_store WHERE grp >= ? ORDER BY grp LIMIT 1";
+
+pub async fn connect(
+    db_path: &Path,
+    group_depth: Option<usize>,
+    config: &NamespaceConfig,
+) -> Result<SqlitePool> {
+    connect_as(db_path, group_depth, config, None).await
+}
+
+/// Like `connect`; with `external_changes` set in `config`, the change log entries written
+/// through the pool are tagged with `origin`.
+pub async fn connect_as(
+    db_path: &Path,
+    group_depth: Option<usize>,
+    config: &NamespaceConfig,
+    origin: Option<&str>,
+) -> Result<SqlitePool> {
     // This logic remains crucial. SQLite will not create the parent directory.
-    if let Some(parent) = db_path.parent() {
-        if !parent.exists() {
-            // Ensure the base directory for our databases exists.
-            std::fs::create_dir_all(parent)?;
-        }
+    if let Some(parent) = db_path.parent()
+        && !parent.exists()
+    {
+        // Ensure the base directory for our databases exists.
+        std::fs::create_dir_all(parent)?;
     }
 
     // Be more explicit with connection options to ensure the database file is created.
-    let connection_options = SqliteConnectOptions::new()
-        .filename(db_path)
-        .create_if_missing(true); // Explicitly tell sqlx to create the DB file
+    let connection_options = connect_options(db_path, config)?
+        .create_if_missing(!config.read_only) // Explicitly tell sqlx to create the DB file
+        .read_only(config.read_only);
 
     // Use `connect_with` to apply our explicit options.
+    if config.pool.max_connections == 0 || config.pool.min_connections > config.pool.max_connections
+    {
+        return Err(PathmapError::InvalidConfig(format!(
+            "invalid pool size (min {}, max {})",
+            config.pool.min_connections, config.pool.max_connections
+        )));
+    }
+    let mut pool_options = SqlitePoolOptions::new()
+        .max_connections(config.pool.max_connections)
+        .min_connections(config.pool.min_connections)
+        .acquire_timeout(config.pool.acquire_timeout)
+        .idle_timeout(config.pool.idle_timeout);
+
+    if config.read_only {
+        let pool = pool_options.connect_with(connection_options).await?;
+        // Nothing may be written, so the file has to be usable as it is.
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&pool)
+            .await?;
+        if version < SCHEMA_VERSION {
+            pool.close().await;
+            return Err(PathmapError::InvalidConfig(format!(
+                "read-only database '{}' is at schema version {} and needs migrating to {}",
+                db_path.display(),
+                version,
+                SCHEMA_VERSION
+            )));
+        }
+        return Ok(pool);
+    }
+
+    // The schema is brought up to date on a connection of its own before the pool opens, so
+    // every pooled connection finds it complete.
+    let setup = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(connection_options.clone())
+        .await?;
+    let prepared = prepare(&setup, group_depth, config).await;
+    setup.close().await;
+    prepared?;
+
+    if let Some(origin) = origin.filter(|_| config.external_changes) {
+        let trigger = origin_trigger_sql(origin);
+        pool_options = pool_options.after_connect(move |conn, _| {
+            let trigger = trigger.clone();
+            Box::pin(async move {
+                sqlx::query(&trigger).execute(conn).await?;
+                Ok(())
+            })
+        });
+    }
+    Ok(pool_options.connect_with(connection_options).await?)
+}
+
+/// Opens an existing database read-only and as it is, without migrating it or applying the
+/// settings of `config`, e.g. to read a backup without changing it.
+pub async fn open_read_only(db_path: &Path, config: &NamespaceConfig) -> Result<SqlitePool> {
+    let connection_options = connect_options(db_path, config)?.read_only(true);
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(1)
         .connect_with(connection_options)
         .await?;
+    Ok(pool)
+}
 
-    // Create table if not exists using a dynamic query
-    sqlx::query(
+/// Connection options for `db_path` with the collation of `config` registered.
+fn connect_options(db_path: &Path, config: &NamespaceConfig) -> Result<SqliteConnectOptions> {
+    let mut connection_options = SqliteConnectOptions::new().filename(db_path);
+    // Custom collations must be registered on every connection that touches the table.
+    if let Collation::Custom { name, compare } = &config.collation {
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(PathmapError::InvalidConfig(format!(
+                "invalid collation name '{}'",
+                name
+            )));
+        }
+        let compare = compare.clone();
+        connection_options =
+            connection_options.collation(name.as_str(), move |a: &str, b: &str| compare(a, b));
+    }
+    Ok(connection_options)
+}
+
+/// Brings a database that was replaced under an open pool, e.g. by a restore, up to the current
+/// schema and the file-level settings of `config`.
+pub async fn upgrade(
+    pool: &SqlitePool,
+    group_depth: Option<usize>,
+    config: &NamespaceConfig,
+) -> Result<()> {
+    if config.read_only {
+        return Ok(());
+    }
+    prepare(pool, group_depth, config).await
+}
+
+/// Migrates a writable database and applies the file-level settings of `config`.
+async fn prepare(
+    pool: &SqlitePool,
+    group_depth: Option<usize>,
+    config: &NamespaceConfig,
+) -> Result<()> {
+    migrate(pool, group_depth, &config.collation).await?;
+    check_group_depth(pool, group_depth).await?;
+    // A file some process polls keeps its change log, whatever this instance's config says:
+    // dropping the triggers would silently stop the other process's events.
+    let polled = has_changelog_origin(pool).await?;
+    if config.external_changes && !polled {
+        sqlx::query("ALTER TABLE kv_changelog ADD COLUMN origin TEXT")
+            .execute(pool)
+            .await?;
+    }
+    configure_changelog(pool, config.changelog || config.external_changes || polled).await?;
+    match config.full_text_search {
+        Some(enabled) => configure_full_text(pool, enabled).await,
+        None => Ok(()),
+    }
+}
+
+/// Brings the database up to `SCHEMA_VERSION`, one step per version inside a single transaction.
+async fn migrate(
+    pool: &SqlitePool,
+    group_depth: Option<usize>,
+    collation: &Collation,
+) -> Result<()> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+    if version >= SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    if version < 1 {
+        migrate_v1(&mut tx, group_depth, collation).await?;
+    }
+    if version < 2 {
+        // v2: values above the namespace's blob threshold live in files next to the database.
+        sqlx::query("ALTER TABLE kv_store ADD COLUMN external INTEGER NOT NULL DEFAULT 0")
+            .execute(&mut *tx)
+            .await?;
+    }
+    if version < 3 {
+        // v3: an optional change log of touched keys, filled by triggers while enabled.
+        sqlx::query(&format!(
+            r#"
+            CREATE TABLE kv_changelog (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                grp TEXT NOT NULL COLLATE {collation},
+                key TEXT NOT NULL COLLATE {collation}
+            )
+            "#,
+            collation = collation.name()
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("CREATE TABLE kv_meta (name TEXT PRIMARY KEY, value INTEGER NOT NULL)")
+            .execute(&mut *tx)
+            .await?;
+    }
+    if version < 4 {
+        // v4: optional descriptions and owners of keys, dropped together with the key.
+        sqlx::query(&format!(
+            r#"
+            CREATE TABLE kv_annotations (
+                grp TEXT NOT NULL COLLATE {collation},
+                key TEXT NOT NULL COLLATE {collation},
+                description TEXT,
+                owner TEXT,
+                PRIMARY KEY (grp, key)
+            )
+            "#,
+            collation = collation.name()
+        ))
+        .execute(&mut *tx)
+        .await?;
+        // Overwrites update the row in place and do not fire delete triggers, so they keep the
+        // annotation.
+        sqlx::query(
+            "CREATE TRIGGER kv_annotations_delete AFTER DELETE ON kv_store BEGIN \
+             DELETE FROM kv_annotations WHERE grp = OLD.grp AND key = OLD.key; END",
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    if version < 5 {
+        // v5: delayed actions on keys (Unix milliseconds), dropped together with the key.
+        sqlx::query(&format!(
+            r#"
+            CREATE TABLE kv_schedule (
+                grp TEXT NOT NULL COLLATE {collation},
+                key TEXT NOT NULL COLLATE {collation},
+                action TEXT NOT NULL,
+                at INTEGER NOT NULL,
+                PRIMARY KEY (grp, key, action)
+            )
+            "#,
+            collation = collation.name()
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("CREATE INDEX kv_schedule_at ON kv_schedule (at)")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "CREATE TRIGGER kv_schedule_delete AFTER DELETE ON kv_store BEGIN \
+             DELETE FROM kv_schedule WHERE grp = OLD.grp AND key = OLD.key; END",
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    if version < 6 {
+        // v6: audit trail of mutations (Unix milliseconds); rows outlive the keys they name.
+        sqlx::query(&format!(
+            r#"
+            CREATE TABLE kv_audit (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                at INTEGER NOT NULL,
+                actor TEXT,
+                op TEXT NOT NULL,
+                grp TEXT NOT NULL COLLATE {collation},
+                key TEXT NOT NULL COLLATE {collation},
+                old_hash TEXT
+            )
+            "#,
+            collation = collation.name()
+        ))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("CREATE INDEX kv_audit_at ON kv_audit (at)")
+            .execute(&mut *tx)
+            .await?;
+    }
+    if version < 7 {
+        // v7: when each value was last written (Unix milliseconds); NULL for older rows.
+        sqlx::query("ALTER TABLE kv_store ADD COLUMN updated_at INTEGER")
+            .execute(&mut *tx)
+            .await?;
+    }
+    if version < 8 {
+        // v8: the number of stored values, kept by triggers so quota checks need not count.
+        sqlx::query(
+            "INSERT OR REPLACE INTO kv_meta (name, value) \
+             SELECT 'value_count', COUNT(*) FROM kv_store",
+        )
+        .execute(&mut *tx)
+        .await?;
+        for (event, delta) in [("INSERT", "+ 1"), ("DELETE", "- 1")] {
+            sqlx::query(&format!(
+                "CREATE TRIGGER kv_count_{name} AFTER {event} ON kv_store BEGIN \
+                 UPDATE kv_meta SET value = value {delta} WHERE name = 'value_count'; END",
+                name = event.to_lowercase(),
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    sqlx::query(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Records the group depth the rows of a database are split by, and refuses to open it with
+/// another: its rows would keep the old `(grp, key)` split and no longer be found under the new
+/// one. A database without a recorded depth has its rows checked against `group_depth` first.
+async fn check_group_depth(pool: &SqlitePool, group_depth: Option<usize>) -> Result<()> {
+    // Without a fixed depth, -1.
+    let depth = group_depth.map_or(-1, |depth| depth as i64);
+    let recorded: Option<i64> =
+        sqlx::query_scalar("SELECT value FROM kv_meta WHERE name = 'group_depth'")
+            .fetch_optional(pool)
+            .await?;
+    let describe = |depth: i64| match depth {
+        -1 => "without a fixed group depth".to_string(),
+        depth => format!("with a group depth of {}", depth),
+    };
+    match recorded {
+        Some(recorded) if recorded != depth => {
+            return Err(PathmapError::InvalidConfig(format!(
+                "the database's keys are split {}, not {}",
+                describe(recorded),
+                describe(depth)
+            )));
+        }
+        Some(_) => return Ok(()),
+        None => {}
+    }
+    let rows = sqlx::query("SELECT grp, key FROM kv_store")
+        .fetch_all(pool)
+        .await?;
+    let stale = rows.iter().any(|row| {
+        let (grp, key): (String, String) = (row.get("grp"), row.get("key"));
+        split_key(&join_key(&grp, &key), group_depth) != (grp.as_str(), key.as_str())
+    });
+    if stale {
+        return Err(PathmapError::InvalidConfig(format!(
+            "the database's keys are not split {}",
+            describe(depth)
+        )));
+    }
+    sqlx::query("INSERT OR IGNORE INTO kv_meta (name, value) VALUES ('group_depth', ?)")
+        .bind(depth)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// v1: keys are stored as separate `(grp, key)` columns.
+/// Legacy files that stored the full dotted key in a single column are split row by row.
+async fn migrate_v1(
+    conn: &mut SqliteConnection,
+    group_depth: Option<usize>,
+    collation: &Collation,
+) -> Result<()> {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('kv_store')")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    if columns.iter().any(|c| c == "grp") {
+        return Ok(());
+    }
+
+    if !columns.is_empty() {
+        sqlx::query("ALTER TABLE kv_store RENAME TO kv_store_legacy")
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sqlx::query(&format!(
         r#"
-        CREATE TABLE IF NOT EXISTS kv_store (
-            key TEXT PRIMARY KEY NOT NULL,
-            value BLOB NOT NULL
+        CREATE TABLE kv_store (
+            grp TEXT NOT NULL COLLATE {collation},
+            key TEXT NOT NULL COLLATE {collation},
+            value BLOB NOT NULL,
+            PRIMARY KEY (grp, key)
         )
         "#,
-    )
-    .execute(&pool)
+        collation = collation.name()
+    ))
+    .execute(&mut *conn)
     .await?;
 
-    Ok(pool)
+    if !columns.is_empty() {
+        let rows = sqlx::query("SELECT key, value FROM kv_store_legacy")
+            .fetch_all(&mut *conn)
+            .await?;
+        for row in rows {
+            let full_key: String = row.get("key");
+            let value: Vec<u8> = row.get("value");
+            let (grp, key) = split_key(&full_key, group_depth);
+            sqlx::query("INSERT INTO kv_store (grp, key, value) VALUES (?, ?, ?)")
+                .bind(grp)
+                .bind(key)
+                .bind(value)
+                .execute(&mut *conn)
+                .await?;
+        }
+        sqlx::query("DROP TABLE kv_store_legacy")
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
 }
 
-pub async fn get(pool: &SqlitePool, key: &str) -> Result<Vec<u8>> {
-    let row = sqlx::query("SELECT value FROM kv_store WHERE key = ?")
+/// A raw stored row. For `external` rows `data` holds the blob file name instead of the value.
+#[derive(Debug, Clone)]
+pub struct StoredValue {
+    pub data: Vec<u8>,
+    pub external: bool,
+}
+
+pub async fn find<'e, E>(executor: E, grp: &str, key: &str) -> Result<Option<StoredValue>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(FIND_SQL)
+        .bind(grp)
         .bind(key)
-        .fetch_optional(pool)
+        .fetch_optional(executor)
         .await?;
 
-    row.map(|r| r.get("value"))
-        .ok_or_else(|| PathmapError::ValueNotFound(key.to_string()))
+    Ok(row.map(|r| StoredValue {
+        data: r.get("value"),
+        external: r.get("external"),
+    }))
+}
+
+pub async fn get(pool: &SqlitePool, grp: &str, key: &str) -> Result<StoredValue> {
+    find(pool, grp, key)
+        .await?
+        .ok_or_else(|| PathmapError::ValueNotFound(join_key(grp, key)))
 }
 
-pub async fn set(pool: &SqlitePool, key: &str, value: &[u8]) -> Result<()> {
-    sqlx::query("INSERT INTO kv_store (key, value) VALUES (?, ?)")
+pub async fn set<'e, E>(
+    executor: E,
+    grp: &str,
+    key: &str,
+    value: &[u8],
+    external: bool,
+    updated_at: i64,
+) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(SET_SQL)
+        .bind(grp)
         .bind(key)
         .bind(value)
-        .execute(pool)
-        .await?;
+        .bind(external)
+        .bind(updated_at)
+        .execute(executor)
+        .await
+        .map_err(|e| unique_index_error(e.into()))?;
     Ok(())
 }
 
-pub async fn exists(pool: &SqlitePool, key: &str) -> Result<bool> {
-    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM kv_store WHERE key LIKE ?")
-        .bind(format!("{}%", key))
+/// Checks whether a value is stored under exactly this group and name.
+pub async fn exists_value(pool: &SqlitePool, grp: &str, key: &str) -> Result<bool> {
+    let exists = sqlx::query_scalar(EXISTS_VALUE_SQL)
+        .bind(grp)
+        .bind(key)
         .fetch_one(pool)
         .await?;
-    Ok(count > 0)
+    Ok(exists)
+}
+
+/// Checks whether a group holds any value, directly or in a nested group. The root group ("")
+/// exists when the namespace holds any value.
+pub async fn exists_group<'e, E>(executor: E, grp: &str) -> Result<bool>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let exists = if grp.is_empty() {
+        sqlx::query_scalar(EXISTS_ANY_SQL)
+            .fetch_one(executor)
+            .await?
+    } else {
+        sqlx::query_scalar(EXISTS_GROUP_SQL)
+            .bind(grp)
+            .bind(format!("{}.", grp))
+            .bind(format!("{}/", grp))
+            .fetch_one(executor)
+            .await?
+    };
+    Ok(exists)
 }
 
-pub async fn delete(pool: &SqlitePool, key: &str) -> Result<()> {
-    sqlx::query("DELETE FROM kv_store WHERE key = ?")
+pub async fn delete<'e, E>(executor: E, grp: &str, key: &str) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(DELETE_SQL)
+        .bind(grp)
         .bind(key)
-        .execute(pool)
+        .execute(executor)
         .await?;
     Ok(())
 }
 
-pub async fn overwrite(pool: &SqlitePool, key: &str, value: &[u8]) -> Result<()> {
-    sqlx::query("INSERT OR REPLACE INTO kv_store (key, value) VALUES (?, ?)")
+/// Deletes a row and returns what it held, in one statement: of several concurrent callers
+/// only one receives the row.
+pub async fn take<'e, E>(executor: E, grp: &str, key: &str) -> Result<Option<StoredValue>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row =
+        sqlx::query("DELETE FROM kv_store WHERE grp = ? AND key = ? RETURNING value, external")
+            .bind(grp)
+            .bind(key)
+            .fetch_optional(executor)
+            .await?;
+
+    Ok(row.map(|r| StoredValue {
+        data: r.get("value"),
+        external: r.get("external"),
+    }))
+}
+
+/// A field read out of a stored value by `find_field`.
+pub struct FieldRow {
+    /// Blob file name of an external value, whose field SQLite cannot reach.
+    pub blob: Option<Vec<u8>>,
+    /// JSON text of the field of an inline value; `None` if it has no such field.
+    pub field: Option<String>,
+}
+
+/// The JSON text at `json_path` (e.g. `$.profile.email`) of the value of `grp`/`key`, extracted
+/// by SQLite so the rest of the document is not transferred.
+pub async fn find_field(
+    pool: &SqlitePool,
+    grp: &str,
+    key: &str,
+    json_path: &str,
+) -> Result<Option<FieldRow>> {
+    let row = sqlx::query(
+        "SELECT CASE WHEN external THEN value END AS blob, CASE WHEN external THEN NULL ELSE CAST(value AS TEXT) -> ? END AS field FROM kv_store WHERE grp = ? AND key = ?",
+    )
+    .bind(json_path)
+    .bind(grp)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| FieldRow {
+        blob: r.get("blob"),
+        field: r.get("field"),
+    }))
+}
+
+/// The JSON text at `json_path` of the serialized value `json`, or `None` if it has no such field.
+pub async fn extract_field(
+    pool: &SqlitePool,
+    json: &[u8],
+    json_path: &str,
+) -> Result<Option<String>> {
+    let field = sqlx::query_scalar("SELECT ? -> ?")
+        .bind(String::from_utf8_lossy(json))
+        .bind(json_path)
+        .fetch_one(pool)
+        .await?;
+    Ok(field)
+}
+
+/// `target` with the RFC 7396 merge patch `patch` applied by SQLite's `json_patch`.
+pub async fn merge_patch<'e, E>(executor: E, target: &[u8], patch: &str) -> Result<Vec<u8>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let merged: String = sqlx::query_scalar("SELECT json_patch(?, ?)")
+        .bind(String::from_utf8_lossy(target))
+        .bind(patch)
+        .fetch_one(executor)
+        .await?;
+    Ok(merged.into_bytes())
+}
+
+/// The (group, key) pairs of a group ("" for the namespace root) and every group nested in it,
+/// without their values.
+pub async fn nested_keys(pool: &SqlitePool, grp: &str) -> Result<Vec<(String, String)>> {
+    let rows = if grp.is_empty() {
+        sqlx::query("SELECT grp, key FROM kv_store ORDER BY grp, key")
+            .fetch_all(pool)
+            .await?
+    } else {
+        sqlx::query(
+            "SELECT grp, key FROM kv_store WHERE grp = ? OR (grp >= ? AND grp < ?) ORDER BY grp, key",
+        )
+        .bind(grp)
+        .bind(format!("{}.", grp))
+        .bind(format!("{}/", grp))
+        .fetch_all(pool)
+        .await?
+    };
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("grp"), row.get("key")))
+        .collect())
+}
+
+/// Counts the rows of a group ("" for the namespace root) and its nested groups.
+pub async fn count_nested(pool: &SqlitePool, grp: &str) -> Result<u64> {
+    let count: i64 = if grp.is_empty() {
+        sqlx::query_scalar("SELECT COUNT(*) FROM kv_store")
+            .fetch_one(pool)
+            .await?
+    } else {
+        sqlx::query_scalar("SELECT COUNT(*) FROM kv_store WHERE grp = ? OR (grp >= ? AND grp < ?)")
+            .bind(grp)
+            .bind(format!("{}.", grp))
+            .bind(format!("{}/", grp))
+            .fetch_one(pool)
+            .await?
+    };
+    Ok(count as u64)
+}
+
+/// A full stored row, as returned by bulk reads.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub grp: String,
+    pub key: String,
+    pub value: StoredValue,
+}
+
+/// Reads every row of a group ("" for the namespace root), optionally including nested groups,
+/// ordered by group and key.
+pub async fn entries(pool: &SqlitePool, grp: &str, nested: bool) -> Result<Vec<Entry>> {
+    let rows = match (grp.is_empty(), nested) {
+        (true, true) => {
+            sqlx::query("SELECT grp, key, value, external FROM kv_store ORDER BY grp, key")
+                .fetch_all(pool)
+                .await?
+        }
+        (false, true) => {
+            sqlx::query(
+                "SELECT grp, key, value, external FROM kv_store WHERE grp = ? OR (grp >= ? AND grp < ?) ORDER BY grp, key",
+            )
+            .bind(grp)
+            .bind(format!("{}.", grp))
+            .bind(format!("{}/", grp))
+            .fetch_all(pool)
+            .await?
+        }
+        (_, false) => {
+            sqlx::query(
+                "SELECT grp, key, value, external FROM kv_store WHERE grp = ? ORDER BY key",
+            )
+            .bind(grp)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    let entries = rows
+        .into_iter()
+        .map(|row| Entry {
+            grp: row.get("grp"),
+            key: row.get("key"),
+            value: StoredValue {
+                data: row.get("value"),
+                external: row.get("external"),
+            },
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Reads up to `limit` rows in `(grp, key)` order, starting after the given position.
+/// Used to page through a whole namespace without holding it in memory.
+pub async fn entries_after(
+    pool: &SqlitePool,
+    after: Option<(&str, &str)>,
+    limit: i64,
+) -> Result<Vec<Entry>> {
+    let rows = match after {
+        None => {
+            sqlx::query("SELECT grp, key, value, external FROM kv_store ORDER BY grp, key LIMIT ?")
+                .bind(limit)
+                .fetch_all(pool)
+                .await?
+        }
+        Some((grp, key)) => {
+            sqlx::query(
+                "SELECT grp, key, value, external FROM kv_store WHERE (grp, key) > (?, ?) ORDER BY grp, key LIMIT ?",
+            )
+            .bind(grp)
+            .bind(key)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+
+    let entries = rows
+        .into_iter()
+        .map(|row| Entry {
+            grp: row.get("grp"),
+            key: row.get("key"),
+            value: StoredValue {
+                data: row.get("value"),
+                external: row.get("external"),
+            },
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Like `entries_after`, limited to a group and its nested groups ("" for the whole namespace).
+pub async fn group_entries_after(
+    pool: &SqlitePool,
+    grp: &str,
+    after: Option<(&str, &str)>,
+    limit: i64,
+) -> Result<Vec<Entry>> {
+    if grp.is_empty() {
+        return entries_after(pool, after, limit).await;
+    }
+    let (after_grp, after_key) = after.unwrap_or(("", ""));
+    let rows = sqlx::query(
+        "SELECT grp, key, value, external FROM kv_store WHERE (grp = ? OR (grp >= ? AND grp < ?)) AND (grp, key) > (?, ?) ORDER BY grp, key LIMIT ?",
+    )
+    .bind(grp)
+    .bind(format!("{}.", grp))
+    .bind(format!("{}/", grp))
+    .bind(after_grp)
+    .bind(after_key)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    let entries = rows
+        .into_iter()
+        .map(|row| Entry {
+            grp: row.get("grp"),
+            key: row.get("key"),
+            value: StoredValue {
+                data: row.get("value"),
+                external: row.get("external"),
+            },
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Lists the blob file names referenced by a group and its nested groups ("" for the whole namespace).
+pub async fn external_refs(pool: &SqlitePool, grp: &str) -> Result<Vec<String>> {
+    let rows = if grp.is_empty() {
+        sqlx::query("SELECT value FROM kv_store WHERE external = 1")
+            .fetch_all(pool)
+            .await?
+    } else {
+        sqlx::query(GROUP_REFS_SQL)
+            .bind(grp)
+            .bind(format!("{}.", grp))
+            .bind(format!("{}/", grp))
+            .fetch_all(pool)
+            .await?
+    };
+
+    let refs = rows
+        .into_iter()
+        .map(|row| String::from_utf8_lossy(&row.get::<Vec<u8>, _>("value")).into_owned())
+        .collect();
+    Ok(refs)
+}
+
+/// Deletes a group together with all of its nested groups.
+pub async fn delete_group<'e, E>(executor: E, grp: &str) -> Result<u64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let result = sqlx::query(DELETE_GROUP_SQL)
+        .bind(grp)
+        .bind(format!("{}.", grp))
+        .bind(format!("{}/", grp))
+        .execute(executor)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn overwrite<'e, E>(
+    executor: E,
+    grp: &str,
+    key: &str,
+    value: &[u8],
+    external: bool,
+    updated_at: i64,
+) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(OVERWRITE_SQL)
+        .bind(grp)
         .bind(key)
         .bind(value)
-        .execute(pool)
+        .bind(external)
+        .bind(updated_at)
+        .execute(executor)
+        .await
+        .map_err(|e| unique_index_error(e.into()))?;
+    Ok(())
+}
+
+/// Replaces the stored payload of an existing row, keeping its `updated_at`: the value is
+/// re-encoded, not changed. A row deleted in the meantime stays deleted.
+pub async fn rewrite<'e, E>(
+    executor: E,
+    grp: &str,
+    key: &str,
+    value: &[u8],
+    external: bool,
+) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query("UPDATE kv_store SET value = ?, external = ? WHERE grp = ? AND key = ?")
+        .bind(value)
+        .bind(external)
+        .bind(grp)
+        .bind(key)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// `time` in Unix milliseconds, as stored in `updated_at`.
+pub fn unix_millis(time: std::time::SystemTime) -> i64 {
+    time.duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+/// A row written by `overwrite_many`.
+#[derive(Debug, Clone)]
+pub struct BulkRow {
+    pub grp: String,
+    pub key: String,
+    pub data: Vec<u8>,
+    pub external: bool,
+    /// Unix milliseconds.
+    pub updated_at: i64,
+}
+
+/// Inserts or replaces rows with a single multi-row statement. Each row binds five parameters;
+/// callers keep batches well under SQLite's limit of 32766.
+pub async fn overwrite_many<'e, E>(executor: E, rows: &[BulkRow]) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    if rows.is_empty() {
+        return Ok(());
+    }
+    let sql = format!(
+        "INSERT INTO kv_store (grp, key, value, external, updated_at) VALUES {} \
+         ON CONFLICT (grp, key) DO UPDATE SET value = excluded.value, external = excluded.external, updated_at = excluded.updated_at",
+        vec!["(?, ?, ?, ?, ?)"; rows.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for row in rows {
+        query = query
+            .bind(&row.grp)
+            .bind(&row.key)
+            .bind(&row.data)
+            .bind(row.external)
+            .bind(row.updated_at);
+    }
+    query
+        .execute(executor)
+        .await
+        .map_err(|e| unique_index_error(e.into()))?;
+    Ok(())
+}
+
+/// Blob names referenced by the stored rows of the given keys, i.e. the blobs `overwrite_many`
+/// would orphan.
+pub async fn external_refs_of<'e, E>(executor: E, rows: &[BulkRow]) -> Result<Vec<Vec<u8>>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    if rows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        "SELECT value FROM kv_store WHERE external = 1 AND (grp, key) IN (VALUES {})",
+        vec!["(?, ?)"; rows.len()].join(", ")
+    );
+    let mut query = sqlx::query_scalar(&sql);
+    for row in rows {
+        query = query.bind(&row.grp).bind(&row.key);
+    }
+    Ok(query.fetch_all(executor).await?)
+}
+
+/// The connection's `PRAGMA synchronous` level (0 = OFF, 1 = NORMAL, 2 = FULL, 3 = EXTRA).
+pub async fn synchronous(conn: &mut SqliteConnection) -> Result<i64> {
+    Ok(sqlx::query_scalar("PRAGMA synchronous")
+        .fetch_one(conn)
+        .await?)
+}
+
+pub async fn set_synchronous(conn: &mut SqliteConnection, level: i64) -> Result<()> {
+    sqlx::query(&format!("PRAGMA synchronous = {}", level))
+        .execute(conn)
         .await?;
     Ok(())
 }
@@ -91,14 +943,907 @@ pub async fn vacuum(pool: &SqlitePool) -> Result<()> {
     Ok(())
 }
 
-/// Lists all keys starting with a given prefix.
-pub async fn list_keys(pool: &SqlitePool, prefix: &str) -> Result<Vec<String>> {
-    let query_pattern = format!("{}%", prefix);
-    let rows = sqlx::query("SELECT key FROM kv_store WHERE key LIKE ?")
-        .bind(query_pattern)
+/// The database's total and free page counts.
+pub async fn page_counts(pool: &SqlitePool) -> Result<(u64, u64)> {
+    let pages: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(pool)
+        .await?;
+    let free: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+        .fetch_one(pool)
+        .await?;
+    Ok((pages as u64, free as u64))
+}
+
+/// Number of stored values, as kept in `kv_meta` by triggers.
+pub async fn value_count<'e, E>(executor: E) -> Result<u64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let count: Option<i64> =
+        sqlx::query_scalar("SELECT value FROM kv_meta WHERE name = 'value_count'")
+            .fetch_optional(executor)
+            .await?;
+    Ok(count.unwrap_or(0).max(0) as u64)
+}
+
+/// Bytes of the database's pages that are in use, i.e. excluding the freelist.
+pub async fn used_bytes<'e, E>(executor: E) -> Result<u64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let bytes: i64 = sqlx::query_scalar(
+        "SELECT (p.page_count - f.freelist_count) * s.page_size \
+         FROM pragma_page_count() p, pragma_freelist_count() f, pragma_page_size() s",
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(bytes.max(0) as u64)
+}
+
+/// Problems found by `PRAGMA quick_check` (`quick`) or `PRAGMA integrity_check`; empty when the
+/// database is intact.
+pub async fn integrity_errors(pool: &SqlitePool, quick: bool) -> Result<Vec<String>> {
+    let pragma = if quick {
+        "PRAGMA quick_check"
+    } else {
+        "PRAGMA integrity_check"
+    };
+    let lines: Vec<String> = sqlx::query_scalar(pragma).fetch_all(pool).await?;
+    Ok(lines.into_iter().filter(|line| line != "ok").collect())
+}
+
+/// Rebuilds every index of the database, e.g. after the collation's implementation changed.
+pub async fn reindex(pool: &SqlitePool) -> Result<()> {
+    sqlx::query("REINDEX").execute(pool).await?;
+    Ok(())
+}
+
+/// The schema version a database file is at, read without migrating it.
+pub async fn schema_version(conn: &mut SqliteConnection) -> Result<i64> {
+    Ok(sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(conn)
+        .await?)
+}
+
+/// Whether the file at `db_path` holds a kvmap store (a `kv_store` table, of any schema
+/// version), checked over a read-only connection that leaves the file as it is.
+pub async fn has_store(db_path: &Path) -> Result<bool> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(db_path)
+        .read_only(true)
+        .connect()
+        .await?;
+    let present = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'kv_store')",
+    )
+    .fetch_one(&mut conn)
+    .await?;
+    conn.close().await?;
+    Ok(present)
+}
+
+/// Up to `limit` stored values with a rowid above `after`, in rowid order, from a database at
+/// schema `version` that has not been migrated yet.
+pub async fn values_after_rowid(
+    conn: &mut SqliteConnection,
+    version: i64,
+    after: i64,
+    limit: i64,
+) -> Result<Vec<(i64, StoredValue)>> {
+    if version < 1 {
+        let columns: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM pragma_table_info('kv_store')")
+                .fetch_all(&mut *conn)
+                .await?;
+        if columns.is_empty() {
+            return Ok(Vec::new());
+        }
+    }
+    // Before v2 every value was stored inline.
+    let sql = if version < 2 {
+        "SELECT rowid, value, 0 AS external FROM kv_store WHERE rowid > ? ORDER BY rowid LIMIT ?"
+    } else {
+        "SELECT rowid, value, external FROM kv_store WHERE rowid > ? ORDER BY rowid LIMIT ?"
+    };
+    let rows = sqlx::query(sql)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(conn)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            (
+                row.get("rowid"),
+                StoredValue {
+                    data: row.get("value"),
+                    external: row.get("external"),
+                },
+            )
+        })
+        .collect())
+}
+
+/// Switches the closed database at `path` to a rollback journal, so it is a single file that
+/// can be opened without write access to its directory.
+pub async fn use_rollback_journal(path: &Path) -> Result<()> {
+    let conn = SqliteConnectOptions::new()
+        .filename(path)
+        .journal_mode(sqlx::sqlite::SqliteJournalMode::Delete)
+        .connect()
+        .await?;
+    conn.close().await?;
+    Ok(())
+}
+
+/// Writes a compacted copy of the database to a new file.
+pub async fn vacuum_into(pool: &SqlitePool, dest: &Path) -> Result<()> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(dest.to_string_lossy().into_owned())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Every row holding a blob reference, as `(grp, key, blob name)`.
+pub async fn external_rows(pool: &SqlitePool) -> Result<Vec<(String, String, String)>> {
+    let rows = sqlx::query("SELECT grp, key, value FROM kv_store WHERE external = 1")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let name: Vec<u8> = row.get("value");
+            (
+                row.get("grp"),
+                row.get("key"),
+                String::from_utf8_lossy(&name).into_owned(),
+            )
+        })
+        .collect())
+}
+
+/// Turns a copy of a namespace into a publishable dataset in one transaction: stores the
+/// `inlined` blob contents in their rows, drops the audit trail, change log and schedules, and
+/// stamps `published_at` (Unix milliseconds) into `kv_meta`.
+pub async fn seal_published(
+    pool: &SqlitePool,
+    inlined: &[(String, String, Vec<u8>)],
+    published_at: i64,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for (grp, key, data) in inlined {
+        sqlx::query("UPDATE kv_store SET value = ?, external = 0 WHERE grp = ? AND key = ?")
+            .bind(data)
+            .bind(grp)
+            .bind(key)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for table in ["kv_audit", "kv_changelog", "kv_schedule"] {
+        sqlx::query(&format!("DELETE FROM {}", table))
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("INSERT OR REPLACE INTO kv_meta (name, value) VALUES ('published_at', ?)")
+        .bind(published_at)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// When the database was sealed by `seal_published`, if it was.
+pub async fn published_at(pool: &SqlitePool) -> Result<Option<i64>> {
+    Ok(
+        sqlx::query_scalar("SELECT value FROM kv_meta WHERE name = 'published_at'")
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+/// Number of stored values.
+pub async fn count_values(pool: &SqlitePool) -> Result<u64> {
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM kv_store")
+        .fetch_one(pool)
+        .await?;
+    Ok(count as u64)
+}
+
+/// Counts the values of `grp` whose name is `prefix` followed by a single segment, i.e. the
+/// values directly below the key `prefix` names within the group.
+pub async fn count_direct_values<'e, E>(executor: E, grp: &str, prefix: &str) -> Result<u64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM kv_store WHERE grp = ?1 AND substr(key, 1, length(?2)) = ?2 \
+         AND instr(substr(key, length(?2) + 1), '.') = 0",
+    )
+    .bind(grp)
+    .bind(prefix)
+    .fetch_one(executor)
+    .await?;
+    Ok(count as u64)
+}
+
+/// Lists the value names stored directly in a group ("" for the namespace root).
+pub async fn list_values(pool: &SqlitePool, grp: &str) -> Result<Vec<String>> {
+    let rows = sqlx::query(LIST_VALUES_SQL)
+        .bind(grp)
         .fetch_all(pool)
         .await?;
 
     let keys = rows.into_iter().map(|row| row.get("key")).collect();
     Ok(keys)
 }
+
+/// Names of the direct subgroups of a group ("" for the root). Seeks from one subgroup to the
+/// next instead of reading every row below the group, so the cost grows with the number of
+/// subgroups rather than the number of values in them.
+pub async fn list_child_groups(pool: &SqlitePool, grp: &str) -> Result<Vec<String>> {
+    let (prefix, upper) = if grp.is_empty() {
+        (String::new(), None)
+    } else {
+        (format!("{}.", grp), Some(format!("{}/", grp)))
+    };
+    let mut groups = Vec::new();
+    // Ranges still to search, each as (lower bound, whether it is inclusive, upper bound).
+    let mut ranges = vec![(prefix.clone(), false, upper)];
+    while let Some((lower, inclusive, upper)) = ranges.pop() {
+        let Some(found) = first_group(pool, &lower, inclusive, upper.as_deref()).await? else {
+            continue;
+        };
+        // Slice rather than strip so case-insensitive collations still line up.
+        let sub_path = found.get(prefix.len()..).unwrap_or(&found);
+        let name = sub_path.split_once('.').map_or(sub_path, |(name, _)| name);
+        let child = format!("{}{}", prefix, name);
+        if !name.is_empty() {
+            groups.push(name.to_string());
+        }
+        // Skip everything nested in the child, `[child., child/)`. Groups sorting between
+        // `child` and `child.` (such as `child-2`) come before those from `child/` on.
+        ranges.push((format!("{}/", child), true, upper));
+        ranges.push((child.clone(), false, Some(format!("{}.", child))));
+    }
+    Ok(groups)
+}
+
+async fn first_group(
+    pool: &SqlitePool,
+    lower: &str,
+    inclusive: bool,
+    upper: Option<&str>,
+) -> Result<Option<String>> {
+    let sql = match (inclusive, upper.is_some()) {
+        (false, true) => NEXT_GROUP_SQL,
+        (false, false) => NEXT_ROOT_GROUP_SQL,
+        (true, true) => SKIP_GROUP_SQL,
+        (true, false) => SKIP_ROOT_GROUP_SQL,
+    };
+    let mut query = sqlx::query_scalar(sql).bind(lower);
+    if let Some(upper) = upper {
+        query = query.bind(upper);
+    }
+    Ok(query.fetch_optional(pool).await?)
+}
+
+/// The text `search` finds in a row: the strings of an inline value, joined by spaces.
+fn indexed_text(row: &str) -> String {
+    format!(
+        "(SELECT group_concat(value, ' ') FROM json_tree(CASE WHEN {row}.external THEN NULL ELSE CAST({row}.value AS TEXT) END) WHERE type = 'text')"
+    )
+}
+
+/// Creates or drops the FTS5 index of `search` and the triggers that keep it in sync with
+/// `kv_store`. `kv_fts_keys` gives each key a stable FTS row id (`kv_store` row ids change on
+/// `VACUUM`). Enabling indexes the values already stored.
+async fn configure_full_text(pool: &SqlitePool, enabled: bool) -> Result<()> {
+    if has_full_text(pool).await? == enabled {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    if enabled {
+        let new_text = indexed_text("NEW");
+        let statements = [
+            "CREATE TABLE kv_fts_keys (id INTEGER PRIMARY KEY, grp TEXT NOT NULL, key TEXT NOT NULL, UNIQUE (grp, key))".to_string(),
+            "CREATE VIRTUAL TABLE kv_fts USING fts5(body)".to_string(),
+            "INSERT INTO kv_fts_keys (grp, key) SELECT grp, key FROM kv_store".to_string(),
+            format!(
+                "INSERT INTO kv_fts (rowid, body) SELECT k.id, {} FROM kv_fts_keys k \
+                 JOIN kv_store s ON s.grp = k.grp AND s.key = k.key",
+                indexed_text("s")
+            ),
+            format!(
+                "CREATE TRIGGER kv_fts_insert AFTER INSERT ON kv_store BEGIN \
+                 INSERT INTO kv_fts_keys (grp, key) VALUES (NEW.grp, NEW.key); \
+                 INSERT INTO kv_fts (rowid, body) VALUES (last_insert_rowid(), {}); END",
+                new_text
+            ),
+            format!(
+                "CREATE TRIGGER kv_fts_update AFTER UPDATE ON kv_store BEGIN \
+                 UPDATE kv_fts SET body = {} WHERE rowid = \
+                 (SELECT id FROM kv_fts_keys WHERE grp = NEW.grp AND key = NEW.key); END",
+                new_text
+            ),
+            "CREATE TRIGGER kv_fts_delete AFTER DELETE ON kv_store BEGIN \
+             DELETE FROM kv_fts WHERE rowid = \
+             (SELECT id FROM kv_fts_keys WHERE grp = OLD.grp AND key = OLD.key); \
+             DELETE FROM kv_fts_keys WHERE grp = OLD.grp AND key = OLD.key; END"
+                .to_string(),
+        ];
+        for statement in statements {
+            sqlx::query(&statement).execute(&mut *tx).await?;
+        }
+    } else {
+        for statement in [
+            "DROP TRIGGER IF EXISTS kv_fts_insert",
+            "DROP TRIGGER IF EXISTS kv_fts_update",
+            "DROP TRIGGER IF EXISTS kv_fts_delete",
+            "DROP TABLE IF EXISTS kv_fts",
+            "DROP TABLE IF EXISTS kv_fts_keys",
+        ] {
+            sqlx::query(statement).execute(&mut *tx).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Whether the namespace has the full-text index of `search`.
+pub async fn has_full_text(pool: &SqlitePool) -> Result<bool> {
+    let installed = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'kv_fts')",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(installed)
+}
+
+/// The matches of an FTS5 `query`, best first, as (group, key, snippet) with the matched terms
+/// between `[` and `]`.
+pub async fn search(pool: &SqlitePool, query: &str) -> Result<Vec<(String, String, String)>> {
+    let rows = sqlx::query(
+        "SELECT k.grp, k.key, snippet(kv_fts, 0, '[', ']', '…', 12) AS snippet \
+         FROM kv_fts JOIN kv_fts_keys k ON k.id = kv_fts.rowid \
+         WHERE kv_fts MATCH ? ORDER BY rank",
+    )
+    .bind(query)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("grp"), row.get("key"), row.get("snippet")))
+        .collect())
+}
+
+/// Installs or removes the triggers that record every touched key in `kv_changelog`.
+/// Enabling records the current sequence as the floor: deltas from before it would be incomplete.
+async fn configure_changelog(pool: &SqlitePool, enabled: bool) -> Result<()> {
+    let installed: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'trigger' AND name = 'kv_changelog_insert')",
+    )
+    .fetch_one(pool)
+    .await?;
+    if installed == enabled {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    if enabled {
+        for (event, row) in [("INSERT", "NEW"), ("UPDATE", "NEW"), ("DELETE", "OLD")] {
+            sqlx::query(&format!(
+                "CREATE TRIGGER kv_changelog_{name} AFTER {event} ON kv_store BEGIN \
+                 INSERT INTO kv_changelog (grp, key) VALUES ({row}.grp, {row}.key); END",
+                name = event.to_lowercase(),
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+        let floor = changelog_seq(&mut *tx).await?;
+        sqlx::query("INSERT OR REPLACE INTO kv_meta (name, value) VALUES ('changelog_floor', ?)")
+            .bind(floor)
+            .execute(&mut *tx)
+            .await?;
+    } else {
+        for name in ["insert", "update", "delete"] {
+            sqlx::query(&format!("DROP TRIGGER IF EXISTS kv_changelog_{}", name))
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM kv_meta WHERE name = 'changelog_floor'")
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// A temporary trigger that tags the change log entries a connection writes with `origin`.
+fn origin_trigger_sql(origin: &str) -> String {
+    // Temporary triggers resolve unqualified names in `temp` first, then `main`.
+    format!(
+        "CREATE TEMP TRIGGER IF NOT EXISTS kv_changelog_origin AFTER INSERT ON main.kv_changelog \
+         BEGIN UPDATE kv_changelog SET origin = '{}' WHERE seq = NEW.seq; END",
+        origin.replace('\'', "''")
+    )
+}
+
+/// Whether the change log has the column in which entries record the instance that wrote them,
+/// added when a namespace with `external_changes` first opens the file.
+async fn has_changelog_origin(pool: &SqlitePool) -> Result<bool> {
+    let present = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('kv_changelog') WHERE name = 'origin')",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(present)
+}
+
+/// The highest sequence number handed out by the change log (0 if none yet).
+pub async fn changelog_seq<'e, E>(executor: E) -> Result<i64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let seq: Option<i64> =
+        sqlx::query_scalar("SELECT seq FROM sqlite_sequence WHERE name = 'kv_changelog'")
+            .fetch_optional(executor)
+            .await?;
+    Ok(seq.unwrap_or(0))
+}
+
+/// The lowest sequence a delta can start from, or `None` while the change log is disabled.
+pub async fn changelog_floor<'e, E>(executor: E) -> Result<Option<i64>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let floor = sqlx::query_scalar("SELECT value FROM kv_meta WHERE name = 'changelog_floor'")
+        .fetch_optional(executor)
+        .await?;
+    Ok(floor)
+}
+
+/// A key touched after some sequence, with its current row (`None` if it was deleted).
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// The sequence of the key's last change.
+    pub seq: i64,
+    pub grp: String,
+    pub key: String,
+    pub value: Option<StoredValue>,
+}
+
+fn change_from_row(row: sqlx::sqlite::SqliteRow) -> Change {
+    Change {
+        seq: row.get("seq"),
+        grp: row.get("grp"),
+        key: row.get("key"),
+        value: row
+            .get::<Option<Vec<u8>>, _>("value")
+            .map(|data| StoredValue {
+                data,
+                external: row.get::<Option<bool>, _>("external").unwrap_or(false),
+            }),
+    }
+}
+
+/// Lists every key touched after `since`, once each in the order of their last change, joined
+/// with its current value.
+pub async fn changes_since<'e, E>(executor: E, since: i64) -> Result<Vec<Change>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows = sqlx::query(
+        r#"
+        SELECT c.seq, c.grp, c.key, s.value, s.external
+        FROM (
+            SELECT grp, key, MAX(seq) AS seq FROM kv_changelog WHERE seq > ? GROUP BY grp, key
+        ) c
+        LEFT JOIN kv_store s ON s.grp = c.grp AND s.key = c.key
+        ORDER BY c.seq
+        "#,
+    )
+    .bind(since)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(change_from_row).collect())
+}
+
+/// Lists the keys touched in sequences `since` (exclusive) to `until` by anyone but `origin`, once
+/// each in the order of their last change, joined with their current value.
+pub async fn foreign_changes(
+    pool: &SqlitePool,
+    since: i64,
+    until: i64,
+    origin: &str,
+) -> Result<Vec<Change>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT c.seq, c.grp, c.key, s.value, s.external
+        FROM (
+            SELECT grp, key, MAX(seq) AS seq FROM kv_changelog
+            WHERE seq > ? AND seq <= ? AND origin IS NOT ?
+            GROUP BY grp, key
+        ) c
+        LEFT JOIN kv_store s ON s.grp = c.grp AND s.key = c.key
+        ORDER BY c.seq
+        "#,
+    )
+    .bind(since)
+    .bind(until)
+    .bind(origin)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(change_from_row).collect())
+}
+
+/// Drops change log entries up to and including `upto`, raising the floor accordingly.
+pub async fn trim_changelog(pool: &SqlitePool, upto: i64) -> Result<u64> {
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query("DELETE FROM kv_changelog WHERE seq <= ?")
+        .bind(upto)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE kv_meta SET value = MAX(value, ?) WHERE name = 'changelog_floor'")
+        .bind(upto)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Replaces the annotation of an existing key. Returns false if the key does not exist.
+pub async fn set_annotation(
+    pool: &SqlitePool,
+    grp: &str,
+    key: &str,
+    meta: &KeyMeta,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT OR REPLACE INTO kv_annotations (grp, key, description, owner) \
+         SELECT grp, key, ?, ? FROM kv_store WHERE grp = ? AND key = ?",
+    )
+    .bind(&meta.description)
+    .bind(&meta.owner)
+    .bind(grp)
+    .bind(key)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_annotation(pool: &SqlitePool, grp: &str, key: &str) -> Result<()> {
+    sqlx::query("DELETE FROM kv_annotations WHERE grp = ? AND key = ?")
+        .bind(grp)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn annotation(pool: &SqlitePool, grp: &str, key: &str) -> Result<Option<KeyMeta>> {
+    let row =
+        sqlx::query("SELECT description, owner FROM kv_annotations WHERE grp = ? AND key = ?")
+            .bind(grp)
+            .bind(key)
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.map(|r| KeyMeta {
+        description: r.get("description"),
+        owner: r.get("owner"),
+    }))
+}
+
+/// Annotations of the values directly in a group, keyed by value name.
+pub async fn group_annotations(pool: &SqlitePool, grp: &str) -> Result<Vec<(String, KeyMeta)>> {
+    let rows = sqlx::query("SELECT key, description, owner FROM kv_annotations WHERE grp = ?")
+        .bind(grp)
+        .fetch_all(pool)
+        .await?;
+    let annotations = rows
+        .into_iter()
+        .map(|row| {
+            let meta = KeyMeta {
+                description: row.get("description"),
+                owner: row.get("owner"),
+            };
+            (row.get("key"), meta)
+        })
+        .collect();
+    Ok(annotations)
+}
+
+/// A value directly in a group, as described by `group_entries`.
+#[derive(Debug, Clone)]
+pub struct EntryRow {
+    pub key: String,
+    /// Stored length: of the serialized value, or of the blob name for external rows.
+    pub size: u64,
+    /// The first byte of the serialized value, or the blob name for external rows.
+    pub head: Vec<u8>,
+    pub external: bool,
+    /// Unix milliseconds, if written since schema v7.
+    pub updated_at: Option<i64>,
+}
+
+/// The values directly in a group, with what a detailed listing needs but not their contents.
+pub async fn group_entries(pool: &SqlitePool, grp: &str) -> Result<Vec<EntryRow>> {
+    let rows = sqlx::query(
+        "SELECT key, length(value) AS size, \
+         CASE WHEN external = 1 THEN value ELSE substr(value, 1, 1) END AS head, \
+         external, updated_at FROM kv_store WHERE grp = ?",
+    )
+    .bind(grp)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| EntryRow {
+            key: row.get("key"),
+            size: row.get::<i64, _>("size") as u64,
+            head: row.get("head"),
+            external: row.get("external"),
+            updated_at: row.get("updated_at"),
+        })
+        .collect())
+}
+
+/// Schedules `action` on an existing key at `at`, replacing an earlier schedule of the same
+/// action. Returns false if the key does not exist.
+pub async fn schedule(
+    pool: &SqlitePool,
+    grp: &str,
+    key: &str,
+    action: &str,
+    at: i64,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "INSERT OR REPLACE INTO kv_schedule (grp, key, action, at) \
+         SELECT grp, key, ?, ? FROM kv_store WHERE grp = ? AND key = ?",
+    )
+    .bind(action)
+    .bind(at)
+    .bind(grp)
+    .bind(key)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Removes the schedule of `action` on a key; with `at`, only if it is still due then.
+pub async fn unschedule(
+    pool: &SqlitePool,
+    grp: &str,
+    key: &str,
+    action: &str,
+    at: Option<i64>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        "DELETE FROM kv_schedule WHERE grp = ? AND key = ? AND action = ? AND (? IS NULL OR at = ?)",
+    )
+    .bind(grp)
+    .bind(key)
+    .bind(action)
+    .bind(at)
+    .bind(at)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The scheduled `(action, at)` pairs of a key, earliest first.
+pub async fn schedules(pool: &SqlitePool, grp: &str, key: &str) -> Result<Vec<(String, i64)>> {
+    let rows =
+        sqlx::query("SELECT action, at FROM kv_schedule WHERE grp = ? AND key = ? ORDER BY at")
+            .bind(grp)
+            .bind(key)
+            .fetch_all(pool)
+            .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("action"), row.get("at")))
+        .collect())
+}
+
+/// A scheduled action that has come due.
+pub struct DueAction {
+    pub grp: String,
+    pub key: String,
+    pub action: String,
+    pub at: i64,
+}
+
+/// Up to `limit` actions due at `now`, earliest first.
+pub async fn due_actions<'e, E>(executor: E, now: i64, limit: i64) -> Result<Vec<DueAction>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows = sqlx::query(
+        "SELECT grp, key, action, at FROM kv_schedule WHERE at <= ? ORDER BY at LIMIT ?",
+    )
+    .bind(now)
+    .bind(limit)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| DueAction {
+            grp: row.get("grp"),
+            key: row.get("key"),
+            action: row.get("action"),
+            at: row.get("at"),
+        })
+        .collect())
+}
+
+/// One recorded mutation of the audit trail.
+pub struct AuditRow {
+    pub seq: i64,
+    pub at: i64,
+    pub actor: Option<String>,
+    pub op: String,
+    pub grp: String,
+    pub key: String,
+    pub old_hash: Option<String>,
+}
+
+pub async fn insert_audit<'e, E>(executor: E, row: &AuditRow) -> Result<()>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO kv_audit (at, actor, op, grp, key, old_hash) VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(row.at)
+    .bind(&row.actor)
+    .bind(&row.op)
+    .bind(&row.grp)
+    .bind(&row.key)
+    .bind(&row.old_hash)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Audit rows after `after_seq` within `since..until` (Unix milliseconds), oldest first. An empty
+/// `grp` matches every row; otherwise rows of the group and its nested groups match.
+pub async fn audit_rows(
+    pool: &SqlitePool,
+    grp: &str,
+    actor: Option<&str>,
+    since: i64,
+    until: i64,
+    after_seq: i64,
+    limit: i64,
+) -> Result<Vec<AuditRow>> {
+    let rows = sqlx::query(
+        "SELECT seq, at, actor, op, grp, key, old_hash FROM kv_audit \
+         WHERE seq > ? AND at >= ? AND at < ? AND (? IS NULL OR actor = ?) \
+         AND (? = '' OR grp = ? OR (grp >= ? AND grp < ?)) ORDER BY seq LIMIT ?",
+    )
+    .bind(after_seq)
+    .bind(since)
+    .bind(until)
+    .bind(actor)
+    .bind(actor)
+    .bind(grp)
+    .bind(grp)
+    .bind(format!("{}.", grp))
+    .bind(format!("{}/", grp))
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| AuditRow {
+            seq: row.get("seq"),
+            at: row.get("at"),
+            actor: row.get("actor"),
+            op: row.get("op"),
+            grp: row.get("grp"),
+            key: row.get("key"),
+            old_hash: row.get("old_hash"),
+        })
+        .collect())
+}
+
+/// Removes audit rows recorded before `before` (Unix milliseconds), returning how many.
+pub async fn trim_audit(pool: &SqlitePool, before: i64) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM kv_audit WHERE at < ?")
+        .bind(before)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+/// An operation whose SQL can be inspected with `explain`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplainOp {
+    Get,
+    Set,
+    Overwrite,
+    Delete,
+    DeleteGroup,
+    Exists,
+    List,
+}
+
+/// SQLite's query plan for one statement an operation runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlan {
+    pub sql: String,
+    /// `EXPLAIN QUERY PLAN` detail lines, e.g. `SEARCH kv_store USING INDEX ...`.
+    /// Plain inserts have no steps.
+    pub steps: Vec<String>,
+}
+
+impl QueryPlan {
+    /// Whether any step reads the whole table instead of searching an index.
+    pub fn is_full_scan(&self) -> bool {
+        self.steps.iter().any(|step| step.starts_with("SCAN"))
+    }
+}
+
+/// Returns the query plans of the statements `op` runs for `grp`/`key`. For group operations
+/// (`DeleteGroup`, `List`) `grp` is the group; for `Exists` it is the full dotted prefix.
+pub async fn explain(
+    pool: &SqlitePool,
+    op: ExplainOp,
+    grp: &str,
+    key: &str,
+) -> Result<Vec<QueryPlan>> {
+    let range = [grp.to_string(), format!("{}.", grp), format!("{}/", grp)];
+    let pair = [grp.to_string(), key.to_string()];
+    // `Set` and `Exists` also look for a group named like the full key.
+    let full_key = join_key(grp, key);
+    let group = [
+        full_key.clone(),
+        format!("{}.", full_key),
+        format!("{}/", full_key),
+    ];
+    let row = [
+        grp.to_string(),
+        key.to_string(),
+        String::new(),
+        "0".to_string(),
+        "0".to_string(),
+    ];
+    let statements: Vec<(&str, &[String])> = match op {
+        ExplainOp::Get => vec![(FIND_SQL, &pair)],
+        ExplainOp::Set => vec![
+            (EXISTS_VALUE_SQL, &pair),
+            (EXISTS_GROUP_SQL, &group),
+            (SET_SQL, &row),
+        ],
+        ExplainOp::Overwrite => vec![(FIND_SQL, &pair), (OVERWRITE_SQL, &row)],
+        ExplainOp::Delete => vec![(FIND_SQL, &pair), (DELETE_SQL, &pair)],
+        ExplainOp::DeleteGroup => vec![(GROUP_REFS_SQL, &range), (DELETE_GROUP_SQL, &range)],
+        ExplainOp::Exists => vec![(EXISTS_VALUE_SQL, &pair), (EXISTS_GROUP_SQL, &group)],
+        ExplainOp::List if grp.is_empty() => vec![
+            (LIST_VALUES_SQL, &range[..1]),
+            (NEXT_ROOT_GROUP_SQL, &range[..1]),
+            (SKIP_ROOT_GROUP_SQL, &range[..1]),
+        ],
+        ExplainOp::List => vec![
+            (LIST_VALUES_SQL, &range[..1]),
+            (NEXT_GROUP_SQL, &range[1..]),
+            (SKIP_GROUP_SQL, &range[1..]),
+        ],
+    };
+
+    let mut plans = Vec::with_capacity(statements.len());
+    for (sql, binds) in statements {
+        let explain_sql = format!("EXPLAIN QUERY PLAN {}", sql);
+        let mut query = sqlx::query(&explain_sql);
+        for bind in binds {
+            query = query.bind(bind);
+        }
+        let rows = query.fetch_all(pool).await?;
+        plans.push(QueryPlan {
+            sql: sql.to_string(),
+            steps: rows.into_iter().map(|row| row.get("detail")).collect(),
+        });
+    }
+    Ok(plans)
+}
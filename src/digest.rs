@@ -0,0 +1,16 @@
+/* src/digest.rs */
+
+//! Hex encoding and SHA-256 digests shared by the audit trail, duplicate detection, publishing
+//! and generated names.
+
+use sha2::{Digest, Sha256};
+
+/// Lower-case hex of `bytes`, two characters per byte.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Lower-case hex SHA-256 digest of `data`.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
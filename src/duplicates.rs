@@ -0,0 +1,70 @@
+/* src/duplicates.rs */
+
+//! Finding keys and values that several namespaces share, e.g. before consolidating them.
+
+use crate::Pathmap;
+use crate::digest;
+use crate::error::Result;
+use crate::iter::IterFilter;
+use futures_util::TryStreamExt;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// What makes two stored values duplicates for `find_duplicates`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateBy {
+    /// The same dotted key.
+    Key,
+    /// Byte-identical serialized values, compared by SHA-256.
+    ValueHash,
+}
+
+/// Values that share a key or a value across namespaces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate {
+    /// The shared key, or the hex SHA-256 of the shared value.
+    pub by: String,
+    /// Every `(ns, key)` holding it, sorted.
+    pub locations: Vec<(String, String)>,
+}
+
+impl Pathmap {
+    /// Reports the keys (`DuplicateBy::Key`) or values (`DuplicateBy::ValueHash`) that occur in
+    /// more than one of `namespaces` (every namespace when empty), sorted by what they share. A
+    /// value repeated within a single namespace only is not a duplicate, but once it crosses
+    /// namespaces every location is listed. Namespaces are streamed with `iter_all`; only keys
+    /// and hashes are kept in memory.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn find_duplicates(
+        &self,
+        namespaces: &[&str],
+        by: DuplicateBy,
+    ) -> Result<Vec<Duplicate>> {
+        let mut filter = IterFilter::new();
+        if !namespaces.is_empty() {
+            filter = filter.namespaces(namespaces.iter().copied());
+        }
+        let mut seen: BTreeMap<String, BTreeSet<(String, String)>> = BTreeMap::new();
+        let mut entries = self.iter_all(filter);
+        while let Some((ns, key, value)) = entries.try_next().await? {
+            let shared = match by {
+                DuplicateBy::Key => key.clone(),
+                DuplicateBy::ValueHash => digest::sha256_hex(&value),
+            };
+            seen.entry(shared).or_default().insert((ns, key));
+        }
+        Ok(seen
+            .into_iter()
+            .filter(|(_, locations)| {
+                let first = locations.first().map(|(ns, _)| ns);
+                locations.iter().any(|(ns, _)| Some(ns) != first)
+            })
+            .map(|(by, locations)| Duplicate {
+                by,
+                locations: locations.into_iter().collect(),
+            })
+            .collect())
+    }
+}
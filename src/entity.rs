@@ -0,0 +1,102 @@
+/* src/entity.rs */
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use crate::path::{escape_segment, unescape_segment};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// A struct stored as values of a fixed group, one per entity and named by its id, so
+/// application code does not assemble paths by hand. Usually derived (feature `derive`):
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, KvEntity)]
+/// #[kvmap(ns = "app", group = "users")]
+/// struct User {
+///     #[kvmap(id)]
+///     name: String,
+///     age: u32,
+/// }
+///
+/// User { name: "ann".into(), age: 30 }.save(&pm).await?; // app::users.ann
+/// let ann = User::load(&pm, "ann").await?;
+/// User::delete(&pm, "ann").await?;
+/// ```
+///
+/// Without `ns`, the group resolves against the default namespace (`with_default_ns`); `group`
+/// defaults to the struct name in snake case. A struct without an `#[kvmap(id)]` field is a
+/// single value stored at the group path itself, and its `load` and `delete` take no id.
+pub trait KvEntity: Serialize + DeserializeOwned {
+    /// Namespace of the entities, or `None` for the default namespace.
+    const NAMESPACE: Option<&'static str>;
+    /// Group holding the entities, or the key of a single entity.
+    const GROUP: &'static str;
+
+    /// Id of this entity, or `None` if the type has a single entity.
+    fn entity_id(&self) -> Option<String>;
+
+    /// Path of the group, or of the single entity.
+    fn group_path() -> String {
+        match Self::NAMESPACE {
+            Some(ns) => format!("{}::{}", ns, Self::GROUP),
+            None => Self::GROUP.to_string(),
+        }
+    }
+
+    /// Path of the entity with `id`, escaped so an id containing `.` stays one key segment.
+    fn entity_path(id: Option<&str>) -> String {
+        match id {
+            Some(id) => format!("{}.{}", Self::group_path(), escape_segment(id)),
+            None => Self::group_path(),
+        }
+    }
+}
+
+impl Pathmap {
+    /// Reads the entity with `id` (`None` for a type with a single entity).
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn load_entity<E: KvEntity>(&self, id: Option<&str>) -> Result<E> {
+        self.get(&E::entity_path(id)).await
+    }
+
+    /// Writes an entity, replacing any stored under the same id.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn save_entity<E: KvEntity>(&self, entity: &E) -> Result<()> {
+        let path = E::entity_path(entity.entity_id().as_deref());
+        self.overwrite(&path, entity).await?;
+        Ok(())
+    }
+
+    /// Deletes the entity with `id` (`None` for a type with a single entity).
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn delete_entity<E: KvEntity>(&self, id: Option<&str>) -> Result<()> {
+        self.delete(&E::entity_path(id)).await?;
+        Ok(())
+    }
+
+    /// Ids of the stored entities of `E`, unescaped; empty if its namespace does not exist.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn entity_ids<E: KvEntity>(&self) -> Result<Vec<String>> {
+        match self.list(&E::group_path()).await {
+            Ok(listing) => Ok(listing
+                .values
+                .iter()
+                .map(|name| unescape_segment(name).into_owned())
+                .collect()),
+            Err(e) if matches!(e.root(), PathmapError::NamespaceNotFound(_)) => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+}
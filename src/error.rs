@@ -1,7 +1,27 @@
 /* src/error.rs */
 
+use std::fmt;
 use thiserror::Error;
 
+/// Where an error happened: the public operation and the path it was called with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// The operation, named after its method, e.g. `get` or `import_bulk`.
+    pub operation: &'static str,
+    pub ns: String,
+    /// The dotted key or group of the path, if it named one.
+    pub key: Option<String>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{} '{}::{}'", self.operation, self.ns, key),
+            None => write!(f, "{} '{}'", self.operation, self.ns),
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum PathmapError {
     #[error("Namespace '{0}' already exists")]
@@ -25,6 +45,68 @@ pub enum PathmapError {
     #[error("Invalid path format: {0}")]
     InvalidPath(String),
 
+    #[error("Store at '{0}' is locked by another instance")]
+    StoreLocked(String),
+
+    #[error("Base path '{0}' {1}")]
+    BasePathUnavailable(String, String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Backup error: {0}")]
+    BackupError(String),
+
+    #[error("Published dataset error: {0}")]
+    PublishError(String),
+
+    #[error("Write queue is full ({0} pending mutations)")]
+    WriteQueueFull(usize),
+
+    #[error("Remote error: {0}")]
+    RemoteError(String),
+
+    #[error("Batched write failed: {0}")]
+    BatchFailed(String),
+
+    #[error("Pathmap has been closed")]
+    Closed,
+
+    #[error("Write rate limit exceeded for group '{0}'")]
+    Throttled(String),
+
+    #[error("Quota exceeded for namespace '{0}': {1}")]
+    QuotaExceeded(String, String),
+
+    #[error("Another value in group '{0}' has the same '{1}', which must be unique")]
+    UniqueViolation(String, String),
+
+    /// A `search` query SQLite's FTS5 could not parse, with its message.
+    #[error("Invalid search query '{0}': {1}")]
+    InvalidQuery(String, String),
+
+    /// A `transaction_multi` that committed in some namespaces but not in others. Commits run
+    /// one namespace at a time once all of them accepted the writes, so this only happens when
+    /// a commit itself fails (e.g. the disk fills or the process loses its lock) after an
+    /// earlier one succeeded: `committed` kept their writes, `rolled_back` did not, and the
+    /// caller has to reconcile them. Any failure before the first commit rolls back every
+    /// namespace and is returned as it is.
+    #[error(
+        "Transaction committed in {committed:?} but was rolled back in {rolled_back:?}: {source}"
+    )]
+    PartialCommit {
+        committed: Vec<String>,
+        rolled_back: Vec<String>,
+        source: Box<PathmapError>,
+    },
+
+    #[error("Timed out waiting for '{0}'")]
+    WaitTimedOut(String),
+
+    #[error("Database is busy or locked (gave up after {0} attempts)")]
+    Busy(u32),
+
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 
@@ -33,6 +115,79 @@ pub enum PathmapError {
 
     #[error("JSON serialization/deserialization error: {0}")]
     JsonError(#[from] serde_json::Error),
+
+    /// Any of the other errors, raised by the operation described by `context`.
+    #[error("{context}: {source}")]
+    WithContext {
+        context: ErrorContext,
+        source: Box<PathmapError>,
+    },
+}
+
+impl PathmapError {
+    /// Attaches the operation `operation` on `path` (`ns::key`, or a bare namespace). An error
+    /// that already has context keeps it, as it names the innermost operation that failed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn with_context(self, operation: &'static str, path: &str) -> Self {
+        if let PathmapError::WithContext { .. } = self {
+            return self;
+        }
+        let (ns, key) = path.split_once("::").unwrap_or((path, ""));
+        PathmapError::WithContext {
+            context: ErrorContext {
+                operation,
+                ns: ns.to_string(),
+                key: (!key.is_empty()).then(|| key.to_string()),
+            },
+            source: Box::new(self),
+        }
+    }
+
+    /// The error without its context, to match on its kind.
+    pub fn root(&self) -> &PathmapError {
+        match self {
+            PathmapError::WithContext { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// Consumes the error, dropping its context.
+    pub fn into_root(self) -> PathmapError {
+        match self {
+            PathmapError::WithContext { source, .. } => source.into_root(),
+            e => e,
+        }
+    }
+
+    /// The operation and path the error was raised by, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            PathmapError::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    pub fn operation(&self) -> Option<&'static str> {
+        self.context().map(|c| c.operation)
+    }
+
+    pub fn namespace(&self) -> Option<&str> {
+        self.context().map(|c| c.ns.as_str())
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.context().and_then(|c| c.key.as_deref())
+    }
 }
 
 pub type Result<T> = std::result::Result<T, PathmapError>;
+
+/// Runs the public operation `op` on `path`, attaching both to its error as context.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn in_context<T>(
+    op: &'static str,
+    path: &str,
+    operation: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    operation.await.map_err(|e| e.with_context(op, path))
+}
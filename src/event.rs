@@ -0,0 +1,252 @@
+/* src/event.rs */
+
+//! The event model shared by everything that reports mutations (and storage alerts): callbacks
+//! registered with `with_hook` and receivers from `subscribe`.
+
+use crate::error::{PathmapError, Result};
+use crate::usage::StorageAlert;
+use crate::{db, rt};
+use futures_util::future::{self, Either};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Events a `subscribe` receiver may fall behind by before it starts missing them.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+tokio::task_local! {
+    /// Who the current task is acting for, set by `as_actor`.
+    static ACTOR: String;
+}
+
+/// The kind of mutation an event reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Operation {
+    /// A new value was created with `set`.
+    Set,
+    /// A value was created or replaced with `overwrite` (or a batched or queued write).
+    Overwrite,
+    Delete,
+    /// A group and everything below it was deleted; `path` names the group.
+    DeleteGroup,
+    /// A value was removed because it expired.
+    Expire,
+    /// A scheduled `Action::Publish` came due; the value was not changed.
+    Publish,
+    /// Values were loaded in bulk; `path` names the namespace (`ns::`).
+    Import,
+    /// The namespace's contents were replaced from a backup; `path` names the namespace.
+    Restore,
+    /// The namespace's storage crossed a configured threshold; `path` names the namespace and
+    /// `alert` holds the measurement.
+    StorageAlert,
+}
+
+/// One mutation, reported after it was committed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    pub op: Operation,
+    /// The affected path, e.g. `ns::group.key`.
+    pub path: String,
+    /// Size in bytes of the value before the mutation, if there was one.
+    pub old_size: Option<u64>,
+    /// Size in bytes of the value after the mutation, if there is one.
+    pub new_size: Option<u64>,
+    /// Position of the event among all events of this instance, starting at 1.
+    pub version: u64,
+    /// Who the mutation was made for, as set with `as_actor`.
+    pub actor: Option<String>,
+    /// Set for `StorageAlert` events.
+    pub alert: Option<StorageAlert>,
+    /// Whether another process made the mutation, as found by `start_change_polling`. Such
+    /// events report the latest state of the key: `Overwrite` or `Delete`, without `old_size`.
+    #[serde(default)]
+    pub remote: bool,
+}
+
+/// Callback invoked for every committed mutation.
+pub type Hook = Arc<dyn Fn(&ChangeEvent) + Send + Sync>;
+
+/// The actor set with `as_actor` for the current task, if any.
+pub(crate) fn current_actor() -> Option<String> {
+    ACTOR.try_with(Clone::clone).ok()
+}
+
+/// Runs `future` on behalf of `actor`, which is recorded in the events of the mutations it makes.
+/// Work spawned onto other tasks (e.g. batches committed by their timer) is not covered.
+pub async fn as_actor<F: Future>(actor: &str, future: F) -> F::Output {
+    ACTOR.scope(actor.to_string(), future).await
+}
+
+pub(crate) struct Events {
+    hooks: RwLock<Vec<Hook>>,
+    sender: broadcast::Sender<ChangeEvent>,
+    version: AtomicU64,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            hooks: RwLock::new(Vec::new()),
+            sender: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+            version: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Events {
+    pub(crate) fn add_hook(&self, hook: Hook) {
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Whether anyone listens, so writers can skip the work of describing their changes.
+    pub(crate) fn is_active(&self) -> bool {
+        self.sender.receiver_count() > 0 || !self.hooks.read().unwrap().is_empty()
+    }
+
+    pub(crate) fn emit(
+        &self,
+        op: Operation,
+        path: String,
+        old_size: Option<u64>,
+        new_size: Option<u64>,
+    ) {
+        self.send(op, path, old_size, new_size, None, false);
+    }
+
+    /// Reports a mutation another process made.
+    pub(crate) fn emit_remote(&self, op: Operation, path: String, new_size: Option<u64>) {
+        self.send(op, path, None, new_size, None, true);
+    }
+
+    pub(crate) fn emit_alert(&self, path: String, alert: StorageAlert) {
+        self.send(
+            Operation::StorageAlert,
+            path,
+            None,
+            None,
+            Some(alert),
+            false,
+        );
+    }
+
+    fn send(
+        &self,
+        op: Operation,
+        path: String,
+        old_size: Option<u64>,
+        new_size: Option<u64>,
+        alert: Option<StorageAlert>,
+        remote: bool,
+    ) {
+        if !self.is_active() {
+            return;
+        }
+        let event = ChangeEvent {
+            op,
+            path,
+            old_size,
+            new_size,
+            version: self.version.fetch_add(1, Ordering::Relaxed) + 1,
+            actor: current_actor(),
+            alert,
+            remote,
+        };
+        for hook in self.hooks.read().unwrap().iter() {
+            hook(&event);
+        }
+        // Sending only fails when nobody subscribed.
+        let _ = self.sender.send(event);
+    }
+}
+
+impl crate::Pathmap {
+    /// Calls `hook` for every mutation made through this instance, right after it is committed.
+    /// The hook runs on the writing task, so it should hand slow work off elsewhere.
+    pub fn with_hook<F>(self, hook: F) -> Self
+    where
+        F: Fn(&ChangeEvent) + Send + Sync + 'static,
+    {
+        self.events.add_hook(Arc::new(hook));
+        self
+    }
+
+    /// Receives every mutation made through this instance from now on, and those of other
+    /// processes while `start_change_polling` runs. A receiver that falls more than 1024 events
+    /// behind skips the oldest ones and is told how many it missed.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.events.subscribe()
+    }
+
+    /// Returns the value at `path`, waiting up to `timeout` for it to be written if it does not
+    /// exist yet (`WaitTimedOut` after that), for handshakes where one task waits for a value
+    /// another produces. Writes made through this instance wake the waiter, and those of other
+    /// processes once `start_change_polling` reports them; it does not poll the value itself.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn get_wait<T: DeserializeOwned>(&self, path: &str, timeout: Duration) -> Result<T> {
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        let watched = format!("{}::{}", ns, db::join_key(grp, key));
+        // Subscribe before the first read, so a write between the two is not missed.
+        let mut events = self.subscribe();
+        let missing = |e: &PathmapError| {
+            matches!(
+                e.root(),
+                PathmapError::ValueNotFound(_) | PathmapError::NamespaceNotFound(_)
+            )
+        };
+        match self.get(path).await {
+            Err(e) if missing(&e) => {}
+            result => return result,
+        }
+        let wait = async {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.path == watched => {}
+                    // Bulk loads report the namespace rather than each key.
+                    Ok(event)
+                        if matches!(event.op, Operation::Import | Operation::Restore)
+                            && event.path.trim_end_matches("::") == ns => {}
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => return Err(PathmapError::Closed),
+                }
+                match self.get(path).await {
+                    Err(e) if missing(&e) => {}
+                    result => return result,
+                }
+            }
+        };
+        let wait = std::pin::pin!(wait);
+        let expired = std::pin::pin!(rt::sleep(timeout));
+        match future::select(wait, expired).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(PathmapError::WaitTimedOut(watched.clone())),
+        }
+    }
+
+    /// Reports a mutation of `ns::key`, where `key` is the full dotted key or group path.
+    pub(crate) fn emit_change(
+        &self,
+        op: Operation,
+        ns: &str,
+        key: &str,
+        old_size: Option<u64>,
+        new_size: Option<u64>,
+    ) {
+        if self.events.is_active() {
+            self.events
+                .emit(op, format!("{}::{}", ns, key), old_size, new_size);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+/* src/export.rs */
+
+use crate::Pathmap;
+use crate::db;
+use crate::error::{self, PathmapError, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+impl Pathmap {
+    /// Dumps a whole namespace as a pretty-printed, nested JSON document.
+    /// Groups become objects and values are embedded as-is, with keys in sorted order so
+    /// exports diff cleanly under version control.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn export_ns<W: AsyncWrite + Unpin>(&self, ns: &str, writer: &mut W) -> Result<()> {
+        error::in_context("export_ns", ns, async {
+            let document = self.in_background(ns, self.export_value(ns, "")).await?;
+            let bytes = serde_json::to_vec_pretty(&document)?;
+            writer.write_all(&bytes).await?;
+            writer.flush().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Writes the values stored directly in a group as CSV, one row per value.
+    /// The first column holds the value name; each top-level field of an object value becomes a
+    /// column (sorted by name), and non-object values go into a `value` column. Nested objects and
+    /// arrays are written as JSON text, `null` and missing fields as empty cells.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn export_csv<W: AsyncWrite + Unpin>(
+        &self,
+        path: &str,
+        writer: &mut W,
+    ) -> Result<()> {
+        let (ns, grp) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        let entries = self
+            .in_background(ns, db::entries(&pool, &grp, false))
+            .await?;
+        let mut rows = Vec::new();
+        for entry in entries {
+            let raw_value = self.load_value(ns, entry.value).await?;
+            let value: Value = serde_json::from_slice(&raw_value)?;
+            let fields = match value {
+                Value::Object(map) => map,
+                other => Map::from_iter([("value".to_string(), other)]),
+            };
+            rows.push((entry.key, fields));
+        }
+
+        let columns: BTreeSet<&str> = rows
+            .iter()
+            .flat_map(|(_, fields)| fields.keys().map(String::as_str))
+            .collect();
+        // Keep the name column distinct from a field that happens to be called `key`.
+        let key_column = if columns.contains("key") {
+            "_key"
+        } else {
+            "key"
+        };
+
+        let mut out = String::new();
+        write_csv_row(
+            &mut out,
+            std::iter::once(key_column.to_string()).chain(columns.iter().map(|c| c.to_string())),
+        );
+        for (key, fields) in &rows {
+            let cells = columns.iter().map(|column| match fields.get(*column) {
+                None | Some(Value::Null) => String::new(),
+                Some(Value::String(text)) => text.clone(),
+                Some(other) => other.to_string(),
+            });
+            write_csv_row(&mut out, std::iter::once(key.clone()).chain(cells));
+        }
+        writer.write_all(out.as_bytes()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Builds the nested JSON object for a group ("" for the namespace root).
+    pub(crate) async fn export_value(&self, ns: &str, grp: &str) -> Result<Value> {
+        let pool = self.get_pool(ns).await?;
+        let mut root = Map::new();
+        for entry in db::entries(&pool, grp, true).await? {
+            let raw_value = self.load_value(ns, entry.value).await?;
+            let value: Value = serde_json::from_slice(&raw_value)?;
+
+            let relative = if grp.is_empty() {
+                entry.grp.as_str()
+            } else {
+                entry
+                    .grp
+                    .get(grp.len()..)
+                    .unwrap_or("")
+                    .trim_start_matches('.')
+            };
+            let mut node = &mut root;
+            if !relative.is_empty() {
+                for segment in relative.split('.') {
+                    let child = node
+                        .entry(segment.to_string())
+                        .or_insert_with(|| Value::Object(Map::new()));
+                    node = match child {
+                        Value::Object(map) => map,
+                        _ => return Err(conflict(ns, &entry.grp)),
+                    };
+                }
+            }
+            if node.insert(entry.key.clone(), value).is_some() {
+                return Err(conflict(ns, &db::join_key(&entry.grp, &entry.key)));
+            }
+        }
+        Ok(Value::Object(root))
+    }
+}
+
+/// A name used both as a value and as a group cannot be represented in a single JSON object.
+fn conflict(ns: &str, key: &str) -> PathmapError {
+    PathmapError::InvalidPath(format!(
+        "{}::{} is both a value and a group and cannot be exported",
+        ns, key
+    ))
+}
+
+/// Appends one CSV record, quoting cells that contain separators, quotes or line breaks.
+fn write_csv_row(out: &mut String, cells: impl Iterator<Item = String>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if cell.contains([',', '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&cell.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(&cell);
+        }
+    }
+    out.push_str("\r\n");
+}
@@ -0,0 +1,264 @@
+/* src/ffi.rs */
+
+//! C ABI over the blocking API, declared in `include/kvmap.h`. Only available with the `ffi`
+//! feature; build a C library with `cargo rustc --release --features ffi --crate-type cdylib`
+//! (or `staticlib`).
+//!
+//! Values cross the boundary as UTF-8 JSON byte buffers, so C and C++ callers read and write the
+//! same store format as Rust callers. Every call returns a [`KvmapStatus`]; on failure
+//! `kvmap_last_error` describes the error on the calling thread.
+
+use crate::blocking::Pathmap;
+use crate::error::PathmapError;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+
+/// Result of every `kvmap_*` call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KvmapStatus {
+    Ok = 0,
+    NotFound = 1,
+    AlreadyExists = 2,
+    InvalidArgument = 3,
+    Error = 4,
+}
+
+/// Opaque store handle owned by the caller.
+pub struct KvmapHandle {
+    pm: Pathmap,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn fail(status: KvmapStatus, message: String) -> KvmapStatus {
+    set_last_error(message);
+    status
+}
+
+fn status_of(err: PathmapError) -> KvmapStatus {
+    let status = match err.root() {
+        PathmapError::NamespaceNotFound(_)
+        | PathmapError::GroupNotFound(..)
+        | PathmapError::ValueNotFound(_) => KvmapStatus::NotFound,
+        PathmapError::NamespaceAlreadyExists(_)
+        | PathmapError::GroupAlreadyExists(..)
+        | PathmapError::ValueAlreadyExists(_) => KvmapStatus::AlreadyExists,
+        PathmapError::InvalidPath(_)
+        | PathmapError::InvalidQuery(..)
+        | PathmapError::JsonError(_) => KvmapStatus::InvalidArgument,
+        _ => KvmapStatus::Error,
+    };
+    fail(status, err.to_string())
+}
+
+/// Runs `body`, turning panics into `KvmapStatus::Error` so they never unwind into C.
+fn guard<F: FnOnce() -> KvmapStatus>(body: F) -> KvmapStatus {
+    catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|_| fail(KvmapStatus::Error, "panic inside kvmap".to_string()))
+}
+
+/// Reads a NUL-terminated UTF-8 string argument.
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, KvmapStatus> {
+    if ptr.is_null() {
+        return Err(fail(
+            KvmapStatus::InvalidArgument,
+            format!("{} is null", name),
+        ));
+    }
+    // SAFETY: guaranteed by the caller.
+    unsafe { CStr::from_ptr(ptr) }.to_str().map_err(|_| {
+        fail(
+            KvmapStatus::InvalidArgument,
+            format!("{} is not valid UTF-8", name),
+        )
+    })
+}
+
+/// Creates a store rooted at `base_path` and writes its handle to `out`.
+///
+/// # Safety
+/// `base_path` must be a NUL-terminated string and `out` a valid pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kvmap_open(
+    base_path: *const c_char,
+    out: *mut *mut KvmapHandle,
+) -> KvmapStatus {
+    guard(|| {
+        if out.is_null() {
+            return fail(KvmapStatus::InvalidArgument, "out is null".to_string());
+        }
+        // SAFETY: guaranteed by the caller.
+        let base_path = match unsafe { str_arg(base_path, "base_path") } {
+            Ok(path) => path,
+            Err(status) => return status,
+        };
+        let pm = match Pathmap::from_async(crate::Pathmap::new().with_base_path(base_path)) {
+            Ok(pm) => pm,
+            Err(e) => return status_of(e),
+        };
+        // SAFETY: `out` is non-null and valid per the caller.
+        unsafe { *out = Box::into_raw(Box::new(KvmapHandle { pm })) };
+        KvmapStatus::Ok
+    })
+}
+
+/// Closes a store opened with `kvmap_open`, committing pending writes first. Passing null is a
+/// no-op.
+///
+/// # Safety
+/// `handle` must come from `kvmap_open` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kvmap_close(handle: *mut KvmapHandle) {
+    if !handle.is_null() {
+        // SAFETY: the handle was created by `Box::into_raw` in `kvmap_open`.
+        let handle = unsafe { Box::from_raw(handle) };
+        let _ = catch_unwind(AssertUnwindSafe(|| {
+            // There is nobody left to report a failed flush to.
+            let _ = handle.pm.close();
+            drop(handle);
+        }));
+    }
+}
+
+/// Reads the JSON value at `path` into a new buffer, released with `kvmap_buffer_free`.
+///
+/// # Safety
+/// `handle` must be live, `path` NUL-terminated, `out_data` and `out_len` valid pointers.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kvmap_get(
+    handle: *const KvmapHandle,
+    path: *const c_char,
+    out_data: *mut *mut u8,
+    out_len: *mut usize,
+) -> KvmapStatus {
+    guard(|| {
+        if handle.is_null() || out_data.is_null() || out_len.is_null() {
+            return fail(KvmapStatus::InvalidArgument, "null argument".to_string());
+        }
+        // SAFETY: guaranteed by the caller.
+        let (handle, path) = match unsafe { (&*handle, str_arg(path, "path")) } {
+            (handle, Ok(path)) => (handle, path),
+            (_, Err(status)) => return status,
+        };
+        let value: Value = match handle.pm.get(path) {
+            Ok(value) => value,
+            Err(e) => return status_of(e),
+        };
+        let bytes = match serde_json::to_vec(&value) {
+            Ok(bytes) => bytes.into_boxed_slice(),
+            Err(e) => return status_of(e.into()),
+        };
+        // SAFETY: both pointers are non-null and valid per the caller.
+        unsafe {
+            *out_len = bytes.len();
+            *out_data = Box::into_raw(bytes).cast::<u8>();
+        }
+        KvmapStatus::Ok
+    })
+}
+
+/// Stores `len` bytes of JSON at `path`. With `overwrite` set an existing value is replaced,
+/// otherwise the call fails with `KVMAP_ALREADY_EXISTS`.
+///
+/// # Safety
+/// `handle` must be live, `path` NUL-terminated and `data` valid for `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kvmap_set(
+    handle: *const KvmapHandle,
+    path: *const c_char,
+    data: *const u8,
+    len: usize,
+    overwrite: bool,
+) -> KvmapStatus {
+    guard(|| {
+        if handle.is_null() || (data.is_null() && len > 0) {
+            return fail(KvmapStatus::InvalidArgument, "null argument".to_string());
+        }
+        // SAFETY: guaranteed by the caller.
+        let (handle, path) = match unsafe { (&*handle, str_arg(path, "path")) } {
+            (handle, Ok(path)) => (handle, path),
+            (_, Err(status)) => return status,
+        };
+        let bytes = if len == 0 {
+            &[][..]
+        } else {
+            // SAFETY: `data` is valid for `len` bytes per the caller.
+            unsafe { std::slice::from_raw_parts(data, len) }
+        };
+        let value: Value = match serde_json::from_slice(bytes) {
+            Ok(value) => value,
+            Err(e) => return status_of(e.into()),
+        };
+        let result = if overwrite {
+            handle.pm.overwrite(path, value).map(drop)
+        } else {
+            handle.pm.set(path, value)
+        };
+        match result {
+            Ok(()) => KvmapStatus::Ok,
+            Err(e) => status_of(e),
+        }
+    })
+}
+
+/// Deletes the value at `path`.
+///
+/// # Safety
+/// `handle` must be live and `path` NUL-terminated.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kvmap_delete(
+    handle: *const KvmapHandle,
+    path: *const c_char,
+) -> KvmapStatus {
+    guard(|| {
+        if handle.is_null() {
+            return fail(KvmapStatus::InvalidArgument, "handle is null".to_string());
+        }
+        // SAFETY: guaranteed by the caller.
+        let (handle, path) = match unsafe { (&*handle, str_arg(path, "path")) } {
+            (handle, Ok(path)) => (handle, path),
+            (_, Err(status)) => return status,
+        };
+        match handle.pm.delete(path) {
+            Ok(_) => KvmapStatus::Ok,
+            Err(e) => status_of(e),
+        }
+    })
+}
+
+/// Releases a buffer returned by `kvmap_get`. Passing null is a no-op.
+///
+/// # Safety
+/// `data` and `len` must be exactly what `kvmap_get` returned.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn kvmap_buffer_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        // SAFETY: the buffer was created from a boxed slice of `len` bytes in `kvmap_get`.
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
+    }
+}
+
+/// The message of the last failed call on this thread, or null. The pointer stays valid until the
+/// next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn kvmap_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
@@ -0,0 +1,256 @@
+/* src/grpc.rs */
+
+//! gRPC access to a `Pathmap`, generated from `proto/kvmap.proto`.
+//! Only available with the `grpc` feature; building it requires `protoc`.
+//! Other languages can generate their own clients from the same proto file; Rust callers can use
+//! [`proto::kvmap_client::KvmapClient`].
+
+use crate::error::{PathmapError, Result};
+use crate::replica::{Primary, PrimaryFuture};
+use crate::{Pathmap, WriteOutcome};
+use futures_util::stream::{self, Stream};
+use proto::kvmap_client::KvmapClient;
+use proto::kvmap_server::{Kvmap, KvmapServer};
+use proto::{
+    DeleteRequest, DeleteResponse, GetRequest, GetResponse, ListRequest, ListResponse, SetRequest,
+    SetResponse, WatchEvent, WatchRequest,
+};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response, Status};
+
+/// Generated message types, server trait and client.
+pub mod proto {
+    tonic::include_proto!("kvmap");
+}
+
+/// How often a `Watch` stream polls the change log for new entries.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Serves the gRPC API on `addr` until the process stops.
+pub async fn serve_grpc(pm: Arc<Pathmap>, addr: SocketAddr) -> Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(service(pm))
+        .serve(addr)
+        .await
+        .map_err(std::io::Error::other)?;
+    Ok(())
+}
+
+/// The gRPC service, for adding to an existing tonic server.
+pub fn service(pm: Arc<Pathmap>) -> KvmapServer<KvmapService> {
+    KvmapServer::new(KvmapService { pm })
+}
+
+/// Implementation of the `Kvmap` service over a shared `Pathmap`.
+#[derive(Clone)]
+pub struct KvmapService {
+    pm: Arc<Pathmap>,
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = std::result::Result<WatchEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Kvmap for KvmapService {
+    async fn get(
+        &self,
+        request: Request<GetRequest>,
+    ) -> std::result::Result<Response<GetResponse>, Status> {
+        let value: Value = self.pm.get(&request.get_ref().path).await.map_err(status)?;
+        Ok(Response::new(GetResponse {
+            json: value.to_string(),
+        }))
+    }
+
+    async fn set(
+        &self,
+        request: Request<SetRequest>,
+    ) -> std::result::Result<Response<SetResponse>, Status> {
+        let request = request.into_inner();
+        let value: Value = serde_json::from_str(&request.json)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let replaced = if request.overwrite {
+            let outcome = self.pm.overwrite(&request.path, value).await;
+            outcome.map_err(status)? == WriteOutcome::Replaced
+        } else {
+            self.pm.set(&request.path, value).await.map_err(status)?;
+            false
+        };
+        Ok(Response::new(SetResponse { replaced }))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> std::result::Result<Response<DeleteResponse>, Status> {
+        let request = request.into_inner();
+        let removed = if request.group {
+            self.pm.delete_group(&request.path).await.map_err(status)?
+        } else {
+            u64::from(self.pm.delete(&request.path).await.map_err(status)?)
+        };
+        Ok(Response::new(DeleteResponse { removed }))
+    }
+
+    async fn list(
+        &self,
+        request: Request<ListRequest>,
+    ) -> std::result::Result<Response<ListResponse>, Status> {
+        let listing = self
+            .pm
+            .list(&request.get_ref().path)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(ListResponse {
+            groups: listing.groups,
+            values: listing.values,
+        }))
+    }
+
+    type WatchStream = EventStream;
+
+    async fn watch(
+        &self,
+        request: Request<WatchRequest>,
+    ) -> std::result::Result<Response<Self::WatchStream>, Status> {
+        let WatchRequest { ns, since } = request.into_inner();
+        // Fail fast if the change log is unavailable, rather than on the first poll.
+        self.pm.changes_after(&ns, since).await.map_err(status)?;
+
+        // The state becomes `None` after an error, which ends the stream.
+        let state = Some((Arc::clone(&self.pm), ns, since, VecDeque::new()));
+        let events = stream::unfold(state, |state| async move {
+            let (pm, ns, mut since, mut pending) = state?;
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), Some((pm, ns, since, pending))));
+                }
+                match poll(&pm, &ns, since).await {
+                    Ok((until, events)) if events.is_empty() => {
+                        since = until;
+                        tokio::time::sleep(WATCH_POLL_INTERVAL).await;
+                    }
+                    Ok((until, events)) => {
+                        since = until;
+                        pending.extend(events);
+                    }
+                    Err(e) => return Some((Err(status(e)), None)),
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Reads the changes after `since` as watch events.
+async fn poll(pm: &Pathmap, ns: &str, since: i64) -> Result<(i64, Vec<WatchEvent>)> {
+    let (until, changes) = pm.changes_after(ns, since).await?;
+    let mut events = Vec::with_capacity(changes.len());
+    for change in changes {
+        let json = match change.value {
+            Some(stored) => {
+                let raw_value = pm.load_value(ns, stored).await?;
+                Some(String::from_utf8_lossy(&raw_value).into_owned())
+            }
+            None => None,
+        };
+        events.push(WatchEvent {
+            seq: change.seq,
+            key: crate::db::join_key(&change.grp, &change.key),
+            json,
+        });
+    }
+    Ok((until, events))
+}
+
+/// Lets a replica read through to and write to a remote gRPC service.
+impl Primary for KvmapClient<Channel> {
+    fn get<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, Option<Value>> {
+        Box::pin(async move {
+            let request = GetRequest {
+                path: path.to_string(),
+            };
+            match self.clone().get(request).await {
+                Ok(response) => Ok(Some(serde_json::from_str(&response.get_ref().json)?)),
+                Err(e) if e.code() == Code::NotFound => Ok(None),
+                Err(e) => Err(remote_error(e)),
+            }
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        path: &'a str,
+        value: &'a Value,
+        overwrite: bool,
+    ) -> PrimaryFuture<'a, bool> {
+        Box::pin(async move {
+            let request = SetRequest {
+                path: path.to_string(),
+                json: value.to_string(),
+                overwrite,
+            };
+            match self.clone().set(request).await {
+                Ok(response) => Ok(response.get_ref().replaced),
+                Err(e) if e.code() == Code::AlreadyExists => {
+                    Err(PathmapError::ValueAlreadyExists(path.to_string()))
+                }
+                Err(e) => Err(remote_error(e)),
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, bool> {
+        Box::pin(async move {
+            let request = DeleteRequest {
+                path: path.to_string(),
+                group: false,
+            };
+            let response = self.clone().delete(request).await.map_err(remote_error)?;
+            Ok(response.get_ref().removed > 0)
+        })
+    }
+
+    fn delete_group<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, u64> {
+        Box::pin(async move {
+            let request = DeleteRequest {
+                path: path.to_string(),
+                group: true,
+            };
+            let response = self.clone().delete(request).await.map_err(remote_error)?;
+            Ok(response.get_ref().removed)
+        })
+    }
+}
+
+fn remote_error(status: Status) -> PathmapError {
+    PathmapError::RemoteError(status.message().to_string())
+}
+
+fn status(err: PathmapError) -> Status {
+    let message = err.to_string();
+    match err.root() {
+        PathmapError::NamespaceNotFound(_)
+        | PathmapError::GroupNotFound(..)
+        | PathmapError::ValueNotFound(_) => Status::not_found(message),
+        PathmapError::NamespaceAlreadyExists(_)
+        | PathmapError::GroupAlreadyExists(..)
+        | PathmapError::ValueAlreadyExists(_) => Status::already_exists(message),
+        PathmapError::InvalidPath(_)
+        | PathmapError::InvalidQuery(..)
+        | PathmapError::JsonError(_) => Status::invalid_argument(message),
+        PathmapError::BackupError(_) | PathmapError::PublishError(_) => {
+            Status::failed_precondition(message)
+        }
+        PathmapError::Throttled(_) | PathmapError::QuotaExceeded(..) => {
+            Status::resource_exhausted(message)
+        }
+        PathmapError::Busy(_) => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
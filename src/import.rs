@@ -0,0 +1,571 @@
+/* src/import.rs */
+
+use crate::Pathmap;
+use crate::audit::RowWrite;
+use crate::db;
+use crate::error::{self, PathmapError, Result};
+use crate::event::Operation;
+use crate::path;
+use crate::transform::Pipeline;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::pool::PoolConnection;
+use sqlx::{Connection, Sqlite, Transaction};
+use std::collections::{HashMap, HashSet};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Rows per multi-row INSERT in `import_bulk`: 2000 bound parameters, far below SQLite's limit.
+const BULK_CHUNK_ROWS: usize = 500;
+
+/// What to do when an imported key already exists in the namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the existing value.
+    Skip,
+    /// Replace the existing value.
+    Overwrite,
+    /// Keep whichever value has the later timestamp in the `ConflictRules::timestamp_field`.
+    /// Timestamps are numbers or RFC 3339 strings; a value without the field is older than one
+    /// with it, and ties keep the existing value.
+    NewerWins,
+    /// Abort the whole import with `ValueAlreadyExists`.
+    Error,
+}
+
+/// Conflict policies for an import: a default, overrides for groups (the most specific group
+/// containing a key wins), the timestamp `NewerWins` compares, and whether to only report what
+/// would happen. A bare `ConflictPolicy` converts into rules that apply it to every key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictRules {
+    pub default: ConflictPolicy,
+    /// Group overrides, most specific first.
+    groups: Vec<(String, ConflictPolicy)>,
+    /// JSON Pointer to the timestamp `NewerWins` compares; `/updated_at` by default.
+    pub timestamp_field: String,
+    /// Resolve conflicts and fill in the report without writing anything. `Error` conflicts are
+    /// reported instead of aborting.
+    pub dry_run: bool,
+}
+
+impl ConflictRules {
+    pub fn new(default: ConflictPolicy) -> Self {
+        ConflictRules {
+            default,
+            groups: Vec::new(),
+            timestamp_field: "/updated_at".to_string(),
+            dry_run: false,
+        }
+    }
+
+    /// Resolves conflicts on keys in `group`, or nested below it, with `policy`.
+    pub fn group(mut self, group: &str, policy: ConflictPolicy) -> Self {
+        self.groups.retain(|(g, _)| g != group);
+        self.groups.push((group.to_string(), policy));
+        self.groups.sort_by_key(|(g, _)| std::cmp::Reverse(g.len()));
+        self
+    }
+
+    pub fn timestamp_field(mut self, pointer: &str) -> Self {
+        self.timestamp_field = pointer.to_string();
+        self
+    }
+
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// The policy for the dotted `key`.
+    fn policy_for(&self, key: &str) -> ConflictPolicy {
+        self.groups
+            .iter()
+            .find(|(group, _)| path::in_group(group, key))
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
+
+impl From<ConflictPolicy> for ConflictRules {
+    fn from(policy: ConflictPolicy) -> Self {
+        ConflictRules::new(policy)
+    }
+}
+
+/// How an import resolved a key that already existed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictOutcome {
+    /// The existing value was kept.
+    Kept,
+    /// The imported value replaced it.
+    Replaced,
+    /// The key's policy is `Error`; only reported by dry runs, real imports abort.
+    Rejected,
+}
+
+/// A key that existed before the import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportConflict {
+    pub key: String,
+    pub outcome: ConflictOutcome,
+}
+
+/// Outcome of an import.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of values written, or in a dry run that would be.
+    pub written: usize,
+    /// Keys left untouched because they already existed.
+    pub skipped: Vec<String>,
+    /// Every key that already existed, with how it was resolved.
+    pub conflicts: Vec<ImportConflict>,
+    pub dry_run: bool,
+}
+
+/// An import in progress: its open transaction and what it has done so far.
+pub(crate) struct EntryImport<'a> {
+    ns: &'a str,
+    /// `None` for a dry run into a namespace that does not exist.
+    tx: Option<Transaction<'static, Sqlite>>,
+    report: ImportReport,
+    /// Blob files written for imported values, removed again if the import rolls back.
+    new_blobs: Vec<Vec<u8>>,
+    /// Blob files of replaced values, removed once the import has committed.
+    replaced_blobs: Vec<Vec<u8>>,
+}
+
+/// Settings for `import_bulk`.
+#[derive(Debug, Clone, Default)]
+pub struct BulkImportOptions {
+    /// Runs the import with `PRAGMA synchronous = OFF`, restoring the previous level afterwards.
+    /// Faster, but a power loss or OS crash during the import can corrupt the database, so only
+    /// use it for loads that can be redone from scratch.
+    pub synchronous_off: bool,
+}
+
+impl BulkImportOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn synchronous_off(mut self, enabled: bool) -> Self {
+        self.synchronous_off = enabled;
+        self
+    }
+}
+
+impl Pathmap {
+    /// Loads a nested JSON document (as produced by `export_ns`) into a namespace in one transaction,
+    /// creating the namespace if needed.
+    /// Objects are treated as groups; with `with_group_depth(n)` only the first `n` levels are,
+    /// so deeper objects are stored as values. Without a fixed depth, object values are flattened.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn import_ns<R: AsyncRead + Unpin>(
+        &self,
+        ns: &str,
+        reader: &mut R,
+        policy: impl Into<ConflictRules>,
+    ) -> Result<ImportReport> {
+        self.import_ns_with(ns, reader, policy, &Pipeline::new())
+            .await
+    }
+
+    /// Like `import_ns`, but runs every entry through a transformation pipeline first.
+    /// Keys are normalized and conflicts resolved after the pipeline has rewritten them.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn import_ns_with<R: AsyncRead + Unpin>(
+        &self,
+        ns: &str,
+        reader: &mut R,
+        policy: impl Into<ConflictRules>,
+        pipeline: &Pipeline,
+    ) -> Result<ImportReport> {
+        error::in_context("import_ns_with", ns, async {
+            let mut raw = Vec::new();
+            reader.read_to_end(&mut raw).await?;
+            let document: Value = serde_json::from_slice(&raw)?;
+            let Value::Object(root) = document else {
+                return Err(PathmapError::InvalidPath(format!(
+                    "import document for '{}' must be a JSON object",
+                    ns
+                )));
+            };
+
+            let mut entries = Vec::new();
+            self.flatten_document(root, &mut Vec::new(), &mut entries);
+            let rules = policy.into();
+            self.in_background(ns, self.import_entries(ns, entries, &rules, pipeline))
+                .await
+        })
+        .await
+    }
+
+    /// Collects `(dotted key, value)` pairs from a nested document.
+    pub(crate) fn flatten_document(
+        &self,
+        node: Map<String, Value>,
+        segments: &mut Vec<String>,
+        out: &mut Vec<(String, Value)>,
+    ) {
+        let descend = self.group_depth.is_none_or(|depth| segments.len() < depth);
+        for (name, value) in node {
+            match value {
+                Value::Object(child) if descend => {
+                    segments.push(name);
+                    self.flatten_document(child, segments, out);
+                    segments.pop();
+                }
+                value => {
+                    let mut key = segments.join(".");
+                    if !key.is_empty() {
+                        key.push('.');
+                    }
+                    key.push_str(&name);
+                    out.push((key, value));
+                }
+            }
+        }
+    }
+
+    /// Writes flattened entries into a namespace inside a single transaction. If it rolls back,
+    /// the blob files written for its values are removed again.
+    pub(crate) async fn import_entries(
+        &self,
+        ns: &str,
+        entries: Vec<(String, Value)>,
+        rules: &ConflictRules,
+        pipeline: &Pipeline,
+    ) -> Result<ImportReport> {
+        let mut import = self.begin_import(ns, rules).await?;
+        let mut written = Ok(());
+        for (full_key, value) in entries {
+            written = self
+                .import_entry(&mut import, rules, pipeline, full_key, value)
+                .await;
+            if written.is_err() {
+                break;
+            }
+        }
+        self.finish_import(import, rules, written).await
+    }
+
+    /// Opens the transaction of an import into `ns`. Feed it with `import_entry` and close it
+    /// with `finish_import`.
+    pub(crate) async fn begin_import<'a>(
+        &self,
+        ns: &'a str,
+        rules: &ConflictRules,
+    ) -> Result<EntryImport<'a>> {
+        self.settle_batches(ns, None).await;
+        let pool = if !rules.dry_run {
+            Some(self.get_pool_or_init(ns).await?)
+        } else if self.exists_ns(ns) {
+            Some(self.get_pool(ns).await?)
+        } else {
+            // A dry run into a namespace that does not exist yet has nothing to collide with.
+            None
+        };
+        let tx = match &pool {
+            Some(pool) => Some(pool.begin().await?),
+            None => None,
+        };
+        Ok(EntryImport {
+            ns,
+            tx,
+            report: ImportReport {
+                dry_run: rules.dry_run,
+                ..ImportReport::default()
+            },
+            new_blobs: Vec::new(),
+            replaced_blobs: Vec::new(),
+        })
+    }
+
+    /// Resolves and writes one entry of an import.
+    pub(crate) async fn import_entry(
+        &self,
+        import: &mut EntryImport<'_>,
+        rules: &ConflictRules,
+        pipeline: &Pipeline,
+        full_key: String,
+        value: Value,
+    ) -> Result<()> {
+        let ns = import.ns;
+        let Some((full_key, value)) = pipeline.apply(full_key, value)? else {
+            return Ok(());
+        };
+        let full_key = self.normalize_key(&full_key).into_owned();
+        self.name_rules.check_key(&full_key)?;
+        let (grp, key) = self.split_key(&full_key);
+        let existing = match &mut import.tx {
+            Some(tx) => db::find(&mut **tx, grp, key).await?,
+            None => None,
+        };
+        if let Some(existing) = &existing {
+            let outcome = match rules.policy_for(&full_key) {
+                ConflictPolicy::Skip => ConflictOutcome::Kept,
+                ConflictPolicy::Overwrite => ConflictOutcome::Replaced,
+                ConflictPolicy::NewerWins => {
+                    let field = rules.timestamp_field.as_str();
+                    let current: Value =
+                        serde_json::from_slice(&self.load_value(ns, existing.clone()).await?)
+                            .unwrap_or(Value::Null);
+                    if is_newer(value.pointer(field), current.pointer(field)) {
+                        ConflictOutcome::Replaced
+                    } else {
+                        ConflictOutcome::Kept
+                    }
+                }
+                ConflictPolicy::Error if rules.dry_run => ConflictOutcome::Rejected,
+                ConflictPolicy::Error => {
+                    return Err(PathmapError::ValueAlreadyExists(full_key.clone()));
+                }
+            };
+            import.report.conflicts.push(ImportConflict {
+                key: full_key.clone(),
+                outcome,
+            });
+            if outcome != ConflictOutcome::Replaced {
+                if outcome == ConflictOutcome::Kept {
+                    import.report.skipped.push(full_key.clone());
+                }
+                return Ok(());
+            }
+            if existing.external {
+                import.replaced_blobs.push(existing.data.clone());
+            }
+        }
+        import.report.written += 1;
+        let Some(tx) = import.tx.as_mut().filter(|_| !rules.dry_run) else {
+            return Ok(());
+        };
+        let serialized_value = self.encode_write(ns, &full_key, &value)?;
+        self.check_value_quota(ns, serialized_value.len() as u64)?;
+        let (data, external) = self.store_value(ns, serialized_value).await?;
+        if external {
+            import.new_blobs.push(data.clone());
+        }
+        let write = RowWrite::Overwrite(&data, external);
+        self.write_row_in(ns, tx, grp, key, write, existing.as_ref())
+            .await
+    }
+
+    /// Commits an import whose entries were `written` without error, or rolls it back and removes
+    /// the blob files written for its values.
+    pub(crate) async fn finish_import(
+        &self,
+        import: EntryImport<'_>,
+        rules: &ConflictRules,
+        written: Result<()>,
+    ) -> Result<ImportReport> {
+        let EntryImport {
+            ns,
+            tx,
+            report,
+            new_blobs,
+            replaced_blobs,
+        } = import;
+        let committed = async {
+            written?;
+            let Some(mut tx) = tx.filter(|_| !rules.dry_run) else {
+                return Ok(false);
+            };
+            self.check_quota_totals(ns, &mut tx).await?;
+            tx.commit().await?;
+            Ok::<_, PathmapError>(true)
+        }
+        .await;
+        match committed {
+            Ok(false) => return Ok(report),
+            Ok(true) => {}
+            Err(e) => {
+                for name in new_blobs {
+                    self.remove_blob(ns, &name).await?;
+                }
+                return Err(e);
+            }
+        }
+        self.invalidate_cached_ns(ns);
+        if report.written > 0 {
+            self.emit_change(Operation::Import, ns, "", None, None);
+        }
+
+        for name in replaced_blobs {
+            self.remove_blob(ns, &name).await?;
+        }
+        Ok(report)
+    }
+
+    /// Writes `(dotted key, value)` pairs into a namespace as fast as SQLite allows: one
+    /// transaction, multi-row INSERTs and, optionally, `synchronous = OFF`. Existing keys are
+    /// overwritten and nothing is written if any entry fails. Creates the namespace if needed and
+    /// returns the number of distinct keys written; a key repeated in `entries` keeps its last
+    /// value and counts once. In an audited namespace every row is recorded in
+    /// the audit trail, which writes them one at a time.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn import_bulk<I, K, V>(
+        &self,
+        ns: &str,
+        entries: I,
+        options: BulkImportOptions,
+    ) -> Result<u64>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Serialize,
+    {
+        error::in_context("import_bulk", ns, async {
+            self.settle_batches(ns, None).await;
+            let pool = self.get_pool_or_init(ns).await?;
+            let job = async {
+                let mut conn = pool.acquire().await?;
+                let previous = if options.synchronous_off {
+                    let previous = db::synchronous(&mut conn).await?;
+                    db::set_synchronous(&mut conn, 0).await?;
+                    Some(previous)
+                } else {
+                    None
+                };
+                let result = self.write_bulk(ns, &mut conn, entries).await;
+                if let Some(previous) = previous
+                    && db::set_synchronous(&mut conn, previous).await.is_err()
+                {
+                    // Never hand an unsafe connection back to the pool.
+                    conn.close_on_drop();
+                }
+                result
+            };
+            let written = self.in_background(ns, job).await;
+            self.invalidate_cached_ns(ns);
+            if written.as_ref().is_ok_and(|&n| n > 0) {
+                self.emit_change(Operation::Import, ns, "", None, None);
+            }
+            written
+        })
+        .await
+    }
+
+    async fn write_bulk<I, K, V>(
+        &self,
+        ns: &str,
+        conn: &mut PoolConnection<Sqlite>,
+        entries: I,
+    ) -> Result<u64>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: Serialize,
+    {
+        let blob_dir = crate::blob::blob_dir(&self.get_db_path(ns));
+        let mut new_blobs = Vec::new();
+        let mut orphans = Vec::new();
+        let result = async {
+            let mut tx = conn.begin().await?;
+            // Every key written, so a key repeated in the entries is counted once.
+            let mut written = HashSet::new();
+            let mut chunk: Vec<db::BulkRow> = Vec::with_capacity(BULK_CHUNK_ROWS);
+            // Position of each key in the chunk, so a repeated key replaces its earlier row.
+            let mut positions = HashMap::new();
+            let mut entries = entries.into_iter().peekable();
+            while let Some((full_key, value)) = entries.next() {
+                let full_key = self.normalize_key(full_key.as_ref()).into_owned();
+                self.name_rules.check_key(&full_key)?;
+                let (grp, key) = self.split_key(&full_key);
+                let serialized_value = self.encode_write(ns, &full_key, &value)?;
+                self.check_value_quota(ns, serialized_value.len() as u64)?;
+                let (data, external) = self.store_value(ns, serialized_value).await?;
+                if external {
+                    new_blobs.push(data.clone());
+                }
+                let row = db::BulkRow {
+                    grp: grp.to_string(),
+                    key: key.to_string(),
+                    data,
+                    external,
+                    updated_at: db::unix_millis(self.clock.system_now()),
+                };
+                written.insert((row.grp.clone(), row.key.clone()));
+                match positions.get(&(row.grp.clone(), row.key.clone())) {
+                    Some(&i) => {
+                        let earlier = std::mem::replace(&mut chunk[i], row);
+                        if earlier.external {
+                            orphans.push(earlier.data);
+                        }
+                    }
+                    None => {
+                        positions.insert((row.grp.clone(), row.key.clone()), chunk.len());
+                        chunk.push(row);
+                    }
+                }
+                if chunk.len() == BULK_CHUNK_ROWS || entries.peek().is_none() {
+                    // Without a blob directory no stored row can reference a blob.
+                    if blob_dir.exists() {
+                        orphans.extend(db::external_refs_of(&mut *tx, &chunk).await?);
+                    }
+                    if self.is_audited(ns) {
+                        // Each row needs its own audit entry, so an audited namespace is written
+                        // row by row.
+                        for row in &chunk {
+                            let previous = db::find(&mut *tx, &row.grp, &row.key).await?;
+                            let write = RowWrite::Overwrite(&row.data, row.external);
+                            self.write_row_in(
+                                ns,
+                                &mut tx,
+                                &row.grp,
+                                &row.key,
+                                write,
+                                previous.as_ref(),
+                            )
+                            .await?;
+                        }
+                    } else {
+                        db::overwrite_many(&mut *tx, &chunk).await?;
+                    }
+                    chunk.clear();
+                    positions.clear();
+                }
+            }
+            self.check_quota_totals(ns, &mut tx).await?;
+            tx.commit().await?;
+            Ok::<_, PathmapError>(written.len() as u64)
+        }
+        .await;
+
+        match result {
+            Ok(written) => {
+                for name in orphans {
+                    self.remove_blob(ns, &name).await?;
+                }
+                Ok(written)
+            }
+            Err(e) => {
+                for name in new_blobs {
+                    self.remove_blob(ns, &name).await?;
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Whether an imported timestamp is later than the existing one. Numbers compare numerically,
+/// RFC 3339 strings by instant and other strings lexically; a missing timestamp is the oldest.
+fn is_newer(incoming: Option<&Value>, existing: Option<&Value>) -> bool {
+    let (Some(incoming), Some(existing)) = (incoming, existing) else {
+        return incoming.is_some() && existing.is_none();
+    };
+    match (incoming, existing) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => a > b,
+            _ => false,
+        },
+        (Value::String(a), Value::String(b)) => {
+            match (
+                chrono::DateTime::parse_from_rfc3339(a),
+                chrono::DateTime::parse_from_rfc3339(b),
+            ) {
+                (Ok(a), Ok(b)) => a > b,
+                _ => a > b,
+            }
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,201 @@
+/* src/index.rs */
+
+//! Expression indexes on the JSON fields of a group's values: they speed up `find` conditions
+//! on the field and can require the field to be unique within the group.
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use crate::query::{STORED_DOC, sql_literal};
+use sqlx::{Row, SqlitePool};
+
+/// Index names are this prefix, the group and the JSON path, separated by `::`, which keys
+/// cannot contain.
+const INDEX_PREFIX: &str = "kvmap_index::";
+
+/// An index created with `create_index`, `create_unique_index` or a `unique_fields` pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonIndex {
+    /// The indexed group, relative to the namespace ("" for its root).
+    pub group: String,
+    pub json_path: String,
+    pub unique: bool,
+}
+
+fn index_name(grp: &str, json_path: &str) -> String {
+    format!("{}{}::{}", INDEX_PREFIX, grp, json_path)
+}
+
+/// `name` as a quoted SQL identifier.
+fn sql_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Turns the constraint error of a write that would duplicate a field of a unique index into
+/// `UniqueViolation`; other errors pass through. The row writes of `db` apply it, so every
+/// write path reports the violation the same way.
+pub(crate) fn unique_index_error(e: PathmapError) -> PathmapError {
+    if let PathmapError::DatabaseError(sqlx::Error::Database(db_err)) = &e
+        && db_err.is_unique_violation()
+        && let Some((_, name)) = db_err.message().split_once(INDEX_PREFIX)
+        && let Some((grp, json_path)) = name.trim_end_matches('\'').split_once("::")
+    {
+        return PathmapError::UniqueViolation(grp.to_string(), json_path.to_string());
+    }
+    e
+}
+
+async fn create_on(pool: &SqlitePool, grp: &str, json_path: &str, unique: bool) -> Result<()> {
+    let sql = format!(
+        "CREATE {}INDEX IF NOT EXISTS {} ON kv_store (json_extract({}, {})) WHERE grp = {}",
+        if unique { "UNIQUE " } else { "" },
+        sql_identifier(&index_name(grp, json_path)),
+        STORED_DOC,
+        sql_literal(json_path),
+        sql_literal(grp)
+    );
+    sqlx::query(&sql)
+        .execute(pool)
+        .await
+        .map_err(|e| unique_index_error(e.into()))?;
+    Ok(())
+}
+
+/// Splits a `unique_fields` pattern such as `users.*.email` into its group and JSON path
+/// (`users`, `$.email`); `*.email` names the namespace's root.
+fn parse_unique_field(pattern: &str) -> Result<(&str, String)> {
+    let (grp, field) = match pattern.strip_prefix("*.") {
+        Some(field) => ("", field),
+        None => pattern.split_once(".*.").unwrap_or(("", "")),
+    };
+    if field.is_empty() || field.split('.').any(str::is_empty) || field.contains('*') {
+        return Err(PathmapError::InvalidConfig(format!(
+            "unique field '{}' is not of the form 'group.*.field'",
+            pattern
+        )));
+    }
+    Ok((grp, format!("$.{}", field)))
+}
+
+impl Pathmap {
+    /// Indexes the JSON field at `json_path` (e.g. `$.email`) of the values directly in the group
+    /// at `path`, with a SQLite expression index, so `find` conditions on that field in that
+    /// group no longer scan it. Values in nested groups and in blob files are not indexed.
+    /// Creating an existing index does nothing.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path, json_path = %json_path), err(Display)))]
+    pub async fn create_index(&self, path: &str, json_path: &str) -> Result<()> {
+        self.create_json_index(path, json_path, false).await
+    }
+
+    /// Like `create_index`, and also rejects writes that would give two values of the group the
+    /// same field with `UniqueViolation`; values without the field are not constrained.
+    /// Fails the same way if the group already holds duplicates, and with `InvalidConfig` in a
+    /// namespace with a `blob_threshold`, whose blob values the index could not check.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path, json_path = %json_path), err(Display)))]
+    pub async fn create_unique_index(&self, path: &str, json_path: &str) -> Result<()> {
+        self.create_json_index(path, json_path, true).await
+    }
+
+    async fn create_json_index(&self, path: &str, json_path: &str, unique: bool) -> Result<()> {
+        let (ns, grp) = self.index_target(path, json_path)?;
+        if unique {
+            self.check_unique_allowed(&ns)?;
+        }
+        let pool = self.get_pool(&ns).await?;
+        create_on(&pool, &grp, json_path, unique).await
+    }
+
+    /// Creates the unique indexes of the namespace's `unique_fields` on a newly opened pool. A
+    /// pattern the stored values already violate is logged as a `UniqueViolation` and skipped.
+    pub(crate) async fn apply_unique_fields(&self, ns: &str, pool: &SqlitePool) -> Result<()> {
+        if self.ns_config(ns).unique_fields.is_empty() {
+            return Ok(());
+        }
+        self.check_unique_allowed(ns)?;
+        for pattern in &self.ns_config(ns).unique_fields {
+            let (grp, json_path) = parse_unique_field(pattern)?;
+            let grp = self.normalize_key(grp);
+            self.name_rules.check_key(&grp)?;
+            match create_on(pool, &grp, &json_path, true).await {
+                Ok(()) => {}
+                // Refusing to open would lock the data away over values written before the
+                // pattern was declared; the namespace opens without the constraint instead.
+                Err(e @ PathmapError::UniqueViolation(..)) => {
+                    tracing::warn!(ns, pattern = %pattern, error = %e, "existing values break a unique field, opening without its index");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuses unique indexes in a namespace with a blob threshold: SQLite cannot read the
+    /// fields of values kept in blob files, so large values would escape the constraint.
+    fn check_unique_allowed(&self, ns: &str) -> Result<()> {
+        if self.ns_config(ns).blob_threshold.is_some() {
+            return Err(PathmapError::InvalidConfig(format!(
+                "namespace '{}' keeps large values in blob files, which a unique index cannot check",
+                ns
+            )));
+        }
+        Ok(())
+    }
+
+    /// Removes the index of `json_path` on the group at `path`, returning whether it existed.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path, json_path = %json_path), err(Display)))]
+    pub async fn drop_index(&self, path: &str, json_path: &str) -> Result<bool> {
+        let (ns, grp) = self.index_target(path, json_path)?;
+        let pool = self.get_pool(&ns).await?;
+        let name = index_name(&grp, json_path);
+        let existed: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = ?)",
+        )
+        .bind(&name)
+        .fetch_one(&*pool)
+        .await?;
+        sqlx::query(&format!("DROP INDEX IF EXISTS {}", sql_identifier(&name)))
+            .execute(&*pool)
+            .await?;
+        Ok(existed)
+    }
+
+    /// The JSON field indexes of a namespace, ordered by group and path.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn list_indexes(&self, ns: &str) -> Result<Vec<JsonIndex>> {
+        let pool = self.get_pool(ns).await?;
+        let rows = sqlx::query(
+            "SELECT name, sql LIKE 'CREATE UNIQUE%' AS is_unique FROM sqlite_master \
+             WHERE type = 'index' AND tbl_name = 'kv_store' AND name LIKE 'kvmap\\_index::%' ESCAPE '\\' \
+             ORDER BY name",
+        )
+        .fetch_all(&*pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let name: String = row.get("name");
+                let (grp, json_path) = name.strip_prefix(INDEX_PREFIX)?.split_once("::")?;
+                Some(JsonIndex {
+                    group: grp.to_string(),
+                    json_path: json_path.to_string(),
+                    unique: row.get("is_unique"),
+                })
+            })
+            .collect())
+    }
+
+    /// The namespace and group an index on `path` belongs to.
+    fn index_target(&self, path: &str, json_path: &str) -> Result<(String, String)> {
+        if !json_path.starts_with('$') {
+            return Err(PathmapError::InvalidPath(format!(
+                "JSON path '{}' does not start with '$'",
+                json_path
+            )));
+        }
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        self.name_rules.check_ns(ns)?;
+        let grp = self.normalize_key(grp);
+        self.name_rules.check_key(&grp)?;
+        let (ns, grp) = self.resolve_soft(ns, grp)?;
+        Ok((ns.to_string(), grp.into_owned()))
+    }
+}
@@ -0,0 +1,100 @@
+/* src/integrity.rs */
+
+//! On-demand and periodic SQLite integrity checks of namespace databases.
+
+use crate::Pathmap;
+use crate::clock::Clock;
+use crate::db;
+use crate::error::Result;
+use sqlx::SqlitePool;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How thoroughly `check_ns_with` inspects a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckMode {
+    /// `PRAGMA quick_check`: page structure and record formats, without verifying that indexes
+    /// match their tables. Much faster on large databases.
+    Quick,
+    /// `PRAGMA integrity_check`: everything `Quick` does, plus index consistency.
+    Full,
+}
+
+/// The outcome of an integrity check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    pub ns: String,
+    pub mode: CheckMode,
+    /// SQLite's description of each problem found; empty when the database is intact.
+    pub errors: Vec<String>,
+    /// How long the check took.
+    pub duration: Duration,
+}
+
+impl IntegrityReport {
+    /// Whether the check found no problems.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Callback receiving the report of every periodic integrity check.
+pub type IntegrityCallback = Arc<dyn Fn(&IntegrityReport) + Send + Sync>;
+
+/// Integrity checks run by the background cleanup task, set with `with_integrity_checks`.
+#[derive(Clone)]
+pub(crate) struct PeriodicCheck {
+    pub(crate) every: Duration,
+    pub(crate) mode: CheckMode,
+    pub(crate) callback: IntegrityCallback,
+}
+
+/// Checks the database behind `pool`.
+pub(crate) async fn run_check(
+    ns: &str,
+    pool: &SqlitePool,
+    mode: CheckMode,
+    clock: &dyn Clock,
+) -> Result<IntegrityReport> {
+    let started = clock.now();
+    let errors = db::integrity_errors(pool, mode == CheckMode::Quick).await?;
+    Ok(IntegrityReport {
+        ns: ns.to_string(),
+        mode,
+        errors,
+        duration: clock.now().saturating_duration_since(started),
+    })
+}
+
+impl Pathmap {
+    /// Runs SQLite's full integrity check on a namespace's database.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn check_ns(&self, ns: &str) -> Result<IntegrityReport> {
+        self.check_ns_with(ns, CheckMode::Full).await
+    }
+
+    /// Runs a `Quick` or `Full` integrity check on a namespace's database. The check reads the
+    /// whole file and runs as background work, so it never takes more than the namespace's
+    /// `background_connections`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn check_ns_with(&self, ns: &str, mode: CheckMode) -> Result<IntegrityReport> {
+        let pool = self.get_pool(ns).await?;
+        self.in_background(ns, run_check(ns, &pool, mode, self.clock.as_ref()))
+            .await
+    }
+
+    /// Makes the background cleanup task (`start_background_cleanup`) check every open namespace
+    /// with `mode` once per `every`, passing each report to `callback`, e.g. to page someone.
+    /// Problems are also logged as warnings. Registering again replaces the previous setting.
+    pub fn with_integrity_checks<F>(mut self, every: Duration, mode: CheckMode, callback: F) -> Self
+    where
+        F: Fn(&IntegrityReport) + Send + Sync + 'static,
+    {
+        self.integrity_checks = Some(PeriodicCheck {
+            every,
+            mode,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+}
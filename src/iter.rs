@@ -0,0 +1,224 @@
+/* src/iter.rs */
+
+use crate::Pathmap;
+use crate::db;
+use crate::error::{PathmapError, Result};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use sqlx::SqlitePool;
+
+/// Rows read per page while iterating, which bounds memory for large namespaces.
+const ITER_PAGE_SIZE: i64 = 500;
+
+/// One stored value yielded by `iter_all`: namespace, full dotted key and serialized JSON.
+pub type RawEntry = (String, String, Vec<u8>);
+
+/// Selects what `iter_all` reads.
+#[derive(Debug, Clone)]
+pub struct IterFilter {
+    /// Namespaces to read; `None` reads every namespace `list_ns` reports.
+    pub namespaces: Option<Vec<String>>,
+    /// Only read this group and its nested groups; empty reads whole namespaces.
+    pub group: String,
+    /// Namespaces read at the same time, and so the most pools the iterator opens at once.
+    pub concurrency: usize,
+}
+
+impl Default for IterFilter {
+    fn default() -> Self {
+        IterFilter {
+            namespaces: None,
+            group: String::new(),
+            concurrency: 4,
+        }
+    }
+}
+
+impl IterFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn namespaces<I, S>(mut self, namespaces: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.namespaces = Some(namespaces.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = group.to_string();
+        self
+    }
+
+    pub fn concurrency(mut self, namespaces: usize) -> Self {
+        self.concurrency = namespaces.max(1);
+        self
+    }
+}
+
+/// Where a namespace's iteration stands.
+enum Cursor {
+    Start,
+    /// Reading pages; `temporary` pools were opened for the iteration and are closed at the end.
+    Reading {
+        pool: SqlitePool,
+        temporary: bool,
+        after: Option<(String, String)>,
+    },
+    Done,
+}
+
+impl Pathmap {
+    /// Streams every stored value across namespaces as `(ns, key, raw JSON)`, in key order within
+    /// each namespace; with a `concurrency` above one, namespaces are interleaved. Namespaces are
+    /// opened only when the stream reaches them, and pools not already open are closed again once
+    /// the namespace is done. Soft namespaces are read as part of their host. Rows are read page by
+    /// page, so the stream is not a single snapshot.
+    pub fn iter_all(&self, filter: IterFilter) -> BoxStream<'_, Result<RawEntry>> {
+        let namespaces = match filter.namespaces.clone() {
+            Some(namespaces) => namespaces,
+            None => match self.namespace_names() {
+                Ok(namespaces) => namespaces
+                    .into_iter()
+                    .filter(|ns| !self.soft_ns.contains_key(ns))
+                    .collect(),
+                Err(e) => return stream::once(async { Err(e) }).boxed(),
+            },
+        };
+        let group = filter.group;
+        stream::iter(namespaces)
+            .map(move |ns| self.iter_ns(ns, group.clone()))
+            .flatten_unordered(filter.concurrency.max(1))
+            .boxed()
+    }
+
+    /// Streams the values of a namespace or group and its nested groups as `(key, value)`, keys
+    /// relative to `path` as `list_recursive` reports them, in key order. Values are read page by
+    /// page and decoded as they are yielded, so memory stays bounded however large the group; a
+    /// value that does not decode as `T` is yielded as an error without ending the stream.
+    pub fn iter_group<'a, T>(&'a self, path: &str) -> BoxStream<'a, Result<(String, T)>>
+    where
+        T: DeserializeOwned + Send + 'a,
+    {
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        let resolved = self.name_rules.check_ns(ns).and_then(|()| {
+            let grp = self.normalize_key(grp);
+            self.name_rules.check_key(&grp)?;
+            let (ns, grp) = self.resolve_soft(ns, grp)?;
+            Ok((ns.to_string(), grp.into_owned()))
+        });
+        let (ns, grp) = match resolved {
+            Ok(resolved) => resolved,
+            Err(e) => return stream::once(async { Err(e) }).boxed(),
+        };
+        let prefix_len = grp.len();
+        self.iter_ns(ns, grp)
+            .map(move |entry| {
+                let (_, key, raw) = entry?;
+                // Slice rather than strip so case-insensitive collations still line up.
+                let key = match prefix_len {
+                    0 => key,
+                    len => key.get(len + 1..).unwrap_or("").to_string(),
+                };
+                Ok((key, serde_json::from_slice(&raw)?))
+            })
+            .boxed()
+    }
+
+    /// Streams the values of one namespace, page by page.
+    fn iter_ns(&self, ns: String, group: String) -> BoxStream<'_, Result<RawEntry>> {
+        stream::unfold(Cursor::Start, move |cursor| {
+            let ns = ns.clone();
+            let group = group.clone();
+            async move {
+                let (pool, temporary, after) = match cursor {
+                    Cursor::Done => return None,
+                    Cursor::Start => match self.iter_pool(&ns).await {
+                        Ok((pool, temporary)) => (pool, temporary, None),
+                        Err(e) => return Some((vec![Err(e)], Cursor::Done)),
+                    },
+                    Cursor::Reading {
+                        pool,
+                        temporary,
+                        after,
+                    } => (pool, temporary, after),
+                };
+                let page = self
+                    .in_background(&ns, self.iter_page(&ns, &pool, &group, after))
+                    .await;
+                match page {
+                    Ok((items, Some(next))) => Some((
+                        items,
+                        Cursor::Reading {
+                            pool,
+                            temporary,
+                            after: Some(next),
+                        },
+                    )),
+                    Ok((items, None)) => {
+                        if temporary {
+                            pool.close().await;
+                        }
+                        (!items.is_empty()).then_some((items, Cursor::Done))
+                    }
+                    Err(e) => {
+                        if temporary {
+                            pool.close().await;
+                        }
+                        Some((vec![Err(e)], Cursor::Done))
+                    }
+                }
+            }
+        })
+        .flat_map(stream::iter)
+        .boxed()
+    }
+
+    /// The namespace's open pool, or a temporary one when it is not open yet.
+    async fn iter_pool(&self, ns: &str) -> Result<(SqlitePool, bool)> {
+        self.reject_soft(ns)?;
+        let open = self
+            .pools
+            .read()
+            .unwrap()
+            .get(ns)
+            .and_then(|slot| slot.get().cloned());
+        if let Some(pool) = open {
+            return Ok((pool, false));
+        }
+        let db_path = self.get_db_path(ns);
+        if !db_path.exists() {
+            return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+        }
+        Ok((self.connect(ns, &db_path).await?, true))
+    }
+
+    /// Reads the next page, returning its values and, unless it was the last page, the position
+    /// of its last row.
+    async fn iter_page(
+        &self,
+        ns: &str,
+        pool: &SqlitePool,
+        group: &str,
+        after: Option<(String, String)>,
+    ) -> Result<(Vec<Result<RawEntry>>, Option<(String, String)>)> {
+        let after = after.as_ref().map(|(g, k)| (g.as_str(), k.as_str()));
+        let entries = db::group_entries_after(pool, group, after, ITER_PAGE_SIZE).await?;
+        let next = match entries.last() {
+            Some(last) if entries.len() as i64 == ITER_PAGE_SIZE => {
+                Some((last.grp.clone(), last.key.clone()))
+            }
+            _ => None,
+        };
+        let mut items = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let key = db::join_key(&entry.grp, &entry.key);
+            let value = self.load_value(ns, entry.value).await?;
+            items.push(Ok((ns.to_string(), key, value)));
+        }
+        Ok((items, next))
+    }
+}
@@ -0,0 +1,92 @@
+/* src/lanes.rs */
+
+use crate::Pathmap;
+use crate::config::NamespaceConfig;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+tokio::task_local! {
+    /// Set while a task runs inside the background lane, so nested bulk operations do not wait
+    /// for a second permit held by themselves.
+    static IN_BACKGROUND: ();
+}
+
+/// How urgently an operation needs a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// Latency-sensitive reads and writes; never queued behind bulk work.
+    #[default]
+    Foreground,
+    /// Bulk jobs (exports, backups, rewrites, sweeps) limited to
+    /// `NamespaceConfig::background_connections` connections per namespace.
+    Background,
+}
+
+/// Connections of the namespace's pool the background lane may hold: `background_connections`,
+/// leaving at least one connection reserved for the foreground. A single-connection pool has
+/// nothing to reserve, so both lanes share it.
+fn background_slots(config: &NamespaceConfig) -> u32 {
+    let max = config.pool.max_connections;
+    if max <= 1 {
+        return 1;
+    }
+    config.background_connections.clamp(1, max - 1)
+}
+
+/// One background semaphore per namespace, created on first use.
+#[derive(Default)]
+pub(crate) struct Lanes {
+    background: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl Lanes {
+    /// Waits for a background slot in `ns`, or returns `None` when the current task already
+    /// holds one.
+    pub(crate) async fn background(
+        &self,
+        ns: &str,
+        config: &NamespaceConfig,
+    ) -> Option<OwnedSemaphorePermit> {
+        if IN_BACKGROUND.try_with(|_| ()).is_ok() {
+            return None;
+        }
+        let semaphore = {
+            let mut lanes = self.background.lock().unwrap();
+            Arc::clone(
+                lanes
+                    .entry(ns.to_string())
+                    .or_insert_with(|| Arc::new(Semaphore::new(background_slots(config) as usize))),
+            )
+        };
+        // The semaphore is never closed.
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+impl Pathmap {
+    /// Runs a job in the given lane of a namespace. Background jobs wait until fewer than
+    /// `background_connections` other background jobs are running, leaving the rest of the pool
+    /// to foreground operations; the crate's own bulk operations run there as well.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns)))]
+    pub async fn with_priority<F: Future>(
+        &self,
+        ns: &str,
+        priority: Priority,
+        job: F,
+    ) -> F::Output {
+        match priority {
+            Priority::Foreground => job.await,
+            Priority::Background => self.in_background(ns, job).await,
+        }
+    }
+
+    /// Runs a bulk operation inside the namespace's background lane.
+    pub(crate) async fn in_background<F: Future>(&self, ns: &str, job: F) -> F::Output {
+        let permit = self.lanes.background(ns, self.ns_config(ns)).await;
+        let output = IN_BACKGROUND.scope((), job).await;
+        drop(permit);
+        output
+    }
+}
@@ -1,21 +1,229 @@
 /* src/lib.rs */
 
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use serde::de::DeserializeOwned;
+#[cfg(not(target_arch = "wasm32"))]
+use std::borrow::Cow;
+#[cfg(not(target_arch = "wasm32"))]
 use std::collections::{HashMap, HashSet};
+#[cfg(not(target_arch = "wasm32"))]
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
-use tokio::time;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, Mutex, RwLock, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant, SystemTime};
+#[cfg(not(target_arch = "wasm32"))]
+use unicode_normalization::{UnicodeNormalization, is_nfc};
 
+// Modules without the native marker form the portable core (paths, serialization policies,
+// transforms and the in-memory store) that also builds for wasm32.
+#[cfg(not(target_arch = "wasm32"))]
+mod annotate;
+#[cfg(not(target_arch = "wasm32"))]
+mod audit;
+#[cfg(not(target_arch = "wasm32"))]
+mod batch;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blob;
+#[cfg(feature = "runtime-tokio")]
+pub mod blocking;
+#[cfg(not(target_arch = "wasm32"))]
+mod builder;
+#[cfg(not(target_arch = "wasm32"))]
+mod cache;
+#[cfg(not(target_arch = "wasm32"))]
+mod changelog;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(not(target_arch = "wasm32"))]
+mod checkpoint;
+#[cfg(not(target_arch = "wasm32"))]
+mod cleanup;
+pub mod clock;
+#[cfg(not(target_arch = "wasm32"))]
+mod codegen;
+pub mod config;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod db;
+#[cfg(not(target_arch = "wasm32"))]
+mod digest;
+#[cfg(not(target_arch = "wasm32"))]
+mod duplicates;
+#[cfg(not(target_arch = "wasm32"))]
+mod entity;
 pub mod error;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod event;
+#[cfg(not(target_arch = "wasm32"))]
+mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(not(target_arch = "wasm32"))]
+mod import;
+#[cfg(not(target_arch = "wasm32"))]
+mod index;
+#[cfg(not(target_arch = "wasm32"))]
+mod integrity;
+#[cfg(not(target_arch = "wasm32"))]
+mod iter;
+#[cfg(not(target_arch = "wasm32"))]
+mod lanes;
+#[cfg(not(target_arch = "wasm32"))]
+mod lock;
+pub mod memory;
+#[cfg(not(target_arch = "wasm32"))]
+mod migrate;
+#[cfg(not(target_arch = "wasm32"))]
+mod multi;
+mod names;
+#[cfg(not(target_arch = "wasm32"))]
+mod ndjson;
+#[cfg(not(target_arch = "wasm32"))]
+mod normalize;
+pub mod number;
+pub mod partition;
+pub mod path;
+#[cfg(not(target_arch = "wasm32"))]
+mod poll;
+#[cfg(not(target_arch = "wasm32"))]
+mod publish;
+#[cfg(not(target_arch = "wasm32"))]
+mod query;
+#[cfg(not(target_arch = "wasm32"))]
+mod queue;
+#[cfg(not(target_arch = "wasm32"))]
+mod quota;
+#[cfg(not(target_arch = "wasm32"))]
+mod replica;
+#[cfg(not(target_arch = "wasm32"))]
+mod retry;
+#[cfg(not(target_arch = "wasm32"))]
+mod rt;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(not(target_arch = "wasm32"))]
+mod schedule;
+#[cfg(not(target_arch = "wasm32"))]
+mod search;
+#[cfg(not(target_arch = "wasm32"))]
+mod selftest;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(not(target_arch = "wasm32"))]
+mod settings;
+#[cfg(not(target_arch = "wasm32"))]
+mod shadow;
+#[cfg(not(target_arch = "wasm32"))]
+mod snapshot;
+#[cfg(all(unix, feature = "runtime-tokio"))]
+pub mod socket;
+#[cfg(not(target_arch = "wasm32"))]
+mod throttle;
+#[cfg(not(target_arch = "wasm32"))]
+mod token;
+#[cfg(not(target_arch = "wasm32"))]
+mod transform;
+mod tree;
+#[cfg(not(target_arch = "wasm32"))]
+mod usage;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::annotate::{DetailedListing, KeyMeta, ListedValue, ValueKind};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::audit::RowWrite;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::audit::{AuditEntry, AuditFilter};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::batch::{BatchConfig, FlushHandle};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::builder::PathmapBuilder;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::checkpoint::Checkpoint;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cleanup::{CleanupControl, Wake};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::cleanup::{CleanupHandle, CleanupPolicy};
+pub use crate::clock::{Clock, ManualClock, SystemClock, TimeZone};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::codegen::Lang;
+pub use crate::config::{
+    AuditMode, Collation, ListingOrder, NamespaceConfig, OpenMode, PoolConfig, Quota,
+    StorageThresholds,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::db::{ExplainOp, QueryPlan};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::duplicates::{Duplicate, DuplicateBy};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::entity::KvEntity;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::error::{PathmapError, Result};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::event::{ChangeEvent, Operation, as_actor};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::import::{
+    BulkImportOptions, ConflictOutcome, ConflictPolicy, ConflictRules, ImportConflict, ImportReport,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::index::JsonIndex;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::integrity::{CheckMode, IntegrityCallback, IntegrityReport};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::iter::{IterFilter, RawEntry};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::lanes::Priority;
+pub use crate::memory::MemoryStore;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::migrate::{MigrateOptions, MigrationReport, NamespaceMigration};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::multi::MultiTransaction;
+pub use crate::names::NameRules;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::normalize::Normalizer;
+pub use crate::number::{FloatPolicy, IntegerPolicy};
+pub use crate::partition::{HashRing, KeyMoveBatch, RebalancePlan};
+pub use crate::path::KvPath;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::poll::ChangePollHandle;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::publish::PublishManifest;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::query::FieldFilter;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::queue::{WriteQueueConfig, WriteQueueStats};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::replica::{Primary, PrimaryFuture, ReplicaConfig};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::retry::{Backoff, RetryConfig};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::schedule::{Action, Scheduled};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::selftest::{SelfTestCheck, SelfTestReport};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::settings::{CleanupSettings, PathmapConfig, PoolSettings};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::shadow::{ShadowConfig, ShadowMismatch, ShadowStats};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::snapshot::BackupReport;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::throttle::{ThrottleConfig, ThrottlePolicy};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::transform::Pipeline;
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::usage::{StorageAlert, StorageMetric, StorageUsage};
+#[cfg(all(feature = "derive", not(target_arch = "wasm32")))]
+pub use kvmap_derive::KvEntity;
+#[cfg(not(target_arch = "wasm32"))]
 use sqlx::SqlitePool;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::sync::OnceCell;
 
 /// Represents the contents of a namespace or group.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Listing {
     /// A list of sub-groups.
     pub groups: Vec<String>,
@@ -23,78 +231,457 @@ pub struct Listing {
     pub values: Vec<String>,
 }
 
+/// What `overwrite` did to the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// The key held no value.
+    Created,
+    /// An existing value was replaced.
+    Replaced,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_outcome(existed: bool) -> WriteOutcome {
+    if existed {
+        WriteOutcome::Replaced
+    } else {
+        WriteOutcome::Created
+    }
+}
+
+/// A namespace as reported by `list_ns_info`.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceInfo {
+    pub name: String,
+    /// The SQLite file; for a soft namespace, its host's.
+    pub path: PathBuf,
+    /// Size of the SQLite file in bytes, without its WAL or blob files.
+    pub file_size: u64,
+    pub modified: Option<SystemTime>,
+    /// Whether this instance has a pool open for the namespace.
+    pub open: bool,
+    /// The host of a soft namespace.
+    pub soft_host: Option<String>,
+}
+
+/// A namespace's pool, opened by the first operation that needs it. Each namespace has its own
+/// slot, so opening one never blocks lookups of the others.
+#[cfg(not(target_arch = "wasm32"))]
+type PoolSlot = Arc<OnceCell<SqlitePool>>;
+
+/// A namespace's pool as handed to an operation. It holds on to the pool's slot, so while any
+/// lease is alive the background cleanup sees the namespace in use and does not close the pool.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub(crate) struct PoolLease {
+    pool: SqlitePool,
+    _slot: PoolSlot,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl std::ops::Deref for PoolLease {
+    type Target = SqlitePool;
+
+    fn deref(&self) -> &SqlitePool {
+        &self.pool
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 /// The main struct for interacting with pathmap.
 /// A path-driven, namespaced data store for Rust, powered by SQLite.
 pub struct Pathmap {
     base_path: PathBuf,
-    pools: Arc<Mutex<HashMap<String, SqlitePool>>>,
+    pools: Arc<RwLock<HashMap<String, PoolSlot>>>,
+    attached: RwLock<HashMap<String, PathBuf>>,
+    ns_paths: HashMap<String, PathBuf>,
+    soft_ns: HashMap<String, String>,
+    ns_configs: HashMap<String, NamespaceConfig>,
+    default_ns_config: NamespaceConfig,
+    group_depth: Option<usize>,
+    pool_close_after: Option<Duration>,
+    normalize_keys: bool,
+    instance_lock: Mutex<Option<std::fs::File>>,
+    open_mode: Option<OpenMode>,
+    clock: Arc<dyn Clock>,
+    lanes: Arc<lanes::Lanes>,
+    read_cache: Arc<cache::ReadCache>,
+    events: Arc<event::Events>,
+    storage_alerts: Arc<usage::AlertState>,
+    access: Arc<cleanup::AccessTimes>,
+    #[cfg(feature = "chaos")]
+    chaos: Option<chaos::Chaos>,
+    shadow: Option<shadow::Shadow>,
+    write_queue: Option<queue::WriteQueue>,
+    batcher: Option<Arc<batch::Batcher>>,
+    replica: Option<replica::Replica>,
+    /// Write rate limits by namespace, most specific group first.
+    throttles: HashMap<String, Vec<throttle::GroupThrottle>>,
+    /// Write-time normalizers by namespace, outermost group first.
+    normalizers: HashMap<String, Vec<normalize::GroupNormalizer>>,
+    busy_retry: Option<RetryConfig>,
+    integrity_checks: Option<integrity::PeriodicCheck>,
+    cleanup_policy: CleanupPolicy,
+    name_rules: NameRules,
+    /// Namespace of paths without `::`.
+    default_ns: Option<String>,
+    /// Set to `true` by `close`; the background cleanup task stops when it changes or is dropped.
+    shutdown: tokio::sync::watch::Sender<bool>,
+    /// Tags the change log entries written through this instance, unique among processes.
+    origin: String,
+    /// The task started by `start_change_polling`, shared by later calls while it runs.
+    change_polling: Mutex<Option<poll::ChangePollHandle>>,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Pathmap {
     /// Creates a new Pathmap instance with the default path ("/opt/pathmap/").
     pub fn new() -> Self {
         Pathmap {
             base_path: PathBuf::from("/opt/pathmap/"),
-            pools: Arc::new(Mutex::new(HashMap::new())),
+            pools: Arc::default(),
+            attached: RwLock::new(HashMap::new()),
+            ns_paths: HashMap::new(),
+            soft_ns: HashMap::new(),
+            ns_configs: HashMap::new(),
+            default_ns_config: NamespaceConfig::default(),
+            group_depth: None,
+            pool_close_after: None,
+            normalize_keys: false,
+            instance_lock: Mutex::new(None),
+            open_mode: None,
+            clock: Arc::new(SystemClock),
+            lanes: Arc::new(lanes::Lanes::default()),
+            read_cache: Arc::default(),
+            events: Arc::default(),
+            storage_alerts: Arc::default(),
+            access: Arc::default(),
+            #[cfg(feature = "chaos")]
+            chaos: None,
+            shadow: None,
+            write_queue: None,
+            batcher: None,
+            replica: None,
+            throttles: HashMap::new(),
+            normalizers: HashMap::new(),
+            busy_retry: None,
+            integrity_checks: None,
+            cleanup_policy: CleanupPolicy::default(),
+            name_rules: NameRules::default(),
+            default_ns: None,
+            shutdown: tokio::sync::watch::channel(false).0,
+            origin: poll::next_origin(),
+            change_polling: Mutex::new(None),
         }
     }
 
     /// Overrides the default base path. This must be called before any other operations.
+    /// `Pathmap::builder()` sets it together with the other instance-wide settings and checks it.
     pub fn with_base_path<P: AsRef<Path>>(mut self, path: P) -> Self {
         self.base_path = path.as_ref().to_path_buf();
         self
     }
 
-    /// Lists all available namespaces.
-    /// This corresponds to the .sqlite files in the base directory.
-    pub fn list_ns(&self) -> Result<Vec<String>> {
-        let mut namespaces = Vec::new();
-        if !self.base_path.exists() {
-            return Ok(namespaces);
+    /// Replaces the system clock used for idle detection, TTLs (replica freshness, one-time
+    /// tokens, throttling), timestamps (values, audit and change log, schedules) and
+    /// durations reported by the instance.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Compares a sample of reads against a shadow store (e.g. the target of a backend or codec
+    /// migration) and reports mismatches. Comparisons run in the background on decoded JSON.
+    pub fn with_shadow(mut self, store: Arc<Pathmap>, config: ShadowConfig) -> Self {
+        self.shadow = Some(shadow::Shadow::new(store, config));
+        self
+    }
+
+    /// Returns shadow comparison counters, or `None` when no shadow store is configured.
+    pub fn shadow_stats(&self) -> Option<ShadowStats> {
+        self.shadow.as_ref().map(|s| s.stats())
+    }
+
+    /// Buffers writes in a bounded in-memory queue while the disk reports it is full or failing
+    /// (`ENOSPC`, `EIO`), flushing them in order once it recovers. Queued writes are acknowledged
+    /// before they are durable; see `WriteQueueConfig` for what that means.
+    pub fn with_write_queue(mut self, config: WriteQueueConfig) -> Self {
+        self.write_queue = Some(queue::WriteQueue::new(config));
+        self
+    }
+
+    /// Enables `overwrite_batched` and `delete_batched`, which collect writes per namespace and
+    /// commit them in one transaction when a batch fills up or its delay expires.
+    pub fn with_write_batching(mut self, config: BatchConfig) -> Self {
+        self.batcher = Some(Arc::new(batch::Batcher::new(
+            config,
+            Arc::clone(&self.read_cache),
+            Arc::clone(&self.events),
+        )));
+        self
+    }
+
+    /// Turns this instance into a pull-through replica of `primary`: reads are served from the
+    /// local files and fetched from the primary when missing or older than the TTL, while `set`,
+    /// `overwrite`, `delete` and `delete_group` go to the primary first and then update the local
+    /// copy. Other operations only see what has been fetched so far.
+    pub fn with_replica(mut self, primary: Arc<dyn Primary>, config: ReplicaConfig) -> Self {
+        self.replica = Some(replica::Replica::new(primary, config));
+        self
+    }
+
+    /// Injects faults (busy errors, I/O errors, latency) into namespace operations by probability.
+    #[cfg(feature = "chaos")]
+    pub fn with_chaos(mut self, config: chaos::ChaosConfig) -> Self {
+        self.chaos = Some(chaos::Chaos::new(config));
+        self
+    }
+
+    /// Takes an advisory lock on the base path so that two processes cannot unknowingly run
+    /// conflicting maintenance against the same directory. The lock is taken when the instance
+    /// first opens or deletes a namespace, which fails with `StoreLocked` while another process
+    /// holds a conflicting one, and is held until the instance is dropped. With
+    /// `OpenMode::Coordinated`, processes share the path and take turns vacuuming, restoring and
+    /// migrating each namespace instead.
+    pub fn with_open_mode(mut self, mode: OpenMode) -> Self {
+        self.open_mode = Some(mode);
+        self
+    }
+
+    /// Places a specific namespace in a different directory than the base path,
+    /// e.g. to keep a hot namespace on faster storage.
+    pub fn with_ns_path<P: AsRef<Path>>(mut self, ns: &str, dir: P) -> Self {
+        self.ns_paths
+            .insert(ns.to_string(), dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Stores namespace `ns` inside the file of namespace `host` instead of a file of its own, with
+    /// its name as a prefix of every group, for deployments with many tiny tenants where a file per
+    /// namespace dominates. Values, lists and deletes behave as in a regular namespace; file-level
+    /// operations (backups, changelog, import/export of the whole namespace) go through the host.
+    /// The host's configuration applies. Not combinable with `with_group_depth`.
+    pub fn with_soft_ns(mut self, ns: &str, host: &str) -> Self {
+        self.soft_ns.insert(ns.to_string(), host.to_string());
+        self
+    }
+
+    /// Sets the configuration used by namespaces without a specific `with_ns_config` entry.
+    pub fn with_default_ns_config(mut self, config: NamespaceConfig) -> Self {
+        self.default_ns_config = config;
+        self
+    }
+
+    /// Applies namespace-specific settings such as key collation or pool size.
+    pub fn with_ns_config(mut self, ns: &str, config: NamespaceConfig) -> Self {
+        self.ns_configs.insert(ns.to_string(), config);
+        self
+    }
+
+    /// Fixes the number of group levels in every key.
+    /// With a depth of `n`, the first `n` dot-separated segments of a key are groups and the
+    /// remainder (dots included) is the value name, so `list` no longer guesses structure from dots.
+    /// A database remembers the depth its keys were split with; opening it with another fails
+    /// with `InvalidConfig` instead of missing keys stored under the old split.
+    pub fn with_group_depth(mut self, depth: usize) -> Self {
+        self.group_depth = Some(depth);
+        self
+    }
+
+    /// Normalizes keys to Unicode NFC on every write and lookup, so visually identical keys
+    /// produced by different clients (e.g. NFD filenames from macOS) map to the same entry.
+    pub fn with_key_normalization(mut self, enabled: bool) -> Self {
+        self.normalize_keys = enabled;
+        self
+    }
+
+    /// Lets the background cleanup task close pools of namespaces idle for longer than `after`,
+    /// releasing their file descriptors. Closed namespaces are reopened on next use. A pool is
+    /// never closed while an operation or open write batch still holds it.
+    pub fn with_idle_pool_close(mut self, after: Duration) -> Self {
+        self.pool_close_after = Some(after);
+        self
+    }
+
+    /// Sets when the background cleanup task vacuums a namespace; see `CleanupPolicy` for the
+    /// defaults. A running task is changed with `CleanupHandle::set_policy` instead.
+    pub fn with_cleanup_policy(mut self, policy: CleanupPolicy) -> Self {
+        self.cleanup_policy = policy;
+        self
+    }
+
+    /// Sets which namespace names and keys are accepted. Names that could resolve outside the
+    /// base path are rejected regardless; see `NameRules`.
+    pub fn with_name_rules(mut self, rules: NameRules) -> Self {
+        self.name_rules = rules;
+        self
+    }
+
+    /// Resolves paths without `::` (e.g. `settings.theme`) against `ns`, for applications that
+    /// use a single namespace. Such paths then always name a key or group: `list` and `exists`
+    /// treat them as one instead of as a namespace, which remains reachable as `ns::`.
+    pub fn with_default_ns(mut self, ns: &str) -> Self {
+        self.default_ns = Some(ns.to_string());
+        self
+    }
+
+    /// `path` with the default namespace prepended if it has no `::` and one is set.
+    fn qualify<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        match &self.default_ns {
+            Some(ns) if !path.contains("::") => Cow::Owned(format!("{}::{}", ns, path)),
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    /// Splits `path` into namespace and key, using the default namespace for a path without `::`.
+    fn split_ns<'a>(&'a self, path: &'a str) -> Option<(&'a str, &'a str)> {
+        match (path.split_once("::"), &self.default_ns) {
+            (Some(parts), _) => Some(parts),
+            (None, Some(ns)) => Some((ns.as_str(), path)),
+            (None, None) => None,
+        }
+    }
+
+    /// Lists all available namespaces: the .sqlite files in the base directory plus any
+    /// relocated, attached or soft namespaces. Fails with `BasePathUnavailable` if the base
+    /// directory is missing or cannot be read.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn list_ns(&self) -> Result<Vec<String>> {
+        let infos = self.list_ns_info("").await?;
+        Ok(infos.into_iter().map(|info| info.name).collect())
+    }
+
+    /// Like `list_ns`, for the namespaces whose name starts with `prefix` ("" for all), with
+    /// the size, modification time and location of their files.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(prefix = %prefix), err(Display)))]
+    pub async fn list_ns_info(&self, prefix: &str) -> Result<Vec<NamespaceInfo>> {
+        let base_path = self.base_path.clone();
+        let files = rt::unblock(move || {
+            std::fs::read_dir(&base_path)?
+                .map(|entry| Ok(entry?.file_name()))
+                .collect::<std::io::Result<Vec<_>>>()
+        })
+        .await
+        .map_err(|e| {
+            let reason = match e.kind() {
+                std::io::ErrorKind::NotFound => "does not exist".to_string(),
+                std::io::ErrorKind::NotADirectory => "is not a directory".to_string(),
+                _ => format!("cannot be read: {}", e),
+            };
+            PathmapError::BasePathUnavailable(self.base_path.display().to_string(), reason)
+        })?;
+        let local = files
+            .iter()
+            .filter_map(|name| name.to_str()?.strip_suffix(".sqlite"))
+            .map(str::to_string);
+        let mut infos = Vec::new();
+        for ns in self.known_ns(local) {
+            if !ns.starts_with(prefix) {
+                continue;
+            }
+            let soft_host = self.soft_ns.get(&ns).cloned();
+            let path = self.get_db_path(soft_host.as_deref().unwrap_or(&ns));
+            let meta = rt::fs::metadata(&path).await.ok();
+            let open = self
+                .pools
+                .read()
+                .unwrap()
+                .get(&ns)
+                .is_some_and(|slot| slot.initialized());
+            infos.push(NamespaceInfo {
+                name: ns,
+                file_size: meta.as_ref().map_or(0, |m| m.len()),
+                modified: meta.and_then(|m| m.modified().ok()),
+                path,
+                open,
+                soft_host,
+            });
         }
-        for entry in std::fs::read_dir(&self.base_path)? {
-            let entry = entry?;
-            if let Some(filename_str) = entry.file_name().to_str() {
-                if let Some(ns_name) = filename_str.strip_suffix(".sqlite") {
-                    namespaces.push(ns_name.to_string());
+        Ok(infos)
+    }
+
+    /// Names of the available namespaces, treating a missing base directory as holding none;
+    /// for the operations that visit every namespace.
+    fn namespace_names(&self) -> Result<Vec<String>> {
+        let mut local = Vec::new();
+        if self.base_path.exists() {
+            for entry in std::fs::read_dir(&self.base_path)? {
+                if let Some(ns) = entry?
+                    .file_name()
+                    .to_str()
+                    .and_then(|f| f.strip_suffix(".sqlite"))
+                {
+                    local.push(ns.to_string());
                 }
             }
         }
+        Ok(self.known_ns(local))
+    }
+
+    /// `local` (namespaces found in the base directory) merged with the relocated, attached and
+    /// soft namespaces whose files exist, sorted.
+    fn known_ns(&self, local: impl IntoIterator<Item = String>) -> Vec<String> {
+        let mut namespaces: Vec<String> = self.attached.read().unwrap().keys().cloned().collect();
+        for ns in self.ns_paths.keys() {
+            if self.get_db_path(ns).exists() {
+                namespaces.push(ns.clone());
+            }
+        }
+        for (ns, host) in &self.soft_ns {
+            if self.get_db_path(host).exists() {
+                namespaces.push(ns.clone());
+            }
+        }
+        namespaces.extend(
+            local
+                .into_iter()
+                .filter(|ns| !self.ns_paths.contains_key(ns)),
+        );
         namespaces.sort();
-        Ok(namespaces)
+        namespaces.dedup();
+        namespaces
     }
 
-    /// Lists the contents (groups and values) of a given path.
+    /// Lists the contents (groups and values) of a given path. A path without `::` names a
+    /// namespace, or a group of the default namespace if one is set.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
     pub async fn list(&self, path: &str) -> Result<Listing> {
-        let (ns, prefix) = match path.split_once("::") {
-            Some((ns, group_path)) => (ns, format!("{}.", group_path)),
-            None => (path, String::new()),
+        error::in_context("list", &self.qualify(path), async {
+            self.retry_busy(|| self.list_once(path)).await
+        })
+        .await
+    }
+
+    async fn list_once(&self, path: &str) -> Result<Listing> {
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        self.name_rules.check_ns(ns)?;
+        let grp = self.normalize_key(grp);
+        let grp = grp.as_ref();
+        self.name_rules.check_key(grp)?;
+        let depth = if grp.is_empty() {
+            0
+        } else {
+            grp.split('.').count()
         };
+        if self.group_depth.is_some_and(|max| depth > max) {
+            return Err(PathmapError::InvalidPath(path.to_string()));
+        }
+        let (ns, grp) = self.resolve_soft(ns, Cow::Borrowed(grp))?;
+        let grp = grp.as_ref();
 
         let pool = self.get_pool(ns).await?;
-        let all_keys = db::list_keys(&pool, &prefix).await?;
-
-        let mut groups = HashSet::new();
-        let mut values = Vec::new();
-
-        for key in all_keys {
-            let sub_path = key.strip_prefix(&prefix).unwrap_or(&key);
-            match sub_path.split_once('.') {
-                Some((group_name, _)) => {
-                    groups.insert(group_name.to_string());
-                }
-                None => {
-                    if !sub_path.is_empty() {
-                        values.push(sub_path.to_string());
-                    }
-                }
-            }
+        let mut values = db::list_values(&pool, grp).await?;
+        let mut sorted_groups = db::list_child_groups(&pool, grp).await?;
+        let order = &self.ns_config(ns).listing_order;
+        order.sort(&mut sorted_groups);
+        if !matches!(order, ListingOrder::Binary) {
+            order.sort(&mut values);
         }
 
-        let mut sorted_groups: Vec<String> = groups.into_iter().collect();
-        sorted_groups.sort();
-        values.sort();
-
         Ok(Listing {
             groups: sorted_groups,
             values,
@@ -102,148 +689,1162 @@ impl Pathmap {
     }
 
     /// Initializes a new namespace.
+    /// For a soft namespace this creates the host if needed and always succeeds.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
     pub async fn init_ns(&self, ns: &str) -> Result<bool> {
-        let db_path = self.get_db_path(ns);
-        if db_path.exists() {
-            return Err(PathmapError::NamespaceAlreadyExists(ns.to_string()));
-        }
-        let pool = db::connect(&db_path).await?;
-        let mut pools = self.pools.lock().await;
-        pools.insert(ns.to_string(), pool);
-        Ok(true)
+        error::in_context("init_ns", ns, async {
+            self.name_rules.check_ns(ns)?;
+            let soft = self.soft_ns.contains_key(ns);
+            let ns = self.soft_ns.get(ns).map_or(ns, String::as_str);
+            let db_path = self.get_db_path(ns);
+            if db_path.exists() {
+                if soft {
+                    return Ok(true);
+                }
+                return Err(PathmapError::NamespaceAlreadyExists(ns.to_string()));
+            }
+            let pool = self.connect(ns, &db_path).await?;
+            self.insert_pool(ns, pool);
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Mounts an existing SQLite file from an arbitrary location as a namespace.
+    /// The file is used in place and is not copied into the base path. It must already hold a
+    /// kvmap store, which is migrated to the current schema; other SQLite files are refused with
+    /// `InvalidConfig`, and a missing file fails with an `IoError` of kind `NotFound`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn attach_ns<P: AsRef<Path>>(&self, ns: &str, db_path: P) -> Result<bool> {
+        error::in_context("attach_ns", ns, async {
+            let db_path = db_path.as_ref();
+            self.name_rules.check_ns(ns)?;
+            self.reject_soft(ns)?;
+            if !db_path.is_file() {
+                return Err(PathmapError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("'{}' is not a file", db_path.display()),
+                )));
+            }
+            if self.get_db_path(ns).exists() {
+                return Err(PathmapError::NamespaceAlreadyExists(ns.to_string()));
+            }
+            // Opening migrates the schema, which must not happen to another application's file.
+            if !db::has_store(db_path).await? {
+                return Err(PathmapError::InvalidConfig(format!(
+                    "'{}' is not a kvmap database",
+                    db_path.display()
+                )));
+            }
+            let pool = self.connect(ns, db_path).await?;
+            self.insert_pool(ns, pool);
+            self.attached
+                .write()
+                .unwrap()
+                .insert(ns.to_string(), db_path.to_path_buf());
+            self.invalidate_cached_ns(ns);
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Unmounts an attached namespace, leaving its SQLite file untouched.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn detach_ns(&self, ns: &str) -> Result<bool> {
+        error::in_context("detach_ns", ns, async {
+            if self.attached.write().unwrap().remove(ns).is_none() {
+                return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+            }
+            if let Some(pool) = self.remove_pool(ns) {
+                pool.close().await;
+            }
+            self.invalidate_cached_ns(ns);
+            Ok(true)
+        })
+        .await
     }
 
     /// Deletes a namespace, including its SQLite file.
+    /// An attached namespace is only detached: its file belongs to someone else and is kept.
+    /// A soft namespace only loses its rows in the host, and returns whether it had any.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
     pub async fn delete_ns(&self, ns: &str) -> Result<bool> {
-        {
-            let mut pools = self.pools.lock().await;
-            if let Some(pool) = pools.remove(ns) {
+        error::in_context("delete_ns", ns, async {
+            self.name_rules.check_ns(ns)?;
+            if let Some(host) = self.soft_ns.get(ns) {
+                let pool = self
+                    .get_pool(host)
+                    .await
+                    .map_err(|_| PathmapError::NamespaceNotFound(ns.to_string()))?;
+                let refs = db::external_refs(&pool, ns).await?;
+                let removed = self.delete_group_rows(host, &pool, ns).await?;
+                self.invalidate_cached_ns(host);
+                for name in refs {
+                    self.remove_blob(host, name.as_bytes()).await?;
+                }
+                return Ok(removed > 0);
+            }
+            if self.attached.read().unwrap().contains_key(ns) {
+                return self.detach_ns(ns).await;
+            }
+            self.lock_instance()?;
+            if let Some(pool) = self.remove_pool(ns) {
                 pool.close().await;
             }
+            self.invalidate_cached_ns(ns);
+            let db_path = self.get_db_path(ns);
+            if !db_path.exists() {
+                return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+            }
+            let blob_dir = blob::blob_dir(&db_path);
+            std::fs::remove_file(db_path)?;
+            if blob_dir.exists() {
+                std::fs::remove_dir_all(blob_dir)?;
+            }
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Parses a path string like "namespace::group.key" into (namespace, key).
+    /// Soft namespaces resolve to their host and the prefixed key.
+    fn parse_path<'a>(&'a self, path: &'a str) -> Result<(&'a str, Cow<'a, str>)> {
+        let (ns, key) = self
+            .split_ns(path)
+            .ok_or_else(|| PathmapError::InvalidPath(path.to_string()))?;
+        self.name_rules.check_ns(ns)?;
+        let key = self.normalize_key(key);
+        self.name_rules.check_key(&key)?;
+        self.resolve_soft(ns, key)
+    }
+
+    /// Maps a key of a soft namespace to the host namespace, where the soft namespace's name is
+    /// the first group segment. Regular namespaces pass through unchanged.
+    fn resolve_soft<'a>(
+        &'a self,
+        ns: &'a str,
+        key: Cow<'a, str>,
+    ) -> Result<(&'a str, Cow<'a, str>)> {
+        let Some(host) = self.soft_ns.get(ns) else {
+            return Ok((ns, key));
+        };
+        if self.group_depth.is_some() {
+            return Err(PathmapError::InvalidConfig(format!(
+                "soft namespace '{}' cannot be used with a fixed group depth",
+                ns
+            )));
         }
-        let db_path = self.get_db_path(ns);
-        if !db_path.exists() {
-            return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+        if ns.is_empty() || ns.contains('.') {
+            return Err(PathmapError::InvalidConfig(format!(
+                "invalid soft namespace name '{}'",
+                ns
+            )));
         }
-        std::fs::remove_file(db_path)?;
-        Ok(true)
+        let key = if key.is_empty() {
+            Cow::Borrowed(ns)
+        } else {
+            Cow::Owned(format!("{}.{}", ns, key))
+        };
+        Ok((host.as_str(), key))
     }
 
-    /// Parses a path string like "namespace::group.key" into (namespace, key).
-    fn parse_path<'a>(&self, path: &'a str) -> Result<(&'a str, &'a str)> {
-        path.split_once("::")
-            .ok_or_else(|| PathmapError::InvalidPath(path.to_string()))
+    /// Refuses file-level operations on a soft namespace, which has no file of its own.
+    fn reject_soft(&self, ns: &str) -> Result<()> {
+        match self.soft_ns.get(ns) {
+            Some(host) => Err(PathmapError::InvalidConfig(format!(
+                "'{}' is a soft namespace stored in '{}'; use the host for file-level operations",
+                ns, host
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies NFC normalization when enabled, borrowing when the key is already normalized.
+    fn normalize_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if self.normalize_keys && !is_nfc(key) {
+            Cow::Owned(key.nfc().collect())
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+
+    /// Splits a dotted key into its stored (group, value name) columns.
+    fn split_key<'a>(&self, key: &'a str) -> (&'a str, &'a str) {
+        db::split_key(key, self.group_depth)
     }
 
     /// Retrieves a value.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
     pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        error::in_context("get", &self.qualify(path), async {
+            let raw_value = self.retry_busy(|| self.get_raw(path)).await?;
+            let value: T = serde_json::from_slice(&raw_value)?;
+            Ok(value)
+        })
+        .await
+    }
+
+    /// The serialized value at `path`.
+    async fn get_raw(&self, path: &str) -> Result<Vec<u8>> {
         let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        self.refresh_replica(path, ns, grp, key).await?;
+        let raw_value = match self.queued_value(ns, grp, key).await {
+            Some(queued) => queued,
+            None => self.read_value(ns, grp, key).await?,
+        };
+        if let Some(shadow) = &self.shadow {
+            shadow.observe(path, raw_value.as_deref());
+        }
+        raw_value.ok_or_else(|| PathmapError::ValueNotFound(db::join_key(grp, key)))
+    }
+
+    /// Reads one field of the document at `path`, addressed by a SQLite JSON path such as
+    /// `$.profile.email` or `$.items[0]`, without reading and parsing the whole value: SQLite
+    /// extracts it. A missing field fails with `ValueNotFound`; a path not starting with `$`
+    /// with `InvalidPath`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path, json_path = %json_path), err(Display)))]
+    pub async fn get_field<T: DeserializeOwned>(&self, path: &str, json_path: &str) -> Result<T> {
+        error::in_context("get_field", &self.qualify(path), async {
+            if !json_path.starts_with('$') {
+                return Err(PathmapError::InvalidPath(format!(
+                    "JSON path '{}' does not start with '$'",
+                    json_path
+                )));
+            }
+            let field = self
+                .retry_busy(|| self.get_field_raw(path, json_path))
+                .await?;
+            Ok(serde_json::from_str(&field)?)
+        })
+        .await
+    }
+
+    async fn get_field_raw(&self, path: &str, json_path: &str) -> Result<String> {
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        self.refresh_replica(path, ns, grp, key).await?;
+        let not_found =
+            || PathmapError::ValueNotFound(format!("{} {}", db::join_key(grp, key), json_path));
+        // Pending and cached values are newer than, or as new as, the row.
+        let known = match self.queued_value(ns, grp, key).await {
+            Some(queued) => {
+                Some(queued.ok_or_else(|| PathmapError::ValueNotFound(db::join_key(grp, key)))?)
+            }
+            None => self.read_cache.get(ns, grp, key),
+        };
         let pool = self.get_pool(ns).await?;
-        let raw_value = db::get(&pool, key).await?;
-        let value: T = serde_json::from_slice(&raw_value)?;
-        Ok(value)
+        let field = match known {
+            Some(value) => db::extract_field(&pool, &value, json_path).await?,
+            None => {
+                let row = db::find_field(&pool, grp, key, json_path)
+                    .await?
+                    .ok_or_else(|| PathmapError::ValueNotFound(db::join_key(grp, key)))?;
+                match row.blob {
+                    Some(name) => {
+                        let stored = db::StoredValue {
+                            data: name,
+                            external: true,
+                        };
+                        let value = self.load_value(ns, stored).await?;
+                        db::extract_field(&pool, &value, json_path).await?
+                    }
+                    None => row.field,
+                }
+            }
+        };
+        field.ok_or_else(not_found)
     }
 
     /// Sets a value, failing if the key already exists.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
     pub async fn set<T: Serialize>(&self, path: &str, value: T) -> Result<()> {
+        error::in_context("set", &self.qualify(path), async {
+            self.throttle_write(path).await?;
+            self.retry_busy(|| self.set_once(path, &value)).await
+        })
+        .await
+    }
+
+    async fn set_once<T: Serialize + ?Sized>(&self, path: &str, value: &T) -> Result<()> {
+        if let Some(replica) = &self.replica {
+            let value = serde_json::to_value(value)?;
+            self.forward_write(replica, path, Some(value), false)
+                .await?;
+            return Ok(());
+        }
         let (ns, key) = self.parse_path(path)?;
         let pool = self.get_pool(ns).await?;
-        if db::exists(&pool, key).await? {
-            return Err(PathmapError::ValueAlreadyExists(key.to_string()));
+        let full_key = key;
+        let (grp, key) = self.split_key(&full_key);
+        // A value may not shadow a group of the same name either.
+        if db::exists_value(&pool, grp, key).await? || db::exists_group(&*pool, &full_key).await? {
+            return Err(PathmapError::ValueAlreadyExists(full_key.to_string()));
+        }
+        let serialized_value = self.encode_write(ns, &full_key, value)?;
+        let new_size = serialized_value.len() as u64;
+        self.check_value_quota(ns, new_size)?;
+        if self.write_queue.is_some() {
+            if let Some(Some(_)) = self.queued_value(ns, grp, key).await {
+                return Err(PathmapError::ValueAlreadyExists(db::join_key(grp, key)));
+            }
+            self.write(ns, grp, key, Some(serialized_value)).await?;
+            return Ok(());
+        }
+        let (data, external) = self.store_value(ns, serialized_value).await?;
+        let result = self
+            .write_row(ns, &pool, grp, key, RowWrite::Set(&data, external), None)
+            .await;
+        self.invalidate_cached(ns, grp, key);
+        if result.is_err() && external {
+            self.remove_blob(ns, &data).await?;
+        }
+        result?;
+        self.emit_change(Operation::Set, ns, &full_key, None, Some(new_size));
+        Ok(())
+    }
+
+    /// Like `set`, but reports an existing value (or group) at the path as `Ok(false)` rather
+    /// than `ValueAlreadyExists`, leaving it untouched; `Ok(true)` when the value was stored.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn try_set<T: Serialize>(&self, path: &str, value: T) -> Result<bool> {
+        match self.set(path, value).await {
+            Ok(()) => Ok(true),
+            Err(e) if matches!(e.root(), PathmapError::ValueAlreadyExists(_)) => Ok(false),
+            Err(e) => Err(e),
         }
-        let serialized_value = serde_json::to_vec(&value)?;
-        db::set(&pool, key, &serialized_value).await
     }
 
-    /// Overwrites a value. Creates it if it doesn't exist.
-    pub async fn overwrite<T: Serialize>(&self, path: &str, value: T) -> Result<()> {
+    /// Overwrites a value. Creates it if it doesn't exist, and reports which of the two it did.
+    /// While the write queue holds mutations, an existing value is looked up among them. On a
+    /// replica the primary reports it.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn overwrite<T: Serialize>(&self, path: &str, value: T) -> Result<WriteOutcome> {
+        error::in_context("overwrite", &self.qualify(path), async {
+            self.throttle_write(path).await?;
+            self.retry_busy(|| self.overwrite_once(path, &value)).await
+        })
+        .await
+    }
+
+    async fn overwrite_once<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        value: &T,
+    ) -> Result<WriteOutcome> {
+        if let Some(replica) = &self.replica {
+            let value = serde_json::to_value(value)?;
+            let existed = self.forward_write(replica, path, Some(value), true).await?;
+            return Ok(write_outcome(existed));
+        }
         let (ns, key) = self.parse_path(path)?;
-        let pool = self.get_pool_or_init(ns).await?;
-        let serialized_value = serde_json::to_vec(&value)?;
-        db::overwrite(&pool, key, &serialized_value).await
+        let serialized_value = self.encode_write(ns, &key, value)?;
+        let (grp, key) = self.split_key(&key);
+        self.check_value_quota(ns, serialized_value.len() as u64)?;
+        let existed = self.write(ns, grp, key, Some(serialized_value)).await?;
+        Ok(write_outcome(existed))
     }
 
-    /// Deletes a value.
-    pub async fn delete(&self, path: &str) -> Result<()> {
+    /// Deletes a value, returning whether there was one; deleting a missing key is not an error,
+    /// so check the result where a missing key means a bug. Like `overwrite`, this consults the
+    /// write queue, and on a replica the primary reports it.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn delete(&self, path: &str) -> Result<bool> {
+        error::in_context("delete", &self.qualify(path), async {
+            self.throttle_write(path).await?;
+            self.retry_busy(|| self.delete_once(path)).await
+        })
+        .await
+    }
+
+    async fn delete_once(&self, path: &str) -> Result<bool> {
+        if let Some(replica) = &self.replica {
+            return self.forward_write(replica, path, None, true).await;
+        }
         let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        self.write(ns, grp, key, None).await
+    }
+
+    /// Reads a value and deletes it in one transaction, for work queues and one-shot values: of
+    /// several concurrent callers only one receives the value, the others fail with
+    /// `ValueNotFound`. A value that does not deserialize as `T` is kept. Goes straight to the
+    /// database, bypassing the write queue and write batches, and is not available on a replica.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn take<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        error::in_context("take", &self.qualify(path), async {
+            self.throttle_write(path).await?;
+            self.retry_busy(|| self.take_once(path)).await
+        })
+        .await
+    }
+
+    async fn take_once<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        if self.replica.is_some() {
+            return Err(PathmapError::InvalidConfig(
+                "take cannot be forwarded to the primary of a replica".to_string(),
+            ));
+        }
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        let pool = self.get_pool(ns).await?;
+        let mut tx = pool.begin().await?;
+        let stored = db::take(&mut *tx, grp, key)
+            .await?
+            .ok_or_else(|| PathmapError::ValueNotFound(db::join_key(grp, key)))?;
+        let blob = stored.external.then(|| stored.data.clone());
+        let raw_value = self.load_value(ns, stored).await?;
+        let value: T = serde_json::from_slice(&raw_value)?;
+        self.audit_replaced(ns, &mut tx, Operation::Delete, grp, key, &raw_value)
+            .await?;
+        tx.commit().await?;
+        self.invalidate_cached(ns, grp, key);
+        let old_size = Some(raw_value.len() as u64);
+        self.emit_change(
+            Operation::Delete,
+            ns,
+            &db::join_key(grp, key),
+            old_size,
+            None,
+        );
+        if let Some(name) = blob {
+            self.remove_blob(ns, &name).await?;
+        }
+        Ok(value)
+    }
+
+    /// Applies an RFC 7396 JSON merge patch to the value at `path` in one transaction, so a
+    /// field can change without rewriting the whole document: object members of `patch` replace
+    /// the stored ones, `null` members remove them, and a patch that is not an object replaces
+    /// the value. The merge runs in SQLite (`json_patch`); the result then goes through the
+    /// namespace's normalizers, number policies and quota like any `overwrite`. Goes straight to
+    /// the database, bypassing the write queue and write batches, and is not available on a
+    /// replica.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn patch<T: Serialize>(&self, path: &str, patch: T) -> Result<()> {
+        error::in_context("patch", &self.qualify(path), async {
+            self.throttle_write(path).await?;
+            let patch = serde_json::to_string(&patch)?;
+            self.retry_busy(|| self.patch_once(path, &patch)).await
+        })
+        .await
+    }
+
+    async fn patch_once(&self, path: &str, patch: &str) -> Result<()> {
+        if self.replica.is_some() {
+            return Err(PathmapError::InvalidConfig(
+                "patch cannot be forwarded to the primary of a replica".to_string(),
+            ));
+        }
+        let (ns, full_key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&full_key);
+        self.settle_batches(ns, Some((grp, key))).await;
+        let pool = self.get_pool(ns).await?;
+        let mut tx = pool.begin().await?;
+        let previous = db::find(&mut *tx, grp, key)
+            .await?
+            .ok_or_else(|| PathmapError::ValueNotFound(db::join_key(grp, key)))?;
+        let old_value = self.load_value(ns, previous.clone()).await?;
+        let merged = db::merge_patch(&mut *tx, &old_value, patch).await?;
+        let merged: serde_json::Value = serde_json::from_slice(&merged)?;
+        let serialized_value = self.encode_write(ns, &full_key, &merged)?;
+        let new_size = serialized_value.len() as u64;
+        self.check_value_quota(ns, new_size)?;
+        let (data, external) = self.store_value(ns, serialized_value).await?;
+        let now = db::unix_millis(self.clock.system_now());
+        let written = async {
+            db::overwrite(&mut *tx, grp, key, &data, external, now).await?;
+            let quota = Quota {
+                max_keys: None,
+                ..self.ns_config(ns).quota
+            };
+            quota.check_totals(ns, &mut tx).await?;
+            self.audit_replaced(ns, &mut tx, Operation::Overwrite, grp, key, &old_value)
+                .await?;
+            tx.commit().await?;
+            Ok::<_, PathmapError>(())
+        };
+        if let Err(e) = written.await {
+            if external {
+                self.remove_blob(ns, &data).await?;
+            }
+            return Err(e);
+        }
+        self.invalidate_cached(ns, grp, key);
+        let old_size = Some(old_value.len() as u64);
+        self.emit_change(
+            Operation::Overwrite,
+            ns,
+            &db::join_key(grp, key),
+            old_size,
+            Some(new_size),
+        );
+        if previous.external {
+            self.remove_blob(ns, &previous.data).await?;
+        }
+        Ok(())
+    }
+
+    /// Exchanges the values at two paths of the same namespace in one transaction, so readers
+    /// see either both old values or both new ones, as for blue/green configuration flips. Both
+    /// values must exist (`ValueNotFound` otherwise); a unique index on their group does not
+    /// reject the exchange. Goes straight to the database, bypassing the write queue and write
+    /// batches, and is not available on a replica.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path_a = %path_a, path_b = %path_b), err(Display)))]
+    pub async fn swap(&self, path_a: &str, path_b: &str) -> Result<()> {
+        error::in_context("swap", &self.qualify(path_a), async {
+            self.throttle_write(path_a).await?;
+            self.throttle_write(path_b).await?;
+            self.retry_busy(|| self.swap_once(path_a, path_b)).await
+        })
+        .await
+    }
+
+    async fn swap_once(&self, path_a: &str, path_b: &str) -> Result<()> {
+        if self.replica.is_some() {
+            return Err(PathmapError::InvalidConfig(
+                "swap cannot be forwarded to the primary of a replica".to_string(),
+            ));
+        }
+        let (ns, key_a) = self.parse_path(path_a)?;
+        let (ns_b, key_b) = self.parse_path(path_b)?;
+        if ns != ns_b {
+            return Err(PathmapError::InvalidPath(format!(
+                "cannot swap '{}' and '{}' across namespaces",
+                path_a, path_b
+            )));
+        }
+        let a = self.split_key(&key_a);
+        let b = self.split_key(&key_b);
+        self.settle_batches(ns, Some(a)).await;
+        self.settle_batches(ns, Some(b)).await;
+        let pool = self.get_pool(ns).await?;
+        if a == b {
+            return match db::find(&*pool, a.0, a.1).await? {
+                Some(_) => Ok(()),
+                None => Err(PathmapError::ValueNotFound(db::join_key(a.0, a.1))),
+            };
+        }
+        let (size_a, size_b) = self.swap_rows(ns, &pool, a, b).await?;
+        for ((grp, key), old_size, new_size) in [(a, size_b, size_a), (b, size_a, size_b)] {
+            self.invalidate_cached(ns, grp, key);
+            let key = db::join_key(grp, key);
+            self.emit_change(
+                Operation::Overwrite,
+                ns,
+                &key,
+                Some(old_size),
+                Some(new_size),
+            );
+        }
+        Ok(())
+    }
+
+    /// Every value below a group, at any depth, as dotted keys relative to it and in key order:
+    /// for `ns::a` holding `ns::a.b.c` and `ns::a.d`, `["b.c", "d"]`. A namespace path lists
+    /// the whole namespace; a group without values lists nothing.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn list_recursive(&self, path: &str) -> Result<Vec<String>> {
+        error::in_context("list_recursive", &self.qualify(path), async {
+            self.retry_busy(|| self.list_recursive_once(path)).await
+        })
+        .await
+    }
+
+    async fn list_recursive_once(&self, path: &str) -> Result<Vec<String>> {
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        self.name_rules.check_ns(ns)?;
+        let grp = self.normalize_key(grp);
+        self.name_rules.check_key(&grp)?;
+        let (ns, grp) = self.resolve_soft(ns, grp)?;
         let pool = self.get_pool(ns).await?;
-        db::delete(&pool, key).await
+        let mut keys: Vec<String> = db::nested_keys(&pool, &grp)
+            .await?
+            .into_iter()
+            .map(|(entry_grp, key)| {
+                // Slice rather than strip so case-insensitive collations still line up.
+                let sub_group = match grp.len() {
+                    0 => entry_grp.as_str(),
+                    len => entry_grp.get(len + 1..).unwrap_or(""),
+                };
+                db::join_key(sub_group, &key)
+            })
+            .collect();
+        keys.sort();
+        Ok(keys)
     }
 
-    /// Checks if a path (namespace, group, or value) exists.
+    /// Number of values below a namespace or group at any depth, the length `list_recursive`
+    /// would return, counted by SQLite without reading the keys.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn count(&self, path: &str) -> Result<u64> {
+        error::in_context("count", &self.qualify(path), async {
+            self.retry_busy(|| self.count_once(path)).await
+        })
+        .await
+    }
+
+    async fn count_once(&self, path: &str) -> Result<u64> {
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        self.name_rules.check_ns(ns)?;
+        let grp = self.normalize_key(grp);
+        self.name_rules.check_key(&grp)?;
+        let (ns, grp) = self.resolve_soft(ns, grp)?;
+        let pool = self.get_pool(ns).await?;
+        db::count_nested(&pool, &grp).await
+    }
+
+    /// Deletes a group and everything nested below it, returning the number of removed values.
+    /// A value stored at the group's own path (`ns::a.b` for group `ns::a.b`) is not part of the
+    /// group and is kept; delete it with `delete`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn delete_group(&self, path: &str) -> Result<u64> {
+        error::in_context("delete_group", &self.qualify(path), async {
+            self.retry_busy(|| self.delete_group_once(path)).await
+        })
+        .await
+    }
+
+    async fn delete_group_once(&self, path: &str) -> Result<u64> {
+        if let Some(replica) = &self.replica {
+            return self.forward_delete_group(replica, path).await;
+        }
+        let (ns, grp) = self.parse_path(path)?;
+        if grp.is_empty() {
+            return Err(PathmapError::InvalidPath(path.to_string()));
+        }
+        let pool = self.get_pool(ns).await?;
+        let refs = db::external_refs(&pool, &grp).await?;
+        let removed = self.delete_group_rows(ns, &pool, &grp).await?;
+        self.invalidate_cached_ns(ns);
+        if removed > 0 {
+            self.emit_change(Operation::DeleteGroup, ns, &grp, None, None);
+        }
+        for name in refs {
+            self.remove_blob(ns, name.as_bytes()).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Checks if a path (namespace, group, or value) exists: a path without `::` is checked with
+    /// `exists_ns` (unless a default namespace is set), any other path with `exists_value` or
+    /// `exists_group`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
     pub async fn exists(&self, path: &str) -> Result<bool> {
-        if let Ok((ns, key)) = self.parse_path(path) {
-            if self.get_db_path(ns).exists() {
-                let pool = self.get_pool(ns).await?;
-                return db::exists(&pool, key).await;
+        if !path.contains("::") && self.default_ns.is_none() {
+            return Ok(self.exists_ns(path));
+        }
+        Ok(self.exists_value(path).await? || self.exists_group(path).await?)
+    }
+
+    /// Checks if a namespace exists. A soft namespace exists when its host does; an invalid name
+    /// never exists.
+    pub fn exists_ns(&self, ns: &str) -> bool {
+        if self.name_rules.check_ns(ns).is_err() {
+            return false;
+        }
+        let ns = self.soft_ns.get(ns).map_or(ns, String::as_str);
+        self.get_db_path(ns).exists()
+    }
+
+    /// Checks if a value is stored at exactly this path; `ns::foo` does not match `ns::foobar`
+    /// or `ns::foo.bar`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn exists_value(&self, path: &str) -> Result<bool> {
+        error::in_context("exists_value", &self.qualify(path), async {
+            self.retry_busy(|| self.exists_value_once(path)).await
+        })
+        .await
+    }
+
+    async fn exists_value_once(&self, path: &str) -> Result<bool> {
+        let (ns, key) = self.parse_path(path)?;
+        if !self.get_db_path(ns).exists() {
+            return Ok(false);
+        }
+        let pool = self.get_pool(ns).await?;
+        let (grp, key) = self.split_key(&key);
+        db::exists_value(&pool, grp, key).await
+    }
+
+    /// Checks if a group holds any value, directly or nested; `ns::foo` matches `ns::foo.bar`
+    /// but not `ns::foobar`. `ns::` checks whether the namespace holds anything.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn exists_group(&self, path: &str) -> Result<bool> {
+        error::in_context("exists_group", &self.qualify(path), async {
+            self.retry_busy(|| self.exists_group_once(path)).await
+        })
+        .await
+    }
+
+    async fn exists_group_once(&self, path: &str) -> Result<bool> {
+        let (ns, grp) = self.parse_path(path)?;
+        if !self.get_db_path(ns).exists() {
+            return Ok(false);
+        }
+        let pool = self.get_pool(ns).await?;
+        db::exists_group(&*pool, &grp).await
+    }
+
+    /// Shows how SQLite executes an operation on `path`: the query plan of every statement it runs.
+    /// Plans with `is_full_scan()` read the whole table and slow down as the namespace grows.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn explain(&self, op: ExplainOp, path: &str) -> Result<Vec<QueryPlan>> {
+        error::in_context("explain", &self.qualify(path), async {
+            self.retry_busy(|| self.explain_once(op, path)).await
+        })
+        .await
+    }
+
+    async fn explain_once(&self, op: ExplainOp, path: &str) -> Result<Vec<QueryPlan>> {
+        let (ns, key) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        match op {
+            ExplainOp::DeleteGroup | ExplainOp::List => db::explain(&pool, op, &key, "").await,
+            _ => {
+                let (grp, key) = self.split_key(&key);
+                db::explain(&pool, op, grp, key).await
             }
-        } else if self.get_db_path(path).exists() {
-            return Ok(true);
         }
-        Ok(false)
     }
 
     /// Manually triggers a cleanup (VACUUM) on a namespace's database.
+    /// Blob files no longer referenced by any value are removed as well, once they are older than
+    /// `blob::SWEEP_GRACE`, so files of writes still in flight survive.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
     pub async fn manual_cleanup(&self, ns: &str) -> Result<()> {
-        let pool = self.get_pool(ns).await?;
-        db::vacuum(&pool).await
+        error::in_context("manual_cleanup", ns, async {
+            let pool = self.get_pool(ns).await?;
+            let _lock = self.maintenance_lock(ns)?;
+            self.in_background(ns, db::vacuum(&pool)).await?;
+            let referenced: HashSet<String> =
+                db::external_refs(&pool, "").await?.into_iter().collect();
+            blob::sweep(&blob::blob_dir(&self.get_db_path(ns)), &referenced).await?;
+            Ok(())
+        })
+        .await
     }
 
     /// Starts a background task for automatic cleanup.
-    pub fn start_background_cleanup(&self, check_interval: Duration, idle_timeout: Duration) {
+    /// Namespaces that no operation used for `idle_timeout` are vacuumed at most once per idle
+    /// period, and only when their free pages reach the thresholds of the cleanup policy (see
+    /// `with_cleanup_policy`). Idle pools are closed if `with_idle_pool_close` is set. Storage alerts and the integrity
+    /// checks of `with_integrity_checks` are evaluated for every open namespace on each pass.
+    /// The returned handle stops the task, triggers a pass or changes its intervals. Scheduled
+    /// actions are executed too when it is started with `supervise`.
+    pub fn start_background_cleanup(
+        &self,
+        check_interval: Duration,
+        idle_timeout: Duration,
+    ) -> CleanupHandle {
+        self.spawn_cleanup(check_interval, idle_timeout, None)
+    }
+
+    /// Starts the background cleanup of `start_background_cleanup` on a shared instance, and
+    /// runs the due scheduled actions (`run_due_actions`) at the start of each of its passes.
+    /// The task holds the instance weakly, so it still stops once the instance is dropped.
+    pub fn supervise(
+        self: &Arc<Self>,
+        check_interval: Duration,
+        idle_timeout: Duration,
+    ) -> CleanupHandle {
+        let instance = Arc::downgrade(self);
+        let run_due: cleanup::PassHook = Arc::new(move || {
+            let instance = instance.clone();
+            Box::pin(async move {
+                let Some(pm) = instance.upgrade() else {
+                    return;
+                };
+                if let Err(e) = pm.run_due_actions().await
+                    && !matches!(e.root(), PathmapError::Closed)
+                {
+                    tracing::warn!(error = %e, "scheduler pass failed");
+                }
+            })
+        });
+        self.spawn_cleanup(check_interval, idle_timeout, Some(run_due))
+    }
+
+    fn spawn_cleanup(
+        &self,
+        check_interval: Duration,
+        idle_timeout: Duration,
+        on_pass: Option<cleanup::PassHook>,
+    ) -> CleanupHandle {
         let pools = Arc::clone(&self.pools);
-        let last_access = Arc::new(Mutex::new(HashMap::<String, time::Instant>::new()));
-
-        tokio::spawn(async move {
-            let mut interval = time::interval(check_interval);
-            loop {
-                interval.tick().await;
-                let pools_to_check: Vec<(String, SqlitePool)> = pools
-                    .lock()
-                    .await
+        let access = Arc::clone(&self.access);
+        // When each namespace was last vacuumed, so an idle one is vacuumed at most once per idle
+        // period.
+        let mut vacuumed = HashMap::<String, Instant>::new();
+        let clock = Arc::clone(&self.clock);
+        let pool_close_after = self.pool_close_after;
+        let open_mode = self.open_mode;
+        let lanes = Arc::clone(&self.lanes);
+        let ns_configs = self.ns_configs.clone();
+        let default_ns_config = self.default_ns_config.clone();
+        let events = Arc::clone(&self.events);
+        let storage_alerts = Arc::clone(&self.storage_alerts);
+        let integrity_checks = self.integrity_checks.clone();
+        // When each namespace was last checked for integrity.
+        let mut checked = HashMap::<String, Instant>::new();
+        let mut shutdown = self.shutdown.subscribe();
+        let (mut control, handle) =
+            CleanupControl::new(check_interval, idle_timeout, self.cleanup_policy);
+
+        rt::spawn(async move {
+            // Stop on `stop`, `close`, or once the instance has been dropped.
+            while let Wake::Pass {
+                idle_timeout,
+                policy,
+            } = control.next(&mut shutdown).await
+            {
+                if let Some(on_pass) = &on_pass {
+                    on_pass().await;
+                }
+                // Slots are held weakly, so they don't count as leases themselves.
+                let pools_to_check: Vec<(String, Weak<OnceCell<SqlitePool>>, SqlitePool)> = pools
+                    .read()
+                    .unwrap()
                     .iter()
-                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .filter_map(|(k, slot)| {
+                        Some((k.clone(), Arc::downgrade(slot), slot.get()?.clone()))
+                    })
                     .collect();
 
-                let mut last_access_guard = last_access.lock().await;
+                for (ns, checked_slot, pool) in pools_to_check {
+                    let now = clock.now();
+                    let last = access.last_or_touch(&ns, now);
+                    let config = ns_configs.get(&ns).unwrap_or(&default_ns_config);
 
-                for (ns, pool) in pools_to_check {
-                    let now = time::Instant::now();
-                    let last = last_access_guard.entry(ns.clone()).or_insert(now);
+                    let idle = now.duration_since(last) > idle_timeout;
+                    let due = (idle || !policy.require_idle)
+                        && vacuumed.get(&ns).is_none_or(|at| *at < last);
+                    let fragmented = due
+                        && match db::page_counts(&pool).await {
+                            Ok((pages, free)) => {
+                                let fragmented = policy.is_fragmented(pages, free);
+                                if !fragmented {
+                                    tracing::debug!(ns = %ns, pages, free, "vacuum skipped, namespace is not fragmented");
+                                }
+                                fragmented
+                            }
+                            Err(e) => {
+                                tracing::error!(ns = %ns, error = %e, "reading page counts failed");
+                                false
+                            }
+                        };
+                    if fragmented {
+                        tracing::info!(ns = %ns, "namespace is fragmented, vacuuming");
+                        let db_path = pool.connect_options().get_filename().to_path_buf();
+                        match lock::lock_maintenance(open_mode, &db_path) {
+                            Ok(_lock) => {
+                                let permit = lanes.background(&ns, config).await;
+                                if let Err(e) = db::vacuum(&pool).await {
+                                    tracing::error!(ns = %ns, error = %e, "background vacuum failed");
+                                }
+                                drop(permit);
+                            }
+                            // Another coordinated process is maintaining it right now.
+                            Err(PathmapError::StoreLocked(_)) => {
+                                tracing::debug!(ns = %ns, "vacuum skipped, namespace is locked");
+                            }
+                            Err(e) => {
+                                tracing::error!(ns = %ns, error = %e, "background vacuum failed")
+                            }
+                        }
+                        vacuumed.insert(ns.clone(), now);
+                    }
+
+                    if config.storage_alerts != StorageThresholds::default() {
+                        match usage::measure(&pool).await {
+                            Ok(usage) => {
+                                storage_alerts.check(&events, &ns, &usage, &config.storage_alerts)
+                            }
+                            Err(e) => {
+                                tracing::error!(ns = %ns, error = %e, "measuring storage failed")
+                            }
+                        }
+                    }
+
+                    if let Some(check) = &integrity_checks
+                        && checked
+                            .get(&ns)
+                            .is_none_or(|at| now.duration_since(*at) >= check.every)
+                    {
+                        let permit = lanes.background(&ns, config).await;
+                        match integrity::run_check(&ns, &pool, check.mode, clock.as_ref()).await {
+                            Ok(report) => {
+                                if !report.is_ok() {
+                                    tracing::warn!(ns = %ns, errors = ?report.errors, "integrity check found problems");
+                                }
+                                (check.callback)(&report);
+                            }
+                            Err(e) => {
+                                tracing::error!(ns = %ns, error = %e, "integrity check failed")
+                            }
+                        }
+                        drop(permit);
+                        checked.insert(ns.clone(), now);
+                    }
 
-                    if now.duration_since(*last) > idle_timeout {
-                        println!("Namespace '{}' is idle, performing cleanup...", ns);
-                        if let Err(e) = db::vacuum(&pool).await {
-                            eprintln!("Error during background cleanup of '{}': {}", ns, e);
+                    let busy = pool.num_idle() < pool.size() as usize;
+                    if !busy
+                        && pool_close_after.is_some_and(|after| now.duration_since(last) > after)
+                    {
+                        // Under the write lock no operation can take a new lease on the slot;
+                        // one still holding a lease keeps the pool open until the next pass.
+                        let removed = {
+                            let mut pools = pools.write().unwrap();
+                            let unleased = pools.get(&ns).is_some_and(|slot| {
+                                Arc::downgrade(slot).ptr_eq(&checked_slot)
+                                    && Arc::strong_count(slot) == 1
+                            });
+                            unleased && pools.remove(&ns).is_some()
+                        };
+                        if !removed {
+                            continue;
                         }
-                        *last = now;
+                        tracing::info!(ns = %ns, "namespace is idle, closing its pool");
+                        pool.close().await;
+                        access.forget(&ns);
+                        vacuumed.remove(&ns);
                     }
                 }
             }
         });
+        handle
+    }
+
+    /// Shuts the instance down: commits open write batches and the write queue, stops the
+    /// background cleanup task and closes every pool, waiting for queries in flight. Operations
+    /// started afterwards fail with `Closed`, and closing again does nothing. If flushing fails,
+    /// the instance is still closed and the error is returned; the unflushed writes are lost.
+    ///
+    /// Dropping an instance without closing it stops the cleanup task at its next wake-up and lets
+    /// open batches commit on their timers, but loses writes still in the write queue. Its pools
+    /// close once the last operation using them has finished.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn close(&self) -> Result<()> {
+        if self.is_closed() {
+            return Ok(());
+        }
+        let flushed = match self.flush_batches().await {
+            Ok(()) => self.flush_write_queue().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if self.shutdown.send_replace(true) {
+            // A concurrent `close` got here first.
+            return flushed;
+        }
+        let slots: Vec<PoolSlot> = self
+            .pools
+            .write()
+            .unwrap()
+            .drain()
+            .map(|(_, s)| s)
+            .collect();
+        for slot in slots {
+            if let Some(pool) = slot.get() {
+                pool.close().await;
+            }
+        }
+        flushed
+    }
+
+    /// Whether `close` has been called.
+    pub fn is_closed(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    fn ns_config(&self, ns: &str) -> &NamespaceConfig {
+        self.ns_configs.get(ns).unwrap_or(&self.default_ns_config)
+    }
+
+    async fn connect(&self, ns: &str, db_path: &Path) -> Result<SqlitePool> {
+        if self.is_closed() {
+            return Err(PathmapError::Closed);
+        }
+        self.lock_instance()?;
+        let config = self.ns_config(ns);
+        let pool = db::connect_as(db_path, self.group_depth, config, Some(&self.origin)).await?;
+        if !config.read_only {
+            self.apply_unique_fields(ns, &pool).await?;
+        }
+        Ok(pool)
+    }
+
+    /// Formats a wall-clock time as RFC 3339 in the namespace's configured time zone.
+    pub fn format_timestamp(&self, ns: &str, time: SystemTime) -> String {
+        clock::format_rfc3339(time, self.ns_config(ns).time_zone)
+    }
+
+    /// Serializes a value to JSON according to the namespace's number policies.
+    fn encode<T: Serialize + ?Sized>(&self, ns: &str, value: &T) -> Result<Vec<u8>> {
+        let config = self.ns_config(ns);
+        Ok(number::to_vec(
+            value,
+            config.float_policy,
+            config.integer_policy,
+        )?)
+    }
+
+    /// Overwrites (`Some`) or deletes (`None`) a single row, releasing the blob it replaces.
+    /// Returns whether the row existed.
+    async fn apply_write(
+        &self,
+        ns: &str,
+        grp: &str,
+        key: &str,
+        value: Option<Vec<u8>>,
+    ) -> Result<bool> {
+        self.settle_batches(ns, Some((grp, key))).await;
+        let pool = match value {
+            Some(_) => self.get_pool_or_init(ns).await?,
+            None => self.get_pool(ns).await?,
+        };
+        let previous = db::find(&*pool, grp, key).await?;
+        let new_size = value.as_ref().map(|v| v.len() as u64);
+        match value {
+            Some(value) => {
+                let (data, external) = self.store_value(ns, value).await?;
+                let write = RowWrite::Overwrite(&data, external);
+                let result = self
+                    .write_row(ns, &pool, grp, key, write, previous.as_ref())
+                    .await;
+                if result.is_err() && external {
+                    self.remove_blob(ns, &data).await?;
+                }
+                result?;
+            }
+            None => {
+                self.write_row(ns, &pool, grp, key, RowWrite::Delete, previous.as_ref())
+                    .await?
+            }
+        }
+        self.invalidate_cached(ns, grp, key);
+        if self.events.is_active() && (new_size.is_some() || previous.is_some()) {
+            let old_size = match &previous {
+                Some(previous) => Some(self.stored_size(ns, previous).await),
+                None => None,
+            };
+            let op = match new_size {
+                Some(_) => Operation::Overwrite,
+                None => Operation::Delete,
+            };
+            self.emit_change(op, ns, &db::join_key(grp, key), old_size, new_size);
+        }
+        let existed = previous.is_some();
+        if let Some(previous) = previous.filter(|p| p.external) {
+            self.remove_blob(ns, &previous.data).await?;
+        }
+        Ok(existed)
+    }
+
+    /// Moves a serialized value into a blob file when it exceeds the namespace's threshold.
+    /// Returns the row payload and whether it is an external reference.
+    async fn store_value(&self, ns: &str, value: Vec<u8>) -> Result<(Vec<u8>, bool)> {
+        match self.ns_config(ns).blob_threshold {
+            Some(threshold) if value.len() > threshold => {
+                let dir = blob::blob_dir(&self.get_db_path(ns));
+                let name = blob::write(&dir, &value).await?;
+                Ok((name.into_bytes(), true))
+            }
+            _ => Ok((value, false)),
+        }
+    }
+
+    /// Size of the serialized value a stored row holds, for change events.
+    async fn stored_size(&self, ns: &str, stored: &db::StoredValue) -> u64 {
+        let dir = blob::blob_dir(&self.get_db_path(ns));
+        blob::stored_size(&dir, &stored.data, stored.external).await
+    }
+
+    /// Resolves a stored row into the serialized value, reading blob files transparently.
+    async fn load_value(&self, ns: &str, stored: db::StoredValue) -> Result<Vec<u8>> {
+        if !stored.external {
+            return Ok(stored.data);
+        }
+        let dir = blob::blob_dir(&self.get_db_path(ns));
+        blob::read(&dir, &String::from_utf8_lossy(&stored.data)).await
+    }
+
+    async fn remove_blob(&self, ns: &str, name: &[u8]) -> Result<()> {
+        let dir = blob::blob_dir(&self.get_db_path(ns));
+        blob::remove(&dir, &String::from_utf8_lossy(name)).await
     }
 
     fn get_db_path(&self, ns: &str) -> PathBuf {
-        self.base_path.join(format!("{}.sqlite", ns))
+        if let Some(path) = self.attached.read().unwrap().get(ns) {
+            return path.clone();
+        }
+        let dir = self.ns_paths.get(ns).unwrap_or(&self.base_path);
+        dir.join(format!("{}.sqlite", ns))
     }
 
-    async fn get_pool(&self, ns: &str) -> Result<SqlitePool> {
-        let mut pools = self.pools.lock().await;
-        if let Some(pool) = pools.get(ns) {
-            return Ok(pool.clone());
+    async fn get_pool(&self, ns: &str) -> Result<PoolLease> {
+        self.name_rules.check_ns(ns)?;
+        self.reject_soft(ns)?;
+        if self.is_closed() {
+            return Err(PathmapError::Closed);
+        }
+        self.access.touch(ns, self.clock.now());
+        #[cfg(feature = "chaos")]
+        if let Some(chaos) = &self.chaos {
+            chaos.inject().await?;
+        }
+
+        let slot = self.pool_slot(ns);
+        let opened = slot
+            .get_or_try_init(|| async {
+                let db_path = self.get_db_path(ns);
+                if !db_path.exists() {
+                    return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+                }
+                self.connect(ns, &db_path).await
+            })
+            .await;
+        match opened {
+            Ok(pool) => Ok(PoolLease {
+                pool: pool.clone(),
+                _slot: slot,
+            }),
+            Err(e) => {
+                // Don't keep empty slots around for names that were never opened.
+                let mut pools = self.pools.write().unwrap();
+                if pools
+                    .get(ns)
+                    .is_some_and(|s| Arc::ptr_eq(s, &slot) && s.get().is_none())
+                {
+                    pools.remove(ns);
+                }
+                Err(e)
+            }
         }
+    }
 
-        let db_path = self.get_db_path(ns);
-        if !db_path.exists() {
-            return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+    /// The namespace's pool slot, created empty on first use.
+    fn pool_slot(&self, ns: &str) -> PoolSlot {
+        if let Some(slot) = self.pools.read().unwrap().get(ns) {
+            return Arc::clone(slot);
         }
+        let mut pools = self.pools.write().unwrap();
+        Arc::clone(pools.entry(ns.to_string()).or_default())
+    }
 
-        let pool = db::connect(&db_path).await?;
-        pools.insert(ns.to_string(), pool.clone());
-        Ok(pool)
+    fn insert_pool(&self, ns: &str, pool: SqlitePool) {
+        let slot = Arc::new(OnceCell::new_with(Some(pool)));
+        self.pools.write().unwrap().insert(ns.to_string(), slot);
+    }
+
+    fn remove_pool(&self, ns: &str) -> Option<SqlitePool> {
+        let slot = self.pools.write().unwrap().remove(ns)?;
+        slot.get().cloned()
     }
 
-    async fn get_pool_or_init(&self, ns: &str) -> Result<SqlitePool> {
+    async fn get_pool_or_init(&self, ns: &str) -> Result<PoolLease> {
         match self.get_pool(ns).await {
             Ok(pool) => Ok(pool),
             Err(PathmapError::NamespaceNotFound(_)) => {
@@ -255,6 +1856,7 @@ impl Pathmap {
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl Default for Pathmap {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,79 @@
+/* src/lock.rs */
+
+//! Advisory locks that let several processes share a base path: the instance lock taken by
+//! `with_open_mode`, and the per-namespace maintenance locks of `OpenMode::Coordinated`.
+
+use crate::Pathmap;
+use crate::config::OpenMode;
+use crate::error::{PathmapError, Result};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// Opens (creating if needed) and locks `path` without waiting. `Ok(None)` means another holder
+/// has a conflicting lock.
+fn try_lock(path: &Path, exclusive: bool) -> Result<Option<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    let acquired = if exclusive {
+        file.try_lock()
+    } else {
+        file.try_lock_shared()
+    };
+    match acquired {
+        Ok(()) => Ok(Some(file)),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Takes the instance lock on `base_path` for `mode`. Coordinated openers share it like
+/// `Shared` ones.
+pub(crate) fn lock_instance(base_path: &Path, mode: OpenMode) -> Result<File> {
+    std::fs::create_dir_all(base_path)?;
+    try_lock(&base_path.join(".kvmap.lock"), mode == OpenMode::Exclusive)?
+        .ok_or_else(|| PathmapError::StoreLocked(base_path.display().to_string()))
+}
+
+/// The lock file guarding maintenance of the namespace stored at `db_path`.
+fn maintenance_lock_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Takes the maintenance lock of the namespace stored at `db_path` when `mode` is
+/// `Coordinated`; other modes need none and get `Ok(None)`. The lock is released when the
+/// returned file is dropped.
+pub(crate) fn lock_maintenance(mode: Option<OpenMode>, db_path: &Path) -> Result<Option<File>> {
+    if mode != Some(OpenMode::Coordinated) {
+        return Ok(None);
+    }
+    match try_lock(&maintenance_lock_path(db_path), true)? {
+        Some(file) => Ok(Some(file)),
+        None => Err(PathmapError::StoreLocked(db_path.display().to_string())),
+    }
+}
+
+impl Pathmap {
+    /// Takes the instance lock of the open mode, unless it is held already or no mode is set.
+    pub(crate) fn lock_instance(&self) -> Result<()> {
+        let Some(mode) = self.open_mode else {
+            return Ok(());
+        };
+        let mut held = self.instance_lock.lock().unwrap();
+        if held.is_none() {
+            *held = Some(lock_instance(&self.base_path, mode)?);
+        }
+        Ok(())
+    }
+
+    /// Takes the maintenance lock of `ns` for an operation that rewrites its whole file, failing
+    /// with `StoreLocked` while another coordinated process maintains it.
+    pub(crate) fn maintenance_lock(&self, ns: &str) -> Result<Option<File>> {
+        self.lock_instance()?;
+        lock_maintenance(self.open_mode, &self.get_db_path(ns))
+    }
+}
@@ -0,0 +1,368 @@
+/* src/memory.rs */
+
+//! An in-memory store with the same paths, listings and errors as `Pathmap`, for targets without
+//! SQLite such as `wasm32-unknown-unknown` (e.g. a browser admin frontend) and for tests.
+//! Nothing is persisted; move data in and out with `entries` and `load`, which use the same
+//! key/value pairs as `Pathmap::dump_ndjson`.
+
+use crate::error::{PathmapError, Result};
+use crate::number::{self, FloatPolicy, IntegerPolicy};
+use crate::path::{join_key, split_key};
+use crate::{Listing, WriteOutcome};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::RwLock;
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+
+/// Serialized values of one namespace, ordered by (group, value name).
+type Namespace = BTreeMap<(String, String), Vec<u8>>;
+
+#[derive(Default)]
+pub struct MemoryStore {
+    namespaces: RwLock<BTreeMap<String, Namespace>>,
+    group_depth: Option<usize>,
+    normalize_keys: bool,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fixes the number of group levels in every key, as `Pathmap::with_group_depth`.
+    pub fn with_group_depth(mut self, depth: usize) -> Self {
+        self.group_depth = Some(depth);
+        self
+    }
+
+    /// Normalizes keys to Unicode NFC, as `Pathmap::with_key_normalization`.
+    pub fn with_key_normalization(mut self, enabled: bool) -> Self {
+        self.normalize_keys = enabled;
+        self
+    }
+
+    /// Lists all namespaces in sorted order.
+    pub fn list_ns(&self) -> Vec<String> {
+        self.namespaces.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn init_ns(&self, ns: &str) -> Result<bool> {
+        let mut namespaces = self.namespaces.write().unwrap();
+        if namespaces.contains_key(ns) {
+            return Err(PathmapError::NamespaceAlreadyExists(ns.to_string()));
+        }
+        namespaces.insert(ns.to_string(), Namespace::new());
+        Ok(true)
+    }
+
+    pub fn delete_ns(&self, ns: &str) -> Result<bool> {
+        match self.namespaces.write().unwrap().remove(ns) {
+            Some(_) => Ok(true),
+            None => Err(PathmapError::NamespaceNotFound(ns.to_string())),
+        }
+    }
+
+    /// Lists the contents (groups and values) of a namespace or group.
+    pub fn list(&self, path: &str) -> Result<Listing> {
+        let (ns, grp) = match path.split_once("::") {
+            Some((ns, group_path)) => (ns, group_path),
+            None => (path, ""),
+        };
+        let grp = self.normalize_key(grp);
+        let grp = grp.as_ref();
+        let depth = if grp.is_empty() {
+            0
+        } else {
+            grp.split('.').count()
+        };
+        if self.group_depth.is_some_and(|max| depth > max) {
+            return Err(PathmapError::InvalidPath(path.to_string()));
+        }
+
+        let namespaces = self.namespaces.read().unwrap();
+        let entries = namespaces
+            .get(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let prefix = if grp.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", grp)
+        };
+        let mut groups = BTreeSet::new();
+        let mut values = Vec::new();
+        for (entry_grp, key) in entries.keys() {
+            if entry_grp == grp {
+                values.push(key.clone());
+            } else if let Some(sub_path) = entry_grp.strip_prefix(&prefix) {
+                let group_name = sub_path.split_once('.').map_or(sub_path, |(name, _)| name);
+                if !group_name.is_empty() {
+                    groups.insert(group_name.to_string());
+                }
+            }
+        }
+        Ok(Listing {
+            groups: groups.into_iter().collect(),
+            values,
+        })
+    }
+
+    /// Every value below a group as dotted keys relative to it, as `Pathmap::list_recursive`.
+    pub fn list_recursive(&self, path: &str) -> Result<Vec<String>> {
+        let (ns, grp) = match path.split_once("::") {
+            Some((ns, group_path)) => (ns, group_path),
+            None => (path, ""),
+        };
+        let grp = self.normalize_key(grp);
+        let namespaces = self.namespaces.read().unwrap();
+        let entries = namespaces
+            .get(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let prefix = if grp.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", grp)
+        };
+        let mut keys: Vec<String> = entries
+            .keys()
+            .filter_map(|(entry_grp, key)| {
+                if *entry_grp == *grp {
+                    Some(key.clone())
+                } else {
+                    let sub_group = entry_grp.strip_prefix(&prefix)?;
+                    Some(join_key(sub_group, key))
+                }
+            })
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Number of values below a group, as `Pathmap::count`.
+    pub fn count(&self, path: &str) -> Result<u64> {
+        Ok(self.list_recursive(path)?.len() as u64)
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = split_key(&key, self.group_depth);
+        let namespaces = self.namespaces.read().unwrap();
+        let entries = namespaces
+            .get(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let raw_value = entries
+            .get(&(grp.to_string(), key.to_string()))
+            .ok_or_else(|| PathmapError::ValueNotFound(join_key(grp, key)))?;
+        Ok(serde_json::from_slice(raw_value)?)
+    }
+
+    /// Sets a value, failing if the value or a group of the same name already exists.
+    pub fn set<T: Serialize>(&self, path: &str, value: T) -> Result<()> {
+        let (ns, key) = self.parse_path(path)?;
+        let serialized_value =
+            number::to_vec(&value, FloatPolicy::default(), IntegerPolicy::default())?;
+        let mut namespaces = self.namespaces.write().unwrap();
+        let entries = namespaces
+            .get_mut(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let (grp, name) = split_key(&key, self.group_depth);
+        if entries.contains_key(&(grp.to_string(), name.to_string())) || has_group(entries, &key) {
+            return Err(PathmapError::ValueAlreadyExists(key.to_string()));
+        }
+        let key = name;
+        entries.insert((grp.to_string(), key.to_string()), serialized_value);
+        Ok(())
+    }
+
+    /// Like `set`, but returns `Ok(false)` instead of `ValueAlreadyExists`.
+    pub fn try_set<T: Serialize>(&self, path: &str, value: T) -> Result<bool> {
+        match self.set(path, value) {
+            Ok(()) => Ok(true),
+            Err(PathmapError::ValueAlreadyExists(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Overwrites a value, creating it (and its namespace) if it doesn't exist.
+    pub fn overwrite<T: Serialize>(&self, path: &str, value: T) -> Result<WriteOutcome> {
+        let (ns, key) = self.parse_path(path)?;
+        let serialized_value =
+            number::to_vec(&value, FloatPolicy::default(), IntegerPolicy::default())?;
+        let (grp, key) = split_key(&key, self.group_depth);
+        let previous = self
+            .namespaces
+            .write()
+            .unwrap()
+            .entry(ns.to_string())
+            .or_default()
+            .insert((grp.to_string(), key.to_string()), serialized_value);
+        Ok(match previous {
+            Some(_) => WriteOutcome::Replaced,
+            None => WriteOutcome::Created,
+        })
+    }
+
+    /// Deletes a value, returning whether there was one.
+    pub fn delete(&self, path: &str) -> Result<bool> {
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = split_key(&key, self.group_depth);
+        let mut namespaces = self.namespaces.write().unwrap();
+        let entries = namespaces
+            .get_mut(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        Ok(entries
+            .remove(&(grp.to_string(), key.to_string()))
+            .is_some())
+    }
+
+    /// Reads a value and deletes it; a value that does not deserialize as `T` is kept.
+    pub fn take<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = split_key(&key, self.group_depth);
+        let mut namespaces = self.namespaces.write().unwrap();
+        let entries = namespaces
+            .get_mut(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let id = (grp.to_string(), key.to_string());
+        let raw_value = entries
+            .get(&id)
+            .ok_or_else(|| PathmapError::ValueNotFound(join_key(grp, key)))?;
+        let value = serde_json::from_slice(raw_value)?;
+        entries.remove(&id);
+        Ok(value)
+    }
+
+    /// Exchanges the values at two paths of the same namespace.
+    pub fn swap(&self, path_a: &str, path_b: &str) -> Result<()> {
+        let (ns, key_a) = self.parse_path(path_a)?;
+        let (ns_b, key_b) = self.parse_path(path_b)?;
+        if ns != ns_b {
+            return Err(PathmapError::InvalidPath(format!(
+                "cannot swap '{}' and '{}' across namespaces",
+                path_a, path_b
+            )));
+        }
+        let (grp_a, key_a) = split_key(&key_a, self.group_depth);
+        let (grp_b, key_b) = split_key(&key_b, self.group_depth);
+        let mut namespaces = self.namespaces.write().unwrap();
+        let entries = namespaces
+            .get_mut(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let a = (grp_a.to_string(), key_a.to_string());
+        let b = (grp_b.to_string(), key_b.to_string());
+        for (grp, key) in [&a, &b] {
+            if !entries.contains_key(&(grp.clone(), key.clone())) {
+                return Err(PathmapError::ValueNotFound(join_key(grp, key)));
+            }
+        }
+        if a != b {
+            let value_a = entries.remove(&a).unwrap();
+            let value_b = entries.insert(b, value_a).unwrap();
+            entries.insert(a, value_b);
+        }
+        Ok(())
+    }
+
+    /// Deletes a group and everything nested below it, returning the number of removed values.
+    pub fn delete_group(&self, path: &str) -> Result<u64> {
+        let (ns, grp) = self.parse_path(path)?;
+        if grp.is_empty() {
+            return Err(PathmapError::InvalidPath(path.to_string()));
+        }
+        let mut namespaces = self.namespaces.write().unwrap();
+        let entries = namespaces
+            .get_mut(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        let nested = format!("{}.", grp);
+        let before = entries.len();
+        entries.retain(|(entry_grp, _), _| entry_grp != &*grp && !entry_grp.starts_with(&nested));
+        Ok((before - entries.len()) as u64)
+    }
+
+    /// Checks if a path (namespace, group, or value) exists, as `Pathmap::exists`.
+    pub fn exists(&self, path: &str) -> bool {
+        if !path.contains("::") {
+            return self.exists_ns(path);
+        }
+        self.exists_value(path) || self.exists_group(path)
+    }
+
+    pub fn exists_ns(&self, ns: &str) -> bool {
+        self.namespaces.read().unwrap().contains_key(ns)
+    }
+
+    /// Checks if a value is stored at exactly this path.
+    pub fn exists_value(&self, path: &str) -> bool {
+        let Ok((ns, key)) = self.parse_path(path) else {
+            return false;
+        };
+        let (grp, key) = split_key(&key, self.group_depth);
+        let namespaces = self.namespaces.read().unwrap();
+        namespaces
+            .get(ns)
+            .is_some_and(|entries| entries.contains_key(&(grp.to_string(), key.to_string())))
+    }
+
+    /// Checks if a group holds any value, directly or nested.
+    pub fn exists_group(&self, path: &str) -> bool {
+        let Ok((ns, grp)) = self.parse_path(path) else {
+            return false;
+        };
+        let namespaces = self.namespaces.read().unwrap();
+        namespaces
+            .get(ns)
+            .is_some_and(|entries| has_group(entries, &grp))
+    }
+
+    /// Every value of a namespace as (dotted key, JSON value), in key order.
+    pub fn entries(&self, ns: &str) -> Result<Vec<(String, Value)>> {
+        let namespaces = self.namespaces.read().unwrap();
+        let entries = namespaces
+            .get(ns)
+            .ok_or_else(|| PathmapError::NamespaceNotFound(ns.to_string()))?;
+        entries
+            .iter()
+            .map(|((grp, key), raw_value)| {
+                Ok((join_key(grp, key), serde_json::from_slice(raw_value)?))
+            })
+            .collect()
+    }
+
+    /// Overwrites values from (dotted key, JSON value) pairs, creating the namespace if needed.
+    pub fn load<I>(&self, ns: &str, entries: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (String, Value)>,
+    {
+        for (key, value) in entries {
+            self.overwrite(&format!("{}::{}", ns, key), value)?;
+        }
+        Ok(())
+    }
+
+    fn parse_path<'a>(&self, path: &'a str) -> Result<(&'a str, Cow<'a, str>)> {
+        let (ns, key) = path
+            .split_once("::")
+            .ok_or_else(|| PathmapError::InvalidPath(path.to_string()))?;
+        Ok((ns, self.normalize_key(key)))
+    }
+
+    fn normalize_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        if self.normalize_keys && !is_nfc(key) {
+            Cow::Owned(key.nfc().collect())
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+}
+
+/// Whether a group holds any value, directly or nested; "" is the namespace root.
+fn has_group(entries: &Namespace, grp: &str) -> bool {
+    if grp.is_empty() {
+        return !entries.is_empty();
+    }
+    let nested = format!("{}.", grp);
+    entries
+        .keys()
+        .any(|(entry_grp, _)| entry_grp == grp || entry_grp.starts_with(&nested))
+}
@@ -0,0 +1,253 @@
+/* src/migrate.rs */
+
+//! Controlled schema upgrades. Opening a namespace migrates it implicitly; `migrate` does the
+//! same for every namespace up front, so an upgrade can be run (or previewed) as a separate step.
+
+use crate::Pathmap;
+use crate::db::{self, SCHEMA_VERSION, StoredValue};
+use crate::error::{self, PathmapError, Result};
+use serde_json::Value;
+use sqlx::Connection;
+
+/// Rows read per page while re-encoding.
+const REENCODE_PAGE_ROWS: i64 = 500;
+
+/// Settings for `Pathmap::migrate`.
+#[derive(Debug, Clone, Default)]
+pub struct MigrateOptions {
+    /// Only report what would change; no file is written.
+    pub dry_run: bool,
+    /// Also rewrite values whose stored form no longer matches the namespace's number policies
+    /// or blob threshold.
+    pub reencode: bool,
+}
+
+impl MigrateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    pub fn reencode(mut self, enabled: bool) -> Self {
+        self.reencode = enabled;
+        self
+    }
+}
+
+/// What `migrate` did, or in a dry run would do, to one namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceMigration {
+    pub ns: String,
+    /// Schema version found on disk.
+    pub from_version: i64,
+    pub to_version: i64,
+    /// Values rewritten (or to be rewritten) by re-encoding.
+    pub reencoded: u64,
+}
+
+impl NamespaceMigration {
+    /// True when the namespace was (or would be) changed.
+    pub fn changed(&self) -> bool {
+        self.from_version < self.to_version || self.reencoded > 0
+    }
+}
+
+/// Outcome of `Pathmap::migrate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+    pub namespaces: Vec<NamespaceMigration>,
+}
+
+impl Pathmap {
+    /// Brings every namespace file up to the current schema and rebuilds its indexes, optionally
+    /// re-encoding stored values with the configured policies. Soft namespaces are covered by
+    /// their host. Meant to run before the store starts serving, as an explicit upgrade step.
+    ///
+    /// Re-encoding parses each value as JSON, so numbers beyond `u64`/`f64` are only kept exact
+    /// with the `arbitrary-precision` feature.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn migrate(&self, options: MigrateOptions) -> Result<MigrationReport> {
+        let mut report = MigrationReport {
+            dry_run: options.dry_run,
+            namespaces: Vec::new(),
+        };
+        for ns in self.namespace_names()? {
+            if self.soft_ns.contains_key(&ns) {
+                continue;
+            }
+            report
+                .namespaces
+                .push(self.migrate_ns(&ns, &options).await?);
+        }
+        Ok(report)
+    }
+
+    /// Like `migrate`, for a single namespace.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn migrate_ns(
+        &self,
+        ns: &str,
+        options: &MigrateOptions,
+    ) -> Result<NamespaceMigration> {
+        error::in_context("migrate_ns", ns, async {
+            self.reject_soft(ns)?;
+            let db_path = self.get_db_path(ns);
+            if !db_path.exists() {
+                return Err(PathmapError::NamespaceNotFound(ns.to_string()));
+            }
+            let _lock = if options.dry_run {
+                None
+            } else {
+                self.maintenance_lock(ns)?
+            };
+            let mut conn = crate::snapshot::open(&db_path, false).await?;
+            let from_version = db::schema_version(&mut conn).await?;
+
+            let reencoded = if options.dry_run {
+                let count = if options.reencode {
+                    self.count_reencodes(ns, &mut conn, from_version).await
+                } else {
+                    Ok(0)
+                };
+                conn.close().await?;
+                count?
+            } else {
+                conn.close().await?;
+                // Opening the pool applies the schema migrations.
+                let pool = self.get_pool(ns).await?;
+                db::reindex(&pool).await?;
+                if options.reencode {
+                    self.reencode_ns(ns, &pool).await?
+                } else {
+                    0
+                }
+            };
+
+            Ok(NamespaceMigration {
+                ns: ns.to_string(),
+                from_version,
+                to_version: SCHEMA_VERSION.max(from_version),
+                reencoded,
+            })
+        })
+        .await
+    }
+
+    /// Counts the values `reencode_ns` would rewrite, reading the file as it is on disk.
+    async fn count_reencodes(
+        &self,
+        ns: &str,
+        conn: &mut sqlx::SqliteConnection,
+        version: i64,
+    ) -> Result<u64> {
+        let mut count = 0;
+        let mut after = 0;
+        loop {
+            let page = db::values_after_rowid(conn, version, after, REENCODE_PAGE_ROWS).await?;
+            let Some((last, _)) = page.last() else {
+                return Ok(count);
+            };
+            after = *last;
+            for (_, stored) in page {
+                if self.reencoded(ns, stored).await?.is_some() {
+                    count += 1;
+                }
+            }
+        }
+    }
+
+    /// Rewrites the values whose stored form differs from what the namespace's policies produce,
+    /// one page per transaction.
+    async fn reencode_ns(&self, ns: &str, pool: &sqlx::SqlitePool) -> Result<u64> {
+        let mut count = 0;
+        let mut after: Option<(String, String)> = None;
+        loop {
+            let page = db::entries_after(
+                pool,
+                after.as_ref().map(|(g, k)| (g.as_str(), k.as_str())),
+                REENCODE_PAGE_ROWS,
+            )
+            .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some((last.grp.clone(), last.key.clone()));
+
+            let mut rewrites = Vec::new();
+            for entry in page {
+                if let Some(value) = self.reencoded(ns, entry.value.clone()).await? {
+                    rewrites.push((entry, value));
+                }
+            }
+            if rewrites.is_empty() {
+                continue;
+            }
+
+            let mut new_blobs = Vec::new();
+            let mut old_blobs = Vec::new();
+            let result = async {
+                let mut tx = pool.begin().await?;
+                for (entry, value) in &rewrites {
+                    let (data, external) = self.store_value(ns, value.clone()).await?;
+                    if external {
+                        new_blobs.push(data.clone());
+                    }
+                    db::rewrite(&mut *tx, &entry.grp, &entry.key, &data, external).await?;
+                    if entry.value.external {
+                        old_blobs.push(entry.value.data.clone());
+                    }
+                }
+                tx.commit().await?;
+                Ok::<_, PathmapError>(())
+            }
+            .await;
+            let stale = match result {
+                Ok(()) => old_blobs,
+                Err(e) => {
+                    for name in new_blobs {
+                        self.remove_blob(ns, &name).await?;
+                    }
+                    return Err(e);
+                }
+            };
+            for name in stale {
+                self.remove_blob(ns, &name).await?;
+            }
+            count += rewrites.len() as u64;
+        }
+        self.invalidate_cached_ns(ns);
+        Ok(count)
+    }
+
+    /// The serialized value a stored row should hold under the namespace's current policies, or
+    /// `None` when it already does. Values that are not JSON are left alone.
+    async fn reencoded(&self, ns: &str, stored: StoredValue) -> Result<Option<Vec<u8>>> {
+        let external = stored.external;
+        let raw = self.load_value(ns, stored).await?;
+        let Ok(value) = serde_json::from_slice::<Value>(&raw) else {
+            return Ok(None);
+        };
+        // Compare against the policy-free encoding, so formatting differences alone (e.g. the
+        // order of object keys) do not count as a change.
+        let encoded = self.encode(ns, &value)?;
+        let changed = encoded != serde_json::to_vec(&value)?;
+        let bytes = if changed { encoded } else { raw };
+        let should_be_external = self
+            .ns_config(ns)
+            .blob_threshold
+            .is_some_and(|threshold| bytes.len() > threshold);
+        if changed || should_be_external != external {
+            Ok(Some(bytes))
+        } else {
+            Ok(None)
+        }
+    }
+}
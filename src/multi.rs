@@ -0,0 +1,331 @@
+/* src/multi.rs */
+
+//! Writes to several namespaces committed together. Each namespace is its own SQLite file, so
+//! there is no shared journal: the writes are staged, applied inside one open transaction per
+//! namespace, and the transactions are then committed one after another (a best-effort
+//! two-phase commit). See `PathmapError::PartialCommit` for the one failure it cannot undo.
+
+use crate::Pathmap;
+use crate::audit::RowWrite;
+use crate::db::{self, StoredValue};
+use crate::error::{self, PathmapError, Result};
+use crate::event::Operation;
+use serde::Serialize;
+use sqlx::{Sqlite, Transaction};
+
+enum StagedOp {
+    Set(Vec<u8>),
+    Overwrite(Vec<u8>),
+    Delete,
+}
+
+struct StagedWrite {
+    /// The path as staged, for throttling.
+    path: String,
+    /// The namespace holding the row, after resolving soft namespaces.
+    ns: String,
+    grp: String,
+    key: String,
+    op: StagedOp,
+}
+
+/// The writes of a `transaction_multi`, staged by its closure. Values are serialized when
+/// staged; nothing touches the databases until the closure returns.
+pub struct MultiTransaction<'a> {
+    pm: &'a Pathmap,
+    namespaces: Vec<String>,
+    writes: Vec<StagedWrite>,
+}
+
+impl MultiTransaction<'_> {
+    /// Stages a `set`: the commit fails if the key already holds a value.
+    pub fn set<T: Serialize>(&mut self, path: &str, value: T) -> Result<()> {
+        self.stage_value(path, &value, false)
+    }
+
+    /// Stages an `overwrite`.
+    pub fn overwrite<T: Serialize>(&mut self, path: &str, value: T) -> Result<()> {
+        self.stage_value(path, &value, true)
+    }
+
+    /// Stages a `delete`; deleting a missing key is not an error.
+    pub fn delete(&mut self, path: &str) -> Result<()> {
+        let (ns, grp, key) = self.target(path)?;
+        self.writes.push(StagedWrite {
+            path: path.to_string(),
+            ns,
+            grp,
+            key,
+            op: StagedOp::Delete,
+        });
+        Ok(())
+    }
+
+    fn stage_value<T: Serialize>(&mut self, path: &str, value: &T, overwrite: bool) -> Result<()> {
+        let (ns, grp, key) = self.target(path)?;
+        let serialized = self
+            .pm
+            .encode_write(&ns, &db::join_key(&grp, &key), value)?;
+        self.pm.check_value_quota(&ns, serialized.len() as u64)?;
+        let op = match overwrite {
+            true => StagedOp::Overwrite(serialized),
+            false => StagedOp::Set(serialized),
+        };
+        self.writes.push(StagedWrite {
+            path: path.to_string(),
+            ns,
+            grp,
+            key,
+            op,
+        });
+        Ok(())
+    }
+
+    /// The stored namespace, group and key of `path`, which must name one of the transaction's
+    /// namespaces.
+    fn target(&self, path: &str) -> Result<(String, String, String)> {
+        let declared = self.pm.split_ns(path).map(|(ns, _)| ns);
+        if !declared.is_some_and(|ns| self.namespaces.iter().any(|n| n == ns)) {
+            return Err(PathmapError::InvalidPath(format!(
+                "'{}' is outside the namespaces of the transaction ({})",
+                path,
+                self.namespaces.join(", ")
+            )));
+        }
+        let (ns, full_key) = self.pm.parse_path(path)?;
+        let (grp, key) = self.pm.split_key(&full_key);
+        Ok((ns.to_string(), grp.to_string(), key.to_string()))
+    }
+}
+
+/// A namespace whose writes are applied in an open transaction, waiting to commit.
+struct Prepared<'p> {
+    ns: &'p str,
+    tx: Transaction<'static, Sqlite>,
+    changes: Vec<Change>,
+}
+
+/// A row written by a prepared namespace, for the events, cache and blob cleanup after commit.
+struct Change {
+    op: Operation,
+    grp: String,
+    key: String,
+    previous: Option<StoredValue>,
+    new_size: Option<u64>,
+    /// The blob file the new value was externalized to, removed if the commit fails.
+    new_blob: Option<Vec<u8>>,
+}
+
+impl Pathmap {
+    /// Writes to several namespaces as one unit. `stage` records the writes on a
+    /// `MultiTransaction` (paths must be in `namespaces`); they are then applied inside one
+    /// transaction per namespace, in name order, and committed once every namespace accepted
+    /// them:
+    ///
+    /// ```ignore
+    /// pm.transaction_multi(&["orders", "stock"], |tx| {
+    ///     tx.overwrite("orders::o42", &order)?;
+    ///     tx.overwrite("stock::widget", remaining)
+    /// })
+    /// .await?;
+    /// ```
+    ///
+    /// A failure while applying (a `set` of an existing key, a quota, a unique index, a busy
+    /// database) rolls every namespace back. Commits are not atomic across files, though: if a
+    /// commit fails after an earlier namespace committed, the error is `PartialCommit`, naming
+    /// the namespaces that kept their writes. Goes straight to the databases, bypassing the
+    /// write queue and write batches, and is not available on a replica.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn transaction_multi<R, F>(&self, namespaces: &[&str], stage: F) -> Result<R>
+    where
+        F: FnOnce(&mut MultiTransaction<'_>) -> Result<R>,
+    {
+        let path = namespaces.join(",");
+        error::in_context("transaction_multi", &path, async {
+            if self.replica.is_some() {
+                return Err(PathmapError::InvalidConfig(
+                    "transaction_multi cannot be forwarded to the primary of a replica".to_string(),
+                ));
+            }
+            for ns in namespaces {
+                self.name_rules.check_ns(ns)?;
+            }
+            let mut tx = MultiTransaction {
+                pm: self,
+                namespaces: namespaces.iter().map(|ns| ns.to_string()).collect(),
+                writes: Vec::new(),
+            };
+            let result = stage(&mut tx)?;
+            for write in &tx.writes {
+                self.throttle_write(&write.path).await?;
+            }
+            self.retry_busy(|| self.commit_multi(&tx.writes)).await?;
+            Ok(result)
+        })
+        .await
+    }
+
+    async fn commit_multi(&self, writes: &[StagedWrite]) -> Result<()> {
+        for write in writes {
+            self.settle_batches(&write.ns, Some((&write.grp, &write.key)))
+                .await;
+        }
+        let mut namespaces: Vec<&str> = writes.iter().map(|w| w.ns.as_str()).collect();
+        namespaces.sort_unstable();
+        namespaces.dedup();
+
+        let mut prepared: Vec<Prepared> = Vec::with_capacity(namespaces.len());
+        for ns in namespaces {
+            let staged: Vec<&StagedWrite> = writes.iter().filter(|w| w.ns == ns).collect();
+            match self.prepare_ns(ns, &staged).await {
+                Ok(ns_prepared) => prepared.push(ns_prepared),
+                Err(e) => {
+                    // Dropping the open transactions rolls them back.
+                    for p in prepared {
+                        self.discard_new_blobs(p.ns, &p.changes).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut committed: Vec<(&str, Vec<Change>)> = Vec::with_capacity(prepared.len());
+        let mut pending = prepared.into_iter();
+        while let Some(Prepared { ns, tx, changes }) = pending.next() {
+            if let Err(e) = tx.commit().await {
+                let mut rolled_back = vec![ns.to_string()];
+                self.discard_new_blobs(ns, &changes).await;
+                for p in pending {
+                    rolled_back.push(p.ns.to_string());
+                    self.discard_new_blobs(p.ns, &p.changes).await;
+                }
+                if committed.is_empty() {
+                    return Err(e.into());
+                }
+                let committed_names = committed.iter().map(|(ns, _)| ns.to_string()).collect();
+                for (ns, changes) in committed {
+                    self.finish_ns(ns, changes).await?;
+                }
+                return Err(PathmapError::PartialCommit {
+                    committed: committed_names,
+                    rolled_back,
+                    source: Box::new(e.into()),
+                });
+            }
+            committed.push((ns, changes));
+        }
+        for (ns, changes) in committed {
+            self.finish_ns(ns, changes).await?;
+        }
+        Ok(())
+    }
+
+    /// Applies the staged writes of `ns` inside a new transaction, left open for the commit
+    /// phase. On failure the transaction is rolled back and its new blob files removed.
+    async fn prepare_ns<'p>(&self, ns: &'p str, staged: &[&StagedWrite]) -> Result<Prepared<'p>> {
+        let creates = staged.iter().any(|w| !matches!(w.op, StagedOp::Delete));
+        let pool = match creates {
+            true => self.get_pool_or_init(ns).await?,
+            false => self.get_pool(ns).await?,
+        };
+        let mut tx = pool.begin().await?;
+        let mut changes = Vec::with_capacity(staged.len());
+        let applied = async {
+            for write in staged {
+                let (grp, key) = (write.grp.as_str(), write.key.as_str());
+                let previous = db::find(&mut *tx, grp, key).await?;
+                let change = match &write.op {
+                    StagedOp::Delete => {
+                        let row = RowWrite::Delete;
+                        self.write_row_in(ns, &mut tx, grp, key, row, previous.as_ref())
+                            .await?;
+                        Change {
+                            op: Operation::Delete,
+                            grp: grp.to_string(),
+                            key: key.to_string(),
+                            previous,
+                            new_size: None,
+                            new_blob: None,
+                        }
+                    }
+                    StagedOp::Set(value) | StagedOp::Overwrite(value) => {
+                        let full_key = db::join_key(grp, key);
+                        let is_set = matches!(write.op, StagedOp::Set(_));
+                        // A value may not shadow a group of the same name either, including
+                        // one staged earlier in this transaction.
+                        if is_set
+                            && (previous.is_some() || db::exists_group(&mut *tx, &full_key).await?)
+                        {
+                            return Err(PathmapError::ValueAlreadyExists(full_key));
+                        }
+                        let new_size = value.len() as u64;
+                        let (data, external) = self.store_value(ns, value.clone()).await?;
+                        let new_blob = external.then(|| data.clone());
+                        let (op, row) = match is_set {
+                            true => (Operation::Set, RowWrite::Set(&data, external)),
+                            false => (Operation::Overwrite, RowWrite::Overwrite(&data, external)),
+                        };
+                        let written = self
+                            .write_row_in(ns, &mut tx, grp, key, row, previous.as_ref())
+                            .await;
+                        if let Err(e) = written {
+                            if let Some(blob) = &new_blob {
+                                let _ = self.remove_blob(ns, blob).await;
+                            }
+                            return Err(e);
+                        }
+                        Change {
+                            op,
+                            grp: grp.to_string(),
+                            key: key.to_string(),
+                            previous,
+                            new_size: Some(new_size),
+                            new_blob,
+                        }
+                    }
+                };
+                changes.push(change);
+            }
+            self.check_quota_totals(ns, &mut tx).await
+        };
+        if let Err(e) = applied.await {
+            self.discard_new_blobs(ns, &changes).await;
+            return Err(e);
+        }
+        Ok(Prepared { ns, tx, changes })
+    }
+
+    /// Removes the blob files written for changes that did not commit.
+    async fn discard_new_blobs(&self, ns: &str, changes: &[Change]) {
+        for blob in changes.iter().filter_map(|c| c.new_blob.as_ref()) {
+            // Best effort: an orphaned blob file is only wasted space.
+            let _ = self.remove_blob(ns, blob).await;
+        }
+    }
+
+    /// Invalidates the cache, emits the events and releases the replaced blob files of a
+    /// committed namespace.
+    async fn finish_ns(&self, ns: &str, changes: Vec<Change>) -> Result<()> {
+        for change in &changes {
+            self.invalidate_cached(ns, &change.grp, &change.key);
+            if change.new_size.is_none() && change.previous.is_none() {
+                continue;
+            }
+            let old_size = match &change.previous {
+                Some(previous) => Some(self.stored_size(ns, previous).await),
+                None => None,
+            };
+            let key = db::join_key(&change.grp, &change.key);
+            self.emit_change(change.op, ns, &key, old_size, change.new_size);
+        }
+        for change in changes {
+            if let Some(previous) = change.previous.filter(|p| p.external) {
+                self.remove_blob(ns, &previous.data).await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+/* src/names.rs */
+
+//! Validation of namespace names and keys before they reach the file system or the database.
+
+use crate::error::{PathmapError, Result};
+
+/// Default longest namespace name in bytes.
+pub(crate) const DEFAULT_MAX_NS_LEN: usize = 128;
+
+/// Default longest key in bytes.
+pub(crate) const DEFAULT_MAX_KEY_LEN: usize = 1024;
+
+/// Characters that are not allowed in file names on at least one supported platform.
+pub(crate) const INVALID_FILENAME_CHARS: &[char] = &['/', '\\', '<', '>', ':', '"', '|', '?', '*'];
+
+/// Which namespace names and keys are accepted; others fail with `InvalidPath`.
+///
+/// Independently of these rules, a namespace name is always a single file name: it cannot be
+/// empty, `.` or `..`, or contain path separators, `::`, control characters or characters that
+/// are invalid in file names (`<>:"|?*`), so no name resolves outside its directory. Keys never
+/// contain `::` or control characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameRules {
+    /// Longest namespace name in bytes (default 128).
+    pub max_ns_len: usize,
+    /// Longest key in bytes (default 1024).
+    pub max_key_len: usize,
+    /// Only allows ASCII letters, digits, `_`, `-` and `.` in namespace names (default `false`).
+    pub ascii_ns: bool,
+    /// Allows empty key segments, as in `a..b`, `.a` or `a.` (default `true`, so keys written
+    /// before these rules existed stay readable). Turn it off for new stores to reject them.
+    pub allow_empty_segments: bool,
+}
+
+impl Default for NameRules {
+    fn default() -> Self {
+        Self {
+            max_ns_len: DEFAULT_MAX_NS_LEN,
+            max_key_len: DEFAULT_MAX_KEY_LEN,
+            ascii_ns: false,
+            allow_empty_segments: true,
+        }
+    }
+}
+
+fn invalid(what: &str, name: &str, reason: &str) -> PathmapError {
+    PathmapError::InvalidPath(format!("{} '{}' {}", what, name.escape_debug(), reason))
+}
+
+impl NameRules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_ns_len(mut self, bytes: usize) -> Self {
+        self.max_ns_len = bytes;
+        self
+    }
+
+    pub fn max_key_len(mut self, bytes: usize) -> Self {
+        self.max_key_len = bytes;
+        self
+    }
+
+    pub fn ascii_ns(mut self, enabled: bool) -> Self {
+        self.ascii_ns = enabled;
+        self
+    }
+
+    pub fn allow_empty_segments(mut self, allowed: bool) -> Self {
+        self.allow_empty_segments = allowed;
+        self
+    }
+
+    /// Checks a namespace name.
+    pub fn check_ns(&self, ns: &str) -> Result<()> {
+        if ns.is_empty() {
+            return Err(invalid("namespace", ns, "is empty"));
+        }
+        if ns == "." || ns == ".." {
+            return Err(invalid("namespace", ns, "is a relative path"));
+        }
+        if ns.contains("::") {
+            return Err(invalid("namespace", ns, "contains the '::' separator"));
+        }
+        if let Some(c) = ns
+            .chars()
+            .find(|c| c.is_control() || INVALID_FILENAME_CHARS.contains(c))
+        {
+            return Err(invalid(
+                "namespace",
+                ns,
+                &format!(
+                    "contains '{}', which is not allowed in file names",
+                    c.escape_debug()
+                ),
+            ));
+        }
+        if self.ascii_ns
+            && let Some(c) = ns
+                .chars()
+                .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+        {
+            return Err(invalid(
+                "namespace",
+                ns,
+                &format!("contains '{}', outside [A-Za-z0-9_.-]", c.escape_debug()),
+            ));
+        }
+        if ns.len() > self.max_ns_len {
+            return Err(invalid(
+                "namespace",
+                ns,
+                &format!("is longer than {} bytes", self.max_ns_len),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks a dotted key or group; the empty key, naming a whole namespace, is accepted.
+    pub fn check_key(&self, key: &str) -> Result<()> {
+        if key.contains("::") {
+            return Err(invalid(
+                "key",
+                key,
+                "contains the '::' separator; build it with `KvPath::join` to escape it",
+            ));
+        }
+        if key.chars().any(char::is_control) {
+            return Err(invalid("key", key, "contains control characters"));
+        }
+        if key.len() > self.max_key_len {
+            return Err(invalid(
+                "key",
+                key,
+                &format!("is longer than {} bytes", self.max_key_len),
+            ));
+        }
+        if !self.allow_empty_segments && !key.is_empty() && key.split('.').any(str::is_empty) {
+            return Err(invalid("key", key, "has an empty segment"));
+        }
+        Ok(())
+    }
+}
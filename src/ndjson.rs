@@ -0,0 +1,153 @@
+/* src/ndjson.rs */
+
+use crate::Pathmap;
+use crate::db;
+use crate::error::{self, Result};
+use crate::import::{ConflictRules, EntryImport, ImportReport};
+use crate::transform::Pipeline;
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Rows read per page while dumping, which bounds memory for large namespaces.
+const DUMP_PAGE_SIZE: i64 = 500;
+
+/// One line of an NDJSON dump.
+#[derive(Serialize, Deserialize)]
+struct Line {
+    key: String,
+    value: Value,
+}
+
+impl Pathmap {
+    /// Streams a namespace as newline-delimited JSON, one `{"key": ..., "value": ...}` object per
+    /// line in key order. Each item is a chunk of whole lines, ready to pipe into a file, a
+    /// compressor or an upload. Rows are read page by page, so the dump is not a single snapshot.
+    pub fn dump_ndjson<'a>(&'a self, ns: &'a str) -> BoxStream<'a, Result<Vec<u8>>> {
+        // `None` once the last page has been produced; otherwise the position to continue after.
+        let start: Option<Option<(String, String)>> = Some(None);
+        stream::unfold(start, move |state| async move {
+            let after = state?;
+            match self.in_background(ns, self.dump_page(ns, after)).await {
+                Ok((bytes, _)) if bytes.is_empty() => None,
+                Ok((bytes, next)) => Some((Ok(bytes), next.map(Some))),
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+        .boxed()
+    }
+
+    /// Loads an NDJSON stream (as produced by `dump_ndjson`) into a namespace in one transaction,
+    /// creating the namespace if needed. Items are raw bytes in chunks of any size; blank lines
+    /// are ignored. Each line is written as soon as it has arrived, so memory stays bounded by the
+    /// longest line, but the transaction holds the namespace's write lock until the stream ends.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn load_ndjson<S, B, E>(
+        &self,
+        ns: &str,
+        input: S,
+        policy: impl Into<ConflictRules>,
+    ) -> Result<ImportReport>
+    where
+        S: Stream<Item = std::result::Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<std::io::Error>,
+    {
+        self.load_ndjson_with(ns, input, policy, &Pipeline::new())
+            .await
+    }
+
+    /// Like `load_ndjson`, but runs every entry through a transformation pipeline first.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn load_ndjson_with<S, B, E>(
+        &self,
+        ns: &str,
+        input: S,
+        policy: impl Into<ConflictRules>,
+        pipeline: &Pipeline,
+    ) -> Result<ImportReport>
+    where
+        S: Stream<Item = std::result::Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<std::io::Error>,
+    {
+        error::in_context("load_ndjson_with", ns, async {
+            let rules = policy.into();
+            let job = async {
+                let mut import = self.begin_import(ns, &rules).await?;
+                let written = self.load_lines(&mut import, input, &rules, pipeline).await;
+                self.finish_import(import, &rules, written).await
+            };
+            self.in_background(ns, job).await
+        })
+        .await
+    }
+
+    /// Writes the lines of an NDJSON stream into `import` as they arrive, so only the current
+    /// line is buffered.
+    async fn load_lines<S, B, E>(
+        &self,
+        import: &mut EntryImport<'_>,
+        input: S,
+        rules: &ConflictRules,
+        pipeline: &Pipeline,
+    ) -> Result<()>
+    where
+        S: Stream<Item = std::result::Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<std::io::Error>,
+    {
+        let mut input = std::pin::pin!(input);
+        let mut pending = Vec::new();
+        while let Some(chunk) = input.next().await {
+            pending.extend_from_slice(chunk.map_err(Into::into)?.as_ref());
+            let mut start = 0;
+            while let Some(end) = pending[start..].iter().position(|&b| b == b'\n') {
+                if let Some(Line { key, value }) = parse_line(&pending[start..start + end])? {
+                    self.import_entry(import, rules, pipeline, key, value)
+                        .await?;
+                }
+                start += end + 1;
+            }
+            pending.drain(..start);
+        }
+        if let Some(Line { key, value }) = parse_line(&pending)? {
+            self.import_entry(import, rules, pipeline, key, value)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Renders the next page of a dump, returning the bytes and the position of its last row.
+    async fn dump_page(
+        &self,
+        ns: &str,
+        after: Option<(String, String)>,
+    ) -> Result<(Vec<u8>, Option<(String, String)>)> {
+        let pool = self.get_pool(ns).await?;
+        let after = after.as_ref().map(|(g, k)| (g.as_str(), k.as_str()));
+        let entries = db::entries_after(&pool, after, DUMP_PAGE_SIZE).await?;
+        let next = entries.last().map(|e| (e.grp.clone(), e.key.clone()));
+
+        let mut bytes = Vec::new();
+        for entry in entries {
+            let key = db::join_key(&entry.grp, &entry.key);
+            let raw_value = self.load_value(ns, entry.value).await?;
+            let line = Line {
+                key,
+                value: serde_json::from_slice(&raw_value)?,
+            };
+            serde_json::to_writer(&mut bytes, &line)?;
+            bytes.push(b'\n');
+        }
+        Ok((bytes, next))
+    }
+}
+
+/// One line of an NDJSON stream, or `None` for a blank one.
+fn parse_line(line: &[u8]) -> Result<Option<Line>> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(line)?))
+}
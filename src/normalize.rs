@@ -0,0 +1,186 @@
+/* src/normalize.rs */
+
+//! Write-time value normalization, so data quality rules (trimmed strings, lowercase emails,
+//! numbers within range) are enforced by the store instead of by every writer.
+
+use crate::Pathmap;
+use crate::error::Result;
+use crate::path;
+use serde::Serialize;
+use serde_json::{Number, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// Custom normalization step, applied to the whole value.
+type MapFn = Arc<dyn Fn(Value) -> Value + Send + Sync>;
+
+#[derive(Clone)]
+enum Rule {
+    Trim { pointer: String },
+    Lowercase { pointer: String },
+    Clamp { pointer: String, min: f64, max: f64 },
+    Map(MapFn),
+}
+
+/// Ordered rules that rewrite a value before it is stored. Fields are addressed with JSON
+/// Pointers (RFC 6901) into the value, `""` being the value itself; a rule whose field is
+/// missing or of another type leaves the value alone.
+#[derive(Clone, Default)]
+pub struct Normalizer {
+    rules: Vec<Rule>,
+}
+
+impl Normalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes leading and trailing whitespace from the string at `pointer`.
+    pub fn trim(mut self, pointer: &str) -> Self {
+        self.rules.push(Rule::Trim {
+            pointer: pointer.to_string(),
+        });
+        self
+    }
+
+    /// Lowercases the string at `pointer`, e.g. an email address.
+    pub fn lowercase(mut self, pointer: &str) -> Self {
+        self.rules.push(Rule::Lowercase {
+            pointer: pointer.to_string(),
+        });
+        self
+    }
+
+    /// Limits the number at `pointer` to `min..=max`. Integers clamped to a whole bound stay
+    /// integers.
+    pub fn clamp(mut self, pointer: &str, min: f64, max: f64) -> Self {
+        self.rules.push(Rule::Clamp {
+            pointer: pointer.to_string(),
+            min,
+            max,
+        });
+        self
+    }
+
+    /// Adds an arbitrary rule over the whole value.
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Value) -> Value + Send + Sync + 'static,
+    {
+        self.rules.push(Rule::Map(Arc::new(f)));
+        self
+    }
+
+    /// Runs every rule over `value`, in the order they were added.
+    pub fn apply(&self, mut value: Value) -> Value {
+        for rule in &self.rules {
+            value = match rule {
+                Rule::Trim { pointer } => {
+                    if let Some(Value::String(s)) = value.pointer_mut(pointer) {
+                        let trimmed = s.trim();
+                        if trimmed.len() != s.len() {
+                            *s = trimmed.to_string();
+                        }
+                    }
+                    value
+                }
+                Rule::Lowercase { pointer } => {
+                    if let Some(Value::String(s)) = value.pointer_mut(pointer) {
+                        *s = s.to_lowercase();
+                    }
+                    value
+                }
+                Rule::Clamp { pointer, min, max } => {
+                    if let Some(Value::Number(n)) = value.pointer_mut(pointer) {
+                        clamp_number(n, *min, *max);
+                    }
+                    value
+                }
+                Rule::Map(f) => f(value),
+            };
+        }
+        value
+    }
+}
+
+impl fmt::Debug for Normalizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Normalizer")
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+/// Replaces `n` with the bound it exceeds, if any.
+fn clamp_number(n: &mut Number, min: f64, max: f64) {
+    let Some(x) = n.as_f64() else {
+        return;
+    };
+    let bound = if x < min {
+        min
+    } else if x > max {
+        max
+    } else {
+        return;
+    };
+    let whole = bound.fract() == 0.0 && bound.abs() < i64::MAX as f64;
+    let clamped = if !n.is_f64() && whole {
+        Some(Number::from(bound as i64))
+    } else {
+        Number::from_f64(bound)
+    };
+    if let Some(clamped) = clamped {
+        *n = clamped;
+    }
+}
+
+pub(crate) struct GroupNormalizer {
+    group: String,
+    normalizer: Normalizer,
+}
+
+impl Pathmap {
+    /// Runs every value written (`set`, `overwrite`, `overwrite_batched` and imports) to `group`
+    /// of `ns`, or anything nested below it, through `normalizer` before it is stored; an empty
+    /// group covers the whole namespace. When several groups contain a key, the outermost
+    /// group's normalizer runs first. Registering a group again replaces its normalizer. Soft
+    /// namespaces are normalized by their host, with the soft namespace as the first group.
+    ///
+    /// Normalized values pass through `serde_json::Value`, so numbers beyond `u64`/`f64` are only
+    /// kept exact with the `arbitrary-precision` feature.
+    pub fn with_normalizer(mut self, ns: &str, group: &str, normalizer: Normalizer) -> Self {
+        let normalizers = self.normalizers.entry(ns.to_string()).or_default();
+        normalizers.retain(|n| n.group != group);
+        normalizers.push(GroupNormalizer {
+            group: group.to_string(),
+            normalizer,
+        });
+        normalizers.sort_by_key(|n| n.group.len());
+        self
+    }
+
+    /// Serializes a value written to the dotted `key` of `ns`, applying the normalizers that
+    /// cover it.
+    pub(crate) fn encode_write<T: Serialize + ?Sized>(
+        &self,
+        ns: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<Vec<u8>> {
+        let mut normalizers = self
+            .normalizers
+            .get(ns)
+            .into_iter()
+            .flatten()
+            .filter(|n| path::in_group(&n.group, key))
+            .peekable();
+        if normalizers.peek().is_none() {
+            return self.encode(ns, value);
+        }
+        let mut value = serde_json::to_value(value)?;
+        for n in normalizers {
+            value = n.normalizer.apply(value);
+        }
+        self.encode(ns, &value)
+    }
+}
@@ -0,0 +1,520 @@
+/* src/number.rs */
+
+//! Handling of numbers that JSON cannot carry faithfully.
+//!
+//! By default `serde_json` silently writes non-finite floats as `null`, which then fails to
+//! read back into an `f64`. A namespace's [`FloatPolicy`] decides what happens instead. For a
+//! lossless round trip under any policy, annotate fields with
+//! `#[serde(with = "kvmap::number::nonfinite")]`: the field reads back exactly, including NaN
+//! and the infinities.
+//!
+//! Integers are stored exactly, but consumers that parse JSON numbers as `f64` (JavaScript, jq)
+//! round anything beyond 2^53. [`IntegerPolicy::String`] stores such integers as decimal strings;
+//! fields annotated with `#[serde(with = "kvmap::number::exact")]` read either form back. The
+//! `arbitrary-precision` feature additionally keeps numbers beyond `u64`/`f64` exact in `Value`.
+
+use serde::ser::{self, Serialize, Serializer};
+use std::fmt;
+
+/// What to do when a value being stored contains NaN or an infinite float.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatPolicy {
+    /// Store `null`, matching `serde_json`. Reading back requires an `Option<f64>` or `Value`.
+    #[default]
+    Null,
+    /// Reject the write with a serialization error.
+    Error,
+    /// Store `"NaN"`, `"Infinity"` or `"-Infinity"`. Reading back into a plain `f64` requires the
+    /// field to use [`nonfinite`].
+    String,
+}
+
+/// How integers beyond the range an `f64` holds exactly (±2^53 − 1) are stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IntegerPolicy {
+    /// Store every integer as a JSON number, matching `serde_json`.
+    #[default]
+    Number,
+    /// Store integers outside the safe range as decimal strings. Reading back into an integer
+    /// field requires the field to use [`exact`].
+    String,
+}
+
+/// Largest integer an `f64` represents exactly, and so the largest safe for any JSON consumer.
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Serializes a value to JSON, applying the number policies.
+pub(crate) fn to_vec<T: Serialize + ?Sized>(
+    value: &T,
+    floats: FloatPolicy,
+    integers: IntegerPolicy,
+) -> serde_json::Result<Vec<u8>> {
+    match (floats, integers) {
+        (FloatPolicy::Null, IntegerPolicy::Number) => serde_json::to_vec(value),
+        _ => serde_json::to_vec(&Guarded(value, Policy { floats, integers })),
+    }
+}
+
+fn encode_non_finite(value: f64) -> &'static str {
+    if value.is_nan() {
+        "NaN"
+    } else if value > 0.0 {
+        "Infinity"
+    } else {
+        "-Infinity"
+    }
+}
+
+/// Serde helpers for `f64` fields that must survive a JSON round trip even when non-finite.
+/// Finite values are written as numbers; NaN and the infinities as strings.
+pub mod nonfinite {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::Serializer;
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.serialize_str(super::encode_non_finite(*value))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        struct FloatVisitor;
+
+        impl Visitor<'_> for FloatVisitor {
+            type Value = f64;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a number or one of \"NaN\", \"Infinity\", \"-Infinity\"")
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+                Ok(v)
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<f64, E> {
+                Ok(v as f64)
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<f64, E> {
+                Ok(v as f64)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<f64, E> {
+                match v {
+                    "NaN" => Ok(f64::NAN),
+                    "Infinity" => Ok(f64::INFINITY),
+                    "-Infinity" => Ok(f64::NEG_INFINITY),
+                    other => Err(E::invalid_value(de::Unexpected::Str(other), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(FloatVisitor)
+    }
+}
+
+/// Serde helpers for integer fields stored under [`IntegerPolicy::String`].
+/// Values are written as numbers (the policy quotes them if needed) and read from either a
+/// number or a decimal string.
+pub mod exact {
+    use serde::de::{self, Deserializer, Visitor};
+    use serde::ser::{Serialize, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+    use std::str::FromStr;
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr + TryFrom<u64> + TryFrom<i64>,
+        D: Deserializer<'de>,
+    {
+        struct IntegerVisitor<T>(PhantomData<T>);
+
+        impl<T: FromStr + TryFrom<u64> + TryFrom<i64>> Visitor<'_> for IntegerVisitor<T> {
+            type Value = T;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("an integer or a decimal string")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+                T::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+                T::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+                v.parse()
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(IntegerVisitor(PhantomData))
+    }
+}
+
+/// The policies applied while a value is serialized.
+#[derive(Clone, Copy)]
+struct Policy {
+    floats: FloatPolicy,
+    integers: IntegerPolicy,
+}
+
+/// A value whose numbers are checked against the policies while it is serialized.
+struct Guarded<'a, T: ?Sized>(&'a T, Policy);
+
+impl<T: Serialize + ?Sized> Serialize for Guarded<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(Guard {
+            inner: serializer,
+            policy: self.1,
+        })
+    }
+}
+
+/// Serializer adaptor that intercepts numbers and re-wraps every nested value.
+struct Guard<S> {
+    inner: S,
+    policy: Policy,
+}
+
+impl<S: Serializer> Guard<S> {
+    fn float(self, value: f64) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            return self.inner.serialize_f64(value);
+        }
+        match self.policy.floats {
+            FloatPolicy::Null => self.inner.serialize_none(),
+            FloatPolicy::Error => Err(ser::Error::custom(format!(
+                "cannot store non-finite float {} as JSON",
+                value
+            ))),
+            FloatPolicy::String => self.inner.serialize_str(encode_non_finite(value)),
+        }
+    }
+
+    /// Quotes an integer outside the safe range when the policy asks for it.
+    fn integer<T: Serialize + fmt::Display>(
+        self,
+        value: T,
+        magnitude: u128,
+    ) -> Result<S::Ok, S::Error> {
+        match self.policy.integers {
+            IntegerPolicy::String if magnitude > MAX_SAFE_INTEGER as u128 => {
+                self.inner.collect_str(&value)
+            }
+            _ => value.serialize(self.inner),
+        }
+    }
+}
+
+/// Wraps compound serializers so their elements are guarded too.
+struct GuardCompound<C> {
+    inner: C,
+    policy: Policy,
+}
+
+macro_rules! forward {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+            self.inner.$method(v)
+        })*
+    };
+}
+
+impl<S: Serializer> Serializer for Guard<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = GuardCompound<S::SerializeSeq>;
+    type SerializeTuple = GuardCompound<S::SerializeTuple>;
+    type SerializeTupleStruct = GuardCompound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = GuardCompound<S::SerializeTupleVariant>;
+    type SerializeMap = GuardCompound<S::SerializeMap>;
+    type SerializeStruct = GuardCompound<S::SerializeStruct>;
+    type SerializeStructVariant = GuardCompound<S::SerializeStructVariant>;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.integer(v, v.unsigned_abs() as u128)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        self.integer(v, v.unsigned_abs())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.integer(v, v as u128)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        self.integer(v, v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        if v.is_finite() {
+            self.inner.serialize_f32(v)
+        } else {
+            self.float(v as f64)
+        }
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.float(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_some(&Guarded(value, self.policy))
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_newtype_struct(name, &Guarded(value, self.policy))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_newtype_variant(name, index, variant, &Guarded(value, self.policy))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(GuardCompound {
+            inner: self.inner.serialize_seq(len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(GuardCompound {
+            inner: self.inner.serialize_tuple(len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(GuardCompound {
+            inner: self.inner.serialize_tuple_struct(name, len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(GuardCompound {
+            inner: self
+                .inner
+                .serialize_tuple_variant(name, index, variant, len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(GuardCompound {
+            inner: self.inner.serialize_map(len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(GuardCompound {
+            inner: self.inner.serialize_struct(name, len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(GuardCompound {
+            inner: self
+                .inner
+                .serialize_struct_variant(name, index, variant, len)?,
+            policy: self.policy,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<C: ser::SerializeSeq> ser::SerializeSeq for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_element(&Guarded(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTuple> ser::SerializeTuple for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_element(&Guarded(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleStruct> ser::SerializeTupleStruct for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(&Guarded(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleVariant> ser::SerializeTupleVariant for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_field(&Guarded(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        self.inner.serialize_value(&Guarded(value, self.policy))
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStruct> ser::SerializeStruct for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        self.inner
+            .serialize_field(key, &Guarded(value, self.policy))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStructVariant> ser::SerializeStructVariant for GuardCompound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        self.inner
+            .serialize_field(key, &Guarded(value, self.policy))
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
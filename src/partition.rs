@@ -0,0 +1,199 @@
+/* src/partition.rs */
+
+//! Consistent hashing for spreading keys over several kvmap-backed instances. Adding or removing
+//! an instance only moves the keys of the ring segments it gains or loses, and
+//! `HashRing::plan_rebalance` lists those moves as batches to copy between instances.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Ring points per instance unless set otherwise; enough for an even spread over a few dozen
+/// instances.
+const DEFAULT_VNODES: usize = 128;
+
+/// A consistent hash ring mapping keys to instance ids. The hash is fixed (FNV-1a with a
+/// SplitMix64 finalizer), so every process and release places keys identically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashRing {
+    vnodes: usize,
+    instances: BTreeSet<String>,
+    ring: BTreeMap<u64, String>,
+}
+
+/// Keys that move from one instance to another.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMoveBatch {
+    pub from: String,
+    pub to: String,
+    pub keys: Vec<String>,
+}
+
+/// The moves needed to go from one ring to another, grouped by source and target instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RebalancePlan {
+    pub batches: Vec<KeyMoveBatch>,
+    /// Keys that stay where they are.
+    pub unchanged: u64,
+}
+
+impl RebalancePlan {
+    /// Total number of keys to move.
+    pub fn moved(&self) -> u64 {
+        self.batches.iter().map(|b| b.keys.len() as u64).sum()
+    }
+}
+
+impl HashRing {
+    pub fn new() -> Self {
+        Self::with_vnodes(DEFAULT_VNODES)
+    }
+
+    /// Creates an empty ring with `vnodes` points per instance. More points spread keys more
+    /// evenly at the cost of memory.
+    pub fn with_vnodes(vnodes: usize) -> Self {
+        HashRing {
+            vnodes: vnodes.max(1),
+            instances: BTreeSet::new(),
+            ring: BTreeMap::new(),
+        }
+    }
+
+    /// Adds an instance; adding one that is already present does nothing.
+    pub fn add(&mut self, instance: &str) {
+        if !self.instances.insert(instance.to_string()) {
+            return;
+        }
+        for i in 0..self.vnodes {
+            // On the (unlikely) collision of two points the smaller id wins, independent of the
+            // order instances were added in.
+            let point = hash(&format!("{}#{}", instance, i));
+            match self.ring.get(&point) {
+                Some(owner) if owner.as_str() <= instance => {}
+                _ => {
+                    self.ring.insert(point, instance.to_string());
+                }
+            }
+        }
+    }
+
+    /// Removes an instance; its keys fall to the next points on the ring.
+    pub fn remove(&mut self, instance: &str) {
+        if !self.instances.remove(instance) {
+            return;
+        }
+        let instances: Vec<String> = self.instances.iter().cloned().collect();
+        self.instances.clear();
+        self.ring.clear();
+        for instance in instances {
+            self.add(&instance);
+        }
+    }
+
+    /// Builder form of `add`.
+    pub fn with_instance(mut self, instance: &str) -> Self {
+        self.add(instance);
+        self
+    }
+
+    pub fn instances(&self) -> impl Iterator<Item = &str> {
+        self.instances.iter().map(String::as_str)
+    }
+
+    /// The instance owning `key`, or `None` for an empty ring.
+    pub fn instance_for(&self, key: &str) -> Option<&str> {
+        let point = hash(key);
+        self.ring
+            .range(point..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, instance)| instance.as_str())
+    }
+
+    /// Lists the keys whose owner differs between this ring and `next`, in batches of at most
+    /// `batch_size` keys that share a source and target instance.
+    pub fn plan_rebalance<I, S>(&self, next: &HashRing, keys: I, batch_size: usize) -> RebalancePlan
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let batch_size = batch_size.max(1);
+        let mut moves: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+        let mut plan = RebalancePlan::default();
+        for key in keys {
+            let key = key.as_ref();
+            match (self.instance_for(key), next.instance_for(key)) {
+                (Some(from), Some(to)) if from != to => moves
+                    .entry((from.to_string(), to.to_string()))
+                    .or_default()
+                    .push(key.to_string()),
+                _ => plan.unchanged += 1,
+            }
+        }
+        for ((from, to), keys) in moves {
+            for chunk in keys.chunks(batch_size) {
+                plan.batches.push(KeyMoveBatch {
+                    from: from.clone(),
+                    to: to.clone(),
+                    keys: chunk.to_vec(),
+                });
+            }
+        }
+        plan
+    }
+}
+
+impl Default for HashRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hash(key: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut h = key
+        .bytes()
+        .fold(FNV_OFFSET, |h, b| (h ^ b as u64).wrapping_mul(FNV_PRIME));
+    // SplitMix64 finalizer: FNV alone clusters keys that differ only in their last bytes.
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^ (h >> 31)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl crate::Pathmap {
+    /// Plans the key moves for a namespace when going from ring `current` to `next`. Keys are
+    /// hashed as full paths (`ns::group.key`), the same strings `instance_for` should be given
+    /// when routing requests.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns)))]
+    pub async fn plan_rebalance(
+        &self,
+        ns: &str,
+        current: &HashRing,
+        next: &HashRing,
+        batch_size: usize,
+    ) -> crate::error::Result<RebalancePlan> {
+        let keys = self.in_background(ns, self.paths(ns)).await?;
+        Ok(current.plan_rebalance(next, keys, batch_size))
+    }
+
+    /// Every value path of a namespace, read page by page.
+    async fn paths(&self, ns: &str) -> crate::error::Result<Vec<String>> {
+        const PAGE_SIZE: i64 = 1000;
+        let pool = self.get_pool(ns).await?;
+        let mut paths = Vec::new();
+        let mut after: Option<(String, String)> = None;
+        loop {
+            let position = after.as_ref().map(|(g, k)| (g.as_str(), k.as_str()));
+            let page = crate::db::entries_after(&pool, position, PAGE_SIZE).await?;
+            let Some(last) = page.last() else {
+                return Ok(paths);
+            };
+            after = Some((last.grp.clone(), last.key.clone()));
+            paths.extend(
+                page.iter()
+                    .map(|e| format!("{}::{}", ns, crate::db::join_key(&e.grp, &e.key))),
+            );
+        }
+    }
+}
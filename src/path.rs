@@ -0,0 +1,325 @@
+/* src/path.rs */
+
+//! Key layout shared by every backend: `namespace::group.sub.value`, stored as a
+//! (group, value name) pair, and `KvPath`, a validated path built from escaped segments.
+
+use crate::error::{PathmapError, Result};
+use crate::names::{DEFAULT_MAX_KEY_LEN, DEFAULT_MAX_NS_LEN, INVALID_FILENAME_CHARS, NameRules};
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+
+/// Splits a dotted key into its group and value name.
+/// Without a fixed depth the last segment is the value name; with a depth of `n`
+/// the first `n` segments (at most all but one) form the group.
+pub fn split_key(full_key: &str, group_depth: Option<usize>) -> (&str, &str) {
+    let split_at = match group_depth {
+        None => full_key.rfind('.'),
+        Some(depth) => full_key
+            .match_indices('.')
+            .take(depth)
+            .last()
+            .map(|(idx, _)| idx),
+    };
+    match split_at {
+        Some(idx) => (&full_key[..idx], &full_key[idx + 1..]),
+        None => ("", full_key),
+    }
+}
+
+/// Joins a group and value name back into a dotted key.
+pub fn join_key(grp: &str, key: &str) -> String {
+    if grp.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", grp, key)
+    }
+}
+
+/// Whether the dotted `key` is `group` or nested below it; an empty group contains every key.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn in_group(group: &str, key: &str) -> bool {
+    group.is_empty()
+        || key
+            .strip_prefix(group)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Escapes a single key segment so that it may contain `.`, `:` and other characters that
+/// would otherwise split it: `.`, `:`, `%` and control characters become `%XX`. Segments without
+/// them are returned unchanged, so escaping is invisible for ordinary keys.
+pub fn escape_segment(segment: &str) -> Cow<'_, str> {
+    let needs_escape = |c: char| matches!(c, '.' | ':' | '%') || c.is_control();
+    if !segment.contains(needs_escape) {
+        return Cow::Borrowed(segment);
+    }
+    let mut escaped = String::with_capacity(segment.len() + 8);
+    for c in segment.chars() {
+        if needs_escape(c) {
+            let mut buf = [0u8; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Reverses `escape_segment`, e.g. for group and value names returned by `list`. A `%` not
+/// followed by two hex digits is kept as is.
+pub fn unescape_segment(segment: &str) -> Cow<'_, str> {
+    if !segment.contains('%') {
+        return Cow::Borrowed(segment);
+    }
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// Whether a byte starts or continues a control character: ASCII controls, or the UTF-8
+/// continuation byte of a C1 control (U+0080 to U+009F) after `0xC2`.
+const fn is_control_byte(bytes: &[u8], i: usize) -> bool {
+    let b = bytes[i];
+    b < 0x20 || b == 0x7F || (b == 0xC2 && i + 1 < bytes.len() && bytes[i + 1] <= 0x9F)
+}
+
+/// Whether `path` is a valid `ns::key` path under `KvPath`'s rules, usable in constant
+/// contexts; `kvpath!` checks literals with it at compile time.
+pub const fn is_valid_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    // The namespace ends at the first "::".
+    let mut sep = 0;
+    while sep + 1 < bytes.len() && !(bytes[sep] == b':' && bytes[sep + 1] == b':') {
+        sep += 1;
+    }
+    if sep + 1 >= bytes.len() || sep == 0 || sep > DEFAULT_MAX_NS_LEN {
+        return false;
+    }
+    if (sep == 1 && bytes[0] == b'.') || (sep == 2 && bytes[0] == b'.' && bytes[1] == b'.') {
+        return false;
+    }
+    let mut i = 0;
+    while i < sep {
+        if is_control_byte(bytes, i) {
+            return false;
+        }
+        // The invalid file name characters are all ASCII.
+        let mut c = 0;
+        while c < INVALID_FILENAME_CHARS.len() {
+            if bytes[i] == INVALID_FILENAME_CHARS[c] as u8 {
+                return false;
+            }
+            c += 1;
+        }
+        i += 1;
+    }
+    let key_start = sep + 2;
+    if bytes.len() - key_start > DEFAULT_MAX_KEY_LEN {
+        return false;
+    }
+    let mut segment_len = 0;
+    i = key_start;
+    while i < bytes.len() {
+        if is_control_byte(bytes, i)
+            || (bytes[i] == b':' && i + 1 < bytes.len() && bytes[i + 1] == b':')
+        {
+            return false;
+        }
+        if bytes[i] == b'.' {
+            if segment_len == 0 {
+                return false;
+            }
+            segment_len = 0;
+        } else {
+            segment_len += 1;
+        }
+        i += 1;
+    }
+    key_start == bytes.len() || segment_len > 0
+}
+
+/// The default `NameRules` without empty key segments: the rules of `KvPath`.
+fn path_rules() -> NameRules {
+    NameRules::default().allow_empty_segments(false)
+}
+
+/// A validated `ns::group.value` path. Parse one with `str::parse` (failing with `InvalidPath`
+/// under the default `NameRules`, and on empty key segments such as `a..b`, which would leave
+/// `segments`, `name` and `parent` ambiguous), write a literal with `kvpath!` to have it checked at compile
+/// time, and derive others with `join` and `parent`. Segments added with `join` are escaped with
+/// `escape_segment`, so segments holding dots or `::` (e.g. host names or URLs) stay one group or
+/// value instead of being split. It dereferences to the path string, so it can be passed to every
+/// method that takes one.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KvPath {
+    path: String,
+    /// Byte offset of the "::" separator.
+    sep: usize,
+}
+
+impl KvPath {
+    /// Builds a path from a literal already checked by `is_valid_path`; used by `kvpath!`.
+    #[doc(hidden)]
+    pub fn from_checked(path: &'static str) -> Self {
+        let sep = path.find("::").unwrap_or(path.len());
+        KvPath {
+            path: path.to_string(),
+            sep,
+        }
+    }
+
+    /// The root path of namespace `ns`, i.e. `ns::`.
+    pub fn namespace(ns: &str) -> Result<Self> {
+        path_rules().check_ns(ns)?;
+        Ok(KvPath {
+            path: format!("{}::", ns),
+            sep: ns.len(),
+        })
+    }
+
+    pub fn ns(&self) -> &str {
+        &self.path[..self.sep]
+    }
+
+    /// The dotted key, empty for the namespace root.
+    pub fn key(&self) -> &str {
+        &self.path[self.sep + 2..]
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The key's segments, still escaped (see `unescape_segment`).
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.key().split('.').filter(|s| !s.is_empty())
+    }
+
+    /// The key's segments with their escaping undone, as they were passed to `join`.
+    pub fn unescaped_segments(&self) -> impl Iterator<Item = Cow<'_, str>> {
+        self.segments().map(unescape_segment)
+    }
+
+    /// The last segment: the value name, or the group for a group path. `None` at the root.
+    pub fn name(&self) -> Option<&str> {
+        self.segments().last()
+    }
+
+    /// Whether the path names the namespace itself.
+    pub fn is_root(&self) -> bool {
+        self.key().is_empty()
+    }
+
+    /// The path one segment below this one. `segment` is taken literally and escaped, so it may
+    /// contain dots or `::`.
+    pub fn join(&self, segment: &str) -> Result<Self> {
+        let segment = escape_segment(segment);
+        if segment.is_empty() {
+            return Err(PathmapError::InvalidPath(format!(
+                "{}: empty segment",
+                self.path
+            )));
+        }
+        let path = if self.is_root() {
+            format!("{}{}", self.path, segment)
+        } else {
+            format!("{}.{}", self.path, segment)
+        };
+        path_rules().check_key(&path[self.sep + 2..])?;
+        Ok(KvPath {
+            path,
+            sep: self.sep,
+        })
+    }
+
+    /// The enclosing group, or the namespace root for a top-level key. `None` at the root.
+    pub fn parent(&self) -> Option<Self> {
+        if self.is_root() {
+            return None;
+        }
+        let end = self
+            .key()
+            .rfind('.')
+            .map_or(self.sep + 2, |i| self.sep + 2 + i);
+        Some(KvPath {
+            path: self.path[..end].to_string(),
+            sep: self.sep,
+        })
+    }
+}
+
+impl FromStr for KvPath {
+    type Err = PathmapError;
+
+    fn from_str(path: &str) -> Result<Self> {
+        let (ns, key) = path
+            .split_once("::")
+            .ok_or_else(|| PathmapError::InvalidPath(path.to_string()))?;
+        let rules = path_rules();
+        rules.check_ns(ns)?;
+        rules.check_key(key)?;
+        Ok(KvPath {
+            path: path.to_string(),
+            sep: ns.len(),
+        })
+    }
+}
+
+impl fmt::Display for KvPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+impl Deref for KvPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl AsRef<str> for KvPath {
+    fn as_ref(&self) -> &str {
+        &self.path
+    }
+}
+
+impl From<KvPath> for String {
+    fn from(path: KvPath) -> String {
+        path.path
+    }
+}
+
+/// A `KvPath` from a string literal, checked at compile time: `kvpath!("app::db.host")`. An
+/// invalid literal is a compile error instead of an `InvalidPath` at run time.
+#[macro_export]
+macro_rules! kvpath {
+    ($path:literal) => {{
+        const _: () = assert!(
+            $crate::path::is_valid_path($path),
+            concat!("invalid kvmap path: ", $path)
+        );
+        $crate::path::KvPath::from_checked($path)
+    }};
+}
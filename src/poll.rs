@@ -0,0 +1,221 @@
+/* src/poll.rs */
+
+//! Change polling: reports the mutations other processes make to shared namespace files as
+//! events, by following the change log, where every write is recorded with a sequence number
+//! and, for namespaces with `external_changes`, the instance that made it.
+
+use crate::clock::Clock;
+use crate::error::Result;
+use crate::event::{Events, Operation};
+use crate::{Pathmap, blob, db, digest, rt};
+use futures_util::future::{self, Either};
+use sqlx::SqlitePool;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+/// Instances created by this process so far, for their origin tags.
+static INSTANCES: AtomicU64 = AtomicU64::new(0);
+
+/// How long change log entries are kept after this instance polled them, so that other
+/// processes polling the same file still see them, unless `change_retention` says otherwise.
+const POLLED_RETENTION: Duration = Duration::from_secs(60);
+
+/// Where polling of one namespace stands.
+struct Cursor {
+    /// The change log sequence the namespace was last polled at.
+    seq: i64,
+    /// Recent polls as (when, sequence), oldest first, until they leave the retention window.
+    recent: VecDeque<(Instant, i64)>,
+}
+
+/// A new origin tag, unique among the instances of all processes sharing a file. Process ids
+/// alone are not: processes in separate containers often all run as pid 1, so a random part
+/// tells them apart.
+pub(crate) fn next_origin() -> String {
+    let mut random = [0u8; 8];
+    if getrandom::getrandom(&mut random).is_err() {
+        // Without an entropy source, the start time still separates processes.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        random = nanos.to_le_bytes();
+    }
+    let random = digest::hex(&random);
+    format!(
+        "{}-{}-{}",
+        std::process::id(),
+        INSTANCES.fetch_add(1, Ordering::Relaxed),
+        random
+    )
+}
+
+/// Stops the task started by `start_change_polling`. Dropping the handle does not stop the task;
+/// it runs until `stop`, `close` or the instance is dropped.
+#[derive(Clone)]
+pub struct ChangePollHandle {
+    stop: Arc<watch::Sender<bool>>,
+}
+
+impl ChangePollHandle {
+    /// Stops the task. A poll in progress is finished first.
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    /// Whether `stop` has been called.
+    pub fn is_stopped(&self) -> bool {
+        *self.stop.borrow()
+    }
+}
+
+impl Pathmap {
+    /// Starts a background task that checks the change log of every open namespace with
+    /// `NamespaceConfig::external_changes` each `interval` and reports the mutations other
+    /// processes made since as `remote` events to `subscribe` receivers and hooks, so they see
+    /// changes to shared files and not just those of this instance. A key changed several times
+    /// between two polls is reported once, with its latest state. Changes made before a
+    /// namespace's first poll are not reported.
+    ///
+    /// Unless the namespace also has `NamespaceConfig::changelog` (whose log belongs to the
+    /// incremental backups and is trimmed with `trim_changelog`), the task trims the change log
+    /// entries it polled more than `NamespaceConfig::change_retention` ago (a minute by default),
+    /// so the log does not grow without bound. Other processes polling the same file therefore
+    /// have to poll at least that often.
+    ///
+    /// An instance runs one polling task: while it runs, further calls return its handle and
+    /// keep its `interval`, so no change is reported twice. After `stop`, a call starts a new one.
+    pub fn start_change_polling(&self, interval: Duration) -> ChangePollHandle {
+        let mut running = self.change_polling.lock().unwrap();
+        if let Some(handle) = running.as_ref()
+            && !handle.is_stopped()
+        {
+            return handle.clone();
+        }
+        let pools = Arc::clone(&self.pools);
+        let ns_configs = self.ns_configs.clone();
+        let default_ns_config = self.default_ns_config.clone();
+        let events = Arc::clone(&self.events);
+        let origin = self.origin.clone();
+        let clock = Arc::clone(&self.clock);
+        let mut shutdown = self.shutdown.subscribe();
+        let (stop, mut stopped) = watch::channel(false);
+        let mut cursors = HashMap::<String, Cursor>::new();
+
+        rt::spawn(async move {
+            loop {
+                {
+                    let tick = std::pin::pin!(rt::sleep(interval));
+                    let stop = std::pin::pin!(stopped.changed());
+                    let closed = std::pin::pin!(shutdown.changed());
+                    if let Either::Right(_) =
+                        future::select(tick, future::select(stop, closed)).await
+                    {
+                        // Stopped, closed, or the instance has been dropped.
+                        return;
+                    }
+                }
+                let watched: Vec<(String, SqlitePool)> = pools
+                    .read()
+                    .unwrap()
+                    .iter()
+                    .filter(|(ns, _)| {
+                        let config = ns_configs.get(*ns).unwrap_or(&default_ns_config);
+                        config.external_changes && !config.read_only
+                    })
+                    .filter_map(|(ns, slot)| Some((ns.clone(), slot.get()?.clone())))
+                    .collect();
+                for (ns, pool) in watched {
+                    let config = ns_configs.get(&ns).unwrap_or(&default_ns_config);
+                    let polled = poll_ns(&events, &ns, &pool, &origin, &mut cursors).await;
+                    let trimmed = match polled {
+                        Ok(()) if !config.changelog => {
+                            let retention = config.change_retention.unwrap_or(POLLED_RETENTION);
+                            trim_polled(&pool, clock.as_ref(), &ns, retention, &mut cursors).await
+                        }
+                        other => other,
+                    };
+                    if let Err(e) = trimmed {
+                        tracing::warn!(ns = %ns, error = %e, "polling the change log failed");
+                    }
+                }
+            }
+        });
+        let handle = ChangePollHandle {
+            stop: Arc::new(stop),
+        };
+        *running = Some(handle.clone());
+        handle
+    }
+}
+
+/// Reports the changes other processes made to `ns` since its last poll.
+async fn poll_ns(
+    events: &Events,
+    ns: &str,
+    pool: &SqlitePool,
+    origin: &str,
+    cursors: &mut HashMap<String, Cursor>,
+) -> Result<()> {
+    let seq = db::changelog_seq(pool).await?;
+    let Some(cursor) = cursors.get_mut(ns) else {
+        cursors.insert(
+            ns.to_string(),
+            Cursor {
+                seq,
+                recent: VecDeque::new(),
+            },
+        );
+        return Ok(());
+    };
+    let since = std::mem::replace(&mut cursor.seq, seq);
+    if seq < since {
+        // The file was replaced, e.g. restored from a backup; its sequences start over.
+        cursor.recent.clear();
+    }
+    if seq <= since || !events.is_active() {
+        return Ok(());
+    }
+    let dir = blob::blob_dir(pool.connect_options().get_filename());
+    for change in db::foreign_changes(pool, since, seq, origin).await? {
+        let (op, new_size) = match &change.value {
+            Some(stored) => (
+                Operation::Overwrite,
+                Some(blob::stored_size(&dir, &stored.data, stored.external).await),
+            ),
+            None => (Operation::Delete, None),
+        };
+        let path = format!("{}::{}", ns, db::join_key(&change.grp, &change.key));
+        events.emit_remote(op, path, new_size);
+    }
+    Ok(())
+}
+
+/// Records the poll of `ns` that just finished and trims the change log entries polled longer
+/// than `retention` ago.
+async fn trim_polled(
+    pool: &SqlitePool,
+    clock: &dyn Clock,
+    ns: &str,
+    retention: Duration,
+    cursors: &mut HashMap<String, Cursor>,
+) -> Result<()> {
+    let Some(cursor) = cursors.get_mut(ns) else {
+        return Ok(());
+    };
+    let now = clock.now();
+    cursor.recent.push_back((now, cursor.seq));
+    let mut expired = None;
+    while let Some(&(at, seq)) = cursor.recent.front()
+        && now.duration_since(at) >= retention
+    {
+        expired = Some(seq);
+        cursor.recent.pop_front();
+    }
+    if let Some(upto) = expired {
+        db::trim_changelog(pool, upto).await?;
+    }
+    Ok(())
+}
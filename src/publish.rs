@@ -0,0 +1,219 @@
+/* src/publish.rs */
+
+//! Read-only dataset publishing: self-contained, compacted namespace files with a digest
+//! manifest, for shipping to clients or a CDN.
+
+use crate::Pathmap;
+use crate::blob;
+use crate::config::NamespaceConfig;
+use crate::db;
+use crate::digest;
+use crate::error::{self, PathmapError, Result};
+use crate::rt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Identifies manifests written by `publish_ns`, and the version of their layout.
+const MANIFEST_FORMAT: &str = "kvmap-published/1";
+
+/// Describes a published dataset; stored next to it as `<file>.manifest.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishManifest {
+    pub format: String,
+    /// The namespace the dataset was published from, and is served as by `open_published`.
+    pub ns: String,
+    pub schema_version: i64,
+    /// Number of values in the dataset.
+    pub rows: u64,
+    /// Size of the dataset file in bytes.
+    pub bytes: u64,
+    /// Hex SHA-256 of the dataset file.
+    pub sha256: String,
+    /// When the dataset was published, as RFC 3339 in the namespace's time zone.
+    pub published_at: String,
+}
+
+/// The manifest file belonging to the dataset at `file`.
+fn manifest_path(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".manifest.json");
+    PathBuf::from(name)
+}
+
+async fn sha256_file(path: &Path) -> Result<String> {
+    let path = path.to_path_buf();
+    let hash = rt::unblock(move || {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(hasher.finalize())
+    })
+    .await?;
+    Ok(digest::hex(&hash))
+}
+
+fn publish_error(message: String) -> PathmapError {
+    PathmapError::PublishError(message)
+}
+
+impl Pathmap {
+    /// Publishes a namespace as a read-only dataset at `dest`, next to a `<dest>.manifest.json`
+    /// with its SHA-256, e.g. to ship reference data to clients or a CDN. The dataset is a
+    /// compacted copy with blob values stored inline, so it is a single self-contained file; the
+    /// audit trail, change log and schedules are left out. It is stamped as published, passes
+    /// SQLite's integrity check, uses a rollback journal rather than a write-ahead log and is
+    /// marked read-only on disk. Neither `dest` nor its manifest may exist yet. Serve it with
+    /// `Pathmap::open_published`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn publish_ns<P: AsRef<Path>>(&self, ns: &str, dest: P) -> Result<PublishManifest> {
+        error::in_context("publish_ns", ns, async {
+            let dest = dest.as_ref();
+            let manifest_file = manifest_path(dest);
+            for path in [dest, manifest_file.as_path()] {
+                if path.exists() {
+                    return Err(publish_error(format!(
+                        "publish target '{}' already exists",
+                        path.display()
+                    )));
+                }
+            }
+            if let Some(parent) = dest.parent() {
+                rt::fs::create_dir_all(parent).await?;
+            }
+            let mut staging = dest.as_os_str().to_owned();
+            staging.push(".tmp");
+            let staging = PathBuf::from(staging);
+            if staging.exists() {
+                rt::fs::remove_file(&staging).await?;
+            }
+
+            let pool = self.get_pool(ns).await?;
+            self.in_background(ns, db::vacuum_into(&pool, &staging))
+                .await?;
+            let sealed = self.seal(ns, &staging).await;
+            let (rows, schema_version) = match sealed {
+                Ok(sealed) => sealed,
+                Err(e) => {
+                    let _ = rt::fs::remove_file(&staging).await;
+                    return Err(e);
+                }
+            };
+            rt::fs::rename(&staging, dest).await?;
+
+            let published_at = self.format_timestamp(ns, self.clock.system_now());
+            let manifest = PublishManifest {
+                format: MANIFEST_FORMAT.to_string(),
+                ns: ns.to_string(),
+                schema_version,
+                rows,
+                bytes: rt::fs::metadata(dest).await?.len(),
+                sha256: sha256_file(dest).await?,
+                published_at,
+            };
+            let mut permissions = rt::fs::metadata(dest).await?.permissions();
+            permissions.set_readonly(true);
+            rt::fs::set_permissions(dest, permissions).await?;
+            rt::fs::write(&manifest_file, serde_json::to_vec_pretty(&manifest)?).await?;
+            Ok(manifest)
+        })
+        .await
+    }
+
+    /// Makes the fresh copy at `path` self-contained and stamps it, returning its number of values
+    /// and schema version.
+    async fn seal(&self, ns: &str, path: &Path) -> Result<(u64, i64)> {
+        let config = NamespaceConfig {
+            changelog: false,
+            ..self.ns_config(ns).clone()
+        };
+        let copy = db::connect(path, self.group_depth, &config).await?;
+        let result = async {
+            let blob_dir = blob::blob_dir(&self.get_db_path(ns));
+            let mut inlined = Vec::new();
+            for (grp, key, name) in db::external_rows(&copy).await? {
+                let data = blob::read(&blob_dir, &name).await?;
+                inlined.push((grp, key, data));
+            }
+            let stamp = self
+                .clock
+                .system_now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as i64);
+            db::seal_published(&copy, &inlined, stamp).await?;
+            db::vacuum(&copy).await?;
+            let errors = db::integrity_errors(&copy, false).await?;
+            if !errors.is_empty() {
+                return Err(publish_error(format!(
+                    "integrity check failed: {}",
+                    errors.join("; ")
+                )));
+            }
+            let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+                .fetch_one(&copy)
+                .await?;
+            Ok((db::count_values(&copy).await?, version))
+        }
+        .await;
+        copy.close().await;
+        let result = result?;
+        db::use_rollback_journal(path).await?;
+        Ok(result)
+    }
+
+    /// Opens a dataset written by `publish_ns` for reading. Its manifest must be next to it, and
+    /// the file must match the manifest's digest and carry the published stamp; otherwise this
+    /// fails with `PublishError` before anything is served. The dataset is served read-only under
+    /// the namespace name it was published from. Every namespace of the returned instance opens
+    /// read-only, including other databases in the dataset's directory.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn open_published<P: AsRef<Path>>(file: P) -> Result<Pathmap> {
+        let file = file.as_ref();
+        let manifest_file = manifest_path(file);
+        let manifest: PublishManifest = match rt::fs::read(&manifest_file).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(publish_error(format!(
+                    "manifest '{}' not found",
+                    manifest_file.display()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if manifest.format != MANIFEST_FORMAT {
+            return Err(publish_error(format!(
+                "unsupported manifest format '{}'",
+                manifest.format
+            )));
+        }
+        let digest = sha256_file(file).await?;
+        if digest != manifest.sha256 {
+            return Err(publish_error(format!(
+                "'{}' does not match its manifest (sha256 {}, expected {})",
+                file.display(),
+                digest,
+                manifest.sha256
+            )));
+        }
+
+        let base_path = file.parent().unwrap_or(Path::new("."));
+        let pm = Pathmap::new()
+            .with_base_path(base_path)
+            .with_default_ns_config(NamespaceConfig::new().read_only(true));
+        pm.attached
+            .write()
+            .unwrap()
+            .insert(manifest.ns.clone(), file.to_path_buf());
+        let pool = pm.get_pool(&manifest.ns).await?;
+        if db::published_at(&pool).await?.is_none() {
+            return Err(publish_error(format!(
+                "'{}' is not a published dataset",
+                file.display()
+            )));
+        }
+        Ok(pm)
+    }
+}
@@ -0,0 +1,300 @@
+/* src/query.rs */
+
+//! Selecting values by their content: conditions on JSON fields, translated to SQLite
+//! `json_extract` predicates so only matching rows leave the database.
+
+use crate::Pathmap;
+use crate::db::{self, StoredValue};
+use crate::error::{self, PathmapError, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use sqlx::query::Query;
+use sqlx::sqlite::SqliteArguments;
+use sqlx::{Row, Sqlite, SqlitePool};
+
+/// The JSON text of an inline value, as indexed and queried; NULL for values in blob files,
+/// whose rows hold a file name.
+pub(crate) const STORED_DOC: &str = "CASE WHEN external THEN NULL ELSE CAST(value AS TEXT) END";
+
+/// `text` as a SQL string literal.
+pub(crate) fn sql_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn operator(self) -> &'static str {
+        match self {
+            Comparison::Eq => "IS",
+            Comparison::Ne => "IS NOT",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    fn is_ordering(self) -> bool {
+        !matches!(self, Comparison::Eq | Comparison::Ne)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    json_path: String,
+    comparison: Comparison,
+    value: Value,
+}
+
+/// Conditions on the JSON fields of stored values, for `find`. Fields are addressed by SQLite
+/// JSON paths (`$.age`, `$.profile.email`, `$.tags[0]`); all conditions must hold:
+///
+/// ```ignore
+/// let adults = FieldFilter::ge("$.age", 18).and(FieldFilter::eq("$.active", true));
+/// ```
+///
+/// Numbers compare numerically and strings by their bytes; a condition only holds for a field of
+/// the same JSON type, so a number never equals a string or a boolean.
+/// `lt`, `le`, `gt` and `ge` only apply to numbers and strings; `eq` and `ne` also to booleans,
+/// `null`, arrays and objects (which compare as minified JSON, so object members must be in the
+/// stored order). A value without the field matches `ne` but no other condition.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldFilter {
+    conditions: Vec<Condition>,
+}
+
+/// A value bound into a predicate.
+#[derive(Clone)]
+enum Arg {
+    Text(String),
+    Int(i64),
+    Real(f64),
+}
+
+impl FieldFilter {
+    /// A filter without conditions, matching every value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn single(json_path: &str, comparison: Comparison, value: impl Into<Value>) -> Self {
+        FieldFilter {
+            conditions: vec![Condition {
+                json_path: json_path.to_string(),
+                comparison,
+                value: value.into(),
+            }],
+        }
+    }
+
+    pub fn eq(json_path: &str, value: impl Into<Value>) -> Self {
+        Self::single(json_path, Comparison::Eq, value)
+    }
+
+    pub fn ne(json_path: &str, value: impl Into<Value>) -> Self {
+        Self::single(json_path, Comparison::Ne, value)
+    }
+
+    pub fn lt(json_path: &str, value: impl Into<Value>) -> Self {
+        Self::single(json_path, Comparison::Lt, value)
+    }
+
+    pub fn le(json_path: &str, value: impl Into<Value>) -> Self {
+        Self::single(json_path, Comparison::Le, value)
+    }
+
+    pub fn gt(json_path: &str, value: impl Into<Value>) -> Self {
+        Self::single(json_path, Comparison::Gt, value)
+    }
+
+    pub fn ge(json_path: &str, value: impl Into<Value>) -> Self {
+        Self::single(json_path, Comparison::Ge, value)
+    }
+
+    /// Also requires the conditions of `other`.
+    pub fn and(mut self, other: FieldFilter) -> Self {
+        self.conditions.extend(other.conditions);
+        self
+    }
+
+    /// The filter as a SQL predicate on the JSON text `doc`, with the values to bind in order.
+    fn to_sql(&self, doc: &str) -> Result<(String, Vec<Arg>)> {
+        let mut clauses = Vec::with_capacity(self.conditions.len());
+        let mut args = Vec::new();
+        for condition in &self.conditions {
+            let Condition {
+                json_path,
+                comparison,
+                value,
+            } = condition;
+            if !json_path.starts_with('$') {
+                return Err(PathmapError::InvalidPath(format!(
+                    "JSON path '{}' does not start with '$'",
+                    json_path
+                )));
+            }
+            let operator = comparison.operator();
+            // Inlined rather than bound, so the expression can match a `create_index` index.
+            let field = sql_literal(json_path);
+            // `json_extract` turns booleans into 1 and 0 and returns arrays and objects as JSON
+            // text, so numbers and strings are compared together with the field's type.
+            let typed = |types: &str| {
+                let (operator, negate) = match comparison {
+                    Comparison::Ne => (Comparison::Eq.operator(), "NOT "),
+                    _ => (operator, ""),
+                };
+                format!(
+                    "{}(json_extract({}, {}) {} ? AND json_type({}, {}) IN ({}))",
+                    negate, doc, field, operator, doc, field, types
+                )
+            };
+            let clause = match value {
+                Value::Number(number) => {
+                    args.push(match number.as_i64() {
+                        Some(int) => Arg::Int(int),
+                        None => Arg::Real(number.as_f64().unwrap_or(f64::NAN)),
+                    });
+                    typed("'integer', 'real'")
+                }
+                Value::String(text) => {
+                    args.push(Arg::Text(text.clone()));
+                    typed("'text'")
+                }
+                _ if comparison.is_ordering() => {
+                    return Err(PathmapError::InvalidConfig(format!(
+                        "filter on '{}': only numbers and strings can be ordered, not {}",
+                        json_path, value
+                    )));
+                }
+                // `json_extract` turns booleans into 1 and 0 and JSON null into SQL NULL, so
+                // compare the type instead.
+                Value::Bool(_) | Value::Null => {
+                    args.push(Arg::Text(value.to_string()));
+                    format!("json_type({}, {}) {} ?", doc, field, operator)
+                }
+                Value::Array(_) | Value::Object(_) => {
+                    args.push(Arg::Text(value.to_string()));
+                    format!("({} -> {}) {} ?", doc, field, operator)
+                }
+            };
+            clauses.push(clause);
+        }
+        if clauses.is_empty() {
+            return Ok(("1".to_string(), args));
+        }
+        Ok((clauses.join(" AND "), args))
+    }
+}
+
+fn bind_all<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    args: Vec<Arg>,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    for arg in args {
+        query = match arg {
+            Arg::Text(text) => query.bind(text),
+            Arg::Int(int) => query.bind(int),
+            Arg::Real(real) => query.bind(real),
+        };
+    }
+    query
+}
+
+impl Pathmap {
+    /// The values of a namespace or group and its nested groups that match `filter`, as
+    /// `(key, value)` with keys relative to `path` as `list_recursive` reports them, in key
+    /// order. SQLite evaluates the filter, so non-matching values are not transferred; values
+    /// stored in blob files are read and checked one by one. Reads the database directly, without
+    /// writes still in the write queue or in a batch.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn find<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        filter: FieldFilter,
+    ) -> Result<Vec<(String, T)>> {
+        error::in_context("find", &self.qualify(path), async {
+            self.retry_busy(|| self.find_once(path, &filter)).await
+        })
+        .await
+    }
+
+    async fn find_once<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        filter: &FieldFilter,
+    ) -> Result<Vec<(String, T)>> {
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        self.name_rules.check_ns(ns)?;
+        let grp = self.normalize_key(grp);
+        self.name_rules.check_key(&grp)?;
+        let (ns, grp) = self.resolve_soft(ns, grp)?;
+        let (predicate, args) = filter.to_sql(STORED_DOC)?;
+        let pool = self.get_pool(ns).await?;
+        // Values directly in the group are selected on their own, without an `OR`, so the
+        // partial indexes of `create_index` apply; blob values are checked after reading them.
+        // Sorting here rather than in SQL keeps the planner from preferring the key order.
+        let columns = "SELECT grp, key, value, external FROM kv_store";
+        let exact = format!("grp = {}", sql_literal(&grp));
+        let nested = match grp.len() {
+            0 => "grp <> ''",
+            _ => "grp >= ? AND grp < ?",
+        };
+        let sql = format!(
+            "{columns} WHERE {exact} AND NOT external AND {predicate} \
+             UNION ALL {columns} WHERE {exact} AND external \
+             UNION ALL {columns} WHERE {nested} AND (external OR ({predicate}))"
+        );
+        let mut query = bind_all(sqlx::query(&sql), args.clone());
+        if !grp.is_empty() {
+            query = query.bind(format!("{}.", grp)).bind(format!("{}/", grp));
+        }
+        let mut rows = bind_all(query, args).fetch_all(&*pool).await?;
+        rows.sort_by_cached_key(|row| (row.get::<String, _>("grp"), row.get::<String, _>("key")));
+
+        let mut found = Vec::with_capacity(rows.len());
+        for row in rows {
+            let stored = StoredValue {
+                data: row.get("value"),
+                external: row.get("external"),
+            };
+            let external = stored.external;
+            let value = self.load_value(ns, stored).await?;
+            if external && !matches_filter(&pool, filter, &value).await? {
+                continue;
+            }
+            let entry_grp: String = row.get("grp");
+            let key: String = row.get("key");
+            // Slice rather than strip so case-insensitive collations still line up.
+            let sub_group = match grp.len() {
+                0 => entry_grp.as_str(),
+                len => entry_grp.get(len + 1..).unwrap_or(""),
+            };
+            found.push((
+                db::join_key(sub_group, &key),
+                serde_json::from_slice(&value)?,
+            ));
+        }
+        Ok(found)
+    }
+}
+
+/// Whether the serialized value `json`, read from a blob file, matches `filter`.
+async fn matches_filter(pool: &SqlitePool, filter: &FieldFilter, json: &[u8]) -> Result<bool> {
+    let (predicate, args) = filter.to_sql("doc.v")?;
+    let sql = format!(
+        "WITH doc(v) AS (SELECT ?) SELECT ({}) AS matched FROM doc",
+        predicate
+    );
+    let query = sqlx::query(&sql).bind(String::from_utf8_lossy(json).into_owned());
+    let row = bind_all(query, args).fetch_one(pool).await?;
+    Ok(row.get::<Option<bool>, _>("matched").unwrap_or(false))
+}
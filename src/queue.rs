@@ -0,0 +1,259 @@
+/* src/queue.rs */
+
+use crate::Pathmap;
+use crate::db;
+use crate::error::{PathmapError, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// SQLite primary result codes for I/O failures and a full disk.
+const SQLITE_IOERR: i64 = 10;
+const SQLITE_FULL: i64 = 13;
+
+/// Settings for buffering writes in memory while the disk is failing.
+///
+/// Durability caveat: a queued write has been acknowledged but lives only in this process until
+/// it is flushed. It is lost if the process exits or crashes first, and other processes (or
+/// other `Pathmap` instances) do not see it. Only `get` on this instance reads queued values;
+/// `exists`, `list`, exports and backups see the database alone.
+#[derive(Debug, Clone)]
+pub struct WriteQueueConfig {
+    /// Maximum number of queued mutations; further writes fail with `WriteQueueFull`.
+    pub capacity: usize,
+    /// Minimum time between automatic flush attempts made by later operations.
+    pub retry_interval: Duration,
+}
+
+impl WriteQueueConfig {
+    pub fn new(capacity: usize) -> Self {
+        WriteQueueConfig {
+            capacity,
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+}
+
+/// Running totals of the write queue.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteQueueStats {
+    /// Mutations waiting to be written.
+    pub pending: usize,
+    /// Mutations queued because of a disk error since the instance was created.
+    pub queued: u64,
+    /// Queued mutations written to disk.
+    pub flushed: u64,
+    /// Queued mutations discarded because they failed for a reason other than the disk
+    /// (e.g. the namespace was deleted meanwhile).
+    pub dropped: u64,
+    /// Writes refused because the queue was full.
+    pub rejected: u64,
+    /// The disk error that caused the most recent queueing or failed flush.
+    pub last_error: Option<String>,
+}
+
+/// A buffered overwrite (`Some`) or delete (`None`) of one key.
+struct Mutation {
+    ns: String,
+    grp: String,
+    key: String,
+    value: Option<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: AtomicU64,
+    flushed: AtomicU64,
+    dropped: AtomicU64,
+    rejected: AtomicU64,
+}
+
+pub(crate) struct WriteQueue {
+    config: WriteQueueConfig,
+    pending: Mutex<VecDeque<Mutation>>,
+    last_attempt: std::sync::Mutex<Option<Instant>>,
+    last_error: std::sync::Mutex<Option<String>>,
+    counters: Counters,
+}
+
+impl WriteQueue {
+    pub(crate) fn new(config: WriteQueueConfig) -> Self {
+        WriteQueue {
+            config,
+            pending: Mutex::new(VecDeque::new()),
+            last_attempt: std::sync::Mutex::new(None),
+            last_error: std::sync::Mutex::new(None),
+            counters: Counters::default(),
+        }
+    }
+
+    fn record_error(&self, err: &PathmapError) {
+        *self.last_error.lock().unwrap() = Some(err.to_string());
+    }
+}
+
+/// Whether an error means the disk is (possibly temporarily) unable to take writes.
+pub(crate) fn is_disk_error(err: &PathmapError) -> bool {
+    match err.root() {
+        PathmapError::DatabaseError(sqlx::Error::Database(db)) => db
+            .code()
+            .and_then(|code| code.parse::<i64>().ok())
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_IOERR | SQLITE_FULL)),
+        PathmapError::DatabaseError(sqlx::Error::Io(e)) | PathmapError::IoError(e) => {
+            is_disk_io_error(e)
+        }
+        _ => false,
+    }
+}
+
+fn is_disk_io_error(err: &std::io::Error) -> bool {
+    const ENOSPC: i32 = 28;
+    const EIO: i32 = 5;
+    err.kind() == std::io::ErrorKind::StorageFull
+        || matches!(err.raw_os_error(), Some(ENOSPC | EIO))
+}
+
+impl Pathmap {
+    /// Returns write queue counters, or `None` when no write queue is configured. Waits for a
+    /// running flush to finish, so `pending` is exact.
+    pub async fn write_queue_stats(&self) -> Option<WriteQueueStats> {
+        let queue = self.write_queue.as_ref()?;
+        Some(WriteQueueStats {
+            pending: queue.pending.lock().await.len(),
+            queued: queue.counters.queued.load(Ordering::Relaxed),
+            flushed: queue.counters.flushed.load(Ordering::Relaxed),
+            dropped: queue.counters.dropped.load(Ordering::Relaxed),
+            rejected: queue.counters.rejected.load(Ordering::Relaxed),
+            last_error: queue.last_error.lock().unwrap().clone(),
+        })
+    }
+
+    /// Writes queued mutations to disk in order, stopping at the first disk error.
+    /// Returns how many are still pending. Later writes and reads also flush automatically,
+    /// at most once per `retry_interval`.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn flush_write_queue(&self) -> Result<usize> {
+        let Some(queue) = &self.write_queue else {
+            return Ok(0);
+        };
+        let mut pending = queue.pending.lock().await;
+        *queue.last_attempt.lock().unwrap() = Some(self.clock.now());
+        while let Some(mutation) = pending.front() {
+            match self
+                .apply_write(
+                    &mutation.ns,
+                    &mutation.grp,
+                    &mutation.key,
+                    mutation.value.clone(),
+                )
+                .await
+            {
+                Ok(_) => {
+                    queue.counters.flushed.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) if is_disk_error(&e) => {
+                    queue.record_error(&e);
+                    break;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        ns = %mutation.ns,
+                        key = %crate::db::join_key(&mutation.grp, &mutation.key),
+                        error = %e,
+                        "dropping queued write"
+                    );
+                    queue.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            pending.pop_front();
+        }
+        Ok(pending.len())
+    }
+
+    /// Writes or deletes a key, queueing the mutation instead when the disk fails and a write
+    /// queue is configured. Mutations never overtake queued ones. Returns whether the key held a
+    /// value, taking queued mutations into account.
+    pub(crate) async fn write(
+        &self,
+        ns: &str,
+        grp: &str,
+        key: &str,
+        value: Option<Vec<u8>>,
+    ) -> Result<bool> {
+        let Some(queue) = &self.write_queue else {
+            return self.apply_write(ns, grp, key, value).await;
+        };
+        let flush_due = queue.last_attempt.lock().unwrap().is_none_or(|last| {
+            self.clock.now().duration_since(last) >= queue.config.retry_interval
+        });
+        if flush_due {
+            self.flush_write_queue().await?;
+        }
+
+        let mut pending = queue.pending.lock().await;
+        if pending.is_empty() {
+            match self.apply_write(ns, grp, key, value.clone()).await {
+                Err(e) if is_disk_error(&e) => queue.record_error(&e),
+                result => return result,
+            }
+        }
+        if pending.len() >= queue.config.capacity {
+            queue.counters.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(PathmapError::WriteQueueFull(pending.len()));
+        }
+        let queued = pending
+            .iter()
+            .rev()
+            .find(|m| m.ns == ns && m.grp == grp && m.key == key);
+        let existed = match queued {
+            Some(mutation) => mutation.value.is_some(),
+            // Writes may fail while the database is still readable; if it is not, the outcome
+            // cannot be known and the write fails instead of guessing.
+            None => match self.get_pool(ns).await {
+                Ok(pool) => db::exists_value(&pool, grp, key).await?,
+                Err(e) if matches!(e.root(), PathmapError::NamespaceNotFound(_)) => false,
+                Err(e) => return Err(e),
+            },
+        };
+        pending.push_back(Mutation {
+            ns: ns.to_string(),
+            grp: grp.to_string(),
+            key: key.to_string(),
+            value,
+        });
+        queue.counters.queued.fetch_add(1, Ordering::Relaxed);
+        Ok(existed)
+    }
+
+    /// The most recent queued mutation of a key: `Some(None)` for a pending delete.
+    pub(crate) async fn queued_value(
+        &self,
+        ns: &str,
+        grp: &str,
+        key: &str,
+    ) -> Option<Option<Vec<u8>>> {
+        let queue = self.write_queue.as_ref()?;
+        let flush_due = queue.last_attempt.lock().unwrap().is_some_and(|last| {
+            self.clock.now().duration_since(last) >= queue.config.retry_interval
+        });
+        if flush_due {
+            // Best effort: a failure leaves the mutations queued and is recorded in the stats.
+            let _ = self.flush_write_queue().await;
+        }
+        let pending = queue.pending.lock().await;
+        pending
+            .iter()
+            .rev()
+            .find(|m| m.ns == ns && m.grp == grp && m.key == key)
+            .map(|m| m.value.clone())
+    }
+}
@@ -0,0 +1,70 @@
+/* src/quota.rs */
+
+//! Write-time enforcement of `NamespaceConfig::quota`, so one tenant cannot fill the disk.
+
+use crate::Pathmap;
+use crate::config::Quota;
+use crate::db;
+use crate::error::{PathmapError, Result};
+use sqlx::SqliteConnection;
+
+fn exceeded(ns: &str, detail: String) -> PathmapError {
+    PathmapError::QuotaExceeded(ns.to_string(), detail)
+}
+
+impl Quota {
+    /// Rejects a single serialized value of `size` bytes.
+    fn check_value(&self, ns: &str, size: u64) -> Result<()> {
+        match self.max_value_bytes {
+            Some(max) if size > max => Err(exceeded(
+                ns,
+                format!("value of {} bytes (limit {})", size, max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the key or byte limit is set, which writes have to check in their transaction.
+    pub(crate) fn limits_totals(&self) -> bool {
+        self.max_keys.is_some() || self.max_bytes.is_some()
+    }
+
+    /// Checks the key and byte limits against the state `conn` sees, e.g. the transaction of a
+    /// write before it commits, so concurrent writers cannot both pass the limit.
+    pub(crate) async fn check_totals(&self, ns: &str, conn: &mut SqliteConnection) -> Result<()> {
+        if let Some(max) = self.max_keys {
+            let count = db::value_count(&mut *conn).await?;
+            if count > max {
+                return Err(exceeded(ns, format!("more than {} keys", max)));
+            }
+        }
+        if let Some(max) = self.max_bytes {
+            let used = db::used_bytes(&mut *conn).await?;
+            if used > max {
+                return Err(exceeded(
+                    ns,
+                    format!("{} bytes in use (limit {})", used, max),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Pathmap {
+    /// Checks a single serialized value against the namespace's value size limit. The key and
+    /// byte limits are checked by the transaction that writes the value.
+    pub(crate) fn check_value_quota(&self, ns: &str, size: u64) -> Result<()> {
+        self.ns_config(ns).quota.check_value(ns, size)
+    }
+
+    /// Checks the key and byte limits of `ns` against the state `conn` sees, e.g. a write's
+    /// transaction before it commits.
+    pub(crate) async fn check_quota_totals(
+        &self,
+        ns: &str,
+        conn: &mut SqliteConnection,
+    ) -> Result<()> {
+        self.ns_config(ns).quota.check_totals(ns, conn).await
+    }
+}
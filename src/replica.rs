@@ -0,0 +1,286 @@
+/* src/replica.rs */
+
+//! Pull-through replication: a local `Pathmap` that serves reads from its own files and fetches
+//! misses and expired values from a remote primary, which stays the source of truth for writes.
+
+use crate::db;
+use crate::error::{PathmapError, Result};
+use crate::event::Operation;
+use crate::{Pathmap, WriteOutcome};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Future returned by `Primary` methods.
+pub type PrimaryFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// The store a replica reads through to and forwards writes to. Implemented for a local
+/// `Pathmap`, a `UnixClient` behind a mutex and, with the `grpc` feature, the generated gRPC
+/// client; other transports (e.g. an HTTP client for the REST server) can implement it too.
+pub trait Primary: Send + Sync + 'static {
+    /// The value at `path`, or `None` if the primary has none.
+    fn get<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, Option<Value>>;
+
+    /// Stores a value; with `overwrite` unset it fails with `ValueAlreadyExists` like `set`.
+    /// Returns whether the key held a value, which the overwrite replaced.
+    fn set<'a>(
+        &'a self,
+        path: &'a str,
+        value: &'a Value,
+        overwrite: bool,
+    ) -> PrimaryFuture<'a, bool>;
+
+    /// Deletes a value, returning whether there was one.
+    fn delete<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, bool>;
+
+    /// Deletes a group and everything below it, returning the number of removed values.
+    fn delete_group<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, u64>;
+}
+
+/// How long fetched values are served locally before they are fetched again.
+#[derive(Debug, Clone)]
+pub struct ReplicaConfig {
+    pub ttl: Duration,
+    /// Serve an expired local copy when the primary cannot be reached, instead of failing.
+    pub serve_stale: bool,
+}
+
+impl ReplicaConfig {
+    pub fn new(ttl: Duration) -> Self {
+        ReplicaConfig {
+            ttl,
+            serve_stale: true,
+        }
+    }
+
+    pub fn serve_stale(mut self, serve_stale: bool) -> Self {
+        self.serve_stale = serve_stale;
+        self
+    }
+}
+
+type ReplicaKey = (String, String, String);
+
+/// Tracked keys below which `mark` never sweeps.
+const MIN_SWEEP_AT: usize = 1024;
+
+/// When each key was last fetched from or written to the primary. Absent values are tracked as
+/// well, so repeated misses do not reach the primary within the TTL either.
+struct Fetched {
+    at: HashMap<ReplicaKey, Instant>,
+    /// Size at which expired entries are swept out next, so the map stays proportional to the
+    /// keys used within one TTL instead of growing with every key ever read.
+    sweep_at: usize,
+}
+
+pub(crate) struct Replica {
+    primary: Arc<dyn Primary>,
+    config: ReplicaConfig,
+    fetched: Mutex<Fetched>,
+}
+
+impl Replica {
+    pub(crate) fn new(primary: Arc<dyn Primary>, config: ReplicaConfig) -> Self {
+        Replica {
+            primary,
+            config,
+            fetched: Mutex::new(Fetched {
+                at: HashMap::new(),
+                sweep_at: MIN_SWEEP_AT,
+            }),
+        }
+    }
+
+    fn is_fresh(&self, key: &ReplicaKey, now: Instant) -> bool {
+        self.fetched
+            .lock()
+            .unwrap()
+            .at
+            .get(key)
+            .is_some_and(|at| now.duration_since(*at) < self.config.ttl)
+    }
+
+    fn mark(&self, key: ReplicaKey, now: Instant) {
+        let mut fetched = self.fetched.lock().unwrap();
+        fetched.at.insert(key, now);
+        if fetched.at.len() >= fetched.sweep_at {
+            let ttl = self.config.ttl;
+            fetched.at.retain(|_, at| now.duration_since(*at) < ttl);
+            fetched.sweep_at = (fetched.at.len() * 2).max(MIN_SWEEP_AT);
+        }
+    }
+
+    fn forget(&self, key: &ReplicaKey) {
+        self.fetched.lock().unwrap().at.remove(key);
+    }
+
+    fn forget_ns(&self, ns: &str) {
+        self.fetched
+            .lock()
+            .unwrap()
+            .at
+            .retain(|(n, _, _), _| n != ns);
+    }
+}
+
+fn replica_key(ns: &str, grp: &str, key: &str) -> ReplicaKey {
+    (ns.to_string(), grp.to_string(), key.to_string())
+}
+
+impl Pathmap {
+    /// Makes the local copy of a key current before it is read: fetches it from the primary
+    /// unless it was fetched within the TTL. Does nothing when this instance is not a replica.
+    pub(crate) async fn refresh_replica(
+        &self,
+        path: &str,
+        ns: &str,
+        grp: &str,
+        key: &str,
+    ) -> Result<()> {
+        let Some(replica) = &self.replica else {
+            return Ok(());
+        };
+        let id = replica_key(ns, grp, key);
+        let now = self.clock.now();
+        if replica.is_fresh(&id, now) {
+            return Ok(());
+        }
+        match replica.primary.get(path).await {
+            Ok(value) => {
+                self.store_replicated(ns, grp, key, value.as_ref()).await?;
+                replica.mark(id, now);
+                Ok(())
+            }
+            Err(e) if replica.config.serve_stale => match self.read_value(ns, grp, key).await {
+                Ok(Some(_)) => Ok(()),
+                _ => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Forwards a write (`None` for a delete) to the primary, then updates the local copy.
+    /// Returns whether the primary's key held a value.
+    pub(crate) async fn forward_write(
+        &self,
+        replica: &Replica,
+        path: &str,
+        value: Option<Value>,
+        overwrite: bool,
+    ) -> Result<bool> {
+        let existed = match &value {
+            Some(value) => replica.primary.set(path, value, overwrite).await?,
+            None => replica.primary.delete(path).await?,
+        };
+        let (ns, key) = self.parse_path(path)?;
+        let (grp, key) = self.split_key(&key);
+        let id = replica_key(ns, grp, key);
+        // The primary accepted the write; a failed local update only means the next read fetches
+        // the value again.
+        match self.store_replicated(ns, grp, key, value.as_ref()).await {
+            Ok(()) => replica.mark(id, self.clock.now()),
+            Err(_) => replica.forget(&id),
+        }
+        Ok(existed)
+    }
+
+    /// Forwards a group delete to the primary, then removes the group's local copies.
+    pub(crate) async fn forward_delete_group(&self, replica: &Replica, path: &str) -> Result<u64> {
+        let removed = replica.primary.delete_group(path).await?;
+        let (ns, grp) = self.parse_path(path)?;
+        let pool = match self.get_pool(ns).await {
+            Ok(pool) => pool,
+            Err(PathmapError::NamespaceNotFound(_)) => return Ok(removed),
+            Err(e) => return Err(e),
+        };
+        let refs = db::external_refs(&pool, &grp).await?;
+        let result = self.delete_group_rows(ns, &pool, &grp).await;
+        self.invalidate_cached_ns(ns);
+        match result {
+            Ok(0) => {}
+            Ok(_) => self.emit_change(Operation::DeleteGroup, ns, &grp, None, None),
+            Err(_) => {
+                replica.forget_ns(ns);
+                return Ok(removed);
+            }
+        }
+        for name in refs {
+            self.remove_blob(ns, name.as_bytes()).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Drops what this replica has fetched, so every key is fetched from the primary on its next
+    /// read. Local copies are kept to serve while the primary is unreachable.
+    pub fn expire_replica(&self) {
+        if let Some(replica) = &self.replica {
+            replica.fetched.lock().unwrap().at.clear();
+        }
+    }
+
+    /// Replaces (`Some`) or removes (`None`) the local copy of a key.
+    async fn store_replicated(
+        &self,
+        ns: &str,
+        grp: &str,
+        key: &str,
+        value: Option<&Value>,
+    ) -> Result<()> {
+        let value = match value {
+            Some(value) => Some(self.encode(ns, value)?),
+            None => None,
+        };
+        match self.write(ns, grp, key, value).await {
+            // Nothing to remove from a namespace that was never fetched into.
+            Err(PathmapError::NamespaceNotFound(_)) => Ok(()),
+            result => result.map(drop),
+        }
+    }
+}
+
+impl Primary for Pathmap {
+    fn get<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, Option<Value>> {
+        Box::pin(async move {
+            match Pathmap::get::<Value>(self, path).await {
+                Ok(value) => Ok(Some(value)),
+                Err(e)
+                    if matches!(
+                        e.root(),
+                        PathmapError::ValueNotFound(_) | PathmapError::NamespaceNotFound(_)
+                    ) =>
+                {
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        path: &'a str,
+        value: &'a Value,
+        overwrite: bool,
+    ) -> PrimaryFuture<'a, bool> {
+        Box::pin(async move {
+            if overwrite {
+                let outcome = Pathmap::overwrite(self, path, value).await?;
+                Ok(outcome == WriteOutcome::Replaced)
+            } else {
+                Pathmap::set(self, path, value).await?;
+                Ok(false)
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, bool> {
+        Box::pin(Pathmap::delete(self, path))
+    }
+
+    fn delete_group<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, u64> {
+        Box::pin(Pathmap::delete_group(self, path))
+    }
+}
@@ -0,0 +1,106 @@
+/* src/retry.rs */
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use crate::rt;
+use std::future::Future;
+use std::time::Duration;
+
+// Primary result codes of "database is busy" and "database table is locked"; extended codes
+// (e.g. SQLITE_BUSY_SNAPSHOT) keep them in the low byte.
+const SQLITE_BUSY: i64 = 5;
+const SQLITE_LOCKED: i64 = 6;
+
+/// How long to wait before each retry of a busy operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// The same delay before every retry.
+    Fixed(Duration),
+    /// Starts at `initial` and doubles with every retry, up to `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Delay before retry number `retry`, starting at 1.
+    fn delay(&self, retry: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(1 << (retry - 1).min(31))
+                .map_or(max, |delay| delay.min(max)),
+        }
+    }
+}
+
+/// Automatic retries of operations that fail because another connection or process holds a
+/// lock on the database.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Total attempts, including the first one.
+    pub attempts: u32,
+    pub backoff: Backoff,
+}
+
+impl RetryConfig {
+    /// Makes up to `attempts` attempts, waiting 10 ms before the first retry and doubling the wait
+    /// up to one second.
+    pub fn new(attempts: u32) -> Self {
+        RetryConfig {
+            attempts,
+            backoff: Backoff::Exponential {
+                initial: Duration::from_millis(10),
+                max: Duration::from_secs(1),
+            },
+        }
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+}
+
+/// Whether an error means the database was busy or locked, so trying again may succeed.
+pub(crate) fn is_busy(err: &PathmapError) -> bool {
+    match err.root() {
+        PathmapError::Busy(_) => true,
+        PathmapError::DatabaseError(sqlx::Error::Database(db)) => db
+            .code()
+            .and_then(|code| code.parse::<i64>().ok())
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED)),
+        _ => false,
+    }
+}
+
+impl Pathmap {
+    /// Retries `get`, `set`, `overwrite`, `delete`, `delete_group`, `list` and the `exists`
+    /// checks when they fail because the database is busy or locked, e.g. by another process.
+    /// Without it, and once the attempts are used up, such failures surface as `Busy`.
+    pub fn with_busy_retry(mut self, config: RetryConfig) -> Self {
+        self.busy_retry = Some(config);
+        self
+    }
+
+    /// Runs `op` until it succeeds, fails for another reason or runs out of attempts.
+    pub(crate) async fn retry_busy<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let attempts = self.busy_retry.as_ref().map_or(1, |c| c.attempts.max(1));
+        let mut attempt = 1;
+        loop {
+            match op().await {
+                Err(e) if is_busy(&e) => {
+                    let Some(config) = self.busy_retry.as_ref().filter(|_| attempt < attempts)
+                    else {
+                        return Err(PathmapError::Busy(attempt));
+                    };
+                    rt::sleep(config.backoff.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+/* src/rt.rs */
+
+//! The runtime services the crate needs (spawning, timers and blocking file I/O), selected by the
+//! `runtime-tokio` or `runtime-async-std` feature. Locks, semaphores and I/O traits come from
+//! `tokio::sync` and `tokio::io`, which work under any executor.
+
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-std")))]
+compile_error!("kvmap needs one of the `runtime-tokio` or `runtime-async-std` features");
+
+/// Runs a task in the background, detached from the caller.
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(feature = "runtime-tokio")]
+    tokio::spawn(future);
+    #[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+    async_std::task::spawn(future);
+}
+
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "runtime-tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+    async_std::task::sleep(duration).await;
+}
+
+/// Runs blocking work (file system calls) on the runtime's blocking thread pool.
+pub(crate) async fn unblock<F, T>(work: F) -> io::Result<T>
+where
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(feature = "runtime-tokio")]
+    return tokio::task::spawn_blocking(work)
+        .await
+        .map_err(io::Error::other)?;
+    #[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+    return async_std::task::spawn_blocking(work).await;
+}
+
+/// `std::fs` counterparts that do not block the executor.
+pub(crate) mod fs {
+    use super::unblock;
+    use std::io;
+    use std::path::Path;
+
+    pub(crate) async fn create_dir_all<P: AsRef<Path>>(path: P) -> io::Result<()> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || std::fs::create_dir_all(path)).await
+    }
+
+    pub(crate) async fn read<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || std::fs::read(path)).await
+    }
+
+    /// Up to `len` bytes from the start of a file.
+    pub(crate) async fn read_prefix<P: AsRef<Path>>(path: P, len: u64) -> io::Result<Vec<u8>> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || {
+            let mut prefix = Vec::new();
+            io::Read::read_to_end(
+                &mut io::Read::take(std::fs::File::open(path)?, len),
+                &mut prefix,
+            )?;
+            Ok(prefix)
+        })
+        .await
+    }
+
+    pub(crate) async fn write<P: AsRef<Path>>(path: P, data: Vec<u8>) -> io::Result<()> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || std::fs::write(path, data)).await
+    }
+
+    pub(crate) async fn rename<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> io::Result<()> {
+        let (from, to) = (from.as_ref().to_path_buf(), to.as_ref().to_path_buf());
+        unblock(move || std::fs::rename(from, to)).await
+    }
+
+    pub(crate) async fn metadata<P: AsRef<Path>>(path: P) -> io::Result<std::fs::Metadata> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || std::fs::metadata(path)).await
+    }
+
+    pub(crate) async fn set_permissions<P: AsRef<Path>>(
+        path: P,
+        permissions: std::fs::Permissions,
+    ) -> io::Result<()> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || std::fs::set_permissions(path, permissions)).await
+    }
+
+    pub(crate) async fn remove_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+        let path = path.as_ref().to_path_buf();
+        unblock(move || std::fs::remove_file(path)).await
+    }
+}
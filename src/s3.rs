@@ -0,0 +1,263 @@
+/* src/s3.rs */
+
+use crate::Pathmap;
+use crate::blob;
+use crate::db;
+use crate::error::{PathmapError, Result};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::DisplayErrorContext;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// S3's minimum size for every part of a multipart upload but the last.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// Where namespace snapshots are stored in an S3-compatible bucket.
+/// Only available with the `s3` feature. The client carries credentials, region and endpoint;
+/// for non-AWS services build it with a custom endpoint and path-style addressing.
+#[derive(Debug, Clone)]
+pub struct S3Target {
+    client: Client,
+    bucket: String,
+    prefix: String,
+    part_size: usize,
+}
+
+impl S3Target {
+    pub fn new(client: Client, bucket: &str) -> Self {
+        S3Target {
+            client,
+            bucket: bucket.to_string(),
+            prefix: String::new(),
+            part_size: 8 * 1024 * 1024,
+        }
+    }
+
+    /// Prepended to every object key, e.g. `backups/prod/`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Databases larger than this are uploaded in parts of this size (at least 5 MiB).
+    pub fn part_size(mut self, bytes: usize) -> Self {
+        self.part_size = bytes.max(MIN_PART_SIZE);
+        self
+    }
+
+    fn db_key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn blob_key(&self, name: &str, blob: &str) -> String {
+        format!("{}{}.blobs/{}", self.prefix, name, blob)
+    }
+}
+
+impl Pathmap {
+    /// Takes a compacted backup of a namespace and uploads it to the bucket as `name`, with its
+    /// externalized blobs under `name.blobs/`. Large databases use a multipart upload.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns, name = %name), err(Display)))]
+    pub async fn backup_to_s3(&self, ns: &str, target: &S3Target, name: &str) -> Result<()> {
+        let staging = self.s3_staging_path(ns);
+        let result = async {
+            self.backup_ns(ns, &staging).await?;
+            upload_file(target, &target.db_key(name), &staging).await?;
+
+            let backup = db::open_read_only(&staging, self.ns_config(ns)).await?;
+            let refs = db::external_refs(&backup, "").await?;
+            backup.close().await;
+            let blob_dir = blob::blob_dir(&staging);
+            for blob_name in refs {
+                let data = blob::read(&blob_dir, &blob_name).await?;
+                put_object(target, &target.blob_key(name, &blob_name), data).await?;
+            }
+            Ok::<_, PathmapError>(())
+        }
+        .await;
+        remove_staging(&staging).await;
+        result
+    }
+
+    /// Downloads a backup written by `backup_to_s3` and restores it into a namespace with
+    /// `restore_ns`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns, name = %name), err(Display)))]
+    pub async fn restore_from_s3(&self, ns: &str, target: &S3Target, name: &str) -> Result<()> {
+        let staging = self.s3_staging_path(ns);
+        let result = async {
+            tokio::fs::create_dir_all(&self.base_path).await?;
+            download_file(target, &target.db_key(name), &staging).await?;
+
+            let backup = db::open_read_only(&staging, self.ns_config(ns)).await?;
+            let refs = db::external_refs(&backup, "").await?;
+            backup.close().await;
+            let blob_dir = blob::blob_dir(&staging);
+            if !refs.is_empty() {
+                tokio::fs::create_dir_all(&blob_dir).await?;
+            }
+            for blob_name in refs {
+                download_file(
+                    target,
+                    &target.blob_key(name, &blob_name),
+                    &blob_dir.join(&blob_name),
+                )
+                .await?;
+            }
+            self.restore_ns(ns, &staging).await
+        }
+        .await;
+        remove_staging(&staging).await;
+        result
+    }
+
+    /// A scratch file next to the namespaces, so large databases are not staged on a small tmpfs.
+    fn s3_staging_path(&self, ns: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        self.base_path.join(format!(
+            ".{}.s3-{:x}-{:x}.tmp",
+            ns,
+            nanos,
+            std::process::id()
+        ))
+    }
+}
+
+fn s3_error<E: std::error::Error>(err: E) -> PathmapError {
+    PathmapError::BackupError(DisplayErrorContext(err).to_string())
+}
+
+async fn put_object(target: &S3Target, key: &str, data: Vec<u8>) -> Result<()> {
+    target
+        .client
+        .put_object()
+        .bucket(&target.bucket)
+        .key(key)
+        .body(ByteStream::from(data))
+        .send()
+        .await
+        .map_err(s3_error)?;
+    Ok(())
+}
+
+/// Uploads a file in one request, or in `part_size` parts when it is larger than that.
+async fn upload_file(target: &S3Target, key: &str, path: &Path) -> Result<()> {
+    let len = tokio::fs::metadata(path).await?.len();
+    if len <= target.part_size as u64 {
+        return put_object(target, key, tokio::fs::read(path).await?).await;
+    }
+
+    let upload = target
+        .client
+        .create_multipart_upload()
+        .bucket(&target.bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(s3_error)?;
+    let upload_id = upload
+        .upload_id()
+        .ok_or_else(|| PathmapError::BackupError("multipart upload without an id".to_string()))?
+        .to_string();
+
+    let parts = upload_parts(target, key, &upload_id, path).await;
+    let completed = match parts {
+        Ok(parts) => target
+            .client
+            .complete_multipart_upload()
+            .bucket(&target.bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(s3_error)
+            .map(|_| ()),
+        Err(e) => Err(e),
+    };
+    if completed.is_err() {
+        // Abandoned parts are billed until aborted; the original error is what matters.
+        let _ = target
+            .client
+            .abort_multipart_upload()
+            .bucket(&target.bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .send()
+            .await;
+    }
+    completed
+}
+
+async fn upload_parts(
+    target: &S3Target,
+    key: &str,
+    upload_id: &str,
+    path: &Path,
+) -> Result<Vec<CompletedPart>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut parts = Vec::new();
+    let mut part_number = 1;
+    loop {
+        let mut chunk = Vec::with_capacity(target.part_size);
+        (&mut file)
+            .take(target.part_size as u64)
+            .read_to_end(&mut chunk)
+            .await?;
+        if chunk.is_empty() {
+            break;
+        }
+        let output = target
+            .client
+            .upload_part()
+            .bucket(&target.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(chunk))
+            .send()
+            .await
+            .map_err(s3_error)?;
+        parts.push(
+            CompletedPart::builder()
+                .set_e_tag(output.e_tag().map(str::to_string))
+                .part_number(part_number)
+                .build(),
+        );
+        part_number += 1;
+    }
+    Ok(parts)
+}
+
+/// Streams an object into a file without holding it in memory.
+async fn download_file(target: &S3Target, key: &str, path: &Path) -> Result<()> {
+    let output = target
+        .client
+        .get_object()
+        .bucket(&target.bucket)
+        .key(key)
+        .send()
+        .await
+        .map_err(s3_error)?;
+    let mut body = output.body;
+    let mut file = tokio::fs::File::create(path).await?;
+    while let Some(bytes) = body.try_next().await.map_err(s3_error)? {
+        file.write_all(&bytes).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+async fn remove_staging(path: &Path) {
+    let _ = tokio::fs::remove_file(path).await;
+    let _ = tokio::fs::remove_dir_all(blob::blob_dir(path)).await;
+}
@@ -0,0 +1,198 @@
+/* src/schedule.rs */
+
+//! Delayed actions on keys, persisted in the namespace so they survive restarts.
+
+use crate::Pathmap;
+use crate::db;
+use crate::error::{PathmapError, Result};
+use crate::event::Operation;
+use crate::rt;
+use futures_util::future::{self, Either};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Due actions executed per query, so a backlog is worked off in bounded steps.
+const DUE_BATCH: i64 = 100;
+
+/// What happens to a key when its schedule comes due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Delete the value, like `delete`.
+    Delete,
+    /// Emit an `Operation::Publish` event for the key without changing it, e.g. for reminders.
+    Publish,
+    /// Rewrite the value unchanged, so hooks, subscribers and the change log see a fresh write.
+    Touch,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Delete => "delete",
+            Action::Publish => "publish",
+            Action::Touch => "touch",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "delete" => Some(Action::Delete),
+            "publish" => Some(Action::Publish),
+            "touch" => Some(Action::Touch),
+            _ => None,
+        }
+    }
+}
+
+/// A pending action on a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scheduled {
+    pub action: Action,
+    pub at: SystemTime,
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+impl Pathmap {
+    /// Schedules `action` on the existing value at `path` for `at`, replacing an earlier schedule
+    /// of the same action on it. The schedule is stored in the namespace, is kept when the value
+    /// is overwritten and dropped when it is deleted. Due actions are executed by
+    /// `run_due_actions`, `run_scheduler` or `supervise`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn schedule(&self, path: &str, at: SystemTime, action: Action) -> Result<()> {
+        let (ns, key) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        let (grp, key) = self.split_key(&key);
+        if !db::schedule(&pool, grp, key, action.as_str(), unix_millis(at)).await? {
+            return Err(PathmapError::ValueNotFound(db::join_key(grp, key)));
+        }
+        Ok(())
+    }
+
+    /// Cancels the pending `action` on `path`, returning whether there was one.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn cancel_schedule(&self, path: &str, action: Action) -> Result<bool> {
+        let (ns, key) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        let (grp, key) = self.split_key(&key);
+        db::unschedule(&pool, grp, key, action.as_str(), None).await
+    }
+
+    /// The pending actions on `path`, earliest first.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn schedules(&self, path: &str) -> Result<Vec<Scheduled>> {
+        let (ns, key) = self.parse_path(path)?;
+        let pool = self.get_pool(ns).await?;
+        let (grp, key) = self.split_key(&key);
+        Ok(db::schedules(&pool, grp, key)
+            .await?
+            .into_iter()
+            .filter_map(|(action, at)| {
+                Some(Scheduled {
+                    action: Action::parse(&action)?,
+                    at: UNIX_EPOCH + Duration::from_millis(at.max(0) as u64),
+                })
+            })
+            .collect())
+    }
+
+    /// Executes every action that is due by now in all namespaces, returning how many ran. An
+    /// action is removed once it has run, so one that fails stays due and is retried on the next
+    /// call; a failure is logged and moves on to the next namespace.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn run_due_actions(&self) -> Result<u64> {
+        let now = unix_millis(self.clock.system_now());
+        let mut executed = 0;
+        for ns in self.namespace_names()? {
+            if self.soft_ns.contains_key(&ns) {
+                continue;
+            }
+            match self.run_due_in(&ns, now, &mut executed).await {
+                Ok(()) => {}
+                Err(e) if matches!(e.root(), PathmapError::Closed) => return Err(e),
+                Err(e) => tracing::warn!(ns = %ns, error = %e, "running scheduled actions failed"),
+            }
+        }
+        Ok(executed)
+    }
+
+    /// Executes the actions of `ns` due by `now`, counting them in `executed`.
+    async fn run_due_in(&self, ns: &str, now: i64, executed: &mut u64) -> Result<()> {
+        let pool = self.get_pool(ns).await?;
+        loop {
+            let due = db::due_actions(&*pool, now, DUE_BATCH).await?;
+            let last_batch = (due.len() as i64) < DUE_BATCH;
+            for action in due {
+                self.execute_action(ns, &pool, action).await?;
+                *executed += 1;
+            }
+            if last_batch {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs `run_due_actions` every `interval` until the instance is closed, so scheduled
+    /// actions execute within `interval` of their time. Run it on its own task, e.g. with an
+    /// `Arc<Pathmap>`, or let `supervise` run it with the background cleanup. Failed passes are
+    /// logged and retried at the next interval.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn run_scheduler(&self, interval: Duration) -> Result<()> {
+        let mut shutdown = self.shutdown.subscribe();
+        while !*shutdown.borrow_and_update() {
+            match self.run_due_actions().await {
+                Ok(_) => {}
+                Err(e) if matches!(e.root(), PathmapError::Closed) => break,
+                Err(e) => tracing::warn!(error = %e, "scheduler pass failed"),
+            }
+            let tick = std::pin::pin!(rt::sleep(interval));
+            let stop = std::pin::pin!(shutdown.changed());
+            if let Either::Right((Err(_), _)) = future::select(tick, stop).await {
+                // The instance was dropped.
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute_action(
+        &self,
+        ns: &str,
+        pool: &sqlx::SqlitePool,
+        due: db::DueAction,
+    ) -> Result<()> {
+        let path = format!("{}::{}", ns, db::join_key(&due.grp, &due.key));
+        match Action::parse(&due.action) {
+            Some(Action::Delete) => {
+                self.write(ns, &due.grp, &due.key, None).await?;
+            }
+            Some(Action::Publish) => {
+                self.emit_change(
+                    Operation::Publish,
+                    ns,
+                    &db::join_key(&due.grp, &due.key),
+                    None,
+                    None,
+                );
+            }
+            Some(Action::Touch) => {
+                if let Some(value) = self.read_value(ns, &due.grp, &due.key).await? {
+                    self.write(ns, &due.grp, &due.key, Some(value)).await?;
+                }
+            }
+            None => tracing::warn!(path, action = %due.action, "unknown scheduled action"),
+        }
+        // Removed only now that it ran, so a failure leaves it due; a schedule replaced while it
+        // ran has another time and is kept.
+        db::unschedule(pool, &due.grp, &due.key, &due.action, Some(due.at)).await?;
+        Ok(())
+    }
+}
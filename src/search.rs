@@ -0,0 +1,54 @@
+/* src/search.rs */
+
+//! Full-text search over the strings stored in a namespace, backed by an SQLite FTS5 index that
+//! `NamespaceConfig::full_text_search` enables.
+
+use crate::Pathmap;
+use crate::db;
+use crate::error::{self, PathmapError, Result};
+
+impl Pathmap {
+    /// Searches the string contents of a namespace's values with an FTS5 query (`rust async`,
+    /// `"exact phrase"`, `sqlite OR postgres`, `data*`), returning the matching keys, best match
+    /// first, with a snippet of the matching text in which the matched terms are enclosed in
+    /// `[` and `]`. The index covers every string in a value, at any depth, but not keys or
+    /// values stored in blob files. Fails with `InvalidConfig` unless the namespace was opened
+    /// with `NamespaceConfig::full_text_search(true)`, and with `InvalidQuery` if FTS5 cannot
+    /// parse `query`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns, query = %query), err(Display)))]
+    pub async fn search(&self, ns: &str, query: &str) -> Result<Vec<(String, String)>> {
+        error::in_context("search", ns, async {
+            self.retry_busy(|| self.search_once(ns, query)).await
+        })
+        .await
+    }
+
+    async fn search_once(&self, ns: &str, query: &str) -> Result<Vec<(String, String)>> {
+        let pool = self.get_pool(ns).await?;
+        if !db::has_full_text(&pool).await? {
+            return Err(PathmapError::InvalidConfig(format!(
+                "full-text search is not enabled for namespace '{}'",
+                ns
+            )));
+        }
+        let hits = db::search(&pool, query)
+            .await
+            .map_err(|e| query_error(query, e))?;
+        Ok(hits
+            .into_iter()
+            .map(|(grp, key, snippet)| (db::join_key(&grp, &key), snippet))
+            .collect())
+    }
+}
+
+/// Turns the error FTS5 raises for a query it cannot parse (plain `SQLITE_ERROR`, e.g.
+/// "fts5: syntax error near ..." or "unterminated string") into `InvalidQuery`; busy, I/O and
+/// other errors pass through.
+fn query_error(query: &str, e: PathmapError) -> PathmapError {
+    if let PathmapError::DatabaseError(sqlx::Error::Database(db_err)) = &e
+        && db_err.code().as_deref() == Some("1")
+    {
+        return PathmapError::InvalidQuery(query.to_string(), db_err.message().to_string());
+    }
+    e
+}
@@ -0,0 +1,126 @@
+/* src/selftest.rs */
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use serde_json::{Value, json};
+
+/// Group that self-test values are written under; removed again when the test finishes.
+const SCRATCH_GROUP: &str = "__kvmap_self_test";
+
+/// Result of a single self-test check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub passed: bool,
+    /// Failure description, empty when the check passed.
+    pub detail: String,
+}
+
+/// Outcome of `Pathmap::self_test`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SelfTestReport {
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    /// True when every check passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    fn record(&mut self, name: &str, outcome: std::result::Result<(), String>) {
+        self.checks.push(SelfTestCheck {
+            name: name.to_string(),
+            passed: outcome.is_ok(),
+            detail: outcome.err().unwrap_or_default(),
+        });
+    }
+}
+
+impl Pathmap {
+    /// Runs a write/read/delete round trip of generated values on a scratch group of `ns`,
+    /// exercising the serialization and blob paths configured for the namespace.
+    /// Intended as a one-call smoke test for new deployments; the scratch group is removed afterwards.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn self_test(&self, ns: &str) -> Result<SelfTestReport> {
+        let mut report = SelfTestReport::default();
+        let scratch = format!("{}::{}", ns, SCRATCH_GROUP);
+        self.delete_group(&scratch)
+            .await
+            .or_else(ignore_missing_ns)?;
+
+        for (name, value) in self.sample_values(ns) {
+            let path = format!("{}.{}", scratch, name);
+            let outcome = self.round_trip(&path, &value).await;
+            report.record(&format!("round_trip:{}", name), outcome);
+        }
+
+        let path = format!("{}.conflict", scratch);
+        let outcome = async {
+            self.overwrite(&path, 1).await.map_err(|e| e.to_string())?;
+            match self.set(&path, 2).await {
+                Err(e) if matches!(e.root(), PathmapError::ValueAlreadyExists(_)) => Ok(()),
+                other => Err(format!("set on existing key returned {:?}", other)),
+            }
+        }
+        .await;
+        report.record("set_rejects_existing", outcome);
+
+        let removed = self.delete_group(&scratch).await;
+        let outcome = match removed {
+            Ok(_) => match self.list(&scratch).await {
+                Ok(listing) if listing.values.is_empty() && listing.groups.is_empty() => Ok(()),
+                Ok(listing) => Err(format!("scratch group not empty: {:?}", listing)),
+                Err(e) => Err(e.to_string()),
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        report.record("cleanup", outcome);
+
+        Ok(report)
+    }
+
+    /// Writes, reads back, deletes and re-reads a single value.
+    async fn round_trip(&self, path: &str, value: &Value) -> std::result::Result<(), String> {
+        self.overwrite(path, value)
+            .await
+            .map_err(|e| e.to_string())?;
+        let read: Value = self.get(path).await.map_err(|e| e.to_string())?;
+        if &read != value {
+            return Err(format!("read back {} instead of {}", read, value));
+        }
+        self.delete(path).await.map_err(|e| e.to_string())?;
+        match self.get::<Value>(path).await {
+            Err(e) if matches!(e.root(), PathmapError::ValueNotFound(_)) => Ok(()),
+            other => Err(format!("value still readable after delete: {:?}", other)),
+        }
+    }
+
+    fn sample_values(&self, ns: &str) -> Vec<(&'static str, Value)> {
+        let mut samples = vec![
+            ("null", Value::Null),
+            ("bool", json!(true)),
+            ("int_min", json!(i64::MIN)),
+            ("u64_max", json!(u64::MAX)),
+            ("float", json!(0.1)),
+            ("empty_string", json!("")),
+            ("unicode", json!("caf\u{e9} \u{1F600} \u{65e5}\u{672c}")),
+            ("array", json!([1, "two", null, [3]])),
+            (
+                "object",
+                json!({"name": "kvmap", "nested": {"depth": 2, "list": []}}),
+            ),
+        ];
+        if let Some(threshold) = self.ns_config(ns).blob_threshold {
+            samples.push(("blob", json!("b".repeat(threshold + 1))));
+        }
+        samples
+    }
+}
+
+fn ignore_missing_ns(e: PathmapError) -> Result<u64> {
+    match e.root() {
+        PathmapError::NamespaceNotFound(_) => Ok(0),
+        _ => Err(e),
+    }
+}
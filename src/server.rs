@@ -0,0 +1,148 @@
+/* src/server.rs */
+
+//! REST access to a `Pathmap` for services that are not written in Rust.
+//! Only available with the `server` feature.
+//!
+//! | Request                      | Effect                                  |
+//! |------------------------------|-----------------------------------------|
+//! | `GET /`                      | List namespaces                         |
+//! | `GET /{ns}/`                 | List the namespace root                 |
+//! | `GET /{ns}/group/`           | List a group (note the trailing slash)  |
+//! | `GET /{ns}/group/key`        | Read a value as JSON                    |
+//! | `PUT /{ns}/group/key`        | Write the JSON request body as a value  |
+//! | `DELETE /{ns}/group/key`     | Delete a value                          |
+//! | `DELETE /{ns}/group/`        | Delete a group and everything below it  |
+//!
+//! Path segments map to dotted keys, so `/app/users/alice` is `app::users.alice`; namespaces and
+//! keys the `NameRules` reject (e.g. `..%2Fevil`) fail with 400 before any file is opened.
+//! Errors are returned as `{"error": "..."}` with a matching status code.
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Serves the REST API on `addr` until the process stops.
+pub async fn serve(pm: Arc<Pathmap>, addr: SocketAddr) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router(pm)).await?;
+    Ok(())
+}
+
+/// The REST routes, for mounting into an existing axum application.
+pub fn router(pm: Arc<Pathmap>) -> Router {
+    Router::new()
+        .route("/", get(list_namespaces))
+        .route("/{ns}", get(list_root))
+        .route("/{ns}/", get(list_root))
+        .route(
+            "/{ns}/{*path}",
+            get(get_path).put(put_value).delete(delete_path),
+        )
+        .with_state(pm)
+}
+
+type Shared = State<Arc<Pathmap>>;
+
+async fn list_namespaces(State(pm): Shared) -> std::result::Result<Response, ApiError> {
+    Ok(Json(pm.list_ns().await?).into_response())
+}
+
+async fn list_root(
+    State(pm): Shared,
+    Path(ns): Path<String>,
+) -> std::result::Result<Response, ApiError> {
+    Ok(Json(pm.list(&qualified(&pm, &ns, "")?).await?).into_response())
+}
+
+async fn get_path(
+    State(pm): Shared,
+    Path((ns, path)): Path<(String, String)>,
+) -> std::result::Result<Response, ApiError> {
+    let (key, is_group) = dotted(&path);
+    let target = qualified(&pm, &ns, &key)?;
+    if is_group {
+        return Ok(Json(pm.list(&target).await?).into_response());
+    }
+    let value: Value = pm.get(&target).await?;
+    Ok(Json(value).into_response())
+}
+
+async fn put_value(
+    State(pm): Shared,
+    Path((ns, path)): Path<(String, String)>,
+    Json(value): Json<Value>,
+) -> std::result::Result<Response, ApiError> {
+    let (key, is_group) = dotted(&path);
+    if is_group || key.is_empty() {
+        return Err(PathmapError::InvalidPath(path).into());
+    }
+    pm.overwrite(&qualified(&pm, &ns, &key)?, value).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn delete_path(
+    State(pm): Shared,
+    Path((ns, path)): Path<(String, String)>,
+) -> std::result::Result<Response, ApiError> {
+    let (key, is_group) = dotted(&path);
+    let target = qualified(&pm, &ns, &key)?;
+    if is_group {
+        let removed = pm.delete_group(&target).await?;
+        return Ok(Json(json!({ "removed": removed })).into_response());
+    }
+    pm.delete(&target).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// The `ns::key` path of a request. The parameters arrive percent-decoded, so `%2F` or `%3A`
+/// could smuggle path separators into the namespace; both are checked against the instance's
+/// `NameRules` before they name a file.
+fn qualified(pm: &Pathmap, ns: &str, key: &str) -> Result<String> {
+    pm.name_rules.check_ns(ns)?;
+    pm.name_rules.check_key(key)?;
+    Ok(format!("{}::{}", ns, key))
+}
+
+/// Turns `group/sub/key` into `group.sub.key`; a trailing slash marks a group.
+fn dotted(path: &str) -> (String, bool) {
+    let is_group = path.ends_with('/');
+    let key = path.trim_matches('/').replace('/', ".");
+    (key, is_group)
+}
+
+/// A `PathmapError` rendered as an HTTP response.
+struct ApiError(PathmapError);
+
+impl From<PathmapError> for ApiError {
+    fn from(err: PathmapError) -> Self {
+        ApiError(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0.root() {
+            PathmapError::NamespaceNotFound(_)
+            | PathmapError::GroupNotFound(..)
+            | PathmapError::ValueNotFound(_) => StatusCode::NOT_FOUND,
+            PathmapError::NamespaceAlreadyExists(_)
+            | PathmapError::GroupAlreadyExists(..)
+            | PathmapError::ValueAlreadyExists(_) => StatusCode::CONFLICT,
+            PathmapError::InvalidPath(_)
+            | PathmapError::InvalidQuery(..)
+            | PathmapError::JsonError(_) => StatusCode::BAD_REQUEST,
+            PathmapError::Throttled(_) => StatusCode::TOO_MANY_REQUESTS,
+            PathmapError::QuotaExceeded(..) => StatusCode::INSUFFICIENT_STORAGE,
+            PathmapError::Busy(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
@@ -0,0 +1,290 @@
+/* src/settings.rs */
+
+//! Deployment settings read from a TOML file or `KVMAP_*` environment variables, so they need
+//! not be hard-coded in Rust.
+//!
+//! There are no encryption keys among them: kvmap stores namespaces in plain SQLite files and
+//! does not encrypt them, so data at rest has to be protected by the file system or volume. A
+//! configuration with an `encryption_key` fails like any other unknown key instead of being
+//! silently ignored.
+
+use crate::Pathmap;
+use crate::builder::PathmapBuilder;
+use crate::cleanup::CleanupPolicy;
+use crate::config::{OpenMode, PoolConfig};
+use crate::error::{PathmapError, Result};
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Instance settings as written in a configuration file. Every field is optional; unset ones
+/// keep the defaults of `PathmapBuilder`.
+///
+/// ```toml
+/// base_path = "/var/lib/kvmap"
+/// default_ns = "app"
+/// read_cache = 1000
+/// open_mode = "coordinated"
+///
+/// [pool]
+/// max_connections = 8
+/// acquire_timeout_ms = 5000
+///
+/// [cleanup]
+/// check_interval_secs = 60
+/// idle_timeout_secs = 300
+/// min_freelist_percent = 25
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathmapConfig {
+    pub base_path: Option<PathBuf>,
+    /// Opens every namespace read-only.
+    pub read_only: Option<bool>,
+    /// Values each namespace caches for `get`.
+    pub read_cache: Option<usize>,
+    /// `exclusive`, `shared` or `coordinated`.
+    pub open_mode: Option<OpenMode>,
+    /// Namespace of paths without `::`.
+    pub default_ns: Option<String>,
+    pub pool: PoolSettings,
+    pub cleanup: CleanupSettings,
+}
+
+/// The `[pool]` table: connection pool sizing of each namespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolSettings {
+    pub max_connections: Option<u32>,
+    pub min_connections: Option<u32>,
+    pub acquire_timeout_ms: Option<u64>,
+    /// How long an unused connection stays open; 0 keeps it open.
+    pub idle_timeout_secs: Option<u64>,
+}
+
+/// The `[cleanup]` table. With both intervals set, `Pathmap::from_config` and
+/// `Pathmap::from_env` start the background cleanup task.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CleanupSettings {
+    pub check_interval_secs: Option<u64>,
+    pub idle_timeout_secs: Option<u64>,
+    pub min_freelist_percent: Option<u64>,
+    pub min_free_pages: Option<u64>,
+    pub require_idle: Option<bool>,
+    /// Closes pools of namespaces idle for this long.
+    pub idle_pool_close_secs: Option<u64>,
+}
+
+fn invalid(message: String) -> PathmapError {
+    PathmapError::InvalidConfig(message)
+}
+
+fn parse_var<T: FromStr>(name: &str, value: Option<String>) -> Result<Option<T>> {
+    match value {
+        None => Ok(None),
+        Some(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| invalid(format!("{} has an invalid value '{}'", name, value))),
+    }
+}
+
+/// Reads an enum setting by the name its configuration file entry uses.
+fn parse_enum_var<T: for<'de> Deserialize<'de>>(
+    name: &str,
+    value: Option<String>,
+) -> Result<Option<T>> {
+    match value {
+        None => Ok(None),
+        Some(value) => T::deserialize(value.trim().into_deserializer())
+            .map(Some)
+            .map_err(|e: serde::de::value::Error| {
+                invalid(format!("{} has an invalid value '{}': {}", name, value, e))
+            }),
+    }
+}
+
+impl PathmapConfig {
+    /// Reads a TOML configuration file. Unknown keys are rejected, so typos do not go unnoticed.
+    #[cfg(feature = "config-file")]
+    pub fn from_file<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| invalid(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Reads the settings from the environment only; see `with_env_overrides` for the variables.
+    pub fn from_env() -> Result<Self> {
+        Self::default().with_env_overrides()
+    }
+
+    /// Replaces settings with those given by `KVMAP_*` environment variables, named after the
+    /// setting's table and key: `KVMAP_BASE_PATH`, `KVMAP_READ_ONLY`, `KVMAP_READ_CACHE`,
+    /// `KVMAP_OPEN_MODE`, `KVMAP_DEFAULT_NS`, `KVMAP_POOL_MAX_CONNECTIONS`, `KVMAP_POOL_MIN_CONNECTIONS`,
+    /// `KVMAP_POOL_ACQUIRE_TIMEOUT_MS`, `KVMAP_POOL_IDLE_TIMEOUT_SECS`,
+    /// `KVMAP_CLEANUP_CHECK_INTERVAL_SECS`, `KVMAP_CLEANUP_IDLE_TIMEOUT_SECS`,
+    /// `KVMAP_CLEANUP_MIN_FREELIST_PERCENT`, `KVMAP_CLEANUP_MIN_FREE_PAGES`,
+    /// `KVMAP_CLEANUP_REQUIRE_IDLE` and `KVMAP_CLEANUP_IDLE_POOL_CLOSE_SECS`.
+    pub fn with_env_overrides(self) -> Result<Self> {
+        self.with_overrides(|name| std::env::var(name).ok())
+    }
+
+    fn with_overrides(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        macro_rules! apply {
+            ($field:expr, $name:literal) => {
+                if let Some(value) = parse_var($name, var($name))? {
+                    $field = Some(value);
+                }
+            };
+        }
+        if let Some(path) = var("KVMAP_BASE_PATH") {
+            self.base_path = Some(PathBuf::from(path));
+        }
+        apply!(self.read_only, "KVMAP_READ_ONLY");
+        apply!(self.read_cache, "KVMAP_READ_CACHE");
+        if let Some(mode) = parse_enum_var("KVMAP_OPEN_MODE", var("KVMAP_OPEN_MODE"))? {
+            self.open_mode = Some(mode);
+        }
+        apply!(self.default_ns, "KVMAP_DEFAULT_NS");
+        apply!(self.pool.max_connections, "KVMAP_POOL_MAX_CONNECTIONS");
+        apply!(self.pool.min_connections, "KVMAP_POOL_MIN_CONNECTIONS");
+        apply!(
+            self.pool.acquire_timeout_ms,
+            "KVMAP_POOL_ACQUIRE_TIMEOUT_MS"
+        );
+        apply!(self.pool.idle_timeout_secs, "KVMAP_POOL_IDLE_TIMEOUT_SECS");
+        apply!(
+            self.cleanup.check_interval_secs,
+            "KVMAP_CLEANUP_CHECK_INTERVAL_SECS"
+        );
+        apply!(
+            self.cleanup.idle_timeout_secs,
+            "KVMAP_CLEANUP_IDLE_TIMEOUT_SECS"
+        );
+        apply!(
+            self.cleanup.min_freelist_percent,
+            "KVMAP_CLEANUP_MIN_FREELIST_PERCENT"
+        );
+        apply!(self.cleanup.min_free_pages, "KVMAP_CLEANUP_MIN_FREE_PAGES");
+        apply!(self.cleanup.require_idle, "KVMAP_CLEANUP_REQUIRE_IDLE");
+        apply!(
+            self.cleanup.idle_pool_close_secs,
+            "KVMAP_CLEANUP_IDLE_POOL_CLOSE_SECS"
+        );
+        Ok(self)
+    }
+
+    /// A `PathmapBuilder` with these settings; `build` still validates them.
+    pub fn builder(&self) -> Result<PathmapBuilder> {
+        let mut builder = PathmapBuilder::new();
+        if let Some(path) = &self.base_path {
+            builder = builder.base_path(path);
+        }
+        if let Some(read_only) = self.read_only {
+            builder = builder.read_only(read_only);
+        }
+        if let Some(capacity) = self.read_cache {
+            builder = builder.read_cache(capacity);
+        }
+        if let Some(mode) = self.open_mode {
+            builder = builder.open_mode(mode);
+        }
+        if let Some(ns) = &self.default_ns {
+            builder = builder.default_ns(ns);
+        }
+
+        let mut pool = PoolConfig::new();
+        if let Some(max) = self.pool.max_connections {
+            pool = pool.max_connections(max);
+        }
+        if let Some(min) = self.pool.min_connections {
+            pool = pool.min_connections(min);
+        }
+        if let Some(ms) = self.pool.acquire_timeout_ms {
+            pool = pool.acquire_timeout(Duration::from_millis(ms));
+        }
+        if let Some(secs) = self.pool.idle_timeout_secs {
+            pool = pool.idle_timeout((secs > 0).then(|| Duration::from_secs(secs)));
+        }
+        builder = builder.pool(pool);
+
+        let cleanup = &self.cleanup;
+        let mut policy = CleanupPolicy::new();
+        if let Some(percent) = cleanup.min_freelist_percent {
+            policy = policy.min_freelist_percent(percent);
+        }
+        if let Some(pages) = cleanup.min_free_pages {
+            policy = policy.min_free_pages(pages);
+        }
+        if let Some(require) = cleanup.require_idle {
+            policy = policy.require_idle(require);
+        }
+        builder = builder.cleanup_policy(policy);
+        if let Some(secs) = cleanup.idle_pool_close_secs {
+            builder = builder.idle_pool_close(Duration::from_secs(secs));
+        }
+        Ok(builder)
+    }
+
+    /// The check interval and idle timeout of the background cleanup, if it should run.
+    fn cleanup_intervals(&self) -> Result<Option<(Duration, Duration)>> {
+        match (
+            self.cleanup.check_interval_secs,
+            self.cleanup.idle_timeout_secs,
+        ) {
+            (Some(check), Some(idle)) if check > 0 => Ok(Some((
+                Duration::from_secs(check),
+                Duration::from_secs(idle),
+            ))),
+            (None, None) => Ok(None),
+            _ => Err(invalid(
+                "cleanup needs both check_interval_secs (above 0) and idle_timeout_secs"
+                    .to_string(),
+            )),
+        }
+    }
+
+    /// Builds the instance, and starts the background cleanup task if `[cleanup]` sets both
+    /// intervals. The task runs until the instance is closed or dropped.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn open(&self) -> Result<Pathmap> {
+        let intervals = self.cleanup_intervals()?;
+        let pm = self.builder()?.build()?;
+        if let Some((check_interval, idle_timeout)) = intervals {
+            pm.start_background_cleanup(check_interval, idle_timeout);
+        }
+        Ok(pm)
+    }
+}
+
+impl Pathmap {
+    /// Opens an instance configured by the TOML file at `path`, with `KVMAP_*` environment
+    /// variables taking precedence over it (see `PathmapConfig`).
+    #[cfg(feature = "config-file")]
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn from_config<P: AsRef<std::path::Path>>(path: P) -> Result<Pathmap> {
+        PathmapConfig::from_file(path)?
+            .with_env_overrides()?
+            .open()
+            .await
+    }
+
+    /// Opens an instance configured by `KVMAP_*` environment variables alone.
+    #[cfg_attr(
+        feature = "instrument",
+        tracing::instrument(level = "debug", skip_all, err(Display))
+    )]
+    pub async fn from_env() -> Result<Pathmap> {
+        PathmapConfig::from_env()?.open().await
+    }
+}
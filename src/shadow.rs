@@ -0,0 +1,140 @@
+/* src/shadow.rs */
+
+use crate::Pathmap;
+use crate::error::PathmapError;
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Callback invoked for every sampled read whose results differ between stores.
+pub type MismatchHandler = Arc<dyn Fn(&ShadowMismatch) + Send + Sync>;
+
+/// Settings for comparing reads against a shadow store, e.g. while migrating backends or codecs.
+#[derive(Clone)]
+pub struct ShadowConfig {
+    /// Fraction of reads (0.0 to 1.0) that are also performed against the shadow store.
+    pub sample_rate: f64,
+    /// Called for each mismatch, in addition to the counters in `ShadowStats`.
+    pub on_mismatch: Option<MismatchHandler>,
+}
+
+impl ShadowConfig {
+    pub fn new(sample_rate: f64) -> Self {
+        ShadowConfig {
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            on_mismatch: None,
+        }
+    }
+
+    pub fn on_mismatch<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&ShadowMismatch) + Send + Sync + 'static,
+    {
+        self.on_mismatch = Some(Arc::new(handler));
+        self
+    }
+}
+
+impl fmt::Debug for ShadowConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShadowConfig")
+            .field("sample_rate", &self.sample_rate)
+            .field("on_mismatch", &self.on_mismatch.is_some())
+            .finish()
+    }
+}
+
+/// A sampled read that returned different results from the primary and shadow stores.
+/// `None` means the value was absent in that store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShadowMismatch {
+    pub path: String,
+    pub primary: Option<Value>,
+    pub shadow: Option<Value>,
+    /// Set when the shadow read failed for a reason other than a missing value.
+    pub shadow_error: Option<String>,
+}
+
+/// Running totals of shadow comparisons.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShadowStats {
+    pub reads: u64,
+    pub sampled: u64,
+    pub mismatches: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    reads: AtomicU64,
+    sampled: AtomicU64,
+    mismatches: AtomicU64,
+}
+
+pub(crate) struct Shadow {
+    store: Arc<Pathmap>,
+    config: ShadowConfig,
+    counters: Arc<Counters>,
+}
+
+impl Shadow {
+    pub(crate) fn new(store: Arc<Pathmap>, config: ShadowConfig) -> Self {
+        Shadow {
+            store,
+            config,
+            counters: Arc::default(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> ShadowStats {
+        ShadowStats {
+            reads: self.counters.reads.load(Ordering::Relaxed),
+            sampled: self.counters.sampled.load(Ordering::Relaxed),
+            mismatches: self.counters.mismatches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Records a primary read and, if it falls in the sample, compares it against the shadow
+    /// store in a background task so the caller's latency is unaffected.
+    pub(crate) fn observe(&self, path: &str, primary: Option<&[u8]>) {
+        let n = self.counters.reads.fetch_add(1, Ordering::Relaxed);
+        // Spread samples evenly: read n is sampled when it crosses the next multiple of the rate.
+        let rate = self.config.sample_rate;
+        if ((n + 1) as f64 * rate).floor() <= (n as f64 * rate).floor() {
+            return;
+        }
+        self.counters.sampled.fetch_add(1, Ordering::Relaxed);
+
+        let primary = primary.map(|raw| serde_json::from_slice(raw).unwrap_or(Value::Null));
+        let store = Arc::clone(&self.store);
+        let counters = Arc::clone(&self.counters);
+        let handler = self.config.on_mismatch.clone();
+        let path = path.to_string();
+        crate::rt::spawn(async move {
+            let (shadow, shadow_error) = match store.get::<Value>(&path).await {
+                Ok(value) => (Some(value), None),
+                Err(e)
+                    if matches!(
+                        e.root(),
+                        PathmapError::ValueNotFound(_) | PathmapError::NamespaceNotFound(_)
+                    ) =>
+                {
+                    (None, None)
+                }
+                Err(e) => (None, Some(e.to_string())),
+            };
+            if primary == shadow && shadow_error.is_none() {
+                return;
+            }
+            counters.mismatches.fetch_add(1, Ordering::Relaxed);
+            if let Some(handler) = handler {
+                handler(&ShadowMismatch {
+                    path,
+                    primary,
+                    shadow,
+                    shadow_error,
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,360 @@
+/* src/snapshot.rs */
+
+use crate::Pathmap;
+use crate::blob;
+use crate::db;
+use crate::error::{self, PathmapError, Result};
+use crate::event::Operation;
+use crate::rt;
+use libsqlite3_sys as ffi;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection, Row};
+use std::ffi::CStr;
+use std::path::Path;
+use std::time::Duration;
+
+/// Pages copied per backup step while snapshotting; writers can proceed between steps.
+const PAGES_PER_STEP: i32 = 256;
+
+/// Consecutive backup steps that may find the database busy or locked before giving up; steps
+/// are about a millisecond apart.
+const MAX_BUSY_STEPS: u32 = 5_000;
+
+/// Times a backup may restart because another connection wrote to the source before giving up.
+const MAX_RESTARTS: u32 = 100;
+
+/// Rows compared against the live namespace when verifying a backup.
+const VERIFY_SAMPLE_SIZE: i64 = 64;
+
+/// Outcome of `verify_backup`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupReport {
+    /// Problems reported by SQLite's integrity check; empty when the file is sound.
+    pub integrity_errors: Vec<String>,
+    /// Schema version recorded in the backup.
+    pub schema_version: i64,
+    /// Number of values in the backup.
+    pub rows: u64,
+    /// Blob files referenced by the backup but missing next to it.
+    pub missing_blobs: Vec<String>,
+    /// Number of values compared against the live namespace.
+    pub sampled: usize,
+    /// Sampled keys whose value differs from (or is missing in) the live namespace. Expected for
+    /// keys written since the backup; a high share on a quiet namespace points at a bad backup.
+    pub mismatched: Vec<String>,
+}
+
+impl BackupReport {
+    /// Whether the backup can be restored: the file is intact, its schema is understood and
+    /// every blob it references is present.
+    pub fn is_restorable(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.schema_version <= db::SCHEMA_VERSION
+            && self.missing_blobs.is_empty()
+    }
+}
+
+impl Pathmap {
+    /// Copies a live namespace to `dest` using SQLite's online backup API, without stopping writers.
+    /// The copy is a consistent point-in-time image; externalized blobs are copied alongside it.
+    /// Fails with `Busy` if the database stays locked for seconds, or if writers keep restarting
+    /// the copy.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn snapshot_ns<P: AsRef<Path>>(&self, ns: &str, dest: P) -> Result<()> {
+        error::in_context("snapshot_ns", ns, async {
+            let dest = dest.as_ref();
+            if let Some(parent) = dest.parent() {
+                rt::fs::create_dir_all(parent).await?;
+            }
+            let pool = self.get_pool(ns).await?;
+            let mut target = open(dest, true).await?;
+            self.in_background(ns, async {
+                let mut source = pool.acquire().await?;
+                backup(&mut source, &mut target, PAGES_PER_STEP).await
+            })
+            .await?;
+
+            // Blob files are immutable once written, so copying the ones the snapshot references is enough.
+            let snapshot = db::open_read_only(dest, self.ns_config(ns)).await?;
+            let refs = db::external_refs(&snapshot, "").await?;
+            snapshot.close().await;
+            target.close().await?;
+            copy_blobs(
+                &blob::blob_dir(&self.get_db_path(ns)),
+                &blob::blob_dir(dest),
+                &refs,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Writes a compacted hot backup of a namespace to `dest` with `VACUUM INTO`, so cleanup and
+    /// backup happen in one pass. `dest` must not exist yet; the result can be used with `restore_ns`.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn backup_ns<P: AsRef<Path>>(&self, ns: &str, dest: P) -> Result<()> {
+        error::in_context("backup_ns", ns, async {
+            let dest = dest.as_ref();
+            if dest.exists() {
+                return Err(PathmapError::BackupError(format!(
+                    "backup target '{}' already exists",
+                    dest.display()
+                )));
+            }
+            if let Some(parent) = dest.parent() {
+                rt::fs::create_dir_all(parent).await?;
+            }
+            let pool = self.get_pool(ns).await?;
+            self.in_background(ns, db::vacuum_into(&pool, dest)).await?;
+
+            let backup = db::open_read_only(dest, self.ns_config(ns)).await?;
+            let refs = db::external_refs(&backup, "").await?;
+            backup.close().await;
+            copy_blobs(
+                &blob::blob_dir(&self.get_db_path(ns)),
+                &blob::blob_dir(dest),
+                &refs,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Replaces a namespace's contents with a snapshot taken by `snapshot_ns` or `backup_ns`.
+    /// The swap happens in a single backup step, so readers see either the old or the new data.
+    /// The snapshot itself is only read; a snapshot of an older schema is migrated after the swap.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn restore_ns<P: AsRef<Path>>(&self, ns: &str, src: P) -> Result<()> {
+        error::in_context("restore_ns", ns, async {
+            let src = src.as_ref();
+            if !src.is_file() {
+                return Err(PathmapError::BackupError(format!(
+                    "snapshot '{}' not found",
+                    src.display()
+                )));
+            }
+            let pool = self.get_pool_or_init(ns).await?;
+            let _lock = self.maintenance_lock(ns)?;
+
+            // The snapshot is only read; the restored copy is migrated instead.
+            let snapshot = db::open_read_only(src, self.ns_config(ns)).await?;
+            let refs = db::external_refs(&snapshot, "").await?;
+            snapshot.close().await;
+            copy_blobs(
+                &blob::blob_dir(src),
+                &blob::blob_dir(&self.get_db_path(ns)),
+                &refs,
+            )
+            .await?;
+
+            let mut source = open(src, false).await?;
+            let mut target = pool.acquire().await?;
+            backup(&mut source, &mut target, -1).await?;
+            drop(target);
+            source.close().await?;
+            db::upgrade(&pool, self.group_depth, self.ns_config(ns)).await?;
+            // The indexes of the snapshot replaced those of the live file.
+            if !self.ns_config(ns).read_only {
+                self.apply_unique_fields(ns, &pool).await?;
+            }
+            self.invalidate_cached_ns(ns);
+            self.emit_change(Operation::Restore, ns, "", None, None);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Checks that a backup taken with `snapshot_ns` or `backup_ns` is restorable without touching
+    /// it: runs SQLite's integrity check, confirms referenced blobs exist, and compares a random
+    /// sample of values against the live namespace.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn verify_backup<P: AsRef<Path>>(&self, ns: &str, path: P) -> Result<BackupReport> {
+        error::in_context("verify_backup", ns, async {
+            let path = path.as_ref();
+            if !path.is_file() {
+                return Err(PathmapError::BackupError(format!(
+                    "snapshot '{}' not found",
+                    path.display()
+                )));
+            }
+            let mut backup = SqliteConnectOptions::new()
+                .filename(path)
+                .read_only(true)
+                .connect()
+                .await?;
+            let mut report = BackupReport {
+                schema_version: sqlx::query_scalar("PRAGMA user_version")
+                    .fetch_one(&mut backup)
+                    .await?,
+                ..BackupReport::default()
+            };
+            let integrity: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+                .fetch_all(&mut backup)
+                .await?;
+            report.integrity_errors = integrity.into_iter().filter(|line| line != "ok").collect();
+            if !report.integrity_errors.is_empty() {
+                backup.close().await?;
+                return Ok(report);
+            }
+
+            let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM kv_store")
+                .fetch_one(&mut backup)
+                .await?;
+            report.rows = rows as u64;
+            let backup_blobs = blob::blob_dir(path);
+            let refs: Vec<Vec<u8>> =
+                sqlx::query_scalar("SELECT value FROM kv_store WHERE external = 1")
+                    .fetch_all(&mut backup)
+                    .await?;
+            for name in refs {
+                let name = String::from_utf8_lossy(&name).into_owned();
+                if !backup_blobs.join(&name).is_file() {
+                    report.missing_blobs.push(name);
+                }
+            }
+
+            let sample = sqlx::query(
+                "SELECT grp, key, value, external FROM kv_store ORDER BY random() LIMIT ?",
+            )
+            .bind(VERIFY_SAMPLE_SIZE)
+            .fetch_all(&mut backup)
+            .await?;
+            backup.close().await?;
+
+            let pool = self.get_pool(ns).await?;
+            for row in sample {
+                let grp: String = row.get("grp");
+                let key: String = row.get("key");
+                let data: Vec<u8> = row.get("value");
+                let backed_up = if row.get("external") {
+                    match blob::read(&backup_blobs, &String::from_utf8_lossy(&data)).await {
+                        Ok(value) => value,
+                        // Already reported as a missing blob.
+                        Err(_) => continue,
+                    }
+                } else {
+                    data
+                };
+                let live = match db::find(&*pool, &grp, &key).await? {
+                    Some(stored) => Some(self.load_value(ns, stored).await?),
+                    None => None,
+                };
+                report.sampled += 1;
+                if live.as_deref() != Some(backed_up.as_slice()) {
+                    report.mismatched.push(db::join_key(&grp, &key));
+                }
+            }
+            Ok(report)
+        })
+        .await
+    }
+}
+
+pub(crate) async fn open(path: &Path, create: bool) -> Result<SqliteConnection> {
+    let options = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(create);
+    Ok(options.connect().await?)
+}
+
+/// Owns an in-progress `sqlite3_backup` and finishes it on drop.
+struct BackupHandle(*mut ffi::sqlite3_backup);
+
+// SAFETY: the handle is only used while both connection handles are locked by this task.
+unsafe impl Send for BackupHandle {}
+
+impl Drop for BackupHandle {
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from `sqlite3_backup_init` and is finished exactly once.
+        unsafe {
+            ffi::sqlite3_backup_finish(self.0);
+        }
+    }
+}
+
+/// Runs the online backup from `source` into `target`, `pages_per_step` pages at a time
+/// (`-1` copies everything in one step). Fails with `Busy` once `MAX_BUSY_STEPS` steps in a row
+/// found a database locked, or once writers to the source restarted the copy `MAX_RESTARTS`
+/// times.
+async fn backup(
+    source: &mut SqliteConnection,
+    target: &mut SqliteConnection,
+    pages_per_step: i32,
+) -> Result<()> {
+    let mut source_handle = source.lock_handle().await?;
+    let mut target_handle = target.lock_handle().await?;
+
+    let main = c"main";
+    // SAFETY: both handles are locked for the lifetime of the backup, so no other thread uses them.
+    let handle = BackupHandle(unsafe {
+        ffi::sqlite3_backup_init(
+            target_handle.as_raw_handle().as_ptr(),
+            main.as_ptr(),
+            source_handle.as_raw_handle().as_ptr(),
+            main.as_ptr(),
+        )
+    });
+    if handle.0.is_null() {
+        // SAFETY: the target handle is valid and locked.
+        let message =
+            unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(target_handle.as_raw_handle().as_ptr())) };
+        return Err(PathmapError::BackupError(
+            message.to_string_lossy().into_owned(),
+        ));
+    }
+
+    let mut busy = 0;
+    let mut restarts = 0;
+    let mut remaining = i32::MAX;
+    loop {
+        // SAFETY: `handle` is a live backup object.
+        let rc = unsafe { ffi::sqlite3_backup_step(handle.0, pages_per_step) };
+        match rc {
+            ffi::SQLITE_DONE => break,
+            ffi::SQLITE_OK => {
+                busy = 0;
+                // SAFETY: `handle` is a live backup object that has been stepped.
+                let left = unsafe { ffi::sqlite3_backup_remaining(handle.0) };
+                if left > remaining {
+                    restarts += 1;
+                    if restarts >= MAX_RESTARTS {
+                        return Err(PathmapError::Busy(restarts));
+                    }
+                }
+                remaining = left;
+                rt::sleep(Duration::from_millis(1)).await;
+            }
+            ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => {
+                busy += 1;
+                if busy >= MAX_BUSY_STEPS {
+                    return Err(PathmapError::Busy(busy));
+                }
+                rt::sleep(Duration::from_millis(1)).await;
+            }
+            code => {
+                // SAFETY: `sqlite3_errstr` returns a static string for any result code.
+                let message = unsafe { CStr::from_ptr(ffi::sqlite3_errstr(code)) };
+                return Err(PathmapError::BackupError(
+                    message.to_string_lossy().into_owned(),
+                ));
+            }
+        }
+    }
+    drop(handle);
+    Ok(())
+}
+
+pub(crate) async fn copy_blobs(from: &Path, to: &Path, names: &[String]) -> Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    rt::fs::create_dir_all(to).await?;
+    for name in names {
+        let data = blob::read(from, name).await?;
+        let target = to.join(name);
+        if !target.exists() {
+            rt::fs::write(target, data).await?;
+        }
+    }
+    Ok(())
+}
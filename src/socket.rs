@@ -0,0 +1,307 @@
+/* src/socket.rs */
+
+//! A small request/response protocol over a Unix domain socket, so sidecar processes on the same
+//! host can share one `Pathmap` owner instead of opening the SQLite files concurrently.
+//!
+//! Every frame is a 4-byte big-endian length followed by that many bytes of JSON. Clients send a
+//! [`Request`] and receive one [`Response`] per request, in order.
+
+use crate::error::{PathmapError, Result};
+use crate::replica::{Primary, PrimaryFuture};
+use crate::{Listing, Pathmap, WriteOutcome, write_outcome};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Frames larger than this are rejected instead of allocating for them.
+const MAX_FRAME_LEN: u32 = 64 * 1024 * 1024;
+
+/// An operation sent by a client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    Get { path: String },
+    Set { path: String, value: Value },
+    Overwrite { path: String, value: Value },
+    Delete { path: String },
+    DeleteGroup { path: String },
+    Exists { path: String },
+    List { path: String },
+    ListNs,
+}
+
+/// The server's answer to a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok(Value),
+    Error { kind: ErrorKind, message: String },
+}
+
+/// Error categories a client can act on; the rest arrive as `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    NamespaceNotFound,
+    ValueNotFound,
+    ValueAlreadyExists,
+    InvalidPath,
+    Other,
+}
+
+/// Accepts connections on `path` and serves requests until the process stops.
+/// A stale socket file left by a previous owner is replaced; a live one is an error.
+pub async fn serve_unix<P: AsRef<Path>>(pm: Arc<Pathmap>, path: P) -> Result<()> {
+    let path = path.as_ref();
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(PathmapError::StoreLocked(path.display().to_string()));
+        }
+        tokio::fs::remove_file(path).await?;
+    }
+    let listener = UnixListener::bind(path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let pm = Arc::clone(&pm);
+        tokio::spawn(async move {
+            // A broken connection only affects that client.
+            let _ = handle_connection(&pm, stream).await;
+        });
+    }
+}
+
+async fn handle_connection(pm: &Pathmap, mut stream: UnixStream) -> Result<()> {
+    while let Some(frame) = read_frame(&mut stream).await? {
+        let response = match serde_json::from_slice::<Request>(&frame) {
+            Ok(request) => match execute(pm, request).await {
+                Ok(value) => Response::Ok(value),
+                Err(e) => error_response(e),
+            },
+            Err(e) => error_response(e.into()),
+        };
+        write_frame(&mut stream, &serde_json::to_vec(&response)?).await?;
+    }
+    Ok(())
+}
+
+async fn execute(pm: &Pathmap, request: Request) -> Result<Value> {
+    Ok(match request {
+        Request::Get { path } => pm.get::<Value>(&path).await?,
+        Request::Set { path, value } => {
+            pm.set(&path, value).await?;
+            Value::Null
+        }
+        Request::Overwrite { path, value } => {
+            Value::from(pm.overwrite(&path, value).await? == WriteOutcome::Replaced)
+        }
+        Request::Delete { path } => Value::from(pm.delete(&path).await?),
+        Request::DeleteGroup { path } => Value::from(pm.delete_group(&path).await?),
+        Request::Exists { path } => Value::from(pm.exists(&path).await?),
+        Request::List { path } => serde_json::to_value(pm.list(&path).await?)?,
+        Request::ListNs => serde_json::to_value(pm.list_ns().await?)?,
+    })
+}
+
+fn error_response(err: PathmapError) -> Response {
+    let kind = match err.root() {
+        PathmapError::NamespaceNotFound(_) => ErrorKind::NamespaceNotFound,
+        PathmapError::ValueNotFound(_) => ErrorKind::ValueNotFound,
+        PathmapError::ValueAlreadyExists(_) => ErrorKind::ValueAlreadyExists,
+        PathmapError::InvalidPath(_) => ErrorKind::InvalidPath,
+        _ => ErrorKind::Other,
+    };
+    Response::Error {
+        kind,
+        message: err.to_string(),
+    }
+}
+
+/// A connection to a `serve_unix` owner process. Requests on one client run one at a time.
+#[derive(Debug)]
+pub struct UnixClient {
+    stream: UnixStream,
+}
+
+impl UnixClient {
+    pub async fn connect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(UnixClient {
+            stream: UnixStream::connect(path).await?,
+        })
+    }
+
+    pub async fn get<T: DeserializeOwned>(&mut self, path: &str) -> Result<T> {
+        let value = self
+            .call(Request::Get {
+                path: path.to_string(),
+            })
+            .await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub async fn set<T: Serialize>(&mut self, path: &str, value: T) -> Result<()> {
+        let value = serde_json::to_value(value)?;
+        self.call(Request::Set {
+            path: path.to_string(),
+            value,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Overwrites a value and reports whether it created or replaced it, like
+    /// `Pathmap::overwrite`.
+    pub async fn overwrite<T: Serialize>(&mut self, path: &str, value: T) -> Result<WriteOutcome> {
+        let value = serde_json::to_value(value)?;
+        let replaced = self
+            .call(Request::Overwrite {
+                path: path.to_string(),
+                value,
+            })
+            .await?;
+        Ok(write_outcome(serde_json::from_value(replaced)?))
+    }
+
+    /// Deletes a value, returning whether there was one.
+    pub async fn delete(&mut self, path: &str) -> Result<bool> {
+        let removed = self
+            .call(Request::Delete {
+                path: path.to_string(),
+            })
+            .await?;
+        Ok(serde_json::from_value(removed)?)
+    }
+
+    pub async fn delete_group(&mut self, path: &str) -> Result<u64> {
+        let value = self
+            .call(Request::DeleteGroup {
+                path: path.to_string(),
+            })
+            .await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub async fn exists(&mut self, path: &str) -> Result<bool> {
+        let value = self
+            .call(Request::Exists {
+                path: path.to_string(),
+            })
+            .await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub async fn list(&mut self, path: &str) -> Result<Listing> {
+        let value = self
+            .call(Request::List {
+                path: path.to_string(),
+            })
+            .await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub async fn list_ns(&mut self) -> Result<Vec<String>> {
+        let value = self.call(Request::ListNs).await?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Sends a raw request and waits for its response.
+    pub async fn call(&mut self, request: Request) -> Result<Value> {
+        write_frame(&mut self.stream, &serde_json::to_vec(&request)?).await?;
+        let frame = read_frame(&mut self.stream).await?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "server closed the connection",
+            )
+        })?;
+        match serde_json::from_slice(&frame)? {
+            Response::Ok(value) => Ok(value),
+            Response::Error { kind, message } => Err(match kind {
+                ErrorKind::NamespaceNotFound => PathmapError::NamespaceNotFound(message),
+                ErrorKind::ValueNotFound => PathmapError::ValueNotFound(message),
+                ErrorKind::ValueAlreadyExists => PathmapError::ValueAlreadyExists(message),
+                ErrorKind::InvalidPath => PathmapError::InvalidPath(message),
+                ErrorKind::Other => PathmapError::RemoteError(message),
+            }),
+        }
+    }
+}
+
+/// Lets a replica read through to and write to a socket owner; requests are serialized on the
+/// one connection.
+impl Primary for tokio::sync::Mutex<UnixClient> {
+    fn get<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, Option<Value>> {
+        Box::pin(async move {
+            match self.lock().await.get::<Value>(path).await {
+                Ok(value) => Ok(Some(value)),
+                Err(e)
+                    if matches!(
+                        e.root(),
+                        PathmapError::ValueNotFound(_) | PathmapError::NamespaceNotFound(_)
+                    ) =>
+                {
+                    Ok(None)
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn set<'a>(
+        &'a self,
+        path: &'a str,
+        value: &'a Value,
+        overwrite: bool,
+    ) -> PrimaryFuture<'a, bool> {
+        Box::pin(async move {
+            let mut client = self.lock().await;
+            if overwrite {
+                Ok(client.overwrite(path, value).await? == WriteOutcome::Replaced)
+            } else {
+                client.set(path, value).await?;
+                Ok(false)
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, bool> {
+        Box::pin(async move { self.lock().await.delete(path).await })
+    }
+
+    fn delete_group<'a>(&'a self, path: &'a str) -> PrimaryFuture<'a, u64> {
+        Box::pin(async move { self.lock().await.delete_group(path).await })
+    }
+}
+
+/// Reads one frame, or `None` if the peer closed the connection between frames.
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(PathmapError::InvalidConfig(format!(
+            "frame of {} bytes exceeds the {} byte limit",
+            len, MAX_FRAME_LEN
+        )));
+    }
+    let mut frame = vec![0; len as usize];
+    reader.read_exact(&mut frame).await?;
+    Ok(Some(frame))
+}
+
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> Result<()> {
+    let len = u32::try_from(frame.len())
+        .ok()
+        .filter(|&len| len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            PathmapError::InvalidConfig(format!("frame of {} bytes is too large", frame.len()))
+        })?;
+    writer.write_u32(len).await?;
+    writer.write_all(frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
@@ -0,0 +1,180 @@
+/* src/throttle.rs */
+
+//! Per-group write rate limits, so a high-frequency producer cannot starve other writers of the
+//! same namespace.
+
+use crate::Pathmap;
+use crate::error::{PathmapError, Result};
+use crate::path;
+use crate::rt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What happens to a write that exceeds its group's rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottlePolicy {
+    /// Fail the write with `Throttled`.
+    Reject,
+    /// Delay the write until the rate allows it, but reject it with `Throttled` if that would
+    /// take longer than `max_wait`. Delayed writes run in the order they arrived, timed by the
+    /// instance's `Clock`; a delayed write that is dropped gives its turn back.
+    Queue { max_wait: Duration },
+}
+
+/// A write rate limit for one group.
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    /// Sustained writes per second.
+    pub per_second: f64,
+    /// Writes allowed back to back before the rate applies.
+    pub burst: u32,
+    pub policy: ThrottlePolicy,
+}
+
+impl ThrottleConfig {
+    /// Limits writes to `per_second`, with a burst of one second's worth and queueing for up to
+    /// one second.
+    pub fn new(per_second: f64) -> Self {
+        ThrottleConfig {
+            per_second,
+            burst: (per_second.ceil() as u32).max(1),
+            policy: ThrottlePolicy::Queue {
+                max_wait: Duration::from_secs(1),
+            },
+        }
+    }
+
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    pub fn policy(mut self, policy: ThrottlePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// A token bucket. Queued writers take their token up front, so `tokens` goes negative while
+/// they wait and later writers queue behind them.
+struct Bucket {
+    tokens: f64,
+    updated: Option<Instant>,
+}
+
+pub(crate) struct GroupThrottle {
+    group: String,
+    config: ThrottleConfig,
+    bucket: Mutex<Bucket>,
+}
+
+impl GroupThrottle {
+    pub(crate) fn new(group: &str, config: ThrottleConfig) -> Self {
+        GroupThrottle {
+            group: group.to_string(),
+            bucket: Mutex::new(Bucket {
+                tokens: config.burst as f64,
+                updated: None,
+            }),
+            config,
+        }
+    }
+
+    /// Takes a token at `now`, returning how long the write has to wait for it.
+    fn acquire(&self, now: Instant) -> Result<Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let burst = self.config.burst as f64;
+        if let Some(updated) = bucket.updated {
+            let refill =
+                now.saturating_duration_since(updated).as_secs_f64() * self.config.per_second;
+            bucket.tokens = (bucket.tokens + refill).min(burst);
+        }
+        bucket.updated = Some(now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(Duration::ZERO);
+        }
+        // A rate of zero blocks the group; there is no wait that would free a token.
+        if let ThrottlePolicy::Queue { max_wait } = self.config.policy
+            && self.config.per_second > 0.0
+        {
+            let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / self.config.per_second);
+            if wait <= max_wait {
+                bucket.tokens -= 1.0;
+                return Ok(wait);
+            }
+        }
+        Err(PathmapError::Throttled(self.group.clone()))
+    }
+
+    /// Returns a token taken by a writer that gave up waiting for it.
+    fn refund(&self) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.tokens = (bucket.tokens + 1.0).min(self.config.burst as f64);
+    }
+}
+
+/// A token a queued writer holds while it waits; refunded if the write is dropped before its
+/// turn comes.
+struct Reservation<'a> {
+    throttle: &'a GroupThrottle,
+    waiting: bool,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.waiting {
+            self.throttle.refund();
+        }
+    }
+}
+
+impl Pathmap {
+    /// Limits writes (`set`, `overwrite`, `delete`) to `group` of `ns` and everything nested below
+    /// it; an empty group covers the whole namespace. A write is counted against the most
+    /// specific limited group that contains it, so other groups of the namespace are unaffected.
+    pub fn with_group_throttle(mut self, ns: &str, group: &str, config: ThrottleConfig) -> Self {
+        let throttles = self.throttles.entry(ns.to_string()).or_default();
+        throttles.retain(|t| t.group != group);
+        throttles.push(GroupThrottle::new(group, config));
+        throttles.sort_by_key(|t| std::cmp::Reverse(t.group.len()));
+        self
+    }
+
+    /// Applies the group throttle covering `path`, waiting or failing as its policy says.
+    pub(crate) async fn throttle_write(&self, path: &str) -> Result<()> {
+        if self.throttles.is_empty() {
+            return Ok(());
+        }
+        let Some((ns, key)) = self.split_ns(path) else {
+            return Ok(());
+        };
+        let Some(throttle) = self
+            .throttles
+            .get(ns)
+            .and_then(|throttles| throttles.iter().find(|t| path::in_group(&t.group, key)))
+        else {
+            return Ok(());
+        };
+        let now = self.clock.now();
+        let wait = throttle.acquire(now)?;
+        if wait.is_zero() {
+            return Ok(());
+        }
+        let mut reservation = Reservation {
+            throttle,
+            waiting: true,
+        };
+        // The turn comes by the instance's clock; sleeping only decides when to look at it again.
+        let turn = now + wait;
+        loop {
+            let left = turn.saturating_duration_since(self.clock.now());
+            if left.is_zero() {
+                break;
+            }
+            rt::sleep(left).await;
+        }
+        reservation.waiting = false;
+        Ok(())
+    }
+}
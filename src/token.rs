@@ -0,0 +1,189 @@
+/* src/token.rs */
+
+use crate::Pathmap;
+use crate::db;
+use crate::digest;
+use crate::error::{PathmapError, Result};
+use crate::event::Operation;
+use crate::rt;
+use futures_util::future::{self, Either};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Random bytes per token; 128 bits cannot be guessed or enumerated.
+const TOKEN_BYTES: usize = 16;
+
+/// How a token is stored: the payload plus its expiry in Unix milliseconds.
+#[derive(Serialize, Deserialize)]
+struct StoredToken<T> {
+    expires_at: u64,
+    payload: T,
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+impl Pathmap {
+    /// Stores `payload` under a new unguessable name in the group at `path` (e.g. `"auth::reset"`)
+    /// and returns the token id, which is the full path of the stored value. The token can be
+    /// consumed once, until `ttl` has passed.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn create_token<T: Serialize>(
+        &self,
+        path: &str,
+        payload: T,
+        ttl: Duration,
+    ) -> Result<String> {
+        let mut bytes = [0u8; TOKEN_BYTES];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| PathmapError::IoError(std::io::Error::other(e.to_string())))?;
+        let name = digest::hex(&bytes);
+        let path = self.qualify(path);
+        let id = if path.ends_with("::") {
+            format!("{}{}", path, name)
+        } else if path.contains("::") {
+            format!("{}.{}", path, name)
+        } else {
+            format!("{}::{}", path, name)
+        };
+        let token = StoredToken {
+            expires_at: unix_millis(self.clock.system_now() + ttl),
+            payload,
+        };
+        self.set(&id, token).await?;
+        Ok(id)
+    }
+
+    /// Atomically reads and deletes a token. Returns its payload the first time it is called
+    /// before the token expires, and `None` for unknown, expired or already consumed tokens.
+    /// A token whose payload does not deserialize as `T` is kept and the error returned.
+    /// Writes go straight to the database, bypassing the write queue.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(id = %id), err(Display)))]
+    pub async fn consume_token<T: DeserializeOwned>(&self, id: &str) -> Result<Option<T>> {
+        let (ns, key) = self.parse_path(id)?;
+        let (grp, key) = self.split_key(&key);
+        let pool = self.get_pool(ns).await?;
+        let now = unix_millis(self.clock.system_now());
+        let payload = self
+            .take_token(ns, &pool, grp, key, |token| {
+                // A reader that finds the token expired removes it, so report that as the
+                // expiration.
+                if token.expires_at <= now {
+                    return Ok(Some((Operation::Expire, None)));
+                }
+                let payload = serde_json::from_value(token.payload)?;
+                Ok(Some((Operation::Delete, Some(payload))))
+            })
+            .await?;
+        Ok(payload.flatten())
+    }
+
+    /// Deletes the token at `grp`/`key` of `ns` in one transaction, provided it decodes and
+    /// `accept` returns the operation to report for it; otherwise the delete is rolled back and
+    /// the token kept. Returns what `accept` returned with the operation, or `None` if there was
+    /// no token or it was kept.
+    async fn take_token<R>(
+        &self,
+        ns: &str,
+        pool: &SqlitePool,
+        grp: &str,
+        key: &str,
+        accept: impl FnOnce(StoredToken<Value>) -> Result<Option<(Operation, R)>>,
+    ) -> Result<Option<R>> {
+        let mut tx = pool.begin().await?;
+        // Deleting first takes the write lock, so of several concurrent callers only one
+        // receives the row. Returning before the commit drops `tx`, which rolls the delete back.
+        let Some(stored) = db::take(&mut *tx, grp, key).await? else {
+            return Ok(None);
+        };
+        let blob = stored.external.then(|| stored.data.clone());
+        let raw_value = self.load_value(ns, stored).await?;
+        let token = serde_json::from_slice::<StoredToken<Value>>(&raw_value)?;
+        let Some((op, accepted)) = accept(token)? else {
+            return Ok(None);
+        };
+        self.audit_replaced(ns, &mut tx, Operation::Delete, grp, key, &raw_value)
+            .await?;
+        tx.commit().await?;
+        self.invalidate_cached(ns, grp, key);
+        let old_size = Some(raw_value.len() as u64);
+        self.emit_change(op, ns, &db::join_key(grp, key), old_size, None);
+        if let Some(name) = blob {
+            self.remove_blob(ns, &name).await?;
+        }
+        Ok(Some(accepted))
+    }
+
+    /// Deletes the expired tokens of the group at `path`, returning how many were removed.
+    /// Consumed tokens are already gone; call this periodically for ones that were never used,
+    /// or let `run_token_expiry` do it. Values of the group that are not tokens are kept and
+    /// logged.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn purge_expired_tokens(&self, path: &str) -> Result<u64> {
+        let (ns, grp) = self.split_ns(path).unwrap_or((path, ""));
+        let grp = self.normalize_key(grp);
+        let (ns, grp) = self.resolve_soft(ns, grp)?;
+        let pool = self.get_pool(ns).await?;
+        let now = unix_millis(self.clock.system_now());
+        let mut purged = 0;
+        for entry in db::entries(&pool, &grp, false).await? {
+            let raw_value = self.load_value(ns, entry.value).await?;
+            match serde_json::from_slice::<StoredToken<Value>>(&raw_value) {
+                Ok(token) if token.expires_at > now => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    let key = db::join_key(&entry.grp, &entry.key);
+                    tracing::warn!(ns, key, error = %e, "skipping a value that is not a token");
+                    continue;
+                }
+            }
+            // The token is checked again in the deleting transaction: a concurrent consumer may
+            // have taken it, or a writer replaced it, since it was read. Only count tokens this
+            // call removed.
+            let expired = self
+                .take_token(ns, &pool, &entry.grp, &entry.key, |token| {
+                    Ok((token.expires_at <= now).then_some((Operation::Expire, ())))
+                })
+                .await;
+            match expired {
+                Ok(Some(())) => purged += 1,
+                Ok(None) => {}
+                Err(PathmapError::JsonError(e)) => {
+                    let key = db::join_key(&entry.grp, &entry.key);
+                    tracing::warn!(ns, key, error = %e, "skipping a value that is not a token");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Purges the expired tokens of the group at `path` every `interval` until the instance is
+    /// closed, so each expiration reaches hooks and subscribers as an `Expire` event within
+    /// `interval` of its deadline instead of when a reader next looks. Run it on its own task,
+    /// e.g. with an `Arc<Pathmap>`. Failed passes are logged and retried at the next interval.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn run_token_expiry(&self, path: &str, interval: Duration) -> Result<()> {
+        let mut shutdown = self.shutdown.subscribe();
+        while !*shutdown.borrow_and_update() {
+            match self.purge_expired_tokens(path).await {
+                Ok(_) => {}
+                Err(e) if matches!(e.root(), PathmapError::NamespaceNotFound(_)) => {}
+                Err(e) if matches!(e.root(), PathmapError::Closed) => break,
+                Err(e) => tracing::warn!(path, error = %e, "token expiry pass failed"),
+            }
+            let tick = std::pin::pin!(rt::sleep(interval));
+            let stop = std::pin::pin!(shutdown.changed());
+            if let Either::Right((Err(_), _)) = future::select(tick, stop).await {
+                // The instance was dropped.
+                break;
+            }
+        }
+        Ok(())
+    }
+}
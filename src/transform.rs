@@ -0,0 +1,199 @@
+/* src/transform.rs */
+
+use crate::error::{PathmapError, Result};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::sync::Arc;
+
+/// Custom transformation step: returns the new key and value, or `None` to drop the entry.
+type MapFn = Arc<dyn Fn(String, Value) -> Option<(String, Value)> + Send + Sync>;
+
+#[derive(Clone)]
+enum Step {
+    RenameKey { from: String, to: String },
+    MoveGroup { from: String, to: String },
+    MoveField { from: String, to: String },
+    RemoveField { pointer: String },
+    SetField { pointer: String, value: Value },
+    Map(MapFn),
+}
+
+/// Ordered rewrite rules applied to every entry before `import_ns_with` or `load_ndjson_with`
+/// writes it, so data from legacy systems can be reshaped on the way in. Restores copy snapshots
+/// and change logs back unchanged and never run a pipeline. Keys are the dotted keys inside the
+/// namespace; fields are addressed with JSON Pointers (RFC 6901) into each value.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renames a single key.
+    pub fn rename_key(mut self, from: &str, to: &str) -> Self {
+        self.steps.push(Step::RenameKey {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        self
+    }
+
+    /// Moves a group and everything nested below it, e.g. `legacy.users` to `users`.
+    /// An empty `to` moves the entries to the namespace root.
+    pub fn move_group(mut self, from: &str, to: &str) -> Self {
+        self.steps.push(Step::MoveGroup {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        self
+    }
+
+    /// Moves the field at pointer `from` to pointer `to` inside each value, creating missing
+    /// objects along the way. Values without the field are left alone.
+    pub fn move_field(mut self, from: &str, to: &str) -> Self {
+        self.steps.push(Step::MoveField {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        self
+    }
+
+    /// Removes the field at `pointer` from each value.
+    pub fn remove_field(mut self, pointer: &str) -> Self {
+        self.steps.push(Step::RemoveField {
+            pointer: pointer.to_string(),
+        });
+        self
+    }
+
+    /// Sets the field at `pointer` in each object value, creating missing objects along the way.
+    pub fn set_field(mut self, pointer: &str, value: Value) -> Self {
+        self.steps.push(Step::SetField {
+            pointer: pointer.to_string(),
+            value,
+        });
+        self
+    }
+
+    /// Adds an arbitrary step; returning `None` drops the entry from the import.
+    pub fn map<F>(mut self, f: F) -> Self
+    where
+        F: Fn(String, Value) -> Option<(String, Value)> + Send + Sync + 'static,
+    {
+        self.steps.push(Step::Map(Arc::new(f)));
+        self
+    }
+
+    /// Runs every step over an entry, returning `None` if a step dropped it.
+    pub(crate) fn apply(&self, key: String, value: Value) -> Result<Option<(String, Value)>> {
+        let (mut key, mut value) = (key, value);
+        for step in &self.steps {
+            match step {
+                Step::RenameKey { from, to } => {
+                    if key == *from {
+                        key = to.clone();
+                    }
+                }
+                Step::MoveGroup { from, to } => {
+                    let rest = match key.strip_prefix(from.as_str()) {
+                        Some(rest) if from.is_empty() => Some(rest),
+                        Some(rest) => rest.strip_prefix('.'),
+                        None => None,
+                    };
+                    if let Some(rest) = rest {
+                        key = if to.is_empty() {
+                            rest.to_string()
+                        } else {
+                            format!("{}.{}", to, rest)
+                        };
+                    }
+                }
+                Step::MoveField { from, to } => {
+                    if let Some(field) = take(&mut value, &tokens(from)?) {
+                        insert(&mut value, &tokens(to)?, field);
+                    }
+                }
+                Step::RemoveField { pointer } => {
+                    take(&mut value, &tokens(pointer)?);
+                }
+                Step::SetField {
+                    pointer,
+                    value: field,
+                } => {
+                    insert(&mut value, &tokens(pointer)?, field.clone());
+                }
+                Step::Map(f) => match f(key, value) {
+                    Some((k, v)) => (key, value) = (k, v),
+                    None => return Ok(None),
+                },
+            }
+        }
+        Ok(Some((key, value)))
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
+
+/// Splits a JSON Pointer into unescaped reference tokens.
+fn tokens(pointer: &str) -> Result<Vec<String>> {
+    let Some(rest) = pointer.strip_prefix('/') else {
+        return Err(PathmapError::InvalidConfig(format!(
+            "invalid JSON pointer '{}'",
+            pointer
+        )));
+    };
+    Ok(rest
+        .split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Removes and returns the value at a pointer, descending through objects and arrays.
+fn take(value: &mut Value, tokens: &[String]) -> Option<Value> {
+    let (last, parents) = tokens.split_last()?;
+    let mut node = value;
+    for token in parents {
+        node = match node {
+            Value::Object(map) => map.get_mut(token)?,
+            Value::Array(items) => items.get_mut(token.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    match node {
+        Value::Object(map) => map.remove(last),
+        Value::Array(items) => {
+            let index = last.parse::<usize>().ok().filter(|&i| i < items.len())?;
+            Some(items.remove(index))
+        }
+        _ => None,
+    }
+}
+
+/// Stores a value at a pointer, creating intermediate objects. Paths running through a
+/// non-object value are left untouched.
+fn insert(value: &mut Value, tokens: &[String], field: Value) {
+    let Some((last, parents)) = tokens.split_last() else {
+        return;
+    };
+    let mut node = value;
+    for token in parents {
+        let Value::Object(map) = node else {
+            return;
+        };
+        node = map
+            .entry(token.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if let Value::Object(map) = node {
+        map.insert(last.clone(), field);
+    }
+}
@@ -0,0 +1,146 @@
+/* src/tree.rs */
+
+//! ASCII trees of listings, in the style of `tree(1)`, for command-line and debugging output.
+
+use crate::Listing;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::Pathmap;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::error::Result;
+use std::fmt::{self, Write};
+
+const BRANCH: &str = "├── ";
+const LAST_BRANCH: &str = "└── ";
+#[cfg(not(target_arch = "wasm32"))]
+const INDENT: &str = "│   ";
+#[cfg(not(target_arch = "wasm32"))]
+const LAST_INDENT: &str = "    ";
+
+/// One entry line; groups end in `/`, so they stay distinguishable when not expanded.
+fn entry_line(
+    out: &mut impl Write,
+    prefix: &str,
+    last: bool,
+    name: &str,
+    group: bool,
+) -> fmt::Result {
+    let branch = if last { LAST_BRANCH } else { BRANCH };
+    writeln!(
+        out,
+        "{}{}{}{}",
+        prefix,
+        branch,
+        name,
+        if group { "/" } else { "" }
+    )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn counts(groups: usize, values: usize) -> String {
+    format!(
+        "{} group{}, {} value{}",
+        groups,
+        if groups == 1 { "" } else { "s" },
+        values,
+        if values == 1 { "" } else { "s" }
+    )
+}
+
+/// One level, groups first:
+///
+/// ```text
+/// ├── db/
+/// ├── cache/
+/// └── theme
+/// ```
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.groups.len() + self.values.len();
+        let entries = self
+            .groups
+            .iter()
+            .map(|name| (name, true))
+            .chain(self.values.iter().map(|name| (name, false)));
+        for (i, (name, group)) in entries.enumerate() {
+            entry_line(f, "", i + 1 == total, name, group)?;
+        }
+        Ok(())
+    }
+}
+
+/// Groups and values below the root, for the summary line.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct Totals {
+    groups: usize,
+    values: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Pathmap {
+    /// Renders a namespace or group and everything below it as an ASCII tree, followed by a line
+    /// counting its groups and values. With `max_depth`, groups deeper than that many levels are
+    /// shown but not expanded (and their contents not counted).
+    ///
+    /// ```text
+    /// app::config
+    /// ├── db/
+    /// │   ├── host
+    /// │   └── port
+    /// └── theme
+    ///
+    /// 1 group, 3 values
+    /// ```
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(path = %path), err(Display)))]
+    pub async fn render_tree(&self, path: &str, max_depth: Option<usize>) -> Result<String> {
+        let root = self.qualify(path).into_owned();
+        let mut out = format!("{}\n", root);
+        let mut totals = Totals::default();
+        self.render_level(&root, "", 1, max_depth, &mut out, &mut totals)
+            .await?;
+        out.push('\n');
+        out.push_str(&counts(totals.groups, totals.values));
+        out.push('\n');
+        Ok(out)
+    }
+
+    async fn render_level(
+        &self,
+        path: &str,
+        prefix: &str,
+        depth: usize,
+        max_depth: Option<usize>,
+        out: &mut String,
+        totals: &mut Totals,
+    ) -> Result<()> {
+        let listing = self.list(path).await?;
+        totals.groups += listing.groups.len();
+        totals.values += listing.values.len();
+        let expand = max_depth.is_none_or(|max| depth < max);
+        let total = listing.groups.len() + listing.values.len();
+        for (i, name) in listing.groups.iter().enumerate() {
+            let last = i + 1 == total;
+            // Writing to a `String` cannot fail.
+            let _ = entry_line(out, prefix, last, name, true);
+            if !expand {
+                continue;
+            }
+            let child = if !path.contains("::") {
+                format!("{}::{}", path, name)
+            } else if path.ends_with("::") {
+                format!("{}{}", path, name)
+            } else {
+                format!("{}.{}", path, name)
+            };
+            let indent = if last { LAST_INDENT } else { INDENT };
+            let prefix = format!("{}{}", prefix, indent);
+            let level = self.render_level(&child, &prefix, depth + 1, max_depth, out, totals);
+            Box::pin(level).await?;
+        }
+        for (i, name) in listing.values.iter().enumerate() {
+            let last = listing.groups.len() + i + 1 == total;
+            let _ = entry_line(out, prefix, last, name, false);
+        }
+        Ok(())
+    }
+}
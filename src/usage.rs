@@ -0,0 +1,154 @@
+/* src/usage.rs */
+
+//! Storage usage of namespace files, and alerts when it crosses the configured thresholds.
+
+use crate::Pathmap;
+use crate::config::StorageThresholds;
+use crate::db;
+use crate::error::Result;
+use crate::event::Events;
+use crate::rt;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A measurement a storage threshold applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageMetric {
+    FileBytes,
+    FreelistPercent,
+    WalBytes,
+}
+
+/// A namespace crossing one of its `StorageThresholds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageAlert {
+    pub metric: StorageMetric,
+    pub value: u64,
+    pub threshold: u64,
+    /// `true` when the value rose above the threshold, `false` when it fell back to or below it.
+    pub exceeded: bool,
+}
+
+/// How much disk a namespace's database uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageUsage {
+    pub file_bytes: u64,
+    /// Size of the write-ahead log; 0 when there is none.
+    pub wal_bytes: u64,
+    pub page_count: u64,
+    /// Pages that are allocated but unused, reclaimable by a vacuum.
+    pub freelist_pages: u64,
+}
+
+impl StorageUsage {
+    /// Share of pages on the freelist, in percent.
+    pub fn freelist_percent(&self) -> u64 {
+        (self.freelist_pages * 100)
+            .checked_div(self.page_count)
+            .unwrap_or(0)
+    }
+}
+
+/// Measures the database behind `pool`.
+pub(crate) async fn measure(pool: &SqlitePool) -> Result<StorageUsage> {
+    let path = pool.connect_options().get_filename().to_path_buf();
+    let (page_count, freelist_pages) = db::page_counts(pool).await?;
+    let mut wal = path.clone().into_os_string();
+    wal.push("-wal");
+    Ok(StorageUsage {
+        file_bytes: file_size(&path).await?,
+        wal_bytes: file_size(Path::new(&wal)).await?,
+        page_count,
+        freelist_pages,
+    })
+}
+
+async fn file_size(path: &Path) -> Result<u64> {
+    match rt::fs::metadata(path).await {
+        Ok(meta) => Ok(meta.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Which thresholds each namespace was above at its last check, so only crossings are reported.
+#[derive(Default)]
+pub(crate) struct AlertState {
+    exceeded: Mutex<HashSet<(String, StorageMetric)>>,
+}
+
+impl AlertState {
+    /// Emits a `StorageAlert` event for every threshold `usage` crossed since the last check.
+    pub(crate) fn check(
+        &self,
+        events: &Events,
+        ns: &str,
+        usage: &StorageUsage,
+        thresholds: &StorageThresholds,
+    ) {
+        let measured = [
+            (
+                StorageMetric::FileBytes,
+                usage.file_bytes,
+                thresholds.file_bytes,
+            ),
+            (
+                StorageMetric::FreelistPercent,
+                usage.freelist_percent(),
+                thresholds.freelist_percent,
+            ),
+            (
+                StorageMetric::WalBytes,
+                usage.wal_bytes,
+                thresholds.wal_bytes,
+            ),
+        ];
+        let mut state = self.exceeded.lock().unwrap();
+        for (metric, value, threshold) in measured {
+            let key = (ns.to_string(), metric);
+            let Some(threshold) = threshold else {
+                state.remove(&key);
+                continue;
+            };
+            let exceeded = value > threshold;
+            let was_exceeded = if exceeded {
+                !state.insert(key)
+            } else {
+                state.remove(&key)
+            };
+            if exceeded != was_exceeded {
+                let alert = StorageAlert {
+                    metric,
+                    value,
+                    threshold,
+                    exceeded,
+                };
+                events.emit_alert(format!("{}::", ns), alert);
+            }
+        }
+    }
+}
+
+impl Pathmap {
+    /// Measures how much disk a namespace's database and write-ahead log use.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn storage_usage(&self, ns: &str) -> Result<StorageUsage> {
+        let pool = self.get_pool(ns).await?;
+        measure(&pool).await
+    }
+
+    /// Measures a namespace like `storage_usage` and emits a `StorageAlert` event for every
+    /// threshold in `NamespaceConfig::storage_alerts` it crossed since the previous check. The
+    /// background cleanup runs this for every open namespace on each pass.
+    #[cfg_attr(feature = "instrument", tracing::instrument(level = "debug", skip_all, fields(ns = %ns), err(Display)))]
+    pub async fn check_storage(&self, ns: &str) -> Result<StorageUsage> {
+        let usage = self.storage_usage(ns).await?;
+        self.storage_alerts
+            .check(&self.events, ns, &usage, &self.ns_config(ns).storage_alerts);
+        Ok(usage)
+    }
+}
@@ -0,0 +1,64 @@
+/* tests/cleanup.rs */
+
+mod common;
+
+use kvmap::{BatchConfig, ManualClock, Pathmap};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn idle_close_leaves_pools_in_use_open() {
+    let base = common::base_path("cleanup_idle_close");
+    let clock = Arc::new(ManualClock::new());
+    let pm = Pathmap::new()
+        .with_base_path(&base)
+        .with_clock(clock.clone())
+        .with_write_batching(BatchConfig::new(100, Duration::from_millis(300)))
+        .with_idle_pool_close(Duration::from_secs(1));
+    pm.init_ns("app").await.unwrap();
+    let cleanup = pm.start_background_cleanup(Duration::from_millis(10), Duration::from_secs(3600));
+
+    // The open batch holds the namespace's pool without a connection until it commits, while
+    // the namespace looks idle to the cleanup task.
+    let committed = pm.overwrite_batched("app::key", 1).await.unwrap();
+    clock.advance(Duration::from_secs(60));
+    committed.await.unwrap();
+    assert_eq!(pm.get::<i32>("app::key").await.unwrap(), 1);
+
+    cleanup.stop();
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[tokio::test]
+async fn idle_close_closes_unused_pools() {
+    let base = common::base_path("cleanup_idle_unused");
+    let clock = Arc::new(ManualClock::new());
+    let pm = Pathmap::new()
+        .with_base_path(&base)
+        .with_clock(clock.clone())
+        .with_idle_pool_close(Duration::from_secs(1));
+    pm.overwrite("app::key", 1).await.unwrap();
+    let cleanup = pm.start_background_cleanup(Duration::from_millis(10), Duration::from_secs(3600));
+
+    let is_open = || async {
+        let info = pm.list_ns_info("app").await.unwrap();
+        info.iter().any(|ns| ns.name == "app" && ns.open)
+    };
+    assert!(is_open().await);
+    clock.advance(Duration::from_secs(60));
+    let mut closed = false;
+    for _ in 0..200 {
+        if !is_open().await {
+            closed = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(closed);
+    assert_eq!(pm.get::<i32>("app::key").await.unwrap(), 1);
+
+    cleanup.stop();
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
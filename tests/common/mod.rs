@@ -0,0 +1,10 @@
+/* tests/common/mod.rs */
+
+use std::path::PathBuf;
+
+/// A fresh base path under the temp directory, unique to this test and process.
+pub fn base_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kvmap_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
@@ -0,0 +1,25 @@
+/* tests/explain.rs */
+
+mod common;
+
+use kvmap::error::PathmapError;
+use kvmap::{ExplainOp, Pathmap};
+
+#[tokio::test]
+async fn explain_shows_an_indexed_lookup_and_names_itself_in_errors() {
+    let base = common::base_path("explain");
+    let pm = Pathmap::new().with_base_path(&base);
+    pm.overwrite("app::a.b", 1).await.unwrap();
+
+    let plans = pm.explain(ExplainOp::Get, "app::a.b").await.unwrap();
+    assert!(!plans.is_empty());
+    assert!(plans.iter().all(|plan| !plan.is_full_scan()), "{plans:?}");
+
+    let err = pm.explain(ExplainOp::Get, "a/b::key").await.unwrap_err();
+    assert_eq!(err.operation(), Some("explain"));
+    assert_eq!(err.namespace(), Some("a/b"));
+    assert!(matches!(err.root(), PathmapError::InvalidPath(_)), "{err}");
+
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
@@ -0,0 +1,57 @@
+/* tests/import.rs */
+
+mod common;
+
+use kvmap::error::PathmapError;
+use kvmap::{BulkImportOptions, ConflictPolicy, NameRules, Pathmap};
+
+fn assert_invalid_path<T: std::fmt::Debug>(result: Result<T, PathmapError>) {
+    let err = result.unwrap_err();
+    assert!(matches!(err.root(), PathmapError::InvalidPath(_)), "{err}");
+}
+
+#[tokio::test]
+async fn import_rejects_keys_that_paths_cannot_reach() {
+    let base = common::base_path("import_keys");
+    let pm = Pathmap::new()
+        .with_base_path(&base)
+        .with_name_rules(NameRules::new().max_key_len(16));
+    pm.init_ns("app").await.unwrap();
+
+    for doc in [
+        r#"{"ok": 1, "a::b": 2}"#,
+        r#"{"ok": 1, "bad\u0001key": 2}"#,
+        r#"{"ok": 1, "a_key_longer_than_sixteen_bytes": 2}"#,
+    ] {
+        assert_invalid_path(
+            pm.import_ns("app", &mut doc.as_bytes(), ConflictPolicy::Overwrite)
+                .await,
+        );
+    }
+
+    let lines = vec![Ok::<_, std::io::Error>(
+        b"{\"key\":\"ok\",\"value\":1}\n{\"key\":\"a::b\",\"value\":2}\n".to_vec(),
+    )];
+    assert_invalid_path(
+        pm.load_ndjson(
+            "app",
+            futures_util::stream::iter(lines),
+            ConflictPolicy::Overwrite,
+        )
+        .await,
+    );
+
+    assert_invalid_path(
+        pm.import_bulk(
+            "app",
+            [("ok", 1), ("bad\u{7}key", 2)],
+            BulkImportOptions::default(),
+        )
+        .await,
+    );
+
+    // Each import failed as a whole, so not even its valid entries were written.
+    assert!(!pm.exists("app::ok").await.unwrap());
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
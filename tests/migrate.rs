@@ -0,0 +1,95 @@
+/* tests/migrate.rs */
+
+mod common;
+
+use kvmap::{Listing, Pathmap};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+use std::path::Path;
+
+const LEGACY_ROWS: &[(&str, &str)] = &[
+    ("top", "1"),
+    ("a.b", "\"two\""),
+    ("a.c.d", "[3]"),
+    ("x.y.z", "{\"four\":4}"),
+];
+
+/// Writes a version 0 file: the full dotted key in a single `key` column.
+async fn write_legacy(path: &Path) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .connect()
+        .await
+        .unwrap();
+    sqlx::query("CREATE TABLE kv_store (key TEXT PRIMARY KEY, value BLOB NOT NULL)")
+        .execute(&mut conn)
+        .await
+        .unwrap();
+    for (key, value) in LEGACY_ROWS {
+        sqlx::query("INSERT INTO kv_store (key, value) VALUES (?, ?)")
+            .bind(key)
+            .bind(value.as_bytes())
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+    conn.close().await.unwrap();
+}
+
+async fn assert_rows_read_back(pm: &Pathmap) {
+    for (key, value) in LEGACY_ROWS {
+        let expected: serde_json::Value = serde_json::from_str(value).unwrap();
+        let got: serde_json::Value = pm.get(&format!("legacy::{}", key)).await.unwrap();
+        assert_eq!(got, expected, "{key}");
+    }
+}
+
+#[tokio::test]
+async fn legacy_keys_are_split_into_groups() {
+    let base = common::base_path("migrate_v0");
+    write_legacy(&base.join("legacy.sqlite")).await;
+
+    let pm = Pathmap::new().with_base_path(&base);
+    assert_rows_read_back(&pm).await;
+    assert_eq!(
+        pm.list("legacy").await.unwrap(),
+        Listing {
+            groups: vec!["a".into(), "x".into()],
+            values: vec!["top".into()],
+        }
+    );
+    assert_eq!(
+        pm.list("legacy::a").await.unwrap(),
+        Listing {
+            groups: vec!["c".into()],
+            values: vec!["b".into()],
+        }
+    );
+    pm.close().await.unwrap();
+
+    // The migrated file opens again without another migration.
+    let again = Pathmap::new().with_base_path(&base);
+    assert_rows_read_back(&again).await;
+    again.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[tokio::test]
+async fn legacy_keys_are_split_at_the_group_depth() {
+    let base = common::base_path("migrate_v0_depth");
+    write_legacy(&base.join("legacy.sqlite")).await;
+
+    let pm = Pathmap::new().with_base_path(&base).with_group_depth(1);
+    assert_rows_read_back(&pm).await;
+    assert_eq!(
+        pm.list("legacy::x").await.unwrap(),
+        Listing {
+            groups: vec![],
+            values: vec!["y.z".into()],
+        }
+    );
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
@@ -0,0 +1,44 @@
+/* tests/multi.rs */
+
+mod common;
+
+use kvmap::Pathmap;
+use kvmap::error::PathmapError;
+
+#[tokio::test]
+async fn a_failed_prepare_rolls_back_every_namespace() {
+    let base = common::base_path("multi_rollback");
+    let pm = Pathmap::new().with_base_path(&base);
+    pm.overwrite("orders::o1", 1).await.unwrap();
+    pm.overwrite("stock::widget", 10).await.unwrap();
+
+    // `orders` is prepared first; the `set` on an existing key then fails in `stock`.
+    let err = pm
+        .transaction_multi(&["orders", "stock"], |tx| {
+            tx.overwrite("orders::o1", 2)?;
+            tx.overwrite("orders::o2", 2)?;
+            tx.set("stock::widget", 9)
+        })
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err.root(), PathmapError::ValueAlreadyExists(_)),
+        "{err}"
+    );
+
+    assert_eq!(pm.get::<i32>("orders::o1").await.unwrap(), 1);
+    assert!(!pm.exists("orders::o2").await.unwrap());
+    assert_eq!(pm.get::<i32>("stock::widget").await.unwrap(), 10);
+
+    pm.transaction_multi(&["orders", "stock"], |tx| {
+        tx.overwrite("orders::o2", 2)?;
+        tx.overwrite("stock::widget", 9)
+    })
+    .await
+    .unwrap();
+    assert_eq!(pm.get::<i32>("orders::o2").await.unwrap(), 2);
+    assert_eq!(pm.get::<i32>("stock::widget").await.unwrap(), 9);
+
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
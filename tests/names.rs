@@ -0,0 +1,32 @@
+/* tests/names.rs */
+
+mod common;
+
+use kvmap::error::PathmapError;
+use kvmap::{NameRules, Pathmap};
+
+#[test]
+fn namespaces_cannot_leave_the_base_path() {
+    let rules = NameRules::new();
+    for ns in ["..", ".", "../app", "app/..", "a/b", "a\\b", ""] {
+        let err = rules.check_ns(ns).unwrap_err();
+        assert!(matches!(err, PathmapError::InvalidPath(_)), "{ns:?}: {err}");
+    }
+    rules.check_ns("app.v2").unwrap();
+}
+
+#[tokio::test]
+async fn operations_reject_escaping_namespaces() {
+    let base = common::base_path("names");
+    let pm = Pathmap::new().with_base_path(base.join("inner"));
+    for path in ["..::key", "../outside::key", "a/b::key"] {
+        let err = pm.overwrite(path, 1).await.unwrap_err();
+        assert!(
+            matches!(err.root(), PathmapError::InvalidPath(_)),
+            "{path}: {err}"
+        );
+    }
+    assert!(!base.join("outside.sqlite").exists());
+    assert!(!base.join("inner.sqlite").exists());
+    let _ = std::fs::remove_dir_all(&base);
+}
@@ -0,0 +1,204 @@
+/* tests/poll.rs */
+
+mod common;
+
+use kvmap::config::NamespaceConfig;
+use kvmap::event::{ChangeEvent, Operation};
+use kvmap::{ManualClock, Pathmap};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{ConnectOptions, Connection};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::Receiver;
+
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long to wait for any single event before the test gives up.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const READY: &str = "app::ready";
+
+fn config() -> NamespaceConfig {
+    NamespaceConfig::default().external_changes(true)
+}
+
+fn open(base: &Path) -> Pathmap {
+    Pathmap::new()
+        .with_base_path(base)
+        .with_ns_config("app", config())
+}
+
+/// Keeps rewriting the ready key from `remote` until `rx` reports it, which proves the polling
+/// task has recorded where the change log stands and reports whatever is written next.
+async fn wait_for_polling(remote: &Pathmap, rx: &mut Receiver<ChangeEvent>) {
+    let deadline = tokio::time::Instant::now() + EVENT_TIMEOUT;
+    for round in 0u32.. {
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "polling never started"
+        );
+        remote.overwrite(READY, round).await.unwrap();
+        if let Ok(Ok(event)) = tokio::time::timeout(INTERVAL * 5, rx.recv()).await
+            && event.path == READY
+        {
+            return;
+        }
+    }
+}
+
+/// Returns every event received before the one for `marker`, skipping the ready key. A remote
+/// write of `marker` made after the writes under test is polled no earlier than they are, so
+/// anything they cause, including a wrongly echoed local write, arrives before it.
+async fn events_until(
+    rx: &mut Receiver<ChangeEvent>,
+    marker: &str,
+) -> Vec<(Operation, String, bool)> {
+    let mut events = Vec::new();
+    loop {
+        let event = tokio::time::timeout(EVENT_TIMEOUT, rx.recv())
+            .await
+            .unwrap_or_else(|_| panic!("no event for {marker}; got {events:?}"))
+            .unwrap();
+        if event.path == marker {
+            return events;
+        }
+        if event.path != READY {
+            events.push((event.op, event.path, event.remote));
+        }
+    }
+}
+
+/// Number of change log entries still kept for `key` in the `app` namespace.
+async fn logged(base: &Path, key: &str) -> i64 {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(base.join("app.sqlite"))
+        .read_only(true)
+        .connect()
+        .await
+        .unwrap();
+    let count = sqlx::query_scalar("SELECT COUNT(*) FROM kv_changelog WHERE key = ?")
+        .bind(key)
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+    conn.close().await.unwrap();
+    count
+}
+
+#[tokio::test]
+async fn remote_writes_are_reported_once_and_own_writes_are_not_echoed() {
+    let base = common::base_path("poll");
+    let local = open(&base);
+    let remote = open(&base);
+    local.overwrite("app::seed", 0).await.unwrap();
+
+    let mut rx = local.subscribe();
+    let handle = local.start_change_polling(INTERVAL);
+    wait_for_polling(&remote, &mut rx).await;
+
+    local.overwrite("app::mine", 1).await.unwrap();
+    remote.overwrite("app::theirs", 2).await.unwrap();
+    remote.delete("app::seed").await.unwrap();
+    remote.overwrite("app::done", true).await.unwrap();
+
+    assert_eq!(
+        events_until(&mut rx, "app::done").await,
+        vec![
+            (Operation::Overwrite, "app::mine".to_string(), false),
+            (Operation::Overwrite, "app::theirs".to_string(), true),
+            (Operation::Delete, "app::seed".to_string(), true),
+        ]
+    );
+
+    // Later polls find nothing new, so nothing is reported again.
+    remote.overwrite("app::done_again", true).await.unwrap();
+    assert!(events_until(&mut rx, "app::done_again").await.is_empty());
+
+    handle.stop();
+    local.close().await.unwrap();
+    remote.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[tokio::test]
+async fn a_key_changed_between_polls_is_reported_with_its_latest_state() {
+    let base = common::base_path("poll_latest");
+    let local = open(&base);
+    let remote = open(&base);
+    local.init_ns("app").await.unwrap();
+
+    let mut rx = local.subscribe();
+    let handle = local.start_change_polling(INTERVAL);
+    wait_for_polling(&remote, &mut rx).await;
+
+    // One transaction, so no poll can fall between the three changes.
+    remote
+        .transaction_multi(&["app"], |tx| {
+            tx.overwrite("app::key", 1)?;
+            tx.overwrite("app::key", 2)?;
+            tx.delete("app::key")
+        })
+        .await
+        .unwrap();
+    remote.overwrite("app::done", true).await.unwrap();
+
+    assert_eq!(
+        events_until(&mut rx, "app::done").await,
+        vec![(Operation::Delete, "app::key".to_string(), true)]
+    );
+
+    handle.stop();
+    local.close().await.unwrap();
+    remote.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+/// Has a polling instance with `config` poll a remote write, then two more after its clock
+/// moved on two minutes, and returns how many change log entries of that first write remain.
+async fn logged_two_minutes_after_polling(name: &str, config: NamespaceConfig) -> i64 {
+    let base = common::base_path(name);
+    let clock = Arc::new(ManualClock::new());
+    let local = Pathmap::new()
+        .with_base_path(&base)
+        .with_ns_config("app", config)
+        .with_clock(clock.clone());
+    let remote = open(&base);
+    local.init_ns("app").await.unwrap();
+
+    let mut rx = local.subscribe();
+    let handle = local.start_change_polling(INTERVAL);
+    wait_for_polling(&remote, &mut rx).await;
+
+    remote.overwrite("app::first", 1).await.unwrap();
+    events_until(&mut rx, "app::first").await;
+    clock.advance(Duration::from_secs(120));
+    // The second poll after the clock moved has started only once the first one has trimmed.
+    for marker in ["app::second", "app::third"] {
+        remote.overwrite(marker, 1).await.unwrap();
+        events_until(&mut rx, marker).await;
+    }
+    let count = logged(&base, "first").await;
+
+    handle.stop();
+    local.close().await.unwrap();
+    remote.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+    count
+}
+
+#[tokio::test]
+async fn polled_entries_are_trimmed_after_the_change_retention() {
+    // With the default retention of a minute, the entry is gone two minutes later.
+    assert_eq!(
+        logged_two_minutes_after_polling("poll_retention", config()).await,
+        0
+    );
+
+    // A longer retention keeps it for processes that poll less often.
+    let longer = config().change_retention(Duration::from_secs(600));
+    assert_eq!(
+        logged_two_minutes_after_polling("poll_retention_longer", longer).await,
+        1
+    );
+}
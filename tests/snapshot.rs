@@ -0,0 +1,89 @@
+/* tests/snapshot.rs */
+
+mod common;
+
+use kvmap::Pathmap;
+use kvmap::config::NamespaceConfig;
+use kvmap::error::PathmapError;
+
+#[tokio::test]
+async fn restore_brings_back_the_snapshot() {
+    let base = common::base_path("snapshot_restore");
+    let pm = Pathmap::new()
+        .with_base_path(base.join("data"))
+        .with_ns_config("app", NamespaceConfig::default().blob_threshold(64));
+    let large = "x".repeat(256);
+    pm.overwrite("app::a.b", 1).await.unwrap();
+    pm.overwrite("app::large", &large).await.unwrap();
+
+    let snapshot = base.join("backups").join("app.sqlite");
+    std::fs::create_dir_all(snapshot.parent().unwrap()).unwrap();
+    pm.snapshot_ns("app", &snapshot).await.unwrap();
+    assert!(
+        pm.verify_backup("app", &snapshot)
+            .await
+            .unwrap()
+            .is_restorable()
+    );
+
+    pm.overwrite("app::a.b", 2).await.unwrap();
+    pm.delete("app::large").await.unwrap();
+    pm.overwrite("app::later", 3).await.unwrap();
+
+    pm.restore_ns("app", &snapshot).await.unwrap();
+    assert_eq!(pm.get::<i32>("app::a.b").await.unwrap(), 1);
+    assert_eq!(pm.get::<String>("app::large").await.unwrap(), large);
+    assert!(!pm.exists("app::later").await.unwrap());
+
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[tokio::test]
+async fn restore_keeps_the_unique_fields_of_the_namespace() {
+    let base = common::base_path("snapshot_unique");
+    let snapshot = base.join("plain.sqlite");
+    // A snapshot taken without the constraint carries no unique index.
+    let plain = Pathmap::new().with_base_path(base.join("plain"));
+    plain
+        .overwrite(
+            "users::alice",
+            serde_json::json!({"email": "a@example.com"}),
+        )
+        .await
+        .unwrap();
+    plain.snapshot_ns("users", &snapshot).await.unwrap();
+    plain.close().await.unwrap();
+
+    let pm = Pathmap::new()
+        .with_base_path(base.join("data"))
+        .with_ns_config("users", NamespaceConfig::default().unique_field("*.email"));
+    pm.init_ns("users").await.unwrap();
+    pm.restore_ns("users", &snapshot).await.unwrap();
+
+    let err = pm
+        .overwrite("users::bob", serde_json::json!({"email": "a@example.com"}))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err.root(), PathmapError::UniqueViolation(..)),
+        "{err}"
+    );
+
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[tokio::test]
+async fn verify_backup_errors_name_the_operation() {
+    let base = common::base_path("snapshot_verify");
+    let pm = Pathmap::new().with_base_path(&base);
+    let err = pm
+        .verify_backup("app", base.join("missing.sqlite"))
+        .await
+        .unwrap_err();
+    assert_eq!(err.operation(), Some("verify_backup"));
+    assert_eq!(err.namespace(), Some("app"));
+    assert!(matches!(err.root(), PathmapError::BackupError(_)), "{err}");
+    let _ = std::fs::remove_dir_all(&base);
+}
@@ -0,0 +1,50 @@
+/* tests/token.rs */
+
+mod common;
+
+use kvmap::{ManualClock, Pathmap};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn a_token_is_consumed_once() {
+    let base = common::base_path("token_once");
+    let pm = Pathmap::new().with_base_path(&base);
+    pm.init_ns("auth").await.unwrap();
+
+    let id = pm
+        .create_token("auth::reset", "alice", Duration::from_secs(60))
+        .await
+        .unwrap();
+    assert!(id.starts_with("auth::reset."), "{id}");
+    assert_eq!(
+        pm.consume_token::<String>(&id).await.unwrap().as_deref(),
+        Some("alice")
+    );
+    assert_eq!(pm.consume_token::<String>(&id).await.unwrap(), None);
+    assert!(!pm.exists(&id).await.unwrap());
+
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}
+
+#[tokio::test]
+async fn an_expired_token_is_not_returned() {
+    let base = common::base_path("token_expired");
+    let clock = Arc::new(ManualClock::new());
+    let pm = Pathmap::new()
+        .with_base_path(&base)
+        .with_clock(clock.clone());
+    pm.init_ns("auth").await.unwrap();
+
+    let id = pm
+        .create_token("auth::reset", 7, Duration::from_secs(60))
+        .await
+        .unwrap();
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(pm.consume_token::<i32>(&id).await.unwrap(), None);
+    assert!(!pm.exists(&id).await.unwrap());
+
+    pm.close().await.unwrap();
+    let _ = std::fs::remove_dir_all(&base);
+}